@@ -29,7 +29,7 @@ class AudioProcessorService implements IAudioProcessorService {
     final result = await engine.processFile(input.path, outputPath, presetId);
     if (result != 0) {
       final errors = {-1: 'Read error', -2: 'Write error'};
-      throw Exception('DSP Processing failed: ${errors[result] ?? 'code $result'}');
+      throw Exception('DSP Processing failed: ${engine.lastErrorMessage() ?? errors[result] ?? 'code $result'}');
     }
 
     _progressController.add(1.0);
@@ -65,7 +65,7 @@ class AudioProcessorService implements IAudioProcessorService {
     final result = await engine.processFileWithChain(input.path, outputPath, chainJson);
     if (result != 0) {
       final errors = {-1: 'Read error', -2: 'Write error', -3: 'Invalid chain definition'};
-      throw Exception('DSP Processing failed: ${errors[result] ?? 'code $result'}');
+      throw Exception('DSP Processing failed: ${engine.lastErrorMessage() ?? errors[result] ?? 'code $result'}');
     }
 
     _progressController.add(1.0);
@@ -105,7 +105,7 @@ class AudioProcessorService implements IAudioProcessorService {
     final result = await engine.processFileWithGraph(input.path, outputPath, graphJson);
     if (result != 0) {
       final errors = {-1: 'Read error', -2: 'Write error', -3: 'Invalid graph definition'};
-      throw Exception('DSP Processing failed: ${errors[result] ?? 'code $result'}');
+      throw Exception('DSP Processing failed: ${engine.lastErrorMessage() ?? errors[result] ?? 'code $result'}');
     }
 
     _progressController.add(1.0);
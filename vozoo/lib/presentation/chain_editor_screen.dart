@@ -86,7 +86,12 @@ class _ChainEditorScreenState extends ConsumerState<ChainEditorScreen> {
       newParams[key] = value;
       _nodes[nodeIndex] = node.copyWith(params: newParams);
     });
-    _updatePreviewChain();
+    if (!_previewActive) return;
+    // Move the slider live; only rebuild for params that can't change live.
+    final engine = ref.read(engineProvider);
+    if (engine.setParam(nodeIndex, key, value) != 0) {
+      _updatePreviewChain();
+    }
   }
 
   EffectChain _buildChain() => EffectChain(
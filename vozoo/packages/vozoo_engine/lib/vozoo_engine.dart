@@ -23,6 +23,9 @@ typedef _ProcessFileWithGraphDart = int Function(
 typedef _GetStringNative = Pointer<Utf8> Function();
 typedef _GetStringDart = Pointer<Utf8> Function();
 
+typedef _ValidateDefinitionNative = Pointer<Utf8> Function(Pointer<Utf8> json);
+typedef _ValidateDefinitionDart = Pointer<Utf8> Function(Pointer<Utf8> json);
+
 typedef _FreeStringNative = Void Function(Pointer<Utf8> ptr);
 typedef _FreeStringDart = void Function(Pointer<Utf8> ptr);
 
@@ -37,6 +40,11 @@ typedef _EngineDestroyDart = void Function(Pointer<Void> handle);
 typedef _EngineSetChainNative = Int32 Function(Pointer<Void> handle, Pointer<Utf8> chainJson);
 typedef _EngineSetChainDart = int Function(Pointer<Void> handle, Pointer<Utf8> chainJson);
 
+typedef _EngineSetParamNative = Int32 Function(
+    Pointer<Void> handle, Int32 node, Pointer<Utf8> key, Float value);
+typedef _EngineSetParamDart = int Function(
+    Pointer<Void> handle, int node, Pointer<Utf8> key, double value);
+
 typedef _EngineStartNative = Int32 Function(Pointer<Void> handle);
 typedef _EngineStartDart = int Function(Pointer<Void> handle);
 
@@ -55,6 +63,9 @@ typedef _EngineGetDurationDart = int Function(Pointer<Void> handle);
 typedef _EngineIsRunningNative = Int32 Function(Pointer<Void> handle);
 typedef _EngineIsRunningDart = int Function(Pointer<Void> handle);
 
+typedef _EngineGetStringNative = Pointer<Utf8> Function(Pointer<Void> handle);
+typedef _EngineGetStringDart = Pointer<Utf8> Function(Pointer<Void> handle);
+
 // ── Library loading ───────────────────────────────────────────────
 
 final DynamicLibrary _nativeLib = _loadLibrary();
@@ -103,10 +114,18 @@ final _GetStringDart _getGraphPresets = _nativeLib
     .lookup<NativeFunction<_GetStringNative>>('get_graph_presets')
     .asFunction();
 
+final _ValidateDefinitionDart _validateDefinition = _nativeLib
+    .lookup<NativeFunction<_ValidateDefinitionNative>>('validate_definition')
+    .asFunction();
+
 final _FreeStringDart _freeString = _nativeLib
     .lookup<NativeFunction<_FreeStringNative>>('free_string')
     .asFunction();
 
+final _GetStringDart _lastErrorMessage = _nativeLib
+    .lookup<NativeFunction<_GetStringNative>>('vozoo_last_error_message')
+    .asFunction();
+
 final _EngineCreateDart _engineCreate = _nativeLib
     .lookup<NativeFunction<_EngineCreateNative>>('engine_create')
     .asFunction();
@@ -123,6 +142,10 @@ final _EngineSetChainDart _engineSetGraph = _nativeLib
     .lookup<NativeFunction<_EngineSetChainNative>>('engine_set_graph')
     .asFunction();
 
+final _EngineSetParamDart _engineSetParam = _nativeLib
+    .lookup<NativeFunction<_EngineSetParamNative>>('engine_set_param')
+    .asFunction();
+
 final _EngineStartDart _engineStartRealtime = _nativeLib
     .lookup<NativeFunction<_EngineStartNative>>('engine_start_realtime')
     .asFunction();
@@ -151,6 +174,14 @@ final _EngineIsRunningDart _engineIsRecording = _nativeLib
     .lookup<NativeFunction<_EngineIsRunningNative>>('engine_is_recording')
     .asFunction();
 
+final _EngineGetStringDart _engineGetMeters = _nativeLib
+    .lookup<NativeFunction<_EngineGetStringNative>>('engine_get_meters')
+    .asFunction();
+
+final _EngineGetStringDart _engineGetLatency = _nativeLib
+    .lookup<NativeFunction<_EngineGetStringNative>>('engine_get_latency')
+    .asFunction();
+
 // ── Batch processing API (runs on background isolate) ─────────────
 
 int _processFileSync(List<String> args) {
@@ -178,13 +209,14 @@ int _processFileWithChainSync(List<String> args) {
   }
 }
 
-/// Process a WAV file with the given preset ID.
+/// Process an audio file (WAV, FLAC, Ogg Vorbis, MP3, M4A) with the given preset ID.
+/// The output path's extension picks the output format (.wav, .flac, .opus).
 /// Runs on a background isolate to avoid blocking the UI thread.
 Future<int> processFile(String inputPath, String outputPath, int presetId) {
   return Isolate.run(() => _processFileSync([inputPath, outputPath, '$presetId']));
 }
 
-/// Process a WAV file with a JSON chain definition.
+/// Process an audio file (WAV, FLAC, Ogg Vorbis, MP3, M4A) with a JSON chain definition.
 /// Runs on a background isolate to avoid blocking the UI thread.
 Future<int> processFileWithChain(String inputPath, String outputPath, String chainJson) {
   return Isolate.run(() => _processFileWithChainSync([inputPath, outputPath, chainJson]));
@@ -203,7 +235,7 @@ int _processFileWithGraphSync(List<String> args) {
   }
 }
 
-/// Process a WAV file with a JSON graph definition (DAG routing).
+/// Process an audio file (WAV, FLAC, Ogg Vorbis, MP3, M4A) with a JSON graph definition (DAG routing).
 /// Runs on a background isolate to avoid blocking the UI thread.
 Future<int> processFileWithGraph(String inputPath, String outputPath, String graphJson) {
   return Isolate.run(() => _processFileWithGraphSync([inputPath, outputPath, graphJson]));
@@ -230,6 +262,33 @@ String getPresets() {
   return json;
 }
 
+/// Check a chain or graph definition (JSON) before running it. Returns a
+/// JSON array of diagnostics with `severity`, `code`, `message`, `fix` and,
+/// where they apply, `node`, `edge` and `param`; `[]` if it's clean.
+String validateDefinition(String definitionJson) {
+  final json = definitionJson.toNativeUtf8();
+  try {
+    final ptr = _validateDefinition(json);
+    if (ptr == nullptr) return '[]';
+    final result = ptr.toDartString();
+    _freeString(ptr);
+    return result;
+  } finally {
+    malloc.free(json);
+  }
+}
+
+/// Why the most recent engine call that returned a negative code failed,
+/// e.g. "Invalid JSON at line 3, column 12: ...". Null if nothing has failed.
+/// Shared across isolates, so read it right after the failing call.
+String? lastErrorMessage() {
+  final ptr = _lastErrorMessage();
+  if (ptr == nullptr) return null;
+  final message = ptr.toDartString();
+  _freeString(ptr);
+  return message;
+}
+
 // ── Real-time engine API ──────────────────────────────────────────
 
 /// Manages the lifecycle of a real-time audio engine handle.
@@ -266,6 +325,20 @@ class VozooEngine {
     }
   }
 
+  /// Change one parameter of the running chain or graph without rebuilding
+  /// it. [node] is the node's index in a chain or its ID in a graph.
+  /// Returns 0 on success and -3 if the parameter can't change live, in
+  /// which case the chain has to be set again.
+  int setParam(int node, String key, double value) {
+    _ensureNotDisposed();
+    final keyPtr = key.toNativeUtf8();
+    try {
+      return _engineSetParam(_handle, node, keyPtr, value);
+    } finally {
+      malloc.free(keyPtr);
+    }
+  }
+
   int startRealtime() {
     _ensureNotDisposed();
     return _engineStartRealtime(_handle);
@@ -296,6 +369,29 @@ class VozooEngine {
     return _engineGetDurationMs(_handle);
   }
 
+  /// Latest meter readings as JSON: `input`, `output` and `nodes` (one per
+  /// `meter` node, with its `node` index or ID), each with `peak_db`,
+  /// `rms_db`, `momentary_lufs`, `short_term_lufs` and `spectrum_db`.
+  /// Cheap enough to poll every frame.
+  String getMeters() {
+    _ensureNotDisposed();
+    final ptr = _engineGetMeters(_handle);
+    final json = ptr.toDartString();
+    _freeString(ptr);
+    return json;
+  }
+
+  /// Delay from the mic to the speaker as JSON, in milliseconds:
+  /// `round_trip_ms` and its parts `input_device_ms`, `buffering_ms`,
+  /// `pipeline_ms` and `output_device_ms`.
+  String getLatency() {
+    _ensureNotDisposed();
+    final ptr = _engineGetLatency(_handle);
+    final json = ptr.toDartString();
+    _freeString(ptr);
+    return json;
+  }
+
   bool get isRunning {
     if (_handle == nullptr) return false;
     return _engineIsRunning(_handle) != 0;
@@ -1,4 +1,9 @@
+use std::error::Error;
+
 use clap::{Parser, Subcommand};
+use vozoo_codec::{AudioFile, AudioFormat, CodecRegistry};
+use vozoo_nodes::effects::meter;
+use vozoo_nodes::FileEffect;
 
 #[derive(Parser)]
 #[command(name = "vozoo", about = "Vozoo audio effects CLI")]
@@ -9,12 +14,15 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Process a WAV file offline with a preset, chain, or graph
+    /// Process an audio file offline with a preset, chain, or graph
     Process {
-        /// Input WAV file path
+        /// Input file path (WAV, FLAC, Ogg Vorbis, MP3 or AAC/M4A)
         input: String,
-        /// Output WAV file path
+        /// Output file path
         output: String,
+        /// Output format (wav, flac or opus); defaults to the output file's extension
+        #[arg(long, value_parser = parse_format)]
+        format: Option<AudioFormat>,
         /// Preset ID (0=Gorilla, 1=Cat, 2=Robot, 3=Chorus, 4=Reverb)
         #[arg(long, group = "mode")]
         preset: Option<i32>,
@@ -37,6 +45,22 @@ enum Commands {
         #[arg(long)]
         record: Option<String>,
     },
+    /// Measure a file's peak, RMS, loudness and spectrum
+    Analyze {
+        /// Input file path (WAV, FLAC, Ogg Vorbis, MP3 or AAC/M4A)
+        input: String,
+        /// Print the measurements as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check a chain or graph definition for mistakes without running it
+    Validate {
+        /// Chain or graph JSON (path to .json file or inline string)
+        file: String,
+        /// Print the diagnostics as JSON
+        #[arg(long)]
+        json: bool,
+    },
     /// List available effect presets
     ListPresets,
     /// List available effect nodes and their parameters
@@ -52,42 +76,51 @@ fn resolve_json(value: &str) -> Result<String, String> {
     }
 }
 
+fn parse_format(value: &str) -> Result<AudioFormat, String> {
+    AudioFormat::from_name(value).ok_or_else(|| {
+        let names: Vec<&str> = AudioFormat::ALL.iter().map(|f| f.name()).collect();
+        format!("unknown format '{}' (expected one of: {})", value, names.join(", "))
+    })
+}
+
 fn run_process(
     input: &str,
     output: &str,
+    format: Option<AudioFormat>,
     preset: Option<i32>,
     chain: Option<&str>,
     graph: Option<&str>,
-) -> Result<(), String> {
-    let result = if let Some(id) = preset {
-        vozoo_nodes::process_file(input, output, id)
+) -> Result<(), Box<dyn Error>> {
+    let format = format
+        .or_else(|| AudioFormat::from_path(output))
+        .ok_or_else(|| format!("Can't tell the format of '{}' from its extension; pass --format", output))?;
+    if CodecRegistry::builtin().encoder(format).is_none() {
+        return Err(format!("Writing {} files is not supported by this build", format).into());
+    }
+
+    let chain_json;
+    let graph_json;
+    let effect = if let Some(id) = preset {
+        FileEffect::Preset(id)
     } else if let Some(json_arg) = chain {
-        let json = resolve_json(json_arg)?;
-        vozoo_nodes::process_file_with_chain(input, output, &json)
+        chain_json = resolve_json(json_arg)?;
+        FileEffect::Chain(&chain_json)
     } else if let Some(json_arg) = graph {
-        let json = resolve_json(json_arg)?;
-        vozoo_nodes::process_file_with_graph(input, output, &json)
+        graph_json = resolve_json(json_arg)?;
+        FileEffect::Graph(&graph_json)
     } else {
         return Err("Provide one of --preset, --chain, or --graph".into());
     };
-
-    match result {
-        0 => {
-            println!("Written to {}", output);
-            Ok(())
-        }
-        -1 => Err(format!("Failed to read input file: {}", input)),
-        -2 => Err(format!("Failed to write output file: {}", output)),
-        -3 => Err("Invalid JSON definition".into()),
-        code => Err(format!("Processing failed with code {}", code)),
-    }
+    vozoo_nodes::process_file_as(input, output, effect, Some(format))?;
+    println!("Written to {}", output);
+    Ok(())
 }
 
 fn run_realtime(
     chain: Option<&str>,
     graph: Option<&str>,
     record: Option<&str>,
-) -> Result<(), String> {
+) -> Result<(), Box<dyn Error>> {
     let mut engine = vozoo_io::RealtimeEngine::new();
 
     if let Some(json_arg) = chain {
@@ -109,6 +142,15 @@ fn run_realtime(
     let mut buf = String::new();
     std::io::stdin().read_line(&mut buf).ok();
 
+    let latency = engine.latency();
+    println!(
+        "Round-trip latency: {:.1} ms (devices {:.1} ms, buffering {:.1} ms, effects {:.1} ms)",
+        latency.round_trip_ms(),
+        latency.input_device_ms + latency.output_device_ms,
+        latency.buffering_ms,
+        latency.pipeline_ms
+    );
+
     let duration_ms = engine.stop_recording();
     engine.stop();
 
@@ -118,6 +160,81 @@ fn run_realtime(
     if let Some(err) = engine.take_last_error() {
         eprintln!("Warning: {}", err);
     }
+    let xruns = engine.xruns();
+    if !xruns.is_empty() {
+        eprintln!(
+            "Warning: {} mic frames dropped, {} output frames of silence, {} recorded samples dropped",
+            xruns.input_overruns, xruns.output_underruns, xruns.dropped_recording_samples
+        );
+    }
+    Ok(())
+}
+
+fn run_analyze(input: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    let file = AudioFile::open(input).map_err(|e| format!("Failed to read '{}': {}", input, e))?;
+    let summary = meter::analyze(&file.buffer);
+    let bands = (0..vozoo_core::SPECTRUM_BANDS).map(vozoo_core::MeterSnapshot::band_center_hz);
+
+    if json {
+        let spectrum: Vec<serde_json::Value> = bands
+            .zip(summary.spectrum_db)
+            .map(|(hz, db)| serde_json::json!({ "hz": hz, "db": db }))
+            .collect();
+        let report = serde_json::json!({
+            "sample_rate": file.buffer.sample_rate(),
+            "channels": file.buffer.channels(),
+            "duration_s": file.buffer.frames() as f64 / file.buffer.sample_rate() as f64,
+            "peak_db": summary.peak_db,
+            "rms_db": summary.rms_db,
+            "max_momentary_lufs": summary.max_momentary_lufs,
+            "max_short_term_lufs": summary.max_short_term_lufs,
+            "integrated_lufs": summary.integrated_lufs,
+            "loudness_range_lu": summary.loudness_range_lu,
+            "true_peak_db": summary.true_peak_db,
+            "spectrum": spectrum,
+        });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!(
+        "{}: {} Hz, {} channel(s), {:.2} s",
+        input,
+        file.buffer.sample_rate(),
+        file.buffer.channels(),
+        file.buffer.frames() as f64 / file.buffer.sample_rate() as f64
+    );
+    println!("  Integrated loudness: {:7.1} LUFS", summary.integrated_lufs);
+    println!("  Loudness range:      {:7.1} LU", summary.loudness_range_lu);
+    println!("  True peak:           {:7.1} dBTP", summary.true_peak_db);
+    println!("  Peak:                {:7.1} dBFS", summary.peak_db);
+    println!("  RMS:                 {:7.1} dBFS", summary.rms_db);
+    println!("  Max momentary:       {:7.1} LUFS", summary.max_momentary_lufs);
+    println!("  Max short-term:      {:7.1} LUFS", summary.max_short_term_lufs);
+    println!("  Spectrum:");
+    for (hz, db) in bands.zip(summary.spectrum_db) {
+        // One '#' per 3 dB above -90 dB.
+        let bar = "#".repeat(((db + 90.0) / 3.0).max(0.0) as usize);
+        println!("    {:>7.0} Hz {:7.1} dB {}", hz, db, bar);
+    }
+    Ok(())
+}
+
+fn run_validate(file: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    let diagnostics = vozoo_nodes::validate_json(&resolve_json(file)?);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+    } else if diagnostics.is_empty() {
+        println!("{}: no problems found", file);
+    } else {
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+    }
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    if errors > 0 {
+        return Err(format!("{} error(s), {} warning(s)", errors, diagnostics.len() - errors).into());
+    }
     Ok(())
 }
 
@@ -167,15 +284,18 @@ fn main() {
         Commands::Process {
             input,
             output,
+            format,
             preset,
             chain,
             graph,
-        } => run_process(&input, &output, preset, chain.as_deref(), graph.as_deref()),
+        } => run_process(&input, &output, format, preset, chain.as_deref(), graph.as_deref()),
         Commands::Realtime {
             chain,
             graph,
             record,
         } => run_realtime(chain.as_deref(), graph.as_deref(), record.as_deref()),
+        Commands::Analyze { input, json } => run_analyze(&input, json),
+        Commands::Validate { file, json } => run_validate(&file, json),
         Commands::ListPresets => {
             list_presets();
             Ok(())
@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use vozoo_core::AudioBuffer;
+
+use crate::{AudioFormat, Codec};
+
+/// Decode-only codec for the compressed formats Symphonia reads.
+pub(crate) struct SymphoniaCodec {
+    format: AudioFormat,
+}
+
+impl SymphoniaCodec {
+    pub(crate) fn new(format: AudioFormat) -> Self {
+        Self { format }
+    }
+}
+
+impl Codec for SymphoniaCodec {
+    fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    fn decode(&self, path: &str) -> io::Result<AudioBuffer> {
+        decode_file(path, self.format)
+    }
+}
+
+/// Decode the first audio track of a file into one interleaved buffer.
+///
+/// Encoder delay and padding are trimmed where the container records them,
+/// so MP3 and AAC files keep their original length. Corrupt packets are
+/// skipped rather than failing the whole file.
+pub(crate) fn decode_file(path: &str, format: AudioFormat) -> io::Result<AudioBuffer> {
+    let file = File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension(format.extensions()[0]);
+
+    let format_options = FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+    };
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &format_options, &MetadataOptions::default())
+        .map_err(to_io)?;
+    let mut reader = probed.format;
+
+    let track = reader
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| invalid("No audio track"))?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(to_io)?;
+    let mut sample_rate = track.codec_params.sample_rate;
+    let mut channels = track.codec_params.channels.map(|c| c.count() as u16);
+
+    let mut samples = Vec::new();
+    let mut scratch: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            // A new chained stream starts here; stop at the end of the first.
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(e) => return Err(to_io(e)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(to_io(e)),
+        };
+        let spec = *decoded.spec();
+        sample_rate.get_or_insert(spec.rate);
+        channels.get_or_insert(spec.channels.count() as u16);
+
+        let frames = decoded.capacity();
+        if scratch.as_ref().is_none_or(|b| b.capacity() < frames * spec.channels.count()) {
+            scratch = Some(SampleBuffer::new(frames as u64, spec));
+        }
+        let buffer = scratch.as_mut().expect("scratch buffer was just sized");
+        buffer.copy_interleaved_ref(decoded);
+        samples.extend_from_slice(buffer.samples());
+    }
+
+    let sample_rate = sample_rate.ok_or_else(|| invalid("Unknown sample rate"))?;
+    let channels = channels.filter(|&c| c > 0).ok_or_else(|| invalid("Unknown channel count"))?;
+    Ok(AudioBuffer::with_channels(samples, channels, sample_rate))
+}
+
+fn to_io(err: SymphoniaError) -> io::Error {
+    match err {
+        SymphoniaError::IoError(e) => e,
+        SymphoniaError::Unsupported(what) => io::Error::new(io::ErrorKind::Unsupported, what),
+        e => invalid(&e.to_string()),
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
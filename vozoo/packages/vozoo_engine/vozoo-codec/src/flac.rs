@@ -0,0 +1,549 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use vozoo_core::{AudioBuffer, Quantizer};
+
+use crate::decode::decode_file;
+use crate::{AudioFormat, Codec};
+
+/// Frames per FLAC block. 4096 is the reference encoder's default.
+const BLOCK_SIZE: usize = 4096;
+
+/// Highest order of the fixed polynomial predictors.
+const MAX_FIXED_ORDER: usize = 4;
+
+/// Blocks are split into at most `2^MAX_PARTITION_ORDER` Rice partitions.
+const MAX_PARTITION_ORDER: u32 = 8;
+
+/// Largest Rice parameter of the 4-bit parameter coding method.
+const MAX_RICE_PARAM: u32 = 14;
+
+/// Options for `encode_flac`.
+#[derive(Debug, Clone, Copy)]
+pub struct FlacEncodeOptions {
+    /// 16 or 24.
+    pub bits_per_sample: u32,
+    /// Add TPDF dither when quantizing to 16 bits.
+    pub dither: bool,
+}
+
+impl Default for FlacEncodeOptions {
+    fn default() -> Self {
+        Self {
+            bits_per_sample: 16,
+            dither: true,
+        }
+    }
+}
+
+/// FLAC through Symphonia's decoder and the encoder below.
+pub(crate) struct FlacCodec;
+
+impl Codec for FlacCodec {
+    fn format(&self) -> AudioFormat {
+        AudioFormat::Flac
+    }
+
+    fn can_encode(&self) -> bool {
+        true
+    }
+
+    fn decode(&self, path: &str) -> io::Result<AudioBuffer> {
+        decode_file(path, AudioFormat::Flac)
+    }
+
+    fn encode(&self, path: &str, buffer: &AudioBuffer) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        encode_flac(&mut out, buffer, &FlacEncodeOptions::default())?;
+        out.flush()
+    }
+}
+
+/// Encode a buffer as a FLAC stream.
+///
+/// Each block is coded with the best of the constant, verbatim and fixed
+/// polynomial subframes, with Rice-coded residuals; stereo also tries the
+/// left/side, side/right and mid/side decorrelations. That is roughly the
+/// reference encoder's fast mode. The STREAMINFO MD5 is left unset.
+pub fn encode_flac<W: Write>(out: &mut W, buffer: &AudioBuffer, options: &FlacEncodeOptions) -> io::Result<()> {
+    let bits = options.bits_per_sample;
+    let sample_size_code = match bits {
+        16 => 4,
+        24 => 6,
+        _ => return Err(invalid_input("FLAC output must be 16 or 24 bits")),
+    };
+    let channels = buffer.channels() as usize;
+    if channels > 8 {
+        return Err(invalid_input("FLAC supports at most 8 channels"));
+    }
+    if buffer.sample_rate() >= 1 << 20 {
+        return Err(invalid_input("Sample rate too high for FLAC"));
+    }
+
+    let mut quantizer = Quantizer::new(options.dither && bits <= 16);
+    let mut planes = vec![Vec::with_capacity(BLOCK_SIZE); channels];
+    let mut frames = Vec::new();
+    let mut frame_sizes = (u32::MAX, 0u32);
+
+    for (index, block) in buffer.samples.chunks(BLOCK_SIZE * channels).enumerate() {
+        for plane in &mut planes {
+            plane.clear();
+        }
+        for frame in block.chunks_exact(channels) {
+            for (plane, &s) in planes.iter_mut().zip(frame) {
+                plane.push(quantizer.quantize(s, bits));
+            }
+        }
+
+        let start = frames.len();
+        write_frame(&mut frames, index as u64, &planes, bits, sample_size_code);
+        let size = (frames.len() - start) as u32;
+        frame_sizes = (frame_sizes.0.min(size), frame_sizes.1.max(size));
+    }
+    if frames.is_empty() {
+        frame_sizes = (0, 0);
+    }
+
+    let mut info = BitWriter::new();
+    info.write(BLOCK_SIZE as u64, 16);
+    info.write(BLOCK_SIZE as u64, 16);
+    info.write(frame_sizes.0 as u64, 24);
+    info.write(frame_sizes.1 as u64, 24);
+    info.write(buffer.sample_rate() as u64, 20);
+    info.write(channels as u64 - 1, 3);
+    info.write(bits as u64 - 1, 5);
+    info.write(buffer.frames() as u64, 36);
+    info.write(0, 64);
+    info.write(0, 64);
+
+    out.write_all(b"fLaC")?;
+    // Metadata block header: last block, type 0 (STREAMINFO), 34 bytes.
+    out.write_all(&[0x80, 0, 0, 34])?;
+    out.write_all(&info.bytes)?;
+    out.write_all(&frames)
+}
+
+/// Append one frame holding `planes` (one per channel) to `out`.
+fn write_frame(out: &mut Vec<u8>, index: u64, planes: &[Vec<i64>], bits: u32, sample_size_code: u64) {
+    let n = planes[0].len();
+    let (assignment, subframes) = if planes.len() == 2 {
+        stereo_subframes(&planes[0], &planes[1], bits)
+    } else {
+        let subframes = planes.iter().map(|p| plan_subframe(p, bits)).collect();
+        (planes.len() as u64 - 1, subframes)
+    };
+
+    let mut w = BitWriter::new();
+    w.write(0b11_1111_1111_1110, 14);
+    // Reserved bit, then fixed-blocksize stream.
+    w.write(0, 2);
+    let block_size_code = match n {
+        BLOCK_SIZE => 12,
+        1..=256 => 6,
+        _ => 7,
+    };
+    w.write(block_size_code, 4);
+    // Sample rate: taken from STREAMINFO.
+    w.write(0, 4);
+    w.write(assignment, 4);
+    w.write(sample_size_code, 3);
+    w.write(0, 1);
+    write_utf8_number(&mut w, index);
+    match block_size_code {
+        6 => w.write(n as u64 - 1, 8),
+        7 => w.write(n as u64 - 1, 16),
+        _ => {}
+    }
+    let crc = crc8(&w.bytes);
+    w.write(crc as u64, 8);
+
+    for (samples, bps, plan) in &subframes {
+        write_subframe(&mut w, samples, *bps, plan);
+    }
+    w.align();
+    let crc = crc16(&w.bytes);
+    w.write(crc as u64, 16);
+    out.extend_from_slice(&w.bytes);
+}
+
+/// The cheapest channel assignment for a stereo block and its subframes.
+fn stereo_subframes(left: &[i64], right: &[i64], bits: u32) -> (u64, Vec<(Vec<i64>, u32, Subframe)>) {
+    let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+    let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+
+    let l = plan_subframe(left, bits);
+    let r = plan_subframe(right, bits);
+    let s = plan_subframe(&side, bits + 1);
+    let m = plan_subframe(&mid, bits);
+
+    // Channel assignment codes: 1 = independent, 8 = left/side,
+    // 9 = side/right, 10 = mid/side.
+    let options = [(1, &l, &r), (8, &l, &s), (9, &s, &r), (10, &m, &s)];
+    let (assignment, a, b) = options
+        .into_iter()
+        .min_by_key(|(_, a, b)| a.2.bits + b.2.bits)
+        .expect("options is not empty");
+    (assignment, vec![a.clone(), b.clone()])
+}
+
+/// How one channel of a block is coded.
+#[derive(Clone)]
+struct Subframe {
+    kind: SubframeKind,
+    /// Size of the coded subframe in bits.
+    bits: u64,
+}
+
+#[derive(Clone)]
+enum SubframeKind {
+    Constant,
+    Verbatim,
+    Fixed {
+        order: usize,
+        residual: Vec<i64>,
+        partition_order: u32,
+        params: Vec<u32>,
+    },
+}
+
+/// Pick the smallest coding of `samples` at `bps` bits per sample. Returns
+/// the samples and bit depth alongside, as frames write them later.
+fn plan_subframe(samples: &[i64], bps: u32) -> (Vec<i64>, u32, Subframe) {
+    // Type and wasted-bits headers.
+    let header = 8;
+    let n = samples.len();
+
+    let plan = if samples.iter().all(|&s| s == samples[0]) {
+        Subframe {
+            kind: SubframeKind::Constant,
+            bits: header + bps as u64,
+        }
+    } else {
+        let mut best = Subframe {
+            kind: SubframeKind::Verbatim,
+            bits: header + n as u64 * bps as u64,
+        };
+        for order in 0..=MAX_FIXED_ORDER.min(n - 1) {
+            let residual = fixed_residual(samples, order);
+            let (partition_order, params, rice_bits) = plan_rice(&residual, n, order);
+            let bits = header + (order as u64 * bps as u64) + rice_bits;
+            if bits < best.bits {
+                best = Subframe {
+                    kind: SubframeKind::Fixed {
+                        order,
+                        residual,
+                        partition_order,
+                        params,
+                    },
+                    bits,
+                };
+            }
+        }
+        best
+    };
+    (samples.to_vec(), bps, plan)
+}
+
+/// Residual of the fixed polynomial predictor of `order` (0-4).
+fn fixed_residual(x: &[i64], order: usize) -> Vec<i64> {
+    (order..x.len())
+        .map(|i| match order {
+            0 => x[i],
+            1 => x[i] - x[i - 1],
+            2 => x[i] - 2 * x[i - 1] + x[i - 2],
+            3 => x[i] - 3 * x[i - 1] + 3 * x[i - 2] - x[i - 3],
+            _ => x[i] - 4 * x[i - 1] + 6 * x[i - 2] - 4 * x[i - 3] + x[i - 4],
+        })
+        .collect()
+}
+
+/// Zigzag-fold a signed residual for Rice coding.
+fn fold(r: i64) -> u64 {
+    ((r << 1) ^ (r >> 63)) as u64
+}
+
+/// Choose the partition order and per-partition Rice parameters for a
+/// residual of a block of `n` frames. Returns them with the estimated size
+/// in bits, including the residual headers.
+fn plan_rice(residual: &[i64], n: usize, order: usize) -> (u32, Vec<u32>, u64) {
+    // Folded sums for the finest usable partitioning, merged pairwise for
+    // coarser ones.
+    let max_order = (0..=MAX_PARTITION_ORDER)
+        .rev()
+        .find(|&p| n.is_multiple_of(1 << p) && (n >> p) > order)
+        .unwrap_or(0);
+    let partition_len = n >> max_order;
+    let mut sums: Vec<u64> = Vec::with_capacity(1 << max_order);
+    let mut start = 0;
+    for p in 0..1usize << max_order {
+        let len = if p == 0 { partition_len - order } else { partition_len };
+        sums.push(residual[start..start + len].iter().map(|&r| fold(r)).sum());
+        start += len;
+    }
+
+    let mut best: Option<(u32, Vec<u32>, u64)> = None;
+    for partition_order in (0..=max_order).rev() {
+        let len = n >> partition_order;
+        let mut params = Vec::with_capacity(sums.len());
+        // Coding method and partition order.
+        let mut bits = 6;
+        for (p, &sum) in sums.iter().enumerate() {
+            let count = if p == 0 { len - order } else { len } as u64;
+            let (param, cost) = (0..=MAX_RICE_PARAM)
+                .map(|k| (k, count * (k as u64 + 1) + (sum >> k)))
+                .min_by_key(|&(_, cost)| cost)
+                .expect("parameter range is not empty");
+            params.push(param);
+            bits += 4 + cost;
+        }
+        if best.as_ref().is_none_or(|b| bits < b.2) {
+            best = Some((partition_order, params, bits));
+        }
+        sums = sums.chunks(2).map(|pair| pair.iter().sum()).collect();
+    }
+    best.expect("at least partition order 0 is tried")
+}
+
+fn write_subframe(w: &mut BitWriter, samples: &[i64], bps: u32, plan: &Subframe) {
+    // Zero padding bit, type, no wasted bits.
+    w.write(0, 1);
+    match &plan.kind {
+        SubframeKind::Constant => {
+            w.write(0, 6);
+            w.write(0, 1);
+            w.write_signed(samples[0], bps);
+        }
+        SubframeKind::Verbatim => {
+            w.write(1, 6);
+            w.write(0, 1);
+            for &s in samples {
+                w.write_signed(s, bps);
+            }
+        }
+        SubframeKind::Fixed {
+            order,
+            residual,
+            partition_order,
+            params,
+        } => {
+            w.write(0b001000 | *order as u64, 6);
+            w.write(0, 1);
+            for &s in &samples[..*order] {
+                w.write_signed(s, bps);
+            }
+            // Rice coding with 4-bit parameters.
+            w.write(0, 2);
+            w.write(*partition_order as u64, 4);
+            let len = samples.len() >> partition_order;
+            let mut start = 0;
+            for (p, &k) in params.iter().enumerate() {
+                let count = if p == 0 { len - order } else { len };
+                w.write(k as u64, 4);
+                for &r in &residual[start..start + count] {
+                    let u = fold(r);
+                    w.write_unary(u >> k);
+                    w.write(u & ((1 << k) - 1), k);
+                }
+                start += count;
+            }
+        }
+    }
+}
+
+/// Frame numbers use UTF-8's variable-length coding, extended to 36 bits.
+fn write_utf8_number(w: &mut BitWriter, v: u64) {
+    if v < 0x80 {
+        w.write(v, 8);
+        return;
+    }
+    let len = match v {
+        0..0x800 => 2,
+        0x800..0x1_0000 => 3,
+        0x1_0000..0x20_0000 => 4,
+        0x20_0000..0x400_0000 => 5,
+        0x400_0000..0x8000_0000 => 6,
+        _ => 7,
+    };
+    let prefix = (0xFF00u64 >> len) & 0xFF;
+    w.write(prefix | (v >> (6 * (len - 1))), 8);
+    for i in (0..len - 1).rev() {
+        w.write(0x80 | ((v >> (6 * i)) & 0x3F), 8);
+    }
+}
+
+/// MSB-first bit packer.
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    pending: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            acc: 0,
+            pending: 0,
+        }
+    }
+
+    /// Write the low `n` bits of `value`.
+    fn write(&mut self, value: u64, n: u32) {
+        if n > 32 {
+            self.write(value >> 32, n - 32);
+            self.write(value & 0xFFFF_FFFF, 32);
+            return;
+        }
+        if n == 0 {
+            return;
+        }
+        self.acc = (self.acc << n) | (value & ((1u64 << n) - 1));
+        self.pending += n;
+        while self.pending >= 8 {
+            self.pending -= 8;
+            self.bytes.push((self.acc >> self.pending) as u8);
+        }
+    }
+
+    /// Write a two's complement value in `n` bits.
+    fn write_signed(&mut self, value: i64, n: u32) {
+        self.write(value as u64, n);
+    }
+
+    /// `q` zero bits, then a one.
+    fn write_unary(&mut self, mut q: u64) {
+        while q >= 32 {
+            self.write(0, 32);
+            q -= 32;
+        }
+        self.write(1, q as u32 + 1);
+    }
+
+    /// Pad with zero bits to a byte boundary.
+    fn align(&mut self) {
+        if self.pending > 0 {
+            self.write(0, 8 - self.pending);
+        }
+    }
+}
+
+/// CRC-8, polynomial x^8 + x^2 + x + 1, as used for frame headers.
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |mut crc, &b| {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// CRC-16, polynomial x^16 + x^15 + x^2 + 1, as used for whole frames.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |mut crc, &b| {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vozoo_core::test_signals::sine;
+
+    fn roundtrip(buffer: &AudioBuffer, options: FlacEncodeOptions, name: &str) -> (AudioBuffer, u64) {
+        let path = format!("/tmp/vozoo_codec_test_{name}.flac");
+        let mut out = BufWriter::new(File::create(&path).unwrap());
+        encode_flac(&mut out, buffer, &options).unwrap();
+        out.flush().unwrap();
+        drop(out);
+        let size = std::fs::metadata(&path).unwrap().len();
+        let decoded = crate::AudioFile::open(&path).unwrap();
+        assert_eq!(decoded.format, AudioFormat::Flac);
+        std::fs::remove_file(&path).ok();
+        (decoded.buffer, size)
+    }
+
+    #[test]
+    fn test_mono_roundtrip_is_lossless() {
+        // Not a multiple of the block size, so the last block is short.
+        let buffer = AudioBuffer::new(sine(440.0, 0.5, 44100, 10_000), 44100);
+        let options = FlacEncodeOptions {
+            bits_per_sample: 16,
+            dither: false,
+        };
+        let (decoded, size) = roundtrip(&buffer, options, "mono");
+
+        assert_eq!(decoded.sample_rate(), 44100);
+        assert_eq!(decoded.channels(), 1);
+        assert_eq!(decoded.frames(), 10_000);
+        let mut quantizer = Quantizer::new(false);
+        for (a, b) in buffer.samples.iter().zip(&decoded.samples) {
+            assert_eq!(quantizer.quantize(*a, 16), (b * 32768.0).round() as i64);
+        }
+        // A pure tone compresses well below 16-bit PCM.
+        assert!(size < 10_000, "{size} bytes");
+    }
+
+    #[test]
+    fn test_stereo_24_bit_roundtrip() {
+        let left = sine(300.0, 0.5, 48000, 9000);
+        let right: Vec<f32> = left.iter().enumerate().map(|(i, s)| s * 0.5 + (i % 7) as f32 * 1e-3).collect();
+        let buffer = AudioBuffer::from_planar(&[left, right], 48000);
+        let options = FlacEncodeOptions {
+            bits_per_sample: 24,
+            dither: false,
+        };
+        let (decoded, _) = roundtrip(&buffer, options, "stereo24");
+
+        assert_eq!(decoded.channels(), 2);
+        assert_eq!(decoded.frames(), 9000);
+        let max_err = buffer
+            .samples
+            .iter()
+            .zip(&decoded.samples)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0f32, f32::max);
+        assert!(max_err < 1e-6, "max error {max_err}");
+    }
+
+    #[test]
+    fn test_silence_and_noise_blocks() {
+        // Constant blocks, then full-scale noise that only verbatim fits.
+        let mut state = 1u32;
+        let mut samples = vec![0.0f32; BLOCK_SIZE];
+        samples.extend((0..BLOCK_SIZE).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 * 2.0 - 1.0
+        }));
+        let buffer = AudioBuffer::new(samples, 16000);
+        let (decoded, _) = roundtrip(&buffer, FlacEncodeOptions::default(), "noise");
+        assert_eq!(decoded.frames(), 2 * BLOCK_SIZE);
+        assert!(decoded.samples[..BLOCK_SIZE].iter().all(|s| s.abs() < 1e-4));
+        let max_err = buffer.samples[BLOCK_SIZE..]
+            .iter()
+            .zip(&decoded.samples[BLOCK_SIZE..])
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0f32, f32::max);
+        assert!(max_err < 1e-4, "max error {max_err}");
+    }
+
+    #[test]
+    fn test_rejects_unsupported_bit_depth() {
+        let buffer = AudioBuffer::new(vec![0.0; 16], 48000);
+        let options = FlacEncodeOptions {
+            bits_per_sample: 12,
+            dither: false,
+        };
+        assert!(encode_flac(&mut Vec::new(), &buffer, &options).is_err());
+    }
+}
@@ -0,0 +1,361 @@
+mod decode;
+mod flac;
+#[cfg(feature = "opus")]
+mod opus;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use vozoo_core::{read_wav, write_wav, AudioBuffer};
+
+pub use flac::{encode_flac, FlacEncodeOptions};
+#[cfg(feature = "opus")]
+pub use opus::encode_opus;
+
+/// A container/codec combination that can be read or written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioFormat {
+    Wav,
+    Flac,
+    /// Vorbis in an Ogg container.
+    Vorbis,
+    /// Opus in an Ogg container.
+    Opus,
+    Mp3,
+    /// AAC in an MP4/M4A container or as raw ADTS.
+    Aac,
+}
+
+impl AudioFormat {
+    pub const ALL: [AudioFormat; 6] = [
+        AudioFormat::Wav,
+        AudioFormat::Flac,
+        AudioFormat::Vorbis,
+        AudioFormat::Opus,
+        AudioFormat::Mp3,
+        AudioFormat::Aac,
+    ];
+
+    /// Short name, as accepted by `from_name` and the CLI's `--format`.
+    pub fn name(self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Vorbis => "vorbis",
+            AudioFormat::Opus => "opus",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Aac => "aac",
+        }
+    }
+
+    /// File extensions, the first being the one to write.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            AudioFormat::Wav => &["wav", "wave"],
+            AudioFormat::Flac => &["flac"],
+            AudioFormat::Vorbis => &["ogg", "oga"],
+            AudioFormat::Opus => &["opus"],
+            AudioFormat::Mp3 => &["mp3"],
+            AudioFormat::Aac => &["m4a", "aac", "mp4"],
+        }
+    }
+
+    /// Look up a format by name or extension, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim_start_matches('.').to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|f| f.name() == name || f.extensions().contains(&name.as_str()))
+    }
+
+    /// Format implied by a path's extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        Path::new(path).extension()?.to_str().and_then(Self::from_name)
+    }
+
+    /// Format of the data at the start of a file, from its magic bytes.
+    pub fn sniff(header: &[u8]) -> Option<Self> {
+        let starts = |magic: &[u8]| header.starts_with(magic);
+        if starts(b"RIFF") || starts(b"RF64") || starts(b"BW64") {
+            Some(AudioFormat::Wav)
+        } else if starts(b"fLaC") {
+            Some(AudioFormat::Flac)
+        } else if starts(b"OggS") {
+            // The first page holds the codec's identification header.
+            let opus = header.windows(8).any(|w| w == b"OpusHead");
+            Some(if opus { AudioFormat::Opus } else { AudioFormat::Vorbis })
+        } else if header.get(4..8) == Some(b"ftyp") {
+            Some(AudioFormat::Aac)
+        } else if starts(b"ID3") {
+            Some(AudioFormat::Mp3)
+        } else if header.len() >= 2 && header[0] == 0xFF && header[1] & 0xE0 == 0xE0 {
+            // MPEG audio sync; ADTS is the same sync with the layer bits zero.
+            Some(if header[1] & 0x06 == 0 { AudioFormat::Aac } else { AudioFormat::Mp3 })
+        } else {
+            None
+        }
+    }
+
+    /// Format of an existing file: sniffed from its contents, falling back
+    /// to the extension.
+    pub fn detect(path: &str) -> io::Result<Self> {
+        let mut header = [0u8; 64];
+        let mut file = File::open(path)?;
+        let mut len = 0;
+        while len < header.len() {
+            match file.read(&mut header[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        Self::sniff(&header[..len])
+            .or_else(|| Self::from_path(path))
+            .ok_or_else(|| unsupported(format!("Unrecognized audio file: {path}")))
+    }
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Reads and/or writes one audio format.
+///
+/// Codecs work on whole files: `decode` returns the file's samples at its
+/// own rate and channel count, `encode` writes a buffer as it is.
+pub trait Codec: Send + Sync {
+    fn format(&self) -> AudioFormat;
+
+    fn can_decode(&self) -> bool {
+        true
+    }
+
+    fn can_encode(&self) -> bool {
+        false
+    }
+
+    fn decode(&self, path: &str) -> io::Result<AudioBuffer>;
+
+    fn encode(&self, path: &str, buffer: &AudioBuffer) -> io::Result<()> {
+        let _ = (path, buffer);
+        Err(unsupported(format!("Writing {} files is not supported", self.format())))
+    }
+}
+
+/// The codecs available for reading and writing files.
+///
+/// The built-in set reads WAV, FLAC, Ogg Vorbis, MP3 and AAC/M4A, and writes
+/// WAV, FLAC and, with the `opus` feature, Ogg Opus. Apps can register their
+/// own codecs to add formats or replace built-in ones: when several codecs
+/// handle a format, the most recently registered wins.
+pub struct CodecRegistry {
+    codecs: Vec<Box<dyn Codec>>,
+}
+
+impl CodecRegistry {
+    /// A registry with no codecs.
+    pub fn empty() -> Self {
+        Self { codecs: Vec::new() }
+    }
+
+    /// A registry with every codec built into this crate.
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Box::new(WavCodec));
+        registry.register(Box::new(flac::FlacCodec));
+        for format in [AudioFormat::Vorbis, AudioFormat::Mp3, AudioFormat::Aac] {
+            registry.register(Box::new(decode::SymphoniaCodec::new(format)));
+        }
+        #[cfg(feature = "opus")]
+        registry.register(Box::new(opus::OpusCodec));
+        registry
+    }
+
+    /// The shared registry `AudioFile` uses.
+    pub fn builtin() -> &'static CodecRegistry {
+        static BUILTIN: OnceLock<CodecRegistry> = OnceLock::new();
+        BUILTIN.get_or_init(CodecRegistry::new)
+    }
+
+    pub fn register(&mut self, codec: Box<dyn Codec>) {
+        self.codecs.push(codec);
+    }
+
+    pub fn decoder(&self, format: AudioFormat) -> Option<&dyn Codec> {
+        self.find(format, |c| c.can_decode())
+    }
+
+    pub fn encoder(&self, format: AudioFormat) -> Option<&dyn Codec> {
+        self.find(format, |c| c.can_encode())
+    }
+
+    fn find(&self, format: AudioFormat, pred: impl Fn(&dyn Codec) -> bool) -> Option<&dyn Codec> {
+        self.codecs
+            .iter()
+            .rev()
+            .map(|c| c.as_ref())
+            .find(|c| c.format() == format && pred(*c))
+    }
+
+    /// Read a file in any format this registry can decode.
+    pub fn open(&self, path: &str) -> io::Result<AudioFile> {
+        let format = AudioFormat::detect(path)?;
+        let codec = self
+            .decoder(format)
+            .ok_or_else(|| unsupported(format!("Reading {format} files is not supported")))?;
+        Ok(AudioFile {
+            buffer: codec.decode(path)?,
+            format,
+        })
+    }
+
+    /// Write `buffer` to `path` as `format`.
+    pub fn save(&self, path: &str, buffer: &AudioBuffer, format: AudioFormat) -> io::Result<()> {
+        let codec = self
+            .encoder(format)
+            .ok_or_else(|| unsupported(format!("Writing {format} files is not supported in this build")))?;
+        codec.encode(path, buffer)
+    }
+}
+
+impl Default for CodecRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decoded audio together with the format it was stored in.
+pub struct AudioFile {
+    pub buffer: AudioBuffer,
+    pub format: AudioFormat,
+}
+
+impl AudioFile {
+    /// Read a file with the built-in codecs, detecting its format.
+    pub fn open(path: &str) -> io::Result<Self> {
+        CodecRegistry::builtin().open(path)
+    }
+
+    /// Write the audio to `path` in the format its extension names.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let format = AudioFormat::from_path(path)
+            .ok_or_else(|| unsupported(format!("No audio format for the extension of {path}")))?;
+        self.save_as(path, format)
+    }
+
+    /// Write the audio to `path` as `format`, whatever its extension.
+    pub fn save_as(&self, path: &str, format: AudioFormat) -> io::Result<()> {
+        CodecRegistry::builtin().save(path, &self.buffer, format)
+    }
+}
+
+/// WAV through `vozoo-core`'s reader and writer.
+struct WavCodec;
+
+impl Codec for WavCodec {
+    fn format(&self) -> AudioFormat {
+        AudioFormat::Wav
+    }
+
+    fn can_encode(&self) -> bool {
+        true
+    }
+
+    fn decode(&self, path: &str) -> io::Result<AudioBuffer> {
+        read_wav(path)
+    }
+
+    fn encode(&self, path: &str, buffer: &AudioBuffer) -> io::Result<()> {
+        write_wav(path, buffer)
+    }
+}
+
+fn unsupported(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_names_and_extensions() {
+        assert_eq!(AudioFormat::from_name("FLAC"), Some(AudioFormat::Flac));
+        assert_eq!(AudioFormat::from_name(".ogg"), Some(AudioFormat::Vorbis));
+        assert_eq!(AudioFormat::from_name("m4a"), Some(AudioFormat::Aac));
+        assert_eq!(AudioFormat::from_name("xyz"), None);
+        assert_eq!(AudioFormat::from_path("/tmp/take.Opus"), Some(AudioFormat::Opus));
+        assert_eq!(AudioFormat::from_path("/tmp/take"), None);
+        for format in AudioFormat::ALL {
+            assert_eq!(AudioFormat::from_name(format.name()), Some(format));
+        }
+    }
+
+    #[test]
+    fn test_sniffs_magic_bytes() {
+        assert_eq!(AudioFormat::sniff(b"RIFF\0\0\0\0WAVE"), Some(AudioFormat::Wav));
+        assert_eq!(AudioFormat::sniff(b"fLaC\0"), Some(AudioFormat::Flac));
+        assert_eq!(AudioFormat::sniff(b"OggS\0\x02....\x01vorbis"), Some(AudioFormat::Vorbis));
+        assert_eq!(AudioFormat::sniff(b"OggS\0\x02....OpusHead"), Some(AudioFormat::Opus));
+        assert_eq!(AudioFormat::sniff(b"\0\0\0\x20ftypM4A "), Some(AudioFormat::Aac));
+        assert_eq!(AudioFormat::sniff(b"ID3\x04"), Some(AudioFormat::Mp3));
+        assert_eq!(AudioFormat::sniff(&[0xFF, 0xFB, 0x90]), Some(AudioFormat::Mp3));
+        assert_eq!(AudioFormat::sniff(&[0xFF, 0xF1, 0x50]), Some(AudioFormat::Aac));
+        assert_eq!(AudioFormat::sniff(b"hello"), None);
+    }
+
+    #[test]
+    fn test_wav_contents_win_over_extension() {
+        let path = "/tmp/vozoo_codec_test_misnamed.mp3";
+        let buffer = AudioBuffer::new(vec![0.25; 480], 48000);
+        write_wav(path, &buffer).unwrap();
+
+        let file = AudioFile::open(path).unwrap();
+        assert_eq!(file.format, AudioFormat::Wav);
+        assert_eq!(file.buffer.frames(), 480);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_registered_codec_takes_precedence() {
+        struct Silence;
+
+        impl Codec for Silence {
+            fn format(&self) -> AudioFormat {
+                AudioFormat::Wav
+            }
+
+            fn decode(&self, _path: &str) -> io::Result<AudioBuffer> {
+                Ok(AudioBuffer::new(vec![0.0; 10], 8000))
+            }
+        }
+
+        let path = "/tmp/vozoo_codec_test_registry.wav";
+        write_wav(path, &AudioBuffer::new(vec![0.5; 100], 48000)).unwrap();
+
+        let mut registry = CodecRegistry::new();
+        registry.register(Box::new(Silence));
+        assert_eq!(registry.open(path).unwrap().buffer.frames(), 10);
+        // Encoding still falls through to the built-in WAV codec.
+        assert!(registry.encoder(AudioFormat::Wav).is_some());
+
+        assert!(CodecRegistry::empty().open(path).is_err());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_unsupported_output_is_reported() {
+        let file = AudioFile {
+            buffer: AudioBuffer::new(vec![0.0; 10], 48000),
+            format: AudioFormat::Wav,
+        };
+        let err = file.save_as("/tmp/vozoo_codec_test.mp3", AudioFormat::Mp3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(file.save("/tmp/vozoo_codec_test.xyz").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use audiopus::coder::Encoder;
+use audiopus::{Application, Bitrate, Channels, SampleRate};
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use vozoo_core::{resample, AudioBuffer};
+
+use crate::{AudioFormat, Codec};
+
+/// Opus always runs at 48 kHz; other rates are converted first.
+const OPUS_SAMPLE_RATE: u32 = 48000;
+
+/// 20 ms packets.
+const PACKET_FRAMES: usize = 960;
+
+/// Generous for a 20 ms packet at any bitrate.
+const MAX_PACKET_BYTES: usize = 4000;
+
+const BITRATE_PER_CHANNEL: i32 = 64_000;
+
+/// Ogg logical stream serial number; files hold a single stream.
+const STREAM_SERIAL: u32 = 0x766F_7A6F;
+
+/// Ogg Opus output through libopus. Decoding isn't supported.
+pub(crate) struct OpusCodec;
+
+impl Codec for OpusCodec {
+    fn format(&self) -> AudioFormat {
+        AudioFormat::Opus
+    }
+
+    fn can_decode(&self) -> bool {
+        false
+    }
+
+    fn can_encode(&self) -> bool {
+        true
+    }
+
+    fn decode(&self, _path: &str) -> io::Result<AudioBuffer> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Reading opus files is not supported"))
+    }
+
+    fn encode(&self, path: &str, buffer: &AudioBuffer) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        encode_opus(&mut out, buffer)?;
+        out.flush()
+    }
+}
+
+/// Encode a mono or stereo buffer as an Ogg Opus stream (RFC 7845).
+///
+/// The header records the buffer's original rate so decoders can convert
+/// back, and the final granule position trims the padding of the last
+/// packet, so the decoded length matches the input.
+pub fn encode_opus<W: Write>(out: &mut W, buffer: &AudioBuffer) -> io::Result<()> {
+    let channels = match buffer.channels() {
+        1 => Channels::Mono,
+        2 => Channels::Stereo,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Opus output must be mono or stereo")),
+    };
+    let ch = buffer.channels() as usize;
+
+    let mut encoder = Encoder::new(SampleRate::Hz48000, channels, Application::Audio).map_err(opus_error)?;
+    encoder
+        .set_bitrate(Bitrate::BitsPerSecond(BITRATE_PER_CHANNEL * ch as i32))
+        .map_err(opus_error)?;
+    let pre_skip = encoder.lookahead().map_err(opus_error)? as usize;
+
+    let mut writer = PacketWriter::new(out);
+    let mut head = Vec::with_capacity(19);
+    head.extend_from_slice(b"OpusHead");
+    head.push(1);
+    head.push(ch as u8);
+    head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+    head.extend_from_slice(&buffer.sample_rate().to_le_bytes());
+    // Output gain, then channel mapping family 0 (mono/stereo).
+    head.extend_from_slice(&0i16.to_le_bytes());
+    head.push(0);
+    writer.write_packet(head.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+    let vendor = b"vozoo";
+    let mut tags = Vec::new();
+    tags.extend_from_slice(b"OpusTags");
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor);
+    tags.extend_from_slice(&0u32.to_le_bytes());
+    writer.write_packet(tags.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+    // The encoder's lookahead delays the signal by `pre_skip` frames, which
+    // the decoder drops; feed that much silence at the end to flush it.
+    let mut samples = resample(buffer, OPUS_SAMPLE_RATE).samples;
+    let frames = samples.len() / ch;
+    let end = (frames + pre_skip) as u64;
+    let packets = (frames + pre_skip).div_ceil(PACKET_FRAMES).max(1);
+    samples.resize(packets * PACKET_FRAMES * ch, 0.0);
+
+    let mut packet = vec![0u8; MAX_PACKET_BYTES];
+    for (i, chunk) in samples.chunks(PACKET_FRAMES * ch).enumerate() {
+        let len = encoder.encode_float(chunk, &mut packet).map_err(opus_error)?;
+        let last = i + 1 == packets;
+        let info = if last { PacketWriteEndInfo::EndStream } else { PacketWriteEndInfo::NormalPacket };
+        let granule = (((i + 1) * PACKET_FRAMES) as u64).min(end);
+        writer.write_packet(packet[..len].to_vec().into_boxed_slice(), STREAM_SERIAL, info, granule)?;
+    }
+    Ok(())
+}
+
+fn opus_error(err: audiopus::Error) -> io::Error {
+    io::Error::other(format!("Opus encoder error: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writes_trimmed_ogg_opus_stream() {
+        let samples: Vec<f32> = (0..44100).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+        let buffer = AudioBuffer::new(samples, 44100);
+        let mut out = Vec::new();
+        encode_opus(&mut out, &buffer).unwrap();
+
+        assert_eq!(AudioFormat::sniff(&out), Some(AudioFormat::Opus));
+        let head = out.windows(8).position(|w| w == b"OpusHead").unwrap();
+        let pre_skip = u16::from_le_bytes([out[head + 10], out[head + 11]]) as u64;
+        let input_rate = u32::from_le_bytes(out[head + 12..head + 16].try_into().unwrap());
+        assert_eq!(input_rate, 44100);
+
+        // The last page's granule position ends the stream exactly one
+        // second (at 48 kHz) after the pre-skip.
+        let last_page = out.windows(4).rposition(|w| w == b"OggS").unwrap();
+        let granule = u64::from_le_bytes(out[last_page + 6..last_page + 14].try_into().unwrap());
+        assert_eq!(granule, 48000 + pre_skip);
+        // End-of-stream flag.
+        assert_eq!(out[last_page + 5] & 0x04, 0x04);
+    }
+
+    #[test]
+    fn test_rejects_surround() {
+        let buffer = AudioBuffer::with_channels(vec![0.0; 60], 6, 48000);
+        assert!(encode_opus(&mut Vec::new(), &buffer).is_err());
+    }
+}
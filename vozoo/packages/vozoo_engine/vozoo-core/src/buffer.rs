@@ -1,22 +1,91 @@
-use std::num::NonZeroU32;
+use std::num::{NonZeroU16, NonZeroU32};
 
-/// Mono audio buffer at a fixed sample rate.
+/// Samples per frame of `max_block` that hosts reserve for a block buffer.
+///
+/// Covers stereo output of a node that produces up to 4x the frames it was
+/// given (`pitch_shift_resample` at its slowest), so a prepared block buffer
+/// never has to grow on the audio thread.
+pub const BLOCK_CAPACITY_FACTOR: usize = 8;
+
+/// Rate files and audio devices are converted to for processing. Nodes are
+/// designed for it, and those that only work at one rate are resampled
+/// around when they run elsewhere (see `AudioNode::native_sample_rate`).
+pub const PROCESSING_SAMPLE_RATE: u32 = 48000;
+
+/// Channel layout of an audio signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelLayout {
+    Mono,
+    Stereo,
+    /// Any other channel count (e.g. a 6-channel WAV).
+    Discrete(u16),
+}
+
+impl ChannelLayout {
+    pub fn from_channels(channels: u16) -> Self {
+        match channels {
+            1 => ChannelLayout::Mono,
+            2 => ChannelLayout::Stereo,
+            n => ChannelLayout::Discrete(n.max(1)),
+        }
+    }
+
+    pub fn channels(self) -> u16 {
+        match self {
+            ChannelLayout::Mono => 1,
+            ChannelLayout::Stereo => 2,
+            ChannelLayout::Discrete(n) => n,
+        }
+    }
+}
+
+/// Audio buffer at a fixed sample rate with one or more channels.
 /// All internal processing uses f32 samples normalized to [-1.0, 1.0].
+///
+/// Samples are stored interleaved (`[L0, R0, L1, R1, ...]` for stereo).
+/// A mono buffer is simply one sample per frame, so mono-only code can keep
+/// working on `samples` directly.
 #[derive(Clone)]
 pub struct AudioBuffer {
     pub samples: Vec<f32>,
+    channels: NonZeroU16,
     sample_rate: NonZeroU32,
 }
 
 impl AudioBuffer {
+    /// Create a mono buffer.
     pub fn new(samples: Vec<f32>, sample_rate: u32) -> Self {
+        Self::with_channels(samples, 1, sample_rate)
+    }
+
+    /// Create a buffer from interleaved samples.
+    /// Trailing samples that don't form a whole frame are dropped.
+    pub fn with_channels(mut samples: Vec<f32>, channels: u16, sample_rate: u32) -> Self {
+        let channels = NonZeroU16::new(channels).expect("channels must be non-zero");
+        let ch = channels.get() as usize;
+        samples.truncate(samples.len() / ch * ch);
         Self {
             samples,
+            channels,
             sample_rate: NonZeroU32::new(sample_rate)
                 .expect("sample_rate must be non-zero"),
         }
     }
 
+    /// Create a buffer from planar channel data (one Vec per channel).
+    /// All channels are truncated to the shortest one.
+    pub fn from_planar(planes: &[Vec<f32>], sample_rate: u32) -> Self {
+        let channels = planes.len().max(1);
+        let frames = planes.iter().map(|p| p.len()).min().unwrap_or(0);
+        let mut samples = Vec::with_capacity(frames * channels);
+        for i in 0..frames {
+            for plane in planes {
+                samples.push(plane[i]);
+            }
+        }
+        Self::with_channels(samples, channels as u16, sample_rate)
+    }
+
     pub fn empty(sample_rate: u32) -> Self {
         Self::new(Vec::new(), sample_rate)
     }
@@ -25,25 +94,158 @@ impl AudioBuffer {
         self.sample_rate.get()
     }
 
+    pub fn channels(&self) -> u16 {
+        self.channels.get()
+    }
+
+    /// Relabel the samples with a new rate, e.g. after a node resampled them.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = NonZeroU32::new(sample_rate).expect("sample_rate must be non-zero");
+    }
+
+    pub fn layout(&self) -> ChannelLayout {
+        ChannelLayout::from_channels(self.channels())
+    }
+
+    /// Total number of samples across all channels.
     pub fn len(&self) -> usize {
         self.samples.len()
     }
 
+    /// Number of frames (samples per channel).
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.get() as usize
+    }
+
     pub fn is_empty(&self) -> bool {
         self.samples.is_empty()
     }
 
-    /// Convert stereo interleaved samples to mono by averaging channels.
+    /// Interleaved samples of frame `index`.
+    pub fn frame(&self, index: usize) -> &[f32] {
+        let ch = self.channels.get() as usize;
+        &self.samples[index * ch..(index + 1) * ch]
+    }
+
+    /// Iterate over the samples of a single channel.
+    pub fn channel(&self, channel: u16) -> impl Iterator<Item = &f32> + '_ {
+        assert!(channel < self.channels(), "channel index out of range");
+        self.samples
+            .iter()
+            .skip(channel as usize)
+            .step_by(self.channels.get() as usize)
+    }
+
+    /// Iterate mutably over the samples of a single channel.
+    pub fn channel_mut(&mut self, channel: u16) -> impl Iterator<Item = &mut f32> + '_ {
+        assert!(channel < self.channels(), "channel index out of range");
+        let step = self.channels.get() as usize;
+        self.samples.iter_mut().skip(channel as usize).step_by(step)
+    }
+
+    /// Copy the interleaved data out as one Vec per channel.
+    pub fn to_planar(&self) -> Vec<Vec<f32>> {
+        (0..self.channels())
+            .map(|c| self.channel(c).copied().collect())
+            .collect()
+    }
+
+    /// Replace the contents with interleaved samples in a new channel layout.
+    pub fn set_interleaved(&mut self, samples: Vec<f32>, channels: u16) {
+        *self = Self::with_channels(samples, channels, self.sample_rate());
+    }
+
+    /// Convert interleaved multichannel samples to a mono buffer by averaging channels.
     pub fn from_stereo(interleaved: &[f32], channels: u16, sample_rate: u32) -> Self {
-        if channels <= 1 {
-            return Self::new(interleaved.to_vec(), sample_rate);
+        let mut buffer = Self::with_channels(interleaved.to_vec(), channels.max(1), sample_rate);
+        buffer.conform(ChannelLayout::Mono);
+        buffer
+    }
+
+    /// Convert the buffer to `layout` in place.
+    ///
+    /// Downmixing to mono averages all channels; upmixing from mono copies
+    /// the signal to every channel. Other conversions map output channel `n`
+    /// to input channel `n % input_channels`.
+    ///
+    /// Reuses the existing allocation, so this is safe on the audio thread as
+    /// long as the capacity covers the wider layout.
+    pub fn conform(&mut self, layout: ChannelLayout) {
+        let from = self.channels() as usize;
+        let to = layout.channels() as usize;
+        if from == to {
+            return;
+        }
+
+        let frames = self.frames();
+        let samples = &mut self.samples;
+        if to < from {
+            // Shrinking: each output frame lands at or before its input frame,
+            // so walking forward never overwrites samples still to be read.
+            for i in 0..frames {
+                if to == 1 {
+                    samples[i] = samples[i * from..(i + 1) * from].iter().sum::<f32>() / from as f32;
+                } else {
+                    for c in 0..to {
+                        samples[i * to + c] = samples[i * from + c];
+                    }
+                }
+            }
+            samples.truncate(frames * to);
+        } else {
+            // Growing: walk backward for the same reason.
+            samples.resize(frames * to, 0.0);
+            for i in (0..frames).rev() {
+                for c in (0..to).rev() {
+                    samples[i * to + c] = samples[i * from + c % from];
+                }
+            }
+        }
+        self.channels = NonZeroU16::new(to as u16).expect("layout has at least one channel");
+    }
+
+    /// Resize to `frames` frames of `channels` channels, reusing the
+    /// allocation. Existing samples are kept as raw interleaved data and new
+    /// samples are zero.
+    pub fn reshape(&mut self, frames: usize, channels: u16) {
+        self.channels = NonZeroU16::new(channels).expect("channels must be non-zero");
+        self.samples.resize(frames * channels as usize, 0.0);
+    }
+
+    /// Copy samples, channel count and sample rate from `other`, reusing
+    /// this buffer's allocation.
+    pub fn copy_from(&mut self, other: &AudioBuffer) {
+        self.samples.clear();
+        self.samples.extend_from_slice(&other.samples);
+        self.channels = other.channels;
+        self.sample_rate = other.sample_rate;
+    }
+
+    /// Run `f` over consecutive blocks of at most `max_block` frames and
+    /// replace the buffer with the concatenated results.
+    ///
+    /// Used to drive streaming nodes from a whole file. `f` may change the
+    /// channel count or the number of frames of each block.
+    pub fn process_blocks(&mut self, max_block: usize, mut f: impl FnMut(&mut AudioBuffer)) {
+        let ch = self.channels() as usize;
+        let block_len = max_block.max(1) * ch;
+        let mut out: Option<AudioBuffer> = None;
+
+        for chunk in self.samples.chunks(block_len) {
+            let mut block = AudioBuffer::with_channels(chunk.to_vec(), ch as u16, self.sample_rate());
+            f(&mut block);
+            match out.as_mut() {
+                Some(o) => {
+                    block.conform(o.layout());
+                    o.samples.extend_from_slice(&block.samples);
+                }
+                None => out = Some(block),
+            }
+        }
+
+        if let Some(out) = out {
+            *self = out;
         }
-        let ch = channels as usize;
-        let mono: Vec<f32> = interleaved
-            .chunks_exact(ch)
-            .map(|frame| frame.iter().sum::<f32>() / ch as f32)
-            .collect();
-        Self::new(mono, sample_rate)
     }
 
     /// Hard-limit all samples to [-1.0, 1.0].
@@ -53,3 +255,65 @@ impl AudioBuffer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_planar_interleaved_roundtrip() {
+        let left = vec![0.1, 0.2, 0.3];
+        let right = vec![-0.1, -0.2, -0.3];
+        let buffer = AudioBuffer::from_planar(&[left.clone(), right.clone()], 48000);
+
+        assert_eq!(buffer.channels(), 2);
+        assert_eq!(buffer.frames(), 3);
+        assert_eq!(buffer.samples, vec![0.1, -0.1, 0.2, -0.2, 0.3, -0.3]);
+        assert_eq!(buffer.frame(1), &[0.2, -0.2]);
+        assert_eq!(buffer.to_planar(), vec![left, right]);
+    }
+
+    #[test]
+    fn test_conform_downmix_and_upmix() {
+        let mut buffer = AudioBuffer::with_channels(vec![1.0, 0.0, 0.5, 0.5], 2, 48000);
+        buffer.conform(ChannelLayout::Mono);
+        assert_eq!(buffer.channels(), 1);
+        assert_eq!(buffer.samples, vec![0.5, 0.5]);
+
+        buffer.conform(ChannelLayout::Stereo);
+        assert_eq!(buffer.layout(), ChannelLayout::Stereo);
+        assert_eq!(buffer.samples, vec![0.5, 0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_conform_between_discrete_layouts() {
+        // 3 channels -> stereo keeps the first two, stereo -> 3 wraps around.
+        let mut buffer = AudioBuffer::with_channels(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 3, 48000);
+        buffer.conform(ChannelLayout::Stereo);
+        assert_eq!(buffer.samples, vec![1.0, 2.0, 4.0, 5.0]);
+
+        buffer.conform(ChannelLayout::Discrete(3));
+        assert_eq!(buffer.samples, vec![1.0, 2.0, 1.0, 4.0, 5.0, 4.0]);
+    }
+
+    #[test]
+    fn test_process_blocks_concatenates() {
+        let mut buffer = AudioBuffer::new((0..10).map(|i| i as f32).collect(), 48000);
+        let mut sizes = Vec::new();
+        buffer.process_blocks(4, |block| {
+            sizes.push(block.frames());
+            for s in &mut block.samples {
+                *s *= 2.0;
+            }
+        });
+        assert_eq!(sizes, vec![4, 4, 2]);
+        assert_eq!(buffer.samples, (0..10).map(|i| i as f32 * 2.0).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_partial_frame_is_dropped() {
+        let buffer = AudioBuffer::with_channels(vec![0.0; 5], 2, 48000);
+        assert_eq!(buffer.frames(), 2);
+        assert_eq!(buffer.len(), 4);
+    }
+}
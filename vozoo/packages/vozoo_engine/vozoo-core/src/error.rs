@@ -0,0 +1,89 @@
+use std::fmt;
+use std::io;
+
+/// Why building, running or saving a pipeline failed.
+#[derive(Debug)]
+pub enum VozooError {
+    /// An I/O failure not tied to a particular file.
+    Io(io::Error),
+    /// Reading an input file failed.
+    Read { path: String, source: io::Error },
+    /// Writing an output file failed.
+    Write { path: String, source: io::Error },
+    /// The requested file format can't be written (or isn't known at all).
+    UnsupportedFormat(String),
+    /// A chain or graph definition isn't valid JSON or doesn't match the
+    /// schema. `line` and `column` are 1-based; 0 when unknown.
+    InvalidJson { line: usize, column: usize, message: String },
+    /// The node at `index` in a chain or graph definition has a type no
+    /// factory knows.
+    UnknownNode { index: usize, node_type: String },
+    /// A node has no parameter `key` that can be set this way.
+    InvalidParam { node: u32, key: String },
+    /// A graph's edges form a cycle with no delayed edge to break it.
+    GraphCycle,
+    /// A graph is structurally unusable, e.g. it has no input node.
+    InvalidGraph(String),
+    /// The audio device refused to open or start.
+    Device(String),
+    /// The engine is in the wrong state for the request, e.g. recording
+    /// before it was started.
+    Engine(String),
+}
+
+impl fmt::Display for VozooError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Read { path, source } => write!(f, "Failed to read '{path}': {source}"),
+            Self::Write { path, source } => write!(f, "Failed to write '{path}': {source}"),
+            Self::UnsupportedFormat(msg) => write!(f, "Unsupported format: {msg}"),
+            Self::InvalidJson { line, column, message } if *line > 0 => {
+                write!(f, "Invalid JSON at line {line}, column {column}: {message}")
+            }
+            Self::InvalidJson { message, .. } => write!(f, "Invalid JSON: {message}"),
+            Self::UnknownNode { index, node_type } => write!(f, "Unknown node type '{node_type}' (node {index})"),
+            Self::InvalidParam { node, key } => write!(f, "Node {node} has no live parameter '{key}'"),
+            Self::GraphCycle => write!(f, "Graph contains a cycle without a delayed edge"),
+            Self::InvalidGraph(msg) => write!(f, "Invalid graph: {msg}"),
+            Self::Device(msg) => write!(f, "Audio device error: {msg}"),
+            Self::Engine(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for VozooError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) | Self::Read { source: e, .. } | Self::Write { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for VozooError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_carry_the_details() {
+        let err = VozooError::InvalidJson { line: 3, column: 7, message: "expected `,`".into() };
+        assert_eq!(err.to_string(), "Invalid JSON at line 3, column 7: expected `,`");
+
+        let err = VozooError::UnknownNode { index: 2, node_type: "wobble".into() };
+        assert_eq!(err.to_string(), "Unknown node type 'wobble' (node 2)");
+
+        let err = VozooError::Read {
+            path: "in.wav".into(),
+            source: io::Error::new(io::ErrorKind::NotFound, "No such file"),
+        };
+        assert_eq!(err.to_string(), "Failed to read 'in.wav': No such file");
+        assert!(std::error::Error::source(&err).is_some());
+    }
+}
@@ -1,11 +1,23 @@
 mod buffer;
+mod error;
+mod meter;
 mod node;
 mod param;
+mod resampler;
 mod ring_buffer;
 mod wav;
 
-pub use buffer::AudioBuffer;
+/// Signals for unit tests, here and (with `test-util`) in dependent crates.
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_signals;
+
+pub use buffer::{AudioBuffer, ChannelLayout, BLOCK_CAPACITY_FACTOR, PROCESSING_SAMPLE_RATE};
+pub use error::VozooError;
+pub use meter::{MeterSnapshot, MeterTap, METER_FLOOR_DB, SPECTRUM_BANDS, SPECTRUM_MAX_HZ, SPECTRUM_MIN_HZ};
 pub use node::AudioNode;
-pub use param::{AtomicF32, ParamDescriptor};
-pub use ring_buffer::SpscRingBuffer;
-pub use wav::{read_wav, write_wav};
+pub use param::{smoothing_coeff, AtomicF32, ParamDescriptor, ParamHandle, SmoothedParam, PARAM_SMOOTHING_MS};
+pub use resampler::{resample, Resampler};
+pub use ring_buffer::{ReadSlices, SpscRingBuffer, WriteSlices};
+pub use wav::{
+    read_wav, repair_wav, write_wav, write_wav_with, Quantizer, WavReader, WavSampleFormat, WavWriteOptions, WavWriter,
+};
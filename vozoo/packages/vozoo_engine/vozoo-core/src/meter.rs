@@ -0,0 +1,151 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::param::AtomicF32;
+
+/// Bands in a meter's spectrum, log-spaced from `SPECTRUM_MIN_HZ` to
+/// `SPECTRUM_MAX_HZ`.
+pub const SPECTRUM_BANDS: usize = 32;
+pub const SPECTRUM_MIN_HZ: f32 = 20.0;
+pub const SPECTRUM_MAX_HZ: f32 = 20000.0;
+
+/// Level reported for silence, so snapshots stay finite (and JSON-friendly).
+pub const METER_FLOOR_DB: f32 = -120.0;
+
+/// One reading of a meter tap. Levels are in dBFS, loudness in LUFS, both
+/// clamped to `METER_FLOOR_DB`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeterSnapshot {
+    /// Highest absolute sample since the previous snapshot.
+    pub peak_db: f32,
+    /// RMS over the same span, averaged across channels.
+    pub rms_db: f32,
+    /// K-weighted loudness over the last 400 ms (ITU-R BS.1770).
+    pub momentary_lufs: f32,
+    /// K-weighted loudness over the last 3 s.
+    pub short_term_lufs: f32,
+    /// Band levels in dBFS; a full-scale sine reads 0 dB in its band.
+    pub spectrum_db: [f32; SPECTRUM_BANDS],
+}
+
+impl MeterSnapshot {
+    /// Reading of a tap that has seen only silence.
+    pub const SILENT: Self = Self {
+        peak_db: METER_FLOOR_DB,
+        rms_db: METER_FLOOR_DB,
+        momentary_lufs: METER_FLOOR_DB,
+        short_term_lufs: METER_FLOOR_DB,
+        spectrum_db: [METER_FLOOR_DB; SPECTRUM_BANDS],
+    };
+
+    /// Centre frequency of spectrum band `band`, in Hz.
+    pub fn band_center_hz(band: usize) -> f32 {
+        Self::band_edge_hz(band as f32 + 0.5)
+    }
+
+    /// Lower edge of band `edge` (fractional positions interpolate on the
+    /// log scale); edge `SPECTRUM_BANDS` is the top of the last band.
+    pub fn band_edge_hz(edge: f32) -> f32 {
+        SPECTRUM_MIN_HZ * (SPECTRUM_MAX_HZ / SPECTRUM_MIN_HZ).powf(edge / SPECTRUM_BANDS as f32)
+    }
+}
+
+impl Default for MeterSnapshot {
+    fn default() -> Self {
+        Self::SILENT
+    }
+}
+
+const LEVELS: usize = 4;
+
+/// Lock-free mailbox between a meter on the audio thread and readers on
+/// control threads.
+///
+/// The audio thread publishes whole snapshots; readers always see a
+/// consistent one (a sequence counter makes them retry if they raced a
+/// write), and the writer never waits.
+pub struct MeterTap {
+    seq: AtomicU32,
+    values: [AtomicF32; LEVELS + SPECTRUM_BANDS],
+}
+
+impl MeterTap {
+    pub fn new() -> Arc<Self> {
+        let tap = Self {
+            seq: AtomicU32::new(0),
+            values: std::array::from_fn(|_| AtomicF32::new(METER_FLOOR_DB)),
+        };
+        Arc::new(tap)
+    }
+
+    /// Replace the current snapshot. Single writer only.
+    pub fn publish(&self, snapshot: &MeterSnapshot) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        std::sync::atomic::fence(Ordering::Release);
+        let levels = [
+            snapshot.peak_db,
+            snapshot.rms_db,
+            snapshot.momentary_lufs,
+            snapshot.short_term_lufs,
+        ];
+        for (slot, value) in self.values.iter().zip(levels.iter().chain(snapshot.spectrum_db.iter())) {
+            slot.store(value.max(METER_FLOOR_DB));
+        }
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    /// The most recently published snapshot.
+    pub fn snapshot(&self) -> MeterSnapshot {
+        loop {
+            let before = self.seq.load(Ordering::Acquire);
+            if before & 1 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let mut values = [0.0; LEVELS + SPECTRUM_BANDS];
+            for (v, slot) in values.iter_mut().zip(self.values.iter()) {
+                *v = slot.load();
+            }
+            std::sync::atomic::fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == before {
+                let mut spectrum_db = [0.0; SPECTRUM_BANDS];
+                spectrum_db.copy_from_slice(&values[LEVELS..]);
+                return MeterSnapshot {
+                    peak_db: values[0],
+                    rms_db: values[1],
+                    momentary_lufs: values[2],
+                    short_term_lufs: values[3],
+                    spectrum_db,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tap_round_trips_and_clamps_to_floor() {
+        let tap = MeterTap::new();
+        assert_eq!(tap.snapshot(), MeterSnapshot::SILENT);
+
+        let mut snapshot = MeterSnapshot { peak_db: -3.0, rms_db: f32::NEG_INFINITY, ..MeterSnapshot::SILENT };
+        snapshot.spectrum_db[5] = -12.0;
+        tap.publish(&snapshot);
+        let read = tap.snapshot();
+        assert_eq!(read.peak_db, -3.0);
+        assert_eq!(read.rms_db, METER_FLOOR_DB);
+        assert_eq!(read.spectrum_db[5], -12.0);
+    }
+
+    #[test]
+    fn test_band_edges_span_the_audible_range() {
+        assert!((MeterSnapshot::band_edge_hz(0.0) - SPECTRUM_MIN_HZ).abs() < 1e-3);
+        assert!((MeterSnapshot::band_edge_hz(SPECTRUM_BANDS as f32) - SPECTRUM_MAX_HZ).abs() < 0.5);
+        let centre = MeterSnapshot::band_center_hz(SPECTRUM_BANDS / 2);
+        assert!((centre - 704.6).abs() < 1.0, "{centre}");
+    }
+}
@@ -1,14 +1,135 @@
-use crate::buffer::AudioBuffer;
+use std::sync::Arc;
+
+use crate::buffer::{AudioBuffer, ChannelLayout};
+use crate::meter::MeterTap;
+use crate::param::ParamHandle;
 
 /// Trait for all audio processing nodes in the effect chain.
 /// Each node processes an AudioBuffer in-place (or replaces its samples).
+///
+/// Lifecycle: the host calls `prepare` once before streaming, then `process`
+/// once per block. Nodes keep all state across blocks, so feeding a signal
+/// in one call or in many small blocks produces the same output.
 pub trait AudioNode: Send {
-    /// Process audio buffer in-place.
+    /// Prepare for streaming at `sample_rate` with at most `max_block` frames
+    /// per `process` call. Allocate delay lines and scratch buffers here.
+    ///
+    /// Nodes must still work if `process` is called without `prepare` (they
+    /// prepare lazily on the first block) or with a larger block.
+    fn prepare(&mut self, _sample_rate: u32, _max_block: usize) {}
+
+    /// Upper bound on the frames produced from a block of `input_frames`.
+    /// Hosts use it to prepare downstream nodes of length-changing effects.
+    fn max_output_frames(&self, input_frames: usize) -> usize {
+        input_frames
+    }
+
+    /// Sample rate of the buffers this node produces when fed `input_rate`.
+    /// Only sample rate converters change it; hosts prepare downstream
+    /// nodes at the new rate.
+    fn output_sample_rate(&self, input_rate: u32) -> u32 {
+        input_rate
+    }
+
+    /// The only rate this node works at, if it has one (e.g. RNNoise runs
+    /// at 48 kHz). Chains and graphs resample around such nodes when they
+    /// stream at another rate.
+    fn native_sample_rate(&self) -> Option<u32> {
+        None
+    }
+
+    /// Frames the output lags the input by, at the rate of the buffers the
+    /// node produces. Graphs delay shorter parallel paths by the difference
+    /// so they line up where they mix, and hosts add it up to report their
+    /// latency.
+    ///
+    /// Valid after `prepare`. A node whose delay can change while streaming
+    /// (e.g. a pitch shifter that only switches on once moved) reports the
+    /// new value from the next block on.
+    fn latency_samples(&self) -> usize {
+        0
+    }
+
+    /// The most `latency_samples` can become while streaming, e.g. once a
+    /// pitch shifter built at 0 semitones is moved. Graphs reserve the
+    /// delays aligning their paths for it in `prepare`, so a latency change
+    /// doesn't allocate. Valid after `prepare`.
+    fn max_latency_samples(&self) -> usize {
+        self.latency_samples()
+    }
+
+    /// Output frames produced per input frame, e.g. 2.0 for a node that
+    /// plays audio at half speed. Hosts that pull output at a fixed pace
+    /// feed such nodes proportionally less input.
+    fn time_scale(&self) -> f64 {
+        1.0
+    }
+
+    /// Whether `time_scale` can be anything but 1.0, now or once a live
+    /// parameter moves. Graphs keep every block's length, so they reject
+    /// such nodes.
+    fn changes_length(&self) -> bool {
+        self.time_scale() != 1.0
+    }
+
+    /// Process one block of audio in-place.
     fn process(&mut self, buffer: &mut AudioBuffer);
 
+    /// Names of the node's inputs. The first is the main input, carried by
+    /// the buffer `process` works on; graphs feed the others (e.g. a
+    /// compressor's "sidechain") to `process_ports`.
+    fn input_ports(&self) -> &[&'static str] {
+        &["in"]
+    }
+
+    /// Names of the node's outputs. The first is the buffer `process` leaves
+    /// behind; `process_ports` fills the others.
+    fn output_ports(&self) -> &[&'static str] {
+        &["out"]
+    }
+
+    /// Process one block along with the extra ports.
+    ///
+    /// `inputs[i]` carries input port `i + 1`, at the block's frame count,
+    /// or is empty if nothing is connected to it. `outputs[i]` is output
+    /// port `i + 1`, to be filled with the block's frame count; its storage
+    /// is reused between calls. Nodes with only the main ports keep the
+    /// default, which runs `process`.
+    fn process_ports(&mut self, buffer: &mut AudioBuffer, _inputs: &[AudioBuffer], _outputs: &mut [AudioBuffer]) {
+        self.process(buffer);
+    }
+
     /// Reset internal state (e.g., filter memory, delay lines).
     fn reset(&mut self);
 
     /// Human-readable name for this node.
     fn name(&self) -> &str;
+
+    /// Handles to the parameters that can change while the node streams.
+    ///
+    /// Called on a control thread, typically right after the node is built.
+    /// Parameters missing here (e.g. ones that shape an impulse response)
+    /// only take effect by rebuilding the node.
+    fn params(&self) -> Vec<ParamHandle> {
+        Vec::new()
+    }
+
+    /// Meter taps this node publishes to, for hosts to read on a control
+    /// thread. Only analysis nodes have any.
+    fn meters(&self) -> Vec<Arc<MeterTap>> {
+        Vec::new()
+    }
+
+    /// Channel layout this node expects at its input.
+    ///
+    /// Hosts conform the buffer to this layout before calling `process`.
+    /// `None` means the node handles any channel count as-is (e.g. gain).
+    fn input_layout(&self) -> Option<ChannelLayout> {
+        Some(ChannelLayout::Mono)
+    }
+
+    /// Channel layout this node produces for a given input layout.
+    fn output_layout(&self, input: ChannelLayout) -> ChannelLayout {
+        input
+    }
 }
@@ -1,4 +1,8 @@
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Time constant of the one-pole glide `SmoothedParam` applies to changes.
+pub const PARAM_SMOOTHING_MS: f32 = 20.0;
 
 /// Lock-free f32 parameter for real-time audio thread access.
 /// UI thread writes, audio thread reads — no mutex needed.
@@ -23,10 +27,194 @@ impl AtomicF32 {
 }
 
 /// Descriptor for a node parameter (used for UI binding).
+///
+/// `key` matches the parameter's key in chain JSON; `id` is its position
+/// among the node's parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ParamDescriptor {
     pub id: u32,
+    pub key: &'static str,
     pub name: &'static str,
     pub min: f32,
     pub max: f32,
     pub default: f32,
 }
+
+impl ParamDescriptor {
+    pub fn clamp(&self, value: f32) -> f32 {
+        value.clamp(self.min, self.max)
+    }
+}
+
+/// Control-thread handle to a parameter of a running node.
+///
+/// Setting a value only stores the new target; the node glides to it on the
+/// audio thread.
+#[derive(Clone)]
+pub struct ParamHandle {
+    pub descriptor: ParamDescriptor,
+    target: Arc<AtomicF32>,
+}
+
+impl ParamHandle {
+    /// Set the target value, clamped to the descriptor's range.
+    pub fn set(&self, value: f32) {
+        self.target.store(self.descriptor.clamp(value));
+    }
+
+    pub fn get(&self) -> f32 {
+        self.target.load()
+    }
+}
+
+/// Audio-thread side of a live parameter.
+///
+/// Follows its target with a one-pole glide of `PARAM_SMOOTHING_MS` so
+/// moving a slider doesn't produce zipper noise. A settled parameter holds
+/// its target exactly, so constant settings process identically to a
+/// plain field.
+pub struct SmoothedParam {
+    descriptor: ParamDescriptor,
+    target: Arc<AtomicF32>,
+    current: f32,
+    coeff: f32,
+}
+
+impl SmoothedParam {
+    /// Create a parameter starting at `value`. The value is not clamped, so
+    /// constructors keep accepting whatever they did before; only changes
+    /// through a `ParamHandle` are limited to the descriptor's range.
+    pub fn new(descriptor: ParamDescriptor, value: f32) -> Self {
+        Self {
+            descriptor,
+            target: Arc::new(AtomicF32::new(value)),
+            current: value,
+            coeff: param_coeff(48000),
+        }
+    }
+
+    pub fn descriptor(&self) -> &ParamDescriptor {
+        &self.descriptor
+    }
+
+    pub fn handle(&self) -> ParamHandle {
+        ParamHandle {
+            descriptor: self.descriptor,
+            target: Arc::clone(&self.target),
+        }
+    }
+
+    /// Set the glide time for `sample_rate`. Called from the node's `prepare`.
+    pub fn prepare(&mut self, sample_rate: u32) {
+        self.coeff = param_coeff(sample_rate);
+    }
+
+    /// The value the parameter is gliding towards.
+    pub fn target(&self) -> f32 {
+        self.target.load()
+    }
+
+    /// The current (smoothed) value.
+    pub fn value(&self) -> f32 {
+        self.current
+    }
+
+    /// Whether the value still differs from its target.
+    pub fn is_smoothing(&self) -> bool {
+        self.current != self.target.load()
+    }
+
+    /// Advance by one sample and return the new value.
+    pub fn tick(&mut self) -> f32 {
+        self.advance(1)
+    }
+
+    /// Advance by `frames` samples at once, for parameters that are only
+    /// applied once per block or frame.
+    pub fn advance(&mut self, frames: usize) -> f32 {
+        let target = self.target.load();
+        if self.current != target {
+            let decay = (1.0 - self.coeff).powi(frames.min(i32::MAX as usize) as i32);
+            self.current = target + (self.current - target) * decay;
+            // Snap once the remaining step is inaudible so the value settles.
+            let range = (self.descriptor.max - self.descriptor.min).abs().max(1e-6);
+            if (self.current - target).abs() <= range * 1e-5 {
+                self.current = target;
+            }
+        }
+        self.current
+    }
+
+    /// Jump straight to the target (used on `reset`).
+    pub fn snap(&mut self) {
+        self.current = self.target.load();
+    }
+}
+
+/// One-pole smoothing coefficient for a time constant of `tau` seconds:
+/// the share of the remaining distance covered each sample.
+pub fn smoothing_coeff(tau: f32, sample_rate: f32) -> f32 {
+    1.0 - (-1.0 / (tau * sample_rate)).exp()
+}
+
+fn param_coeff(sample_rate: u32) -> f32 {
+    smoothing_coeff(PARAM_SMOOTHING_MS / 1000.0, sample_rate.max(1) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESC: ParamDescriptor = ParamDescriptor {
+        id: 0,
+        key: "gain",
+        name: "Gain",
+        min: 0.0,
+        max: 4.0,
+        default: 1.0,
+    };
+
+    #[test]
+    fn test_handle_clamps_to_range() {
+        let param = SmoothedParam::new(DESC, 1.0);
+        let handle = param.handle();
+        handle.set(10.0);
+        assert_eq!(param.target(), 4.0);
+        handle.set(-1.0);
+        assert_eq!(handle.get(), 0.0);
+    }
+
+    #[test]
+    fn test_smoothing_glides_and_settles() {
+        let mut param = SmoothedParam::new(DESC, 0.0);
+        param.prepare(48000);
+        param.handle().set(1.0);
+
+        // No single step is anywhere near the full jump.
+        let mut prev = param.value();
+        for _ in 0..48 {
+            let v = param.tick();
+            assert!(v > prev && v - prev < 0.05, "step {prev} -> {v}");
+            prev = v;
+        }
+
+        // Settled exactly on the target well within a second.
+        for _ in 0..48000 {
+            param.tick();
+        }
+        assert!(!param.is_smoothing());
+        assert_eq!(param.value(), 1.0);
+    }
+
+    #[test]
+    fn test_advance_matches_per_sample_steps() {
+        let mut a = SmoothedParam::new(DESC, 0.0);
+        let mut b = SmoothedParam::new(DESC, 0.0);
+        a.handle().set(2.0);
+        b.handle().set(2.0);
+        for _ in 0..100 {
+            a.tick();
+        }
+        assert!((a.value() - b.advance(100)).abs() < 1e-4);
+    }
+}
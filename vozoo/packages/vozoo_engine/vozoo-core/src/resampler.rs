@@ -0,0 +1,364 @@
+use crate::buffer::AudioBuffer;
+
+/// Zero crossings of the sinc kernel on each side when not decimating.
+/// Decimation widens the kernel by the rate ratio.
+const ZERO_CROSSINGS: usize = 16;
+
+/// Most filter phases stored. Ratios needing more (e.g. 44100 → 44101)
+/// interpolate between neighbouring phases.
+const MAX_PHASES: usize = 512;
+
+/// Passband edge as a fraction of the lower Nyquist frequency.
+const ROLLOFF: f64 = 0.94;
+
+/// Kaiser window shape; ~90 dB stopband attenuation.
+const KAISER_BETA: f64 = 8.6;
+
+/// Band-limited sample rate converter for interleaved audio.
+///
+/// Rates are reduced to a ratio `up / down` and every output sample is a
+/// dot product of the input with one phase of a Kaiser-windowed sinc
+/// lowpass, cut off below the lower of the two Nyquist frequencies.
+///
+/// Streams block by block: output sample `t` needs the input up to `t`
+/// plus `delay_frames()` frames of lookahead, so that much input is held
+/// back between calls. Feeding a signal in one call or many produces the
+/// same samples. After `prepare`, `process` doesn't allocate as long as
+/// blocks stay within the prepared size.
+pub struct Resampler {
+    from_rate: u32,
+    to_rate: u32,
+    channels: usize,
+    up: usize,
+    down: usize,
+    /// Kernel half-width in input frames; each output reads `2 * half` frames.
+    half: usize,
+    phases: usize,
+    /// `phases + 1` rows of `2 * half` coefficients, the last row being
+    /// phase 0 shifted by one frame (for interpolation).
+    table: Vec<f32>,
+    /// Interpolated coefficients for positions between stored phases.
+    coeffs: Vec<f32>,
+    /// Interleaved input not yet fully consumed.
+    history: Vec<f32>,
+    /// Frame in `history` at or before the next output position.
+    pos: usize,
+    /// Offset of the next output position past `pos`, in units of `1 / up`.
+    frac: usize,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32, channels: u16) -> Self {
+        let (from_rate, to_rate) = (from_rate.max(1), to_rate.max(1));
+        let g = gcd(from_rate, to_rate);
+        let (up, down) = ((to_rate / g) as usize, (from_rate / g) as usize);
+
+        let ratio = (up as f64 / down as f64).min(1.0);
+        let half = (ZERO_CROSSINGS as f64 / ratio).ceil() as usize;
+        let phases = up.min(MAX_PHASES);
+        let table = if up == down {
+            Vec::new()
+        } else {
+            kernel_table(half, phases, 0.5 * ratio * ROLLOFF)
+        };
+
+        let mut resampler = Self {
+            from_rate,
+            to_rate,
+            channels: channels.max(1) as usize,
+            up,
+            down,
+            half,
+            phases,
+            table,
+            coeffs: vec![0.0; 2 * half],
+            history: Vec::new(),
+            pos: 0,
+            frac: 0,
+        };
+        resampler.reset();
+        resampler
+    }
+
+    pub fn from_rate(&self) -> u32 {
+        self.from_rate
+    }
+
+    pub fn to_rate(&self) -> u32 {
+        self.to_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    /// Whether the rates match and samples are passed through untouched.
+    pub fn is_passthrough(&self) -> bool {
+        self.up == self.down
+    }
+
+    /// Input frames the output lags behind while streaming.
+    pub fn delay_frames(&self) -> usize {
+        if self.is_passthrough() { 0 } else { self.half }
+    }
+
+    /// Reserve room for blocks of up to `max_block` input frames.
+    pub fn prepare(&mut self, max_block: usize) {
+        let capacity = (max_block + 2 * self.half) * self.channels;
+        self.history.reserve(capacity.saturating_sub(self.history.len()));
+    }
+
+    /// Switch to a different channel count, dropping any buffered input.
+    pub fn set_channels(&mut self, channels: u16) {
+        let channels = channels.max(1) as usize;
+        if channels != self.channels {
+            self.channels = channels;
+            self.reset();
+        }
+    }
+
+    /// Upper bound on the frames one `process` call produces from
+    /// `input_frames` frames.
+    pub fn max_output_frames(&self, input_frames: usize) -> usize {
+        (input_frames * self.up).div_ceil(self.down) + 1
+    }
+
+    /// Input frames needed to produce about `output_frames` frames.
+    pub fn input_frames_for(&self, output_frames: usize) -> usize {
+        (output_frames * self.down).div_ceil(self.up)
+    }
+
+    /// Resample interleaved `input`, appending whatever output it completes
+    /// to `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        if self.is_passthrough() {
+            output.extend_from_slice(input);
+            return;
+        }
+
+        let ch = self.channels;
+        let taps = 2 * self.half;
+        self.history.extend_from_slice(&input[..input.len() / ch * ch]);
+        let frames = self.history.len() / ch;
+
+        while self.pos + self.half < frames {
+            let num = self.frac * self.phases;
+            let (phase, rem) = (num / self.up, num % self.up);
+            let row = &self.table[phase * taps..(phase + 1) * taps];
+            let coeffs: &[f32] = if rem == 0 {
+                row
+            } else {
+                let next = &self.table[(phase + 1) * taps..(phase + 2) * taps];
+                let t = rem as f32 / self.up as f32;
+                for ((c, &a), &b) in self.coeffs.iter_mut().zip(row).zip(next) {
+                    *c = a + (b - a) * t;
+                }
+                &self.coeffs
+            };
+
+            let window = &self.history[(self.pos + 1 - self.half) * ch..][..taps * ch];
+            for c in 0..ch {
+                let acc: f32 = window
+                    .iter()
+                    .skip(c)
+                    .step_by(ch)
+                    .zip(coeffs)
+                    .map(|(&x, &k)| x * k)
+                    .sum();
+                output.push(acc);
+            }
+
+            self.frac += self.down;
+            self.pos += self.frac / self.up;
+            self.frac %= self.up;
+        }
+
+        // Drop the frames no future output reads.
+        let consumed = (self.pos + 1 - self.half).min(frames);
+        self.history.copy_within(consumed * ch.., 0);
+        self.history.truncate((frames - consumed) * ch);
+        self.pos -= consumed;
+    }
+
+    /// Clear buffered input; the next sample is treated as the start of a
+    /// new signal.
+    pub fn reset(&mut self) {
+        // Silence before the signal, so the first output is centred on
+        // input frame 0.
+        self.history.clear();
+        self.history.resize((self.half - 1) * self.channels, 0.0);
+        self.pos = self.half - 1;
+        self.frac = 0;
+    }
+}
+
+/// Convert a whole buffer to `to_rate`.
+///
+/// The result is time-aligned with the input (no added delay) and has
+/// `frames * to_rate / from_rate` frames, rounded.
+pub fn resample(buffer: &AudioBuffer, to_rate: u32) -> AudioBuffer {
+    let from_rate = buffer.sample_rate();
+    if from_rate == to_rate {
+        return buffer.clone();
+    }
+
+    let ch = buffer.channels();
+    let frames = buffer.frames() as u64;
+    let expected = ((frames * to_rate as u64 + from_rate as u64 / 2) / from_rate as u64) as usize;
+
+    let mut resampler = Resampler::new(from_rate, to_rate, ch);
+    let mut out = Vec::with_capacity(resampler.max_output_frames(buffer.frames() + resampler.half) * ch as usize);
+    resampler.process(&buffer.samples, &mut out);
+    // Feed the lookahead past the end so the tail comes out too.
+    let tail = vec![0.0; resampler.half * ch as usize];
+    resampler.process(&tail, &mut out);
+
+    out.resize(expected * ch as usize, 0.0);
+    AudioBuffer::with_channels(out, ch, to_rate)
+}
+
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Windowed-sinc lowpass with cutoff `fc` (cycles per input sample),
+/// sampled at `phases + 1` fractional offsets. Each row is normalized to
+/// unity DC gain.
+fn kernel_table(half: usize, phases: usize, fc: f64) -> Vec<f32> {
+    let taps = 2 * half;
+    let norm = bessel_i0(KAISER_BETA);
+    let mut table = Vec::with_capacity((phases + 1) * taps);
+
+    for p in 0..=phases {
+        let frac = p as f64 / phases as f64;
+        let row: Vec<f64> = (0..taps)
+            .map(|j| {
+                // Distance from the output position to input tap j.
+                let x = frac + half as f64 - 1.0 - j as f64;
+                let r = (x / half as f64).clamp(-1.0, 1.0);
+                let window = bessel_i0(KAISER_BETA * (1.0 - r * r).sqrt()) / norm;
+                2.0 * fc * sinc(2.0 * fc * x) * window
+            })
+            .collect();
+        let sum: f64 = row.iter().sum();
+        table.extend(row.iter().map(|&c| (c / sum) as f32));
+    }
+    table
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-12 {
+        1.0
+    } else {
+        let px = std::f64::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+/// Zeroth-order modified Bessel function of the first kind.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let y = x * x / 4.0;
+    for k in 1..50 {
+        term *= y / (k * k) as f64;
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signals::sine;
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_output_length_matches_ratio() {
+        let buffer = AudioBuffer::new(vec![0.0; 44100], 44100);
+        let out = resample(&buffer, 48000);
+        assert_eq!(out.sample_rate(), 48000);
+        assert_eq!(out.frames(), 48000);
+
+        let buffer = AudioBuffer::with_channels(vec![0.0; 2000], 2, 48000);
+        assert_eq!(resample(&buffer, 16000).frames(), 333);
+    }
+
+    #[test]
+    fn test_converts_sine_accurately() {
+        for (from, to) in [(44100, 48000), (48000, 44100), (16000, 48000), (48000, 47999)] {
+            let buffer = AudioBuffer::new(sine(1000.0, 0.5, from, from as usize), from);
+            let out = resample(&buffer, to);
+            let expected = sine(1000.0, 0.5, to, out.frames());
+
+            // Skip the edges, where the input starts and stops abruptly.
+            let edge = to as usize / 50;
+            let err = out.samples[edge..out.frames() - edge]
+                .iter()
+                .zip(&expected[edge..])
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0f32, f32::max);
+            assert!(err < 1e-3, "{from} -> {to}: max error {err}");
+        }
+    }
+
+    #[test]
+    fn test_decimation_removes_aliases() {
+        // 10 kHz is above the 8 kHz Nyquist of the target rate.
+        let buffer = AudioBuffer::new(sine(10_000.0, 0.5, 48000, 48000), 48000);
+        let out = resample(&buffer, 16000);
+        let edge = 400;
+        let level = rms(&out.samples[edge..out.frames() - edge]) / rms(&buffer.samples);
+        assert!(level < 1e-3, "alias at {:.1} dB", 20.0 * level.log10());
+    }
+
+    #[test]
+    fn test_streaming_matches_one_shot() {
+        let input = sine(440.0, 0.5, 44100, 5000);
+        let mut whole = Resampler::new(44100, 48000, 1);
+        let mut expected = Vec::new();
+        whole.process(&input, &mut expected);
+
+        let mut streamed = Resampler::new(44100, 48000, 1);
+        streamed.prepare(300);
+        let mut output = Vec::new();
+        for block in input.chunks(257) {
+            let before = output.len();
+            streamed.process(block, &mut output);
+            assert!(output.len() - before <= streamed.max_output_frames(block.len()));
+        }
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_interleaved_channels_stay_separate() {
+        let left = sine(500.0, 0.5, 48000, 4800);
+        let right: Vec<f32> = left.iter().map(|s| -s).collect();
+        let buffer = AudioBuffer::from_planar(&[left, right], 48000);
+
+        let out = resample(&buffer, 44100).to_planar();
+        for (l, r) in out[0].iter().zip(&out[1]) {
+            assert!((l + r).abs() < 1e-6);
+        }
+        assert!(rms(&out[0]) > 0.3);
+    }
+
+    #[test]
+    fn test_passthrough_copies_input() {
+        let mut resampler = Resampler::new(48000, 48000, 2);
+        assert!(resampler.is_passthrough());
+        assert_eq!(resampler.delay_frames(), 0);
+        let mut out = Vec::new();
+        resampler.process(&[0.1, 0.2, 0.3, 0.4], &mut out);
+        assert_eq!(out, vec![0.1, 0.2, 0.3, 0.4]);
+    }
+}
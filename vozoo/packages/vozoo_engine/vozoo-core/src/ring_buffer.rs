@@ -1,36 +1,68 @@
 use std::cell::UnsafeCell;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
 
-/// Lock-free Single-Producer Single-Consumer ring buffer for audio data.
+/// Lock-free Single-Producer Single-Consumer ring buffer.
 ///
-/// The producer (audio thread) writes samples via `write()`,
-/// the consumer (writer/UI thread) reads via `read()`.
+/// The producer (audio thread) writes via `write()` or `write_slices()`,
+/// the consumer (writer/UI thread) reads via `read()` or `read_slices()`.
+/// The slice views hand out the buffer's own storage as up to two
+/// contiguous regions, so data can be produced or consumed in place; the
+/// count passed to `commit`/`consume` takes effect when the view is dropped.
 ///
-/// Safety contract: exactly one thread may call `write()` and exactly one
-/// thread may call `read()` concurrently. Multiple concurrent writers or
-/// multiple concurrent readers are undefined behavior.
-pub struct SpscRingBuffer {
-    buffer: UnsafeCell<Vec<f32>>,
+/// Items `write()` can't fit are dropped and counted as overruns; items
+/// `read_or_fill()` has to make up are counted as underruns.
+///
+/// Safety contract: exactly one thread may produce and exactly one thread
+/// may consume concurrently. A second view opened on the same side while
+/// one is alive panics.
+pub struct SpscRingBuffer<T: Copy> {
+    buffer: Box<[UnsafeCell<T>]>,
     capacity: usize,
     write_idx: AtomicUsize,
     read_idx: AtomicUsize,
+    /// A `WriteSlices` / `ReadSlices` view is alive.
+    writing: AtomicBool,
+    reading: AtomicBool,
+    overruns: AtomicU64,
+    underruns: AtomicU64,
+    /// Consumer blocked in `wait_available`, and how many items wake it.
+    waiting: AtomicBool,
+    wake_at: AtomicUsize,
+    consumer: Mutex<Option<Thread>>,
 }
 
-impl SpscRingBuffer {
+impl<T: Copy> SpscRingBuffer<T> {
     /// Create a new ring buffer with the given capacity.
     /// Panics if capacity < 2 (need at least 1 sentinel + 1 data slot).
-    pub fn new(capacity: usize) -> Arc<Self> {
+    pub fn new(capacity: usize) -> Arc<Self>
+    where
+        T: Default,
+    {
         assert!(capacity >= 2, "SpscRingBuffer capacity must be >= 2");
         Arc::new(Self {
-            buffer: UnsafeCell::new(vec![0.0f32; capacity]),
+            buffer: (0..capacity).map(|_| UnsafeCell::new(T::default())).collect(),
             capacity,
             write_idx: AtomicUsize::new(0),
             read_idx: AtomicUsize::new(0),
+            writing: AtomicBool::new(false),
+            reading: AtomicBool::new(false),
+            overruns: AtomicU64::new(0),
+            underruns: AtomicU64::new(0),
+            waiting: AtomicBool::new(false),
+            wake_at: AtomicUsize::new(1),
+            consumer: Mutex::new(None),
         })
     }
 
-    /// Number of samples available to read.
+    /// Most items the buffer holds at once.
+    pub fn capacity(&self) -> usize {
+        self.capacity - 1
+    }
+
+    /// Number of items available to read.
     pub fn available(&self) -> usize {
         let w = self.write_idx.load(Ordering::Acquire);
         let r = self.read_idx.load(Ordering::Acquire);
@@ -46,93 +78,273 @@ impl SpscRingBuffer {
         self.capacity - 1 - self.available()
     }
 
-    /// Write samples into the ring buffer. Returns number of samples actually written.
-    /// If the buffer is full, excess samples are dropped (not written).
-    ///
-    /// Safety: must only be called from a single producer thread.
-    pub fn write(&self, data: &[f32]) -> usize {
-        let free = self.free_space();
-        let to_write = data.len().min(free);
-        if to_write == 0 {
-            return 0;
-        }
+    /// Items dropped by `write()` because the buffer was full.
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    /// Items `read_or_fill()` had to make up because the buffer ran dry.
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
 
+    /// View the free space as two regions to fill in place.
+    ///
+    /// Must only be called from the producer thread.
+    pub fn write_slices(&self) -> WriteSlices<'_, T> {
+        assert!(!self.writing.swap(true, Ordering::Acquire), "SpscRingBuffer already has a writer");
         let w = self.write_idx.load(Ordering::Relaxed);
+        let (first, second) = self.regions(w, self.free_space());
+        // Safety: the free region [write_idx, read_idx - 1) is only touched
+        // by the producer, and the `writing` flag makes this the producer's
+        // only view of it.
+        let (first, second) = unsafe {
+            (
+                std::slice::from_raw_parts_mut(self.ptr().add(first.0), first.1),
+                std::slice::from_raw_parts_mut(self.ptr().add(second.0), second.1),
+            )
+        };
+        WriteSlices { ring: self, first, second, committed: 0 }
+    }
 
-        // Safety: UnsafeCell grants interior mutability. Only the single producer
-        // thread writes to the buffer region [write_idx..], and atomic index
-        // updates with Release/Acquire ordering ensure the consumer sees
-        // consistent data.
-        let buf_ptr = unsafe { (*self.buffer.get()).as_mut_ptr() };
+    /// View the readable items as two regions, oldest first.
+    ///
+    /// Must only be called from the consumer thread.
+    pub fn read_slices(&self) -> ReadSlices<'_, T> {
+        assert!(!self.reading.swap(true, Ordering::Acquire), "SpscRingBuffer already has a reader");
+        let r = self.read_idx.load(Ordering::Relaxed);
+        let (first, second) = self.regions(r, self.available());
+        // Safety: the producer has finished writing [read_idx, write_idx)
+        // (Acquire on write_idx) and won't touch it until read_idx moves.
+        let (first, second) = unsafe {
+            (
+                std::slice::from_raw_parts(self.ptr().add(first.0), first.1),
+                std::slice::from_raw_parts(self.ptr().add(second.0), second.1),
+            )
+        };
+        ReadSlices { ring: self, first, second, consumed: 0 }
+    }
 
-        let first_part = (self.capacity - w).min(to_write);
-        unsafe {
-            std::ptr::copy_nonoverlapping(data.as_ptr(), buf_ptr.add(w), first_part);
+    /// Write items into the ring buffer. Returns number of items actually written.
+    /// If the buffer is full, excess items are dropped and counted as overruns.
+    ///
+    /// Safety: must only be called from a single producer thread.
+    pub fn write(&self, data: &[T]) -> usize {
+        let written = self.write_slices().copy_from(data);
+        if written < data.len() {
+            self.overruns.fetch_add((data.len() - written) as u64, Ordering::Relaxed);
         }
+        written
+    }
 
-        if to_write > first_part {
-            let second_part = to_write - first_part;
-            unsafe {
-                std::ptr::copy_nonoverlapping(data.as_ptr().add(first_part), buf_ptr, second_part);
-            }
+    /// Write all of `data` or, if it doesn't fit, none of it, counting the
+    /// whole of it as overruns. For data that mustn't be split, such as
+    /// interleaved frames.
+    pub fn write_all(&self, data: &[T]) -> bool {
+        let view = self.write_slices();
+        if view.len() < data.len() {
+            drop(view);
+            self.overruns.fetch_add(data.len() as u64, Ordering::Relaxed);
+            return false;
         }
-
-        let new_w = (w + to_write) % self.capacity;
-        self.write_idx.store(new_w, Ordering::Release);
-
-        to_write
+        view.copy_from(data);
+        true
     }
 
-    /// Read samples from the ring buffer. Returns number of samples actually read.
+    /// Read items from the ring buffer. Returns number of items actually read.
     ///
     /// Safety: must only be called from a single consumer thread.
-    pub fn read(&self, out: &mut [f32]) -> usize {
-        let avail = self.available();
-        let to_read = out.len().min(avail);
-        if to_read == 0 {
-            return 0;
+    pub fn read(&self, out: &mut [T]) -> usize {
+        self.read_slices().copy_to(out)
+    }
+
+    /// Fill `out` completely, using `fill` for whatever isn't available and
+    /// counting those items as underruns. Returns how many items were read.
+    pub fn read_or_fill(&self, out: &mut [T], fill: T) -> usize {
+        let read = self.read(out);
+        if read < out.len() {
+            out[read..].fill(fill);
+            self.underruns.fetch_add((out.len() - read) as u64, Ordering::Relaxed);
         }
+        read
+    }
 
-        let r = self.read_idx.load(Ordering::Relaxed);
-        // Safety: the consumer only reads from [read_idx..write_idx], which the
-        // producer has finished writing (guaranteed by Acquire on write_idx).
-        let buf_ptr = unsafe { (*self.buffer.get()).as_ptr() };
+    /// Discard everything waiting to be read. Consumer side.
+    pub fn clear(&self) {
+        self.read_slices().consume_all();
+    }
 
-        let first_part = (self.capacity - r).min(to_read);
-        unsafe {
-            std::ptr::copy_nonoverlapping(buf_ptr.add(r), out.as_mut_ptr(), first_part);
+    /// Block until at least `min` items can be read or `timeout` passes,
+    /// and return how many are available. `min` is capped at the capacity.
+    ///
+    /// Meant for writer threads draining what an audio thread produces; the
+    /// producer only pays for a wakeup while a consumer is actually waiting.
+    pub fn wait_available(&self, min: usize, timeout: Duration) -> usize {
+        let min = min.clamp(1, self.capacity());
+        let deadline = Instant::now() + timeout;
+        if let Ok(mut consumer) = self.consumer.lock() {
+            *consumer = Some(thread::current());
         }
+        self.wake_at.store(min, Ordering::Relaxed);
+        loop {
+            self.waiting.store(true, Ordering::SeqCst);
+            fence(Ordering::SeqCst);
+            let available = self.available();
+            let now = Instant::now();
+            if available >= min || now >= deadline {
+                self.waiting.store(false, Ordering::SeqCst);
+                return available;
+            }
+            thread::park_timeout(deadline - now);
+        }
+    }
 
-        if to_read > first_part {
-            let second_part = to_read - first_part;
-            unsafe {
-                std::ptr::copy_nonoverlapping(buf_ptr, out.as_mut_ptr().add(first_part), second_part);
+    /// Publish `count` newly written items and wake a waiting consumer once
+    /// enough are readable.
+    fn commit_write(&self, count: usize) {
+        if count > 0 {
+            let w = self.write_idx.load(Ordering::Relaxed);
+            self.write_idx.store((w + count) % self.capacity, Ordering::Release);
+            fence(Ordering::SeqCst);
+            if self.waiting.load(Ordering::SeqCst) && self.available() >= self.wake_at.load(Ordering::Relaxed) {
+                // Never block the producer: the consumer is registered before
+                // it starts waiting, so the lock is free here.
+                if let Ok(consumer) = self.consumer.try_lock() {
+                    if let Some(thread) = consumer.as_ref() {
+                        thread.unpark();
+                    }
+                }
             }
         }
+        self.writing.store(false, Ordering::Release);
+    }
 
-        let new_r = (r + to_read) % self.capacity;
-        self.read_idx.store(new_r, Ordering::Release);
+    fn commit_read(&self, count: usize) {
+        if count > 0 {
+            let r = self.read_idx.load(Ordering::Relaxed);
+            self.read_idx.store((r + count) % self.capacity, Ordering::Release);
+        }
+        self.reading.store(false, Ordering::Release);
+    }
 
-        to_read
+    /// `(start, len)` of the up to two regions covering `len` items from `start`.
+    fn regions(&self, start: usize, len: usize) -> ((usize, usize), (usize, usize)) {
+        let first = (self.capacity - start).min(len);
+        ((start, first), (0, len - first))
     }
 
-    /// Drain all available samples into a Vec.
-    pub fn drain(&self) -> Vec<f32> {
-        let avail = self.available();
-        if avail == 0 {
-            return Vec::new();
-        }
-        let mut out = vec![0.0f32; avail];
-        self.read(&mut out);
-        out
+    fn ptr(&self) -> *mut T {
+        // `UnsafeCell<T>` has the same layout as `T`, so the cells form one
+        // contiguous `[T]`.
+        UnsafeCell::raw_get(self.buffer.as_ptr())
     }
 }
 
 // Safety: SpscRingBuffer uses UnsafeCell for interior mutability with atomic
 // index operations providing synchronization. The SPSC contract (single producer,
 // single consumer) must be upheld by the caller.
-unsafe impl Send for SpscRingBuffer {}
-unsafe impl Sync for SpscRingBuffer {}
+unsafe impl<T: Copy + Send> Send for SpscRingBuffer<T> {}
+unsafe impl<T: Copy + Send> Sync for SpscRingBuffer<T> {}
+
+/// The producer's view of a ring's free space. Items marked with `commit`
+/// become readable when the view is dropped.
+pub struct WriteSlices<'a, T: Copy> {
+    ring: &'a SpscRingBuffer<T>,
+    first: &'a mut [T],
+    second: &'a mut [T],
+    committed: usize,
+}
+
+impl<T: Copy> WriteSlices<'_, T> {
+    /// Free slots across both regions.
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The free regions, in the order they will be read.
+    pub fn slices(&mut self) -> (&mut [T], &mut [T]) {
+        (&mut *self.first, &mut *self.second)
+    }
+
+    /// Mark the first `count` slots (across both regions) as written.
+    pub fn commit(&mut self, count: usize) {
+        assert!(count <= self.len(), "committed {count} items into {} free slots", self.len());
+        self.committed = count;
+    }
+
+    /// Copy as much of `data` as fits and commit it. Returns the count copied.
+    pub fn copy_from(mut self, data: &[T]) -> usize {
+        let count = data.len().min(self.len());
+        let split = count.min(self.first.len());
+        self.first[..split].copy_from_slice(&data[..split]);
+        self.second[..count - split].copy_from_slice(&data[split..count]);
+        self.commit(count);
+        count
+    }
+}
+
+impl<T: Copy> Drop for WriteSlices<'_, T> {
+    fn drop(&mut self) {
+        self.ring.commit_write(self.committed);
+    }
+}
+
+/// The consumer's view of a ring's readable items. Items marked with
+/// `consume` are released to the producer when the view is dropped.
+pub struct ReadSlices<'a, T: Copy> {
+    ring: &'a SpscRingBuffer<T>,
+    first: &'a [T],
+    second: &'a [T],
+    consumed: usize,
+}
+
+impl<T: Copy> ReadSlices<'_, T> {
+    /// Readable items across both regions.
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The readable regions, oldest first.
+    pub fn slices(&self) -> (&[T], &[T]) {
+        (self.first, self.second)
+    }
+
+    /// Mark the first `count` items (across both regions) as read.
+    pub fn consume(&mut self, count: usize) {
+        assert!(count <= self.len(), "consumed {count} of {} readable items", self.len());
+        self.consumed = count;
+    }
+
+    /// Mark everything in the view as read.
+    pub fn consume_all(&mut self) {
+        self.consumed = self.len();
+    }
+
+    /// Copy as many items as fit into `out` and consume them. Returns the
+    /// count copied.
+    pub fn copy_to(mut self, out: &mut [T]) -> usize {
+        let count = out.len().min(self.len());
+        let split = count.min(self.first.len());
+        out[..split].copy_from_slice(&self.first[..split]);
+        out[split..count].copy_from_slice(&self.second[..count - split]);
+        self.consume(count);
+        count
+    }
+}
+
+impl<T: Copy> Drop for ReadSlices<'_, T> {
+    fn drop(&mut self) {
+        self.ring.commit_read(self.consumed);
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -175,20 +387,103 @@ mod tests {
         let rb = SpscRingBuffer::new(4);
         let data = [1.0f32; 10];
         assert_eq!(rb.write(&data), 3);
+        assert_eq!(rb.overruns(), 7);
+    }
+
+    #[test]
+    fn test_write_all_is_all_or_nothing() {
+        let rb = SpscRingBuffer::new(8);
+        assert!(rb.write_all(&[1.0f32; 4]));
+        assert!(!rb.write_all(&[2.0f32; 4]));
+        assert_eq!(rb.available(), 4);
+        assert_eq!(rb.overruns(), 4);
+    }
+
+    #[test]
+    fn test_read_or_fill_counts_underruns() {
+        let rb = SpscRingBuffer::new(16);
+        rb.write(&[1.0f32, 2.0]);
+        let mut out = [9.0f32; 5];
+        assert_eq!(rb.read_or_fill(&mut out, 0.0), 2);
+        assert_eq!(out, [1.0, 2.0, 0.0, 0.0, 0.0]);
+        assert_eq!(rb.underruns(), 3);
+        assert_eq!(rb.overruns(), 0);
     }
 
     #[test]
-    fn test_drain() {
+    fn test_slices_split_at_the_wrap_and_commit_on_drop() {
+        let rb = SpscRingBuffer::<u32>::new(8);
+        rb.write(&[0; 5]);
+        rb.read(&mut [0; 5]);
+
+        {
+            let mut view = rb.write_slices();
+            assert_eq!(view.len(), 7);
+            let (first, second) = view.slices();
+            assert_eq!((first.len(), second.len()), (3, 4));
+            for (i, slot) in first.iter_mut().chain(second.iter_mut()).enumerate() {
+                *slot = i as u32;
+            }
+            view.commit(6);
+            // Nothing is visible until the view is released.
+            assert_eq!(rb.available(), 0);
+        }
+        assert_eq!(rb.available(), 6);
+
+        {
+            let mut view = rb.read_slices();
+            let (first, second) = view.slices();
+            assert_eq!(first, &[0, 1, 2]);
+            assert_eq!(second, &[3, 4, 5]);
+            view.consume(4);
+        }
+        let mut rest = [0; 2];
+        assert_eq!(rb.read(&mut rest), 2);
+        assert_eq!(rest, [4, 5]);
+    }
+
+    #[test]
+    fn test_clear() {
         let rb = SpscRingBuffer::new(1024);
-        rb.write(&[1.0, 2.0, 3.0]);
-        let drained = rb.drain();
-        assert_eq!(drained, vec![1.0, 2.0, 3.0]);
+        rb.write(&[1.0f32, 2.0, 3.0]);
+        rb.clear();
         assert_eq!(rb.available(), 0);
+        assert_eq!(rb.free_space(), rb.capacity());
+    }
+
+    #[test]
+    #[should_panic(expected = "already has a writer")]
+    fn test_second_write_view_panics() {
+        let rb = SpscRingBuffer::<f32>::new(8);
+        let _first = rb.write_slices();
+        let _second = rb.write_slices();
+    }
+
+    #[test]
+    fn test_wait_available_wakes_on_write() {
+        let rb = SpscRingBuffer::<f32>::new(1024);
+        let producer = Arc::clone(&rb);
+        let handle = thread::spawn(move || {
+            for _ in 0..10 {
+                thread::sleep(Duration::from_millis(2));
+                producer.write(&[0.5; 16]);
+            }
+        });
+
+        let start = Instant::now();
+        let available = rb.wait_available(64, Duration::from_secs(5));
+        assert!(available >= 64, "woke with {available} items");
+        assert!(start.elapsed() < Duration::from_secs(2), "waited for the timeout");
+        handle.join().unwrap();
+
+        // Times out when nothing arrives.
+        rb.clear();
+        assert_eq!(rb.wait_available(1, Duration::from_millis(10)), 0);
     }
 
     #[test]
     #[should_panic(expected = "capacity must be >= 2")]
     fn test_capacity_too_small() {
-        SpscRingBuffer::new(1);
+        SpscRingBuffer::<f32>::new(1);
     }
 }
@@ -0,0 +1,14 @@
+use std::f32::consts::TAU;
+
+use crate::{AudioBuffer, PROCESSING_SAMPLE_RATE};
+
+/// `frames` samples of a `freq` Hz sine at `rate`, peaking at `amplitude`.
+pub fn sine(freq: f32, amplitude: f32, rate: u32, frames: usize) -> Vec<f32> {
+    (0..frames).map(|i| (i as f32 / rate as f32 * freq * TAU).sin() * amplitude).collect()
+}
+
+/// A mono buffer holding `seconds` of sine at the processing rate.
+pub fn tone(freq: f32, amplitude: f32, seconds: f32) -> AudioBuffer {
+    let frames = (PROCESSING_SAMPLE_RATE as f32 * seconds) as usize;
+    AudioBuffer::new(sine(freq, amplitude, PROCESSING_SAMPLE_RATE, frames), PROCESSING_SAMPLE_RATE)
+}
@@ -1,137 +1,650 @@
 use crate::buffer::AudioBuffer;
-use std::fs::File;
-use std::io::{self, Read, Write};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 
-/// Read a WAV file into an AudioBuffer (mono, f32 normalized).
-/// Supports 16-bit PCM and 32-bit float WAV files.
-pub fn read_wav(path: &str) -> io::Result<AudioBuffer> {
-    let mut file = File::open(path)?;
-    let mut data = Vec::new();
-    file.read_to_end(&mut data)?;
+/// `WAVE_FORMAT_PCM`
+const FORMAT_PCM: u16 = 1;
+/// `WAVE_FORMAT_IEEE_FLOAT`
+const FORMAT_FLOAT: u16 = 3;
+/// `WAVE_FORMAT_EXTENSIBLE`: the real format is in the sub-format GUID.
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Size fields that don't fit in 32 bits are set to this and stored in the
+/// RF64/BW64 `ds64` chunk instead.
+const SIZE_IN_DS64: u32 = 0xFFFF_FFFF;
+
+/// Size of the `ds64` chunk body without table entries. `WavWriter` reserves
+/// this much as a `JUNK` chunk so the file can turn into RF64 in place.
+const DS64_SIZE: u32 = 28;
+
+/// Where `WavWriter` puts the `JUNK`/`ds64` chunk.
+const WRITER_DS64_POS: u64 = 12;
 
-    if data.len() < 44 {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "File too small for WAV"));
+/// Where `WavWriter` starts the sample data: RIFF header, reserved `ds64`,
+/// 16-byte `fmt ` chunk and the `data` chunk header.
+const WRITER_DATA_START: u64 = 12 + (8 + DS64_SIZE as u64) + (8 + 16) + 8;
+
+/// Sample encoding of a WAV file's data chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavSampleFormat {
+    /// Unsigned 8-bit PCM.
+    Int8,
+    Int16,
+    /// Packed 24-bit PCM (3 bytes per sample).
+    Int24,
+    Int32,
+    Float32,
+    Float64,
+}
+
+impl WavSampleFormat {
+    pub fn bits_per_sample(self) -> u16 {
+        match self {
+            WavSampleFormat::Int8 => 8,
+            WavSampleFormat::Int16 => 16,
+            WavSampleFormat::Int24 => 24,
+            WavSampleFormat::Int32 | WavSampleFormat::Float32 => 32,
+            WavSampleFormat::Float64 => 64,
+        }
     }
 
-    // Validate RIFF header
-    if &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a WAV file"));
+    pub fn is_float(self) -> bool {
+        matches!(self, WavSampleFormat::Float32 | WavSampleFormat::Float64)
+    }
+
+    fn bytes_per_sample(self) -> usize {
+        self.bits_per_sample() as usize / 8
     }
 
-    // Parse fmt chunk — find it by scanning (handles extra chunks before data)
-    let (audio_format, channels, sample_rate, bits_per_sample, data_start, data_size) =
-        parse_wav_chunks(&data)?;
+    fn format_tag(self) -> u16 {
+        if self.is_float() { FORMAT_FLOAT } else { FORMAT_PCM }
+    }
 
-    let samples = decode_samples(
-        &data[data_start..data_start + data_size],
-        audio_format,
-        bits_per_sample,
-    )?;
+    fn from_header(format_tag: u16, bits_per_sample: u16) -> Option<Self> {
+        match (format_tag, bits_per_sample) {
+            (FORMAT_PCM, 8) => Some(WavSampleFormat::Int8),
+            (FORMAT_PCM, 16) => Some(WavSampleFormat::Int16),
+            (FORMAT_PCM, 24) => Some(WavSampleFormat::Int24),
+            (FORMAT_PCM, 32) => Some(WavSampleFormat::Int32),
+            (FORMAT_FLOAT, 32) => Some(WavSampleFormat::Float32),
+            (FORMAT_FLOAT, 64) => Some(WavSampleFormat::Float64),
+            _ => None,
+        }
+    }
+}
 
-    Ok(AudioBuffer::from_stereo(&samples, channels, sample_rate))
+/// Options for `write_wav_with` and `WavWriter`.
+#[derive(Debug, Clone, Copy)]
+pub struct WavWriteOptions {
+    pub sample_format: WavSampleFormat,
+    /// Add TPDF dither when quantizing to 16 bits or fewer.
+    pub dither: bool,
 }
 
-/// Write an AudioBuffer to a 16-bit PCM WAV file.
+impl Default for WavWriteOptions {
+    /// 16-bit PCM with dither.
+    fn default() -> Self {
+        Self {
+            sample_format: WavSampleFormat::Int16,
+            dither: true,
+        }
+    }
+}
+
+/// Read a WAV file into an AudioBuffer (f32 normalized, channels preserved).
+///
+/// Supports 8/16/24/32-bit integer PCM and 32/64-bit float, with plain or
+/// `WAVE_FORMAT_EXTENSIBLE` headers, in RIFF, RF64 or BW64 files.
+pub fn read_wav(path: &str) -> io::Result<AudioBuffer> {
+    WavReader::open(path)?.read_all()
+}
+
+/// Write an AudioBuffer as 16-bit PCM with TPDF dither, keeping the buffer's
+/// channel count.
 pub fn write_wav(path: &str, buffer: &AudioBuffer) -> io::Result<()> {
-    let num_samples = buffer.samples.len();
-    let data_size = (num_samples * 2) as u32;
-    let file_size = data_size + 36;
-
-    let mut out = Vec::with_capacity(44 + num_samples * 2);
-
-    // RIFF header
-    out.extend_from_slice(b"RIFF");
-    out.extend_from_slice(&file_size.to_le_bytes());
-    out.extend_from_slice(b"WAVE");
-
-    // fmt chunk
-    out.extend_from_slice(b"fmt ");
-    out.extend_from_slice(&16u32.to_le_bytes()); // chunk size
-    out.extend_from_slice(&1u16.to_le_bytes()); // PCM format
-    out.extend_from_slice(&1u16.to_le_bytes()); // mono
-    let sr = buffer.sample_rate();
-    out.extend_from_slice(&sr.to_le_bytes());
-    out.extend_from_slice(&(sr * 2).to_le_bytes()); // byte rate
-    out.extend_from_slice(&2u16.to_le_bytes()); // block align
-    out.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
-
-    // data chunk
-    out.extend_from_slice(b"data");
-    out.extend_from_slice(&data_size.to_le_bytes());
-
-    for &s in &buffer.samples {
-        let clamped = s.clamp(-1.0, 1.0);
-        let i16_val = (clamped * 32767.0) as i16;
-        out.extend_from_slice(&i16_val.to_le_bytes());
-    }
-
-    let mut file = File::create(path)?;
-    file.write_all(&out)?;
-    Ok(())
-}
-
-fn parse_wav_chunks(data: &[u8]) -> io::Result<(u16, u16, u32, u16, usize, usize)> {
-    let mut pos = 12; // skip RIFF header
-    let mut audio_format = 0u16;
-    let mut channels = 0u16;
-    let mut sample_rate = 0u32;
-    let mut bits_per_sample = 0u16;
-    let mut data_start = 0usize;
-    let mut data_size = 0usize;
-
-    while pos + 8 <= data.len() {
-        let chunk_id = &data[pos..pos + 4];
-        let chunk_size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
-
-        if chunk_id == b"fmt " {
-            if chunk_size < 16 || pos + 8 + chunk_size > data.len() {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid fmt chunk"));
+    write_wav_with(path, buffer, &WavWriteOptions::default())
+}
+
+/// Write an AudioBuffer with the given sample format.
+///
+/// Files whose data doesn't fit the 4 GB RIFF limit are written as RF64.
+pub fn write_wav_with(path: &str, buffer: &AudioBuffer, options: &WavWriteOptions) -> io::Result<()> {
+    let mut writer = WavWriter::create(path, buffer.channels(), buffer.sample_rate(), options)?;
+    writer.write_samples(&buffer.samples)?;
+    writer.finalize()
+}
+
+/// Make a WAV file left behind by an interrupted `WavWriter` valid again.
+///
+/// Everything after the start of the data chunk is taken as audio: a
+/// trailing partial frame is cut off and the header sizes are rewritten to
+/// match. Returns the number of frames in the repaired file.
+pub fn repair_wav(path: &str) -> io::Result<u64> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let header = read_header(&mut file)?;
+    let frame_bytes = header.frame_bytes();
+    let data_bytes = (header.file_len - header.data_start) / frame_bytes * frame_bytes;
+
+    file.set_len(header.data_start + data_bytes)?;
+    if data_bytes % 2 == 1 {
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&[0])?; // pad byte
+    }
+    let frames = data_bytes / frame_bytes;
+    patch_sizes(&mut file, header.data_start, header.ds64, data_bytes, frames, true, u32::MAX as u64)?;
+    file.sync_all()?;
+    Ok(frames)
+}
+
+/// Incremental WAV decoder.
+///
+/// Parses the header up front, then hands out interleaved f32 frames a block
+/// at a time so long files never have to fit in memory. A data chunk whose
+/// size was never filled in (the writer stopped before its first flush)
+/// runs to the end of the file.
+pub struct WavReader<R> {
+    inner: R,
+    sample_format: WavSampleFormat,
+    channels: u16,
+    sample_rate: u32,
+    frames: u64,
+    frames_left: u64,
+    scratch: Vec<u8>,
+}
+
+impl WavReader<BufReader<File>> {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> WavReader<R> {
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let header = read_header(&mut inner)?;
+        let available = header.file_len - header.data_start;
+        let data_bytes = header.data_size.unwrap_or(available).min(available);
+        let frames = data_bytes / header.frame_bytes();
+
+        inner.seek(SeekFrom::Start(header.data_start))?;
+        Ok(Self {
+            inner,
+            sample_format: header.sample_format,
+            channels: header.channels,
+            sample_rate: header.sample_rate,
+            frames,
+            frames_left: frames,
+            scratch: Vec::new(),
+        })
+    }
+
+    pub fn sample_format(&self) -> WavSampleFormat {
+        self.sample_format
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Total frames in the data chunk.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Decode up to `out.len() / channels` frames of interleaved samples into
+    /// `out`. Returns the number of frames read, 0 at the end of the data.
+    pub fn read_frames(&mut self, out: &mut [f32]) -> io::Result<usize> {
+        let ch = self.channels as usize;
+        let frames = (out.len() / ch).min(self.frames_left.min(usize::MAX as u64) as usize);
+        let samples = frames * ch;
+
+        self.scratch.resize(samples * self.sample_format.bytes_per_sample(), 0);
+        self.inner.read_exact(&mut self.scratch)?;
+        decode_samples(&self.scratch, self.sample_format, &mut out[..samples]);
+        self.frames_left -= frames as u64;
+        Ok(frames)
+    }
+
+    /// Decode the rest of the file into one buffer.
+    pub fn read_all(mut self) -> io::Result<AudioBuffer> {
+        let mut samples = vec![0.0; self.frames_left as usize * self.channels as usize];
+        self.read_frames(&mut samples)?;
+        Ok(AudioBuffer::with_channels(samples, self.channels, self.sample_rate))
+    }
+}
+
+/// Incremental WAV encoder.
+///
+/// Samples are appended as they arrive. `flush` rewrites the header sizes
+/// so the file on disk is valid up to that point, and `finalize` (or
+/// dropping the writer) completes it. Once the data outgrows the 4 GB RIFF
+/// limit the file is switched to RF64 in place, using the space reserved for
+/// a `ds64` chunk right after the RIFF header.
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    encoder: SampleEncoder,
+    channels: u16,
+    samples_written: u64,
+    /// Largest RIFF size before switching to RF64 (lowered in tests).
+    riff_limit: u64,
+    scratch: Vec<u8>,
+    finished: bool,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: &str, channels: u16, sample_rate: u32, options: &WavWriteOptions) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), channels, sample_rate, options)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Write the header of an empty file and return a writer for its data.
+    pub fn new(mut inner: W, channels: u16, sample_rate: u32, options: &WavWriteOptions) -> io::Result<Self> {
+        if channels == 0 || sample_rate == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "WAV needs at least one channel and a sample rate",
+            ));
+        }
+        let format = options.sample_format;
+        let block_align = channels * format.bytes_per_sample() as u16;
+
+        inner.write_all(b"RIFF")?;
+        inner.write_all(&((WRITER_DATA_START - 8) as u32).to_le_bytes())?;
+        inner.write_all(b"WAVE")?;
+
+        // Room for ds64, skipped by readers until it's needed
+        inner.write_all(b"JUNK")?;
+        inner.write_all(&DS64_SIZE.to_le_bytes())?;
+        inner.write_all(&[0; DS64_SIZE as usize])?;
+
+        // fmt chunk
+        inner.write_all(b"fmt ")?;
+        inner.write_all(&16u32.to_le_bytes())?; // chunk size
+        inner.write_all(&format.format_tag().to_le_bytes())?;
+        inner.write_all(&channels.to_le_bytes())?;
+        inner.write_all(&sample_rate.to_le_bytes())?;
+        inner.write_all(&(sample_rate * block_align as u32).to_le_bytes())?; // byte rate
+        inner.write_all(&block_align.to_le_bytes())?;
+        inner.write_all(&format.bits_per_sample().to_le_bytes())?;
+
+        // data chunk; size 0 means "to the end of the file" until the first
+        // flush fills it in
+        inner.write_all(b"data")?;
+        inner.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            inner,
+            encoder: SampleEncoder::new(*options),
+            channels,
+            samples_written: 0,
+            riff_limit: u32::MAX as u64,
+            scratch: Vec::new(),
+            finished: false,
+        })
+    }
+
+    /// Append interleaved samples.
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        let bytes = self.encoder.format.bytes_per_sample();
+        self.scratch.resize(samples.len() * bytes, 0);
+        for (&s, out) in samples.iter().zip(self.scratch.chunks_exact_mut(bytes)) {
+            self.encoder.encode(s, out);
+        }
+        self.inner.write_all(&self.scratch)?;
+        self.samples_written += samples.len() as u64;
+        Ok(())
+    }
+
+    /// Complete frames written so far.
+    pub fn frames_written(&self) -> u64 {
+        self.samples_written / self.channels as u64
+    }
+
+    /// Bring the header up to date and flush everything to the file, so a
+    /// crash after this point still leaves a readable WAV.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.update_header(false)?;
+        self.inner.flush()
+    }
+
+    /// Pad the data chunk, write the final sizes and flush.
+    pub fn finalize(mut self) -> io::Result<()> {
+        self.finish()
+    }
+
+    fn data_bytes(&self) -> u64 {
+        self.samples_written * self.encoder.format.bytes_per_sample() as u64
+    }
+
+    fn update_header(&mut self, padded: bool) -> io::Result<()> {
+        let end = self.inner.stream_position()?;
+        let ds64 = Some((WRITER_DS64_POS, DS64_SIZE));
+        let (data_bytes, frames) = (self.data_bytes(), self.frames_written());
+        patch_sizes(&mut self.inner, WRITER_DATA_START, ds64, data_bytes, frames, padded, self.riff_limit)?;
+        self.inner.seek(SeekFrom::Start(end))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        if self.data_bytes() % 2 == 1 {
+            self.inner.write_all(&[0])?; // pad byte
+        }
+        self.update_header(true)?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        // Errors can't be reported here; call `finalize` to see them.
+        let _ = self.finish();
+    }
+}
+
+/// Rewrite the RIFF and data chunk sizes for `data_bytes` of samples at
+/// `data_start`, turning the file into RF64 once the RIFF size exceeds
+/// `riff_limit`. `padded` says whether odd-sized data is followed by its
+/// pad byte yet.
+fn patch_sizes<W: Write + Seek>(
+    out: &mut W,
+    data_start: u64,
+    ds64: Option<(u64, u32)>,
+    data_bytes: u64,
+    frames: u64,
+    padded: bool,
+    riff_limit: u64,
+) -> io::Result<()> {
+    let pad = if padded { data_bytes % 2 } else { 0 };
+    let riff_size = data_start - 8 + data_bytes + pad;
+
+    out.seek(SeekFrom::Start(0))?;
+    let data_size = if riff_size <= riff_limit {
+        out.write_all(b"RIFF")?;
+        out.write_all(&(riff_size as u32).to_le_bytes())?;
+        if let Some((pos, size)) = ds64 {
+            out.seek(SeekFrom::Start(pos))?;
+            out.write_all(b"JUNK")?;
+            out.write_all(&size.to_le_bytes())?;
+        }
+        data_bytes as u32
+    } else {
+        let (pos, size) = ds64.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "WAV data exceeds 4 GB and the file has no room for a ds64 chunk",
+            )
+        })?;
+        out.write_all(b"RF64")?;
+        out.write_all(&SIZE_IN_DS64.to_le_bytes())?;
+        out.seek(SeekFrom::Start(pos))?;
+        out.write_all(b"ds64")?;
+        out.write_all(&size.to_le_bytes())?;
+        out.write_all(&riff_size.to_le_bytes())?;
+        out.write_all(&data_bytes.to_le_bytes())?;
+        out.write_all(&frames.to_le_bytes())?; // sample count
+        out.write_all(&0u32.to_le_bytes())?; // table length
+        SIZE_IN_DS64
+    };
+
+    out.seek(SeekFrom::Start(data_start - 4))?;
+    out.write_all(&data_size.to_le_bytes())
+}
+
+/// Converts f32 samples to the bytes of a `WavSampleFormat`, adding TPDF
+/// dither when the options ask for it.
+struct SampleEncoder {
+    format: WavSampleFormat,
+    quantizer: Quantizer,
+}
+
+impl SampleEncoder {
+    fn new(options: WavWriteOptions) -> Self {
+        let dither = options.dither
+            && !options.sample_format.is_float()
+            && options.sample_format.bits_per_sample() <= 16;
+        Self {
+            format: options.sample_format,
+            quantizer: Quantizer::new(dither),
+        }
+    }
+
+    /// Encode `s` into `out`, which is exactly one sample long.
+    fn encode(&mut self, s: f32, out: &mut [u8]) {
+        let q = &mut self.quantizer;
+        match self.format {
+            WavSampleFormat::Int8 => out[0] = (q.quantize(s, 8) + 128) as u8,
+            WavSampleFormat::Int16 => out.copy_from_slice(&(q.quantize(s, 16) as i16).to_le_bytes()),
+            WavSampleFormat::Int24 => out.copy_from_slice(&(q.quantize(s, 24) as i32).to_le_bytes()[..3]),
+            WavSampleFormat::Int32 => out.copy_from_slice(&(q.quantize(s, 32) as i32).to_le_bytes()),
+            WavSampleFormat::Float32 => out.copy_from_slice(&s.to_le_bytes()),
+            WavSampleFormat::Float64 => out.copy_from_slice(&(s as f64).to_le_bytes()),
+        }
+    }
+}
+
+/// Converts f32 samples to signed integers, optionally with TPDF dither.
+///
+/// Shared by every encoder that writes integer PCM, so they all round and
+/// dither the same way.
+pub struct Quantizer {
+    dither: Option<Tpdf>,
+}
+
+impl Quantizer {
+    pub fn new(dither: bool) -> Self {
+        Self {
+            dither: dither.then(Tpdf::new),
+        }
+    }
+
+    /// Quantize `s` to a signed integer of `bits` bits (at most 32).
+    pub fn quantize(&mut self, s: f32, bits: u32) -> i64 {
+        let scale = (1i64 << (bits - 1)) as f64;
+        let noise = self.dither.as_mut().map_or(0.0, Tpdf::next);
+        let v = (s.clamp(-1.0, 1.0) as f64 * scale + noise).round();
+        (v as i64).clamp(-(scale as i64), scale as i64 - 1)
+    }
+}
+
+/// Triangular-PDF dither noise in LSBs (±1 LSB peak), from a fixed-seed
+/// xorshift generator so output files are reproducible.
+struct Tpdf {
+    state: u32,
+}
+
+impl Tpdf {
+    fn new() -> Self {
+        Self { state: 0x9E37_79B9 }
+    }
+
+    fn uniform(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state as f64 / u32::MAX as f64 - 0.5
+    }
+
+    fn next(&mut self) -> f64 {
+        self.uniform() + self.uniform()
+    }
+}
+
+/// Format and location of the audio in a WAV file.
+struct WavHeader {
+    sample_format: WavSampleFormat,
+    channels: u16,
+    sample_rate: u32,
+    data_start: u64,
+    /// Declared data size, `None` if it was never filled in.
+    data_size: Option<u64>,
+    /// Position and size of a `ds64` or `JUNK` chunk that can hold RF64 sizes.
+    ds64: Option<(u64, u32)>,
+    file_len: u64,
+}
+
+impl WavHeader {
+    fn frame_bytes(&self) -> u64 {
+        (self.channels as usize * self.sample_format.bytes_per_sample()) as u64
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_u16(b: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([b[at], b[at + 1]])
+}
+
+fn read_u32(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]])
+}
+
+fn read_u64(b: &[u8], at: usize) -> u64 {
+    (read_u32(b, at) as u64) | ((read_u32(b, at + 4) as u64) << 32)
+}
+
+fn read_chunk_body<R: Read>(r: &mut R, size: u32, min: u32, available: u64, err: &str) -> io::Result<Vec<u8>> {
+    if size < min || size as u64 > available {
+        return Err(invalid(err));
+    }
+    let mut body = vec![0u8; size as usize];
+    r.read_exact(&mut body)?;
+    Ok(body)
+}
+
+/// Scan the chunks up to the sample data (handles extra chunks before data).
+fn read_header<R: Read + Seek>(r: &mut R) -> io::Result<WavHeader> {
+    let file_len = r.seek(SeekFrom::End(0))?;
+    if file_len < 44 {
+        return Err(invalid("File too small for WAV"));
+    }
+    r.seek(SeekFrom::Start(0))?;
+
+    // Validate RIFF header
+    let mut riff = [0u8; 12];
+    r.read_exact(&mut riff)?;
+    if !matches!(&riff[0..4], b"RIFF" | b"RF64" | b"BW64") || &riff[8..12] != b"WAVE" {
+        return Err(invalid("Not a WAV file"));
+    }
+
+    let mut pos = 12u64;
+    let mut format: Option<(u16, u16, u16, u32)> = None;
+    let mut ds64: Option<(u64, u32)> = None;
+    let mut ds64_data_size: Option<u64> = None;
+
+    while pos + 8 <= file_len {
+        let mut chunk = [0u8; 8];
+        r.read_exact(&mut chunk)?;
+        let chunk_id = &chunk[0..4];
+        let chunk_size = read_u32(&chunk, 4);
+        let body = pos + 8;
+        let available = file_len - body;
+
+        if chunk_id == b"ds64" {
+            let ds = read_chunk_body(r, chunk_size, 24, available, "Invalid ds64 chunk")?;
+            ds64_data_size = Some(read_u64(&ds, 8));
+            ds64 = Some((pos, chunk_size));
+        } else if chunk_id == b"JUNK" && chunk_size >= DS64_SIZE && ds64.is_none() {
+            ds64 = Some((pos, chunk_size));
+        } else if chunk_id == b"fmt " {
+            let fmt = read_chunk_body(r, chunk_size, 16, available, "Invalid fmt chunk")?;
+            let mut format_tag = read_u16(&fmt, 0);
+            let channels = read_u16(&fmt, 2);
+            let sample_rate = read_u32(&fmt, 4);
+            let bits_per_sample = read_u16(&fmt, 14);
+
+            if format_tag == FORMAT_EXTENSIBLE {
+                // cbSize(2) validBits(2) channelMask(4) subFormat GUID(16);
+                // the GUID starts with the actual format tag.
+                if chunk_size < 40 {
+                    return Err(invalid("Invalid WAVE_FORMAT_EXTENSIBLE fmt chunk"));
+                }
+                format_tag = read_u16(&fmt, 24);
             }
-            let fmt = &data[pos + 8..];
-            audio_format = u16::from_le_bytes([fmt[0], fmt[1]]);
-            channels = u16::from_le_bytes([fmt[2], fmt[3]]);
-            sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
-            bits_per_sample = u16::from_le_bytes([fmt[14], fmt[15]]);
+            format = Some((format_tag, bits_per_sample, channels, sample_rate));
         } else if chunk_id == b"data" {
-            data_start = pos + 8;
-            data_size = chunk_size.min(data.len() - data_start);
-            break;
+            let (format_tag, bits, channels, sample_rate) =
+                format.ok_or_else(|| invalid("No fmt chunk before data"))?;
+            if channels == 0 || sample_rate == 0 {
+                return Err(invalid("Invalid fmt chunk"));
+            }
+            let sample_format = WavSampleFormat::from_header(format_tag, bits).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("Unsupported WAV format: format={format_tag}, bits={bits}"),
+                )
+            })?;
+            let data_size = match chunk_size {
+                SIZE_IN_DS64 => ds64_data_size,
+                0 => None,
+                size => Some(size as u64),
+            };
+
+            return Ok(WavHeader {
+                sample_format,
+                channels,
+                sample_rate,
+                data_start: body,
+                data_size,
+                ds64,
+                file_len,
+            });
         }
 
-        pos += 8 + chunk_size;
+        pos = body + chunk_size as u64;
         // Chunks are word-aligned
-        if chunk_size % 2 != 0 {
+        if chunk_size % 2 == 1 {
             pos += 1;
         }
+        r.seek(SeekFrom::Start(pos))?;
     }
 
-    if data_start == 0 {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "No data chunk found"));
-    }
-
-    Ok((audio_format, channels, sample_rate, bits_per_sample, data_start, data_size))
+    Err(invalid("No data chunk found"))
 }
 
-fn decode_samples(raw: &[u8], audio_format: u16, bits_per_sample: u16) -> io::Result<Vec<f32>> {
-    match (audio_format, bits_per_sample) {
-        (1, 16) => {
-            // PCM 16-bit
-            Ok(raw
-                .chunks_exact(2)
-                .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
-                .collect())
+fn decode_samples(raw: &[u8], format: WavSampleFormat, out: &mut [f32]) {
+    let samples = raw.chunks_exact(format.bytes_per_sample()).zip(out.iter_mut());
+    match format {
+        WavSampleFormat::Int8 => {
+            for (b, s) in samples {
+                *s = (b[0] as f32 - 128.0) / 128.0;
+            }
+        }
+        WavSampleFormat::Int16 => {
+            for (b, s) in samples {
+                *s = i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0;
+            }
+        }
+        WavSampleFormat::Int24 => {
+            for (b, s) in samples {
+                // Place the 3 bytes in the top of an i32 to sign-extend.
+                *s = (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0;
+            }
+        }
+        WavSampleFormat::Int32 => {
+            for (b, s) in samples {
+                *s = (i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64 / 2_147_483_648.0) as f32;
+            }
+        }
+        WavSampleFormat::Float32 => {
+            for (b, s) in samples {
+                *s = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+            }
         }
-        (3, 32) => {
-            // IEEE Float 32-bit
-            Ok(raw
-                .chunks_exact(4)
-                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
-                .collect())
+        WavSampleFormat::Float64 => {
+            for (b, s) in samples {
+                *s = f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32;
+            }
         }
-        _ => Err(io::Error::new(
-            io::ErrorKind::Unsupported,
-            format!("Unsupported WAV format: format={audio_format}, bits={bits_per_sample}"),
-        )),
     }
 }
 
@@ -139,6 +652,7 @@ fn decode_samples(raw: &[u8], audio_format: u16, bits_per_sample: u16) -> io::Re
 mod tests {
     use super::*;
     use std::fs;
+    use std::io::Cursor;
 
     #[test]
     fn test_wav_roundtrip() {
@@ -160,4 +674,243 @@ mod tests {
             assert!((a - b).abs() < 0.001, "sample mismatch: {a} vs {b}");
         }
     }
+
+    #[test]
+    fn test_wav_stereo_roundtrip() {
+        let left: Vec<f32> = (0..480).map(|i| (i as f32 / 480.0 * std::f32::consts::TAU).sin() * 0.5).collect();
+        let right: Vec<f32> = left.iter().map(|s| -s * 0.5).collect();
+        let buffer = AudioBuffer::from_planar(&[left.clone(), right.clone()], 44100);
+
+        let path = "/tmp/vozoo_test_stereo_roundtrip.wav";
+        write_wav(path, &buffer).unwrap();
+        let loaded = read_wav(path).unwrap();
+        fs::remove_file(path).ok();
+
+        assert_eq!(loaded.channels(), 2);
+        assert_eq!(loaded.sample_rate(), 44100);
+        assert_eq!(loaded.frames(), 480);
+
+        let planes = loaded.to_planar();
+        for (a, b) in left.iter().zip(planes[0].iter()) {
+            assert!((a - b).abs() < 0.001, "left mismatch: {a} vs {b}");
+        }
+        for (a, b) in right.iter().zip(planes[1].iter()) {
+            assert!((a - b).abs() < 0.001, "right mismatch: {a} vs {b}");
+        }
+    }
+
+    fn encode_with(buffer: &AudioBuffer, options: WavWriteOptions, riff_limit: u64) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut out, buffer.channels(), buffer.sample_rate(), &options).unwrap();
+        writer.riff_limit = riff_limit;
+        writer.write_samples(&buffer.samples).unwrap();
+        writer.finalize().unwrap();
+        out.into_inner()
+    }
+
+    fn encode(buffer: &AudioBuffer, sample_format: WavSampleFormat) -> Vec<u8> {
+        encode_with(buffer, WavWriteOptions { sample_format, dither: false }, u32::MAX as u64)
+    }
+
+    fn decode(file: Vec<u8>) -> io::Result<AudioBuffer> {
+        WavReader::new(Cursor::new(file))?.read_all()
+    }
+
+    #[test]
+    fn test_every_sample_format_roundtrips() {
+        let samples: Vec<f32> = (0..301).map(|i| (i as f32 * 0.07).sin() * 0.9).collect();
+        let buffer = AudioBuffer::with_channels(samples.clone(), 1, 48000);
+
+        for (format, tolerance) in [
+            (WavSampleFormat::Int8, 1.0 / 128.0),
+            (WavSampleFormat::Int16, 1.0 / 32768.0),
+            (WavSampleFormat::Int24, 1e-6),
+            (WavSampleFormat::Int32, 1e-7),
+            (WavSampleFormat::Float32, 0.0),
+            (WavSampleFormat::Float64, 0.0),
+        ] {
+            let loaded = decode(encode(&buffer, format)).unwrap();
+            assert_eq!(loaded.samples.len(), samples.len(), "{format:?}");
+            for (a, b) in samples.iter().zip(loaded.samples.iter()) {
+                assert!((a - b).abs() <= tolerance, "{format:?}: {a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_int24_sign_extension() {
+        let raw = [0xFF, 0xFF, 0x7F, 0x00, 0x00, 0x80, 0xFF, 0xFF, 0xFF];
+        let mut decoded = [0.0; 3];
+        decode_samples(&raw, WavSampleFormat::Int24, &mut decoded);
+        assert!((decoded[0] - 1.0).abs() < 1e-6);
+        assert_eq!(decoded[1], -1.0);
+        assert_eq!(decoded[2], -1.0 / 8_388_608.0);
+    }
+
+    #[test]
+    fn test_reads_wave_format_extensible() {
+        // 24-bit stereo, as written by many field recorders.
+        let buffer = AudioBuffer::with_channels(vec![0.5, -0.25, 0.125, -0.0625], 2, 96000);
+        let plain = encode(&buffer, WavSampleFormat::Int24);
+        let data_start = WRITER_DATA_START as usize;
+
+        // Rebuild the file with a 40-byte extensible fmt body.
+        let mut fmt = plain[data_start - 24..data_start - 8].to_vec();
+        fmt[0..2].copy_from_slice(&FORMAT_EXTENSIBLE.to_le_bytes());
+        fmt.extend_from_slice(&22u16.to_le_bytes()); // cbSize
+        fmt.extend_from_slice(&24u16.to_le_bytes()); // valid bits
+        fmt.extend_from_slice(&3u32.to_le_bytes()); // channel mask (FL | FR)
+        fmt.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+        fmt.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71]);
+
+        let mut file = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        file.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+        file.extend_from_slice(&fmt);
+        file.extend_from_slice(&plain[data_start - 8..]);
+        let riff_size = (file.len() - 8) as u32;
+        file[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+        let loaded = decode(file).unwrap();
+        assert_eq!(loaded.channels(), 2);
+        assert_eq!(loaded.sample_rate(), 96000);
+        for (a, b) in buffer.samples.iter().zip(loaded.samples.iter()) {
+            assert!((a - b).abs() < 1e-6, "{a} vs {b}");
+        }
+    }
+
+    #[test]
+    fn test_switches_to_rf64_past_riff_limit() {
+        let buffer = AudioBuffer::with_channels(vec![0.1, -0.2, 0.3, -0.4, 0.5, -0.6], 2, 48000);
+        let options = WavWriteOptions {
+            sample_format: WavSampleFormat::Float32,
+            dither: false,
+        };
+
+        let small = encode_with(&buffer, options, u32::MAX as u64);
+        assert_eq!(&small[0..4], b"RIFF");
+        assert_eq!(&small[12..16], b"JUNK");
+
+        // Pretend the 4 GB limit is just below this file's size.
+        let file = encode_with(&buffer, options, WRITER_DATA_START);
+        assert_eq!(&file[0..4], b"RF64");
+        assert_eq!(&file[12..16], b"ds64");
+        assert_eq!(read_u32(&file, WRITER_DATA_START as usize - 4), SIZE_IN_DS64);
+        // The RIFF size in ds64 matches the file.
+        assert_eq!(read_u64(&file, 20), file.len() as u64 - 8);
+        assert_eq!(read_u64(&file, 28), 24); // data bytes
+        assert_eq!(read_u64(&file, 36), 3); // frames
+
+        let loaded = decode(file).unwrap();
+        assert_eq!(loaded.samples, buffer.samples);
+        assert_eq!(loaded.channels(), 2);
+    }
+
+    #[test]
+    fn test_tpdf_dither_decorrelates_quantization_error() {
+        // A tone well below 1 LSB vanishes without dither but survives, on
+        // average, with it.
+        let lsb = 1.0 / 32768.0;
+        let samples: Vec<f32> = (0..48000)
+            .map(|i| (i as f32 / 48000.0 * 100.0 * std::f32::consts::TAU).sin() * 0.4 * lsb)
+            .collect();
+        let buffer = AudioBuffer::new(samples.clone(), 48000);
+
+        let plain = decode(encode(&buffer, WavSampleFormat::Int16)).unwrap();
+        assert!(plain.samples.iter().all(|&s| s == 0.0));
+
+        let dithered = decode(encode_with(&buffer, WavWriteOptions::default(), u32::MAX as u64)).unwrap();
+        let correlation: f32 = samples.iter().zip(dithered.samples.iter()).map(|(a, b)| a * b).sum();
+        assert!(correlation > 0.0, "dithered output should still carry the tone");
+        // Dither noise stays within ±1 LSB of the signal.
+        let max_err = samples
+            .iter()
+            .zip(dithered.samples.iter())
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0f32, f32::max);
+        assert!(max_err <= 1.5 * lsb, "max error {max_err}");
+    }
+
+    #[test]
+    fn test_unsupported_format_is_reported() {
+        let buffer = AudioBuffer::new(vec![0.0; 4], 48000);
+        let mut file = encode(&buffer, WavSampleFormat::Int16);
+        let fmt = WRITER_DATA_START as usize - 24;
+        file[fmt..fmt + 2].copy_from_slice(&2u16.to_le_bytes()); // ADPCM
+        let err = decode(file).err().expect("ADPCM should be rejected");
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_reader_streams_in_blocks() {
+        let samples: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+        let buffer = AudioBuffer::with_channels(samples.clone(), 2, 48000);
+        let file = encode(&buffer, WavSampleFormat::Float32);
+
+        let mut reader = WavReader::new(Cursor::new(file)).unwrap();
+        assert_eq!(reader.frames(), 500);
+        let mut streamed = Vec::new();
+        let mut block = [0.0f32; 64];
+        loop {
+            let frames = reader.read_frames(&mut block).unwrap();
+            if frames == 0 {
+                break;
+            }
+            streamed.extend_from_slice(&block[..frames * 2]);
+        }
+        assert_eq!(streamed, samples);
+    }
+
+    #[test]
+    fn test_writer_flush_keeps_file_readable() {
+        let path = "/tmp/vozoo_test_writer_flush.wav";
+        let options = WavWriteOptions {
+            sample_format: WavSampleFormat::Int24,
+            dither: false,
+        };
+        let mut writer = WavWriter::create(path, 1, 48000, &options).unwrap();
+
+        writer.write_samples(&[0.25; 101]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(WavReader::open(path).unwrap().frames(), 101);
+
+        writer.write_samples(&[-0.25; 50]).unwrap();
+        writer.finalize().unwrap();
+        let loaded = read_wav(path).unwrap();
+        fs::remove_file(path).ok();
+
+        assert_eq!(loaded.frames(), 151);
+        assert!((loaded.samples[150] + 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_repair_after_unclean_shutdown() {
+        let path = "/tmp/vozoo_test_repair.wav";
+        let file = File::create(path).unwrap();
+        let mut writer = WavWriter::new(file, 2, 48000, &WavWriteOptions::default()).unwrap();
+        writer.write_samples(&[0.5; 200]).unwrap();
+        writer.flush().unwrap();
+        writer.write_samples(&[0.5; 101]).unwrap();
+        // Simulate a crash: the writer never finalizes, the last flush only
+        // covered 100 frames and the file ends in half a frame.
+        std::mem::forget(writer);
+
+        assert_eq!(WavReader::open(path).unwrap().frames(), 100);
+        assert_eq!(repair_wav(path).unwrap(), 150);
+        let loaded = read_wav(path).unwrap();
+        fs::remove_file(path).ok();
+
+        assert_eq!(loaded.channels(), 2);
+        assert_eq!(loaded.frames(), 150);
+    }
+
+    #[test]
+    fn test_unflushed_header_reads_to_end_of_file() {
+        let mut out = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut out, 1, 48000, &WavWriteOptions::default()).unwrap();
+        writer.write_samples(&[0.1; 64]).unwrap();
+        std::mem::forget(writer);
+
+        // The data size is still 0 from the initial header.
+        assert_eq!(decode(out.into_inner()).unwrap().frames(), 64);
+    }
 }
@@ -1,10 +1,52 @@
+// The C ABI functions below take raw pointers from the Dart side and check
+// them for null before use; marking every entry point `unsafe` would not add
+// any safety for FFI callers.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
 use std::ffi::CStr;
+use std::io;
 use std::os::raw::{c_char, c_int};
+use std::sync::Mutex;
 
+use vozoo_core::{MeterSnapshot, VozooError};
 use vozoo_io::RealtimeEngine;
 
 type EngineHandle = *mut RealtimeEngine;
 
+/// Message of the most recent failed call. Process-wide rather than
+/// per-thread: Dart runs batch jobs on background isolates and asks for the
+/// reason from the UI isolate.
+static LAST_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+/// Remember why a call failed and return its result code.
+fn fail(err: VozooError, code: c_int) -> c_int {
+    if let Ok(mut last) = LAST_ERROR.lock() {
+        *last = Some(err.to_string());
+    }
+    code
+}
+
+/// Result code of a batch call: -1 read error, -2 write error (including an
+/// unwritable format), -3 invalid chain or graph definition.
+fn process_result(result: Result<(), VozooError>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(err @ VozooError::Read { .. }) => fail(err, -1),
+        Err(err @ (VozooError::Write { .. } | VozooError::UnsupportedFormat(_) | VozooError::Io(_))) => fail(err, -2),
+        Err(err) => fail(err, -3),
+    }
+}
+
+/// Error for a path or key argument that is null or not valid UTF-8.
+fn bad_arg(what: &str) -> VozooError {
+    VozooError::Io(io::Error::new(io::ErrorKind::InvalidInput, format!("{what} is null or not valid UTF-8")))
+}
+
+/// Error for a chain or graph definition that is null or not valid UTF-8.
+fn bad_json(what: &str) -> VozooError {
+    VozooError::InvalidJson { line: 0, column: 0, message: format!("{what} is null or not valid UTF-8") }
+}
+
 /// Safely convert a C string pointer to a Rust &str.
 /// Returns None if the pointer is null or not valid UTF-8.
 unsafe fn cstr_to_str<'a>(ptr: *const c_char) -> Option<&'a str> {
@@ -24,13 +66,13 @@ pub extern "C" fn process_file(
 ) -> c_int {
     let input_str = match unsafe { cstr_to_str(input_path) } {
         Some(s) => s,
-        None => return -1,
+        None => return fail(bad_arg("input path"), -1),
     };
     let output_str = match unsafe { cstr_to_str(output_path) } {
         Some(s) => s,
-        None => return -2,
+        None => return fail(bad_arg("output path"), -2),
     };
-    vozoo_nodes::process_file(input_str, output_str, preset_id)
+    process_result(vozoo_nodes::process_file(input_str, output_str, preset_id))
 }
 
 #[no_mangle]
@@ -41,21 +83,23 @@ pub extern "C" fn process_file_with_chain(
 ) -> c_int {
     let input_str = match unsafe { cstr_to_str(input_path) } {
         Some(s) => s,
-        None => return -1,
+        None => return fail(bad_arg("input path"), -1),
     };
     let output_str = match unsafe { cstr_to_str(output_path) } {
         Some(s) => s,
-        None => return -2,
+        None => return fail(bad_arg("output path"), -2),
     };
     let chain_str = match unsafe { cstr_to_str(chain_json) } {
         Some(s) => s,
-        None => return -3,
+        None => return fail(bad_json("chain definition"), -3),
     };
-    vozoo_nodes::process_file_with_chain(input_str, output_str, chain_str)
+    process_result(vozoo_nodes::process_file_with_chain(input_str, output_str, chain_str))
 }
 
-/// Process a WAV file using a DAG graph definition (JSON).
-/// Returns 0 on success, -1 on read error, -2 on write error, -3 on parse error.
+/// Process an audio file using a DAG graph definition (JSON). Any format
+/// the codec layer reads is accepted; the output extension picks the format.
+/// Returns 0 on success, -1 on read error, -2 on write error, -3 on an
+/// invalid definition; `vozoo_last_error_message()` tells which and why.
 #[no_mangle]
 pub extern "C" fn process_file_with_graph(
     input_path: *const c_char,
@@ -64,17 +108,17 @@ pub extern "C" fn process_file_with_graph(
 ) -> c_int {
     let input_str = match unsafe { cstr_to_str(input_path) } {
         Some(s) => s,
-        None => return -1,
+        None => return fail(bad_arg("input path"), -1),
     };
     let output_str = match unsafe { cstr_to_str(output_path) } {
         Some(s) => s,
-        None => return -2,
+        None => return fail(bad_arg("output path"), -2),
     };
     let graph_str = match unsafe { cstr_to_str(graph_json) } {
         Some(s) => s,
-        None => return -3,
+        None => return fail(bad_json("graph definition"), -3),
     };
-    vozoo_nodes::process_file_with_graph(input_str, output_str, graph_str)
+    process_result(vozoo_nodes::process_file_with_graph(input_str, output_str, graph_str))
 }
 
 /// Get built-in graph preset definitions as JSON.
@@ -87,21 +131,38 @@ pub extern "C" fn get_graph_presets() -> *mut c_char {
 }
 
 /// Set the engine's effect graph from a DAG graph definition (JSON).
-/// Returns 0 on success, -1 null handle, -2 invalid UTF-8, -3 parse error.
+/// Returns 0 on success, -1 null handle, -2 invalid UTF-8, -3 invalid
+/// definition (the reason is in `vozoo_last_error_message()`).
 #[no_mangle]
 pub extern "C" fn engine_set_graph(handle: EngineHandle, graph_json: *const c_char) -> c_int {
     if handle.is_null() { return -1; }
     let graph_str = match unsafe { cstr_to_str(graph_json) } {
         Some(s) => s,
-        None => return -2,
+        None => return fail(bad_json("graph definition"), -2),
     };
     let engine = unsafe { &*handle };
     match engine.set_graph(graph_str) {
         Ok(()) => 0,
-        Err(_) => -3,
+        Err(e) => fail(e, -3),
     }
 }
 
+/// Check a chain or graph definition (JSON) without building it. Returns a
+/// JSON array of diagnostics, each with `severity` ("error" or "warning"),
+/// `code`, `message`, `fix` and, where they apply, `node`, `edge` and
+/// `param`; empty if the definition is clean. Null for a null or non-UTF-8
+/// argument.
+/// Caller must free the returned string with `free_string()`.
+#[no_mangle]
+pub extern "C" fn validate_definition(json: *const c_char) -> *mut c_char {
+    let json_str = match unsafe { cstr_to_str(json) } {
+        Some(s) => s,
+        None => return std::ptr::null_mut(),
+    };
+    let diagnostics = vozoo_nodes::validate_json(json_str);
+    string_to_c(serde_json::to_string(&diagnostics).unwrap_or_else(|_| "[]".into()))
+}
+
 #[no_mangle]
 pub extern "C" fn get_available_nodes() -> *mut c_char {
     let nodes = vozoo_nodes::available_nodes();
@@ -116,6 +177,17 @@ pub extern "C" fn get_presets() -> *mut c_char {
     string_to_c(json)
 }
 
+/// Human-readable reason for the most recent failed call (a negative
+/// result code), or null if nothing has failed yet.
+/// Caller must free the returned string with `free_string()`.
+#[no_mangle]
+pub extern "C" fn vozoo_last_error_message() -> *mut c_char {
+    match LAST_ERROR.lock().ok().and_then(|last| last.clone()) {
+        Some(message) => string_to_c(message),
+        None => std::ptr::null_mut(),
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn free_string(ptr: *mut c_char) {
     if !ptr.is_null() {
@@ -137,18 +209,45 @@ pub extern "C" fn engine_destroy(handle: EngineHandle) {
     }
 }
 
-/// Returns 0 on success, -1 null handle, -2 invalid UTF-8, -3 parse error.
+/// Returns 0 on success, -1 null handle, -2 invalid UTF-8, -3 invalid
+/// definition (the reason is in `vozoo_last_error_message()`).
 #[no_mangle]
 pub extern "C" fn engine_set_chain(handle: EngineHandle, chain_json: *const c_char) -> c_int {
     if handle.is_null() { return -1; }
     let chain_str = match unsafe { cstr_to_str(chain_json) } {
         Some(s) => s,
-        None => return -2,
+        None => return fail(bad_json("chain definition"), -2),
     };
     let engine = unsafe { &*handle };
     match engine.set_chain(chain_str) {
         Ok(()) => 0,
-        Err(_) => -3,
+        Err(e) => fail(e, -3),
+    }
+}
+
+/// Change a parameter of the running chain or graph without rebuilding it.
+/// `node` is the node index for chains and the node ID for graphs.
+/// Returns 0 on success, -1 null handle, -2 invalid UTF-8, -3 no such live
+/// parameter (rebuild the chain with `engine_set_chain` instead).
+#[no_mangle]
+pub extern "C" fn engine_set_param(
+    handle: EngineHandle,
+    node: c_int,
+    key: *const c_char,
+    value: f32,
+) -> c_int {
+    if handle.is_null() { return -1; }
+    let key_str = match unsafe { cstr_to_str(key) } {
+        Some(s) => s,
+        None => return fail(bad_arg("parameter key"), -2),
+    };
+    let engine = unsafe { &*handle };
+    let Ok(node) = u32::try_from(node) else {
+        return fail(VozooError::Engine(format!("Invalid node {node}")), -3);
+    };
+    match engine.set_param(node, key_str, value) {
+        Ok(()) => 0,
+        Err(e) => fail(e, -3),
     }
 }
 
@@ -158,7 +257,7 @@ pub extern "C" fn engine_start_realtime(handle: EngineHandle) -> c_int {
     let engine = unsafe { &mut *handle };
     match engine.start() {
         Ok(()) => 0,
-        Err(_) => -1,
+        Err(e) => fail(e, -1),
     }
 }
 
@@ -174,12 +273,12 @@ pub extern "C" fn engine_start_recording(handle: EngineHandle, output_path: *con
     if handle.is_null() { return -1; }
     let path_str = match unsafe { cstr_to_str(output_path) } {
         Some(s) => s,
-        None => return -2,
+        None => return fail(bad_arg("output path"), -2),
     };
     let engine = unsafe { &mut *handle };
     match engine.start_recording(path_str) {
         Ok(()) => 0,
-        Err(_) => -1,
+        Err(e) => fail(e, -1),
     }
 }
 
@@ -211,8 +310,96 @@ pub extern "C" fn engine_is_recording(handle: EngineHandle) -> c_int {
     engine.is_recording() as c_int
 }
 
+/// Current meter readings as JSON:
+/// `{"input": {...}, "output": {...}, "nodes": [{"node": 0, ...}]}`, where
+/// each reading has `peak_db`, `rms_db`, `momentary_lufs`, `short_term_lufs`
+/// and a 32-band `spectrum_db`, and `node` is keyed like `engine_set_param`.
+/// Returns null for a null handle.
+/// Caller must free the returned string with `free_string()`.
+#[no_mangle]
+pub extern "C" fn engine_get_meters(handle: EngineHandle) -> *mut c_char {
+    if handle.is_null() { return std::ptr::null_mut(); }
+    let engine = unsafe { &*handle };
+    let meters = engine.meters();
+    let nodes: Vec<serde_json::Value> = meters
+        .nodes
+        .iter()
+        .map(|(node, snapshot)| {
+            let mut json = meter_json(snapshot);
+            json["node"] = (*node).into();
+            json
+        })
+        .collect();
+    let json = serde_json::json!({
+        "input": meter_json(&meters.input),
+        "output": meter_json(&meters.output),
+        "nodes": nodes,
+    });
+    string_to_c(json.to_string())
+}
+
+/// Current delay from the mic to the speaker as JSON, in milliseconds:
+/// `{"round_trip_ms": ..., "input_device_ms": ..., "buffering_ms": ...,
+/// "pipeline_ms": ..., "output_device_ms": ...}`. The device figures stay 0
+/// until the engine is running. Returns null for a null handle.
+/// Caller must free the returned string with `free_string()`.
+#[no_mangle]
+pub extern "C" fn engine_get_latency(handle: EngineHandle) -> *mut c_char {
+    if handle.is_null() { return std::ptr::null_mut(); }
+    let engine = unsafe { &*handle };
+    let latency = engine.latency();
+    let json = serde_json::json!({
+        "round_trip_ms": latency.round_trip_ms(),
+        "input_device_ms": latency.input_device_ms,
+        "buffering_ms": latency.buffering_ms,
+        "pipeline_ms": latency.pipeline_ms,
+        "output_device_ms": latency.output_device_ms,
+    });
+    string_to_c(json.to_string())
+}
+
+fn meter_json(snapshot: &MeterSnapshot) -> serde_json::Value {
+    serde_json::json!({
+        "peak_db": snapshot.peak_db,
+        "rms_db": snapshot.rms_db,
+        "momentary_lufs": snapshot.momentary_lufs,
+        "short_term_lufs": snapshot.short_term_lufs,
+        "spectrum_db": snapshot.spectrum_db.to_vec(),
+    })
+}
+
 fn string_to_c(s: String) -> *mut c_char {
     std::ffi::CString::new(s)
         .unwrap_or_else(|_| std::ffi::CString::new("").unwrap())
         .into_raw()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    fn last_error() -> String {
+        let message = vozoo_last_error_message();
+        assert!(!message.is_null());
+        let text = unsafe { CStr::from_ptr(message) }.to_str().unwrap().to_string();
+        free_string(message);
+        text
+    }
+
+    #[test]
+    fn test_bad_arguments_set_the_error_message() {
+        let path = CString::new("/tmp/vozoo_ffi_out.wav").unwrap();
+
+        assert_eq!(process_file(std::ptr::null(), path.as_ptr(), 0), -1);
+        assert!(last_error().contains("input path"));
+        assert_eq!(process_file_with_chain(path.as_ptr(), path.as_ptr(), std::ptr::null()), -3);
+        assert!(last_error().contains("chain definition"));
+
+        let invalid = [0xffu8, 0];
+        let engine = engine_create();
+        assert_eq!(engine_set_graph(engine, invalid.as_ptr().cast()), -2);
+        assert!(last_error().contains("graph definition"));
+        engine_destroy(engine);
+    }
+}
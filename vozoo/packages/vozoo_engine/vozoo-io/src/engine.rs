@@ -1,16 +1,134 @@
+use std::fs::File;
+use std::io::BufWriter;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{SampleFormat, Stream};
 
-use vozoo_core::{AudioBuffer, SpscRingBuffer};
+use vozoo_core::{
+    AtomicF32, AudioBuffer, AudioNode, ChannelLayout, MeterSnapshot, MeterTap, ParamHandle, Resampler, SpscRingBuffer, VozooError,
+    WavWriteOptions, WavWriter, BLOCK_CAPACITY_FACTOR, PROCESSING_SAMPLE_RATE,
+};
 use vozoo_nodes::chain::LinearChain;
 use vozoo_nodes::chain_def::ChainDef;
+use vozoo_nodes::effects::meter::Meter;
+use vozoo_nodes::effects::noise_reduction::DenoiseWarmup;
+use vozoo_nodes::effects::resample::Resample;
 use vozoo_nodes::graph::AudioGraph;
 use vozoo_nodes::graph_def::GraphDef;
 
+use crate::handoff::Handoff;
+
+/// Largest block handed to the pipeline in one `process` call. Device
+/// callbacks larger than this are split.
+const MAX_BLOCK: usize = 1024;
+
+/// Seconds of audio between header updates of an in-progress recording, so
+/// a crash loses at most this much of the take.
+const RECORD_SYNC_SECS: u64 = 1;
+
+/// Frames of mic input downmixed per ring write.
+const CAPTURE_CHUNK: usize = 1024;
+
+/// How much recorded audio wakes the writer thread, in milliseconds.
+const RECORD_WAKE_MS: usize = 100;
+
+/// Audio lost or made up because one side of the engine couldn't keep up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Xruns {
+    /// Mic frames dropped because the output side fell behind.
+    pub input_overruns: u64,
+    /// Speaker frames left silent because no mic input was waiting.
+    pub output_underruns: u64,
+    /// Recorded samples dropped because the writer thread fell behind.
+    pub dropped_recording_samples: u64,
+}
+
+impl Xruns {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Latest readings of the engine's meters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineMeters {
+    /// Mic input at the processing rate, before the pipeline.
+    pub input: MeterSnapshot,
+    /// Pipeline output as it goes to the speaker and the recording.
+    pub output: MeterSnapshot,
+    /// `meter` nodes in the pipeline, keyed like `set_param`'s `node`.
+    pub nodes: Vec<(u32, MeterSnapshot)>,
+}
+
+/// Where the delay between the mic and the speaker comes from, in
+/// milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EngineLatency {
+    /// Capture to input callback, as reported by the input device.
+    pub input_device_ms: f32,
+    /// Mic audio queued inside the engine: the input ring, the output FIFO
+    /// and the lookahead of both sample rate converters.
+    pub buffering_ms: f32,
+    /// Delay of the running pipeline's nodes, including the delays that
+    /// align a graph's parallel paths.
+    pub pipeline_ms: f32,
+    /// Output callback to playback, as reported by the output device.
+    pub output_device_ms: f32,
+}
+
+impl EngineLatency {
+    /// Total delay from the mic to the speaker.
+    pub fn round_trip_ms(&self) -> f32 {
+        self.input_device_ms + self.buffering_ms + self.pipeline_ms + self.output_device_ms
+    }
+}
+
+/// Latency figures the audio callbacks publish, in milliseconds.
+struct LatencyTaps {
+    input_device_ms: AtomicF32,
+    output_device_ms: AtomicF32,
+    /// Audio queued between the callbacks, as of the last render.
+    queued_ms: AtomicF32,
+    pipeline_ms: AtomicF32,
+}
+
+impl LatencyTaps {
+    fn new() -> Self {
+        Self {
+            input_device_ms: AtomicF32::new(0.0),
+            output_device_ms: AtomicF32::new(0.0),
+            queued_ms: AtomicF32::new(0.0),
+            pipeline_ms: AtomicF32::new(0.0),
+        }
+    }
+
+    fn record_input(&self, info: &cpal::InputCallbackInfo) {
+        let timestamp = info.timestamp();
+        if let Some(delay) = timestamp.callback.duration_since(&timestamp.capture) {
+            self.input_device_ms.store(delay.as_secs_f32() * 1000.0);
+        }
+    }
+
+    fn record_output(&self, info: &cpal::OutputCallbackInfo) {
+        let timestamp = info.timestamp();
+        if let Some(delay) = timestamp.playback.duration_since(&timestamp.callback) {
+            self.output_device_ms.store(delay.as_secs_f32() * 1000.0);
+        }
+    }
+}
+
+/// Milliseconds spanned by `frames` frames at `sample_rate`.
+fn frames_to_ms(frames: usize, sample_rate: u32) -> f32 {
+    frames as f32 * 1000.0 / sample_rate as f32
+}
+
+/// Length of the crossfade when the audio thread switches pipelines.
+const CROSSFADE_MS: u32 = 10;
+
 /// Unified processing pipeline: either a linear chain or a DAG graph.
 enum Pipeline {
     Chain(LinearChain),
@@ -18,6 +136,13 @@ enum Pipeline {
 }
 
 impl Pipeline {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        match self {
+            Pipeline::Chain(chain) => chain.prepare(sample_rate, max_block),
+            Pipeline::Graph(graph) => graph.prepare(sample_rate, max_block),
+        }
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
         match self {
             Pipeline::Chain(chain) => chain.process(buffer),
@@ -25,12 +150,307 @@ impl Pipeline {
         }
     }
 
-    fn reset(&mut self) {
+    /// Live parameters keyed by node index (chains) or node ID (graphs).
+    fn params(&self) -> Vec<(u32, ParamHandle)> {
+        match self {
+            Pipeline::Chain(chain) => chain.params(),
+            Pipeline::Graph(graph) => graph.params(),
+        }
+    }
+
+    /// Frames the output lags the input by, at the pipeline's output rate.
+    fn latency_samples(&self, sample_rate: u32) -> usize {
+        match self {
+            Pipeline::Chain(chain) => chain.latency_samples(sample_rate),
+            Pipeline::Graph(graph) => graph.latency_samples(),
+        }
+    }
+
+    /// Output frames per input frame; graphs keep the block length.
+    fn time_scale(&self) -> f64 {
         match self {
-            Pipeline::Chain(chain) => chain.reset(),
-            Pipeline::Graph(graph) => graph.reset(),
+            Pipeline::Chain(chain) => chain.time_scale(),
+            Pipeline::Graph(_) => 1.0,
         }
     }
+
+    /// Meter taps keyed the same way as `params`.
+    fn meters(&self) -> Vec<(u32, Arc<MeterTap>)> {
+        match self {
+            Pipeline::Chain(chain) => chain.meters(),
+            Pipeline::Graph(graph) => graph.meters(),
+        }
+    }
+}
+
+/// Input callback state: downmixes mic frames to mono into a scratch buffer
+/// allocated up front, converts them from the device rate to the processing
+/// rate, then pushes them to the input ring.
+struct InputCapture {
+    ring: Arc<SpscRingBuffer<f32>>,
+    channels: usize,
+    mono: Vec<f32>,
+    to_processing: Resampler,
+    resampled: Vec<f32>,
+}
+
+impl InputCapture {
+    fn new(ring: Arc<SpscRingBuffer<f32>>, channels: usize, device_rate: u32) -> Self {
+        let mut to_processing = Resampler::new(device_rate, PROCESSING_SAMPLE_RATE, 1);
+        to_processing.prepare(CAPTURE_CHUNK);
+        let resampled = Vec::with_capacity(to_processing.max_output_frames(CAPTURE_CHUNK));
+        Self {
+            ring,
+            channels: channels.max(1),
+            mono: vec![0.0; CAPTURE_CHUNK],
+            to_processing,
+            resampled,
+        }
+    }
+
+    fn push<T: Copy>(&mut self, data: &[T], to_f32: impl Fn(T) -> f32) {
+        let channels = self.channels;
+        for chunk in data.chunks(CAPTURE_CHUNK * channels) {
+            let frames = chunk.len() / channels;
+            for (m, frame) in self.mono.iter_mut().zip(chunk.chunks_exact(channels)) {
+                *m = frame.iter().map(|&s| to_f32(s)).sum::<f32>() / channels as f32;
+            }
+            if self.to_processing.is_passthrough() {
+                self.ring.write(&self.mono[..frames]);
+            } else {
+                self.resampled.clear();
+                self.to_processing.process(&self.mono[..frames], &mut self.resampled);
+                self.ring.write(&self.resampled);
+            }
+        }
+    }
+}
+
+/// Output callback state: input_ring → pipeline → speaker (+ record_ring).
+///
+/// The block buffers are allocated before the stream starts and pipelines
+/// are prepared for `MAX_BLOCK` before they are published, so rendering
+/// never touches the allocator. Per-thread caches some nodes build on first
+/// use are built by `warm`, which the output callback runs once before it
+/// renders.
+///
+/// Pipelines run at `PROCESSING_SAMPLE_RATE`; their output is converted to
+/// the device rate through a FIFO holding what one block rendered beyond the
+/// callback. Recordings are taken before that conversion.
+///
+/// The renderer owns the running pipeline. A newly published one is picked
+/// up at the start of a callback and crossfaded in over `CROSSFADE_MS`; the
+/// old one then goes back through the handoff to be dropped off the audio
+/// thread.
+struct OutputRenderer {
+    pipelines: Arc<Handoff<Pipeline>>,
+    current: Box<Pipeline>,
+    /// Pipeline being faded out, and how many frames of the fade are done.
+    fading_out: Option<Box<Pipeline>>,
+    fade_pos: usize,
+    fade_len: usize,
+    /// Finished pipeline waiting for a free retire slot.
+    retiring: Option<Box<Pipeline>>,
+    input_ring: Arc<SpscRingBuffer<f32>>,
+    record_ring: Arc<SpscRingBuffer<f32>>,
+    is_recording: Arc<AtomicBool>,
+    samples_recorded: Arc<AtomicU64>,
+    output_underruns: Arc<AtomicU64>,
+    latency: Arc<LatencyTaps>,
+    output_layout: ChannelLayout,
+    input_meter: Meter,
+    output_meter: Meter,
+    block: AudioBuffer,
+    /// Output of the pipeline being faded out.
+    fade_block: AudioBuffer,
+    to_device: Resampler,
+    /// Device-rate output not yet handed to the speaker.
+    device_fifo: Vec<f32>,
+    denoise_warmup: DenoiseWarmup,
+}
+
+impl OutputRenderer {
+    /// Renderer for `engine`'s shared state, starting with `current`.
+    fn new(engine: &RealtimeEngine, current: Box<Pipeline>) -> Self {
+        let sample_rate = engine.sample_rate;
+        let block_buffer =
+            || AudioBuffer::new(Vec::with_capacity(MAX_BLOCK * BLOCK_CAPACITY_FACTOR), sample_rate);
+        let output_layout = ChannelLayout::from_channels(engine.output_channels);
+        let mut to_device = Resampler::new(sample_rate, engine.output_rate, output_layout.channels());
+        to_device.prepare(MAX_BLOCK * BLOCK_CAPACITY_FACTOR);
+        let fifo_frames = to_device.max_output_frames(MAX_BLOCK * BLOCK_CAPACITY_FACTOR);
+        let meter = |tap: &Arc<MeterTap>| {
+            let mut meter = Meter::with_tap(Arc::clone(tap));
+            meter.prepare(sample_rate, MAX_BLOCK);
+            meter
+        };
+        Self {
+            pipelines: Arc::clone(&engine.pipelines),
+            current,
+            fading_out: None,
+            fade_pos: 0,
+            fade_len: (sample_rate * CROSSFADE_MS / 1000).max(1) as usize,
+            retiring: None,
+            input_ring: Arc::clone(&engine.input_ring),
+            record_ring: Arc::clone(&engine.record_ring),
+            is_recording: Arc::clone(&engine.is_recording),
+            samples_recorded: Arc::clone(&engine.samples_recorded),
+            output_underruns: Arc::clone(&engine.output_underruns),
+            latency: Arc::clone(&engine.latency),
+            output_layout,
+            input_meter: meter(&engine.input_meter),
+            output_meter: meter(&engine.output_meter),
+            block: block_buffer(),
+            fade_block: block_buffer(),
+            to_device,
+            device_fifo: Vec::with_capacity(fifo_frames * output_layout.channels() as usize),
+            denoise_warmup: DenoiseWarmup::new(),
+        }
+    }
+
+    /// Build the per-thread caches nodes would otherwise allocate on first
+    /// use (nnnoiseless's FFT plans) on the calling thread, whatever the
+    /// pipeline, so noise reduction switched in later doesn't allocate.
+    /// Allocates nothing else.
+    fn warm(&mut self) {
+        self.denoise_warmup.warm();
+    }
+
+    /// Pick up a newly published pipeline and hand back finished ones.
+    fn poll_handoff(&mut self) {
+        if let Some(old) = self.retiring.take() {
+            if let Err(old) = self.pipelines.retire(old) {
+                self.retiring = Some(old);
+                return;
+            }
+        }
+        if self.fading_out.is_some() {
+            return;
+        }
+        if let Some(new) = self.pipelines.take() {
+            self.fading_out = Some(std::mem::replace(&mut self.current, new));
+            self.fade_pos = 0;
+        }
+    }
+
+    fn render(&mut self, data: &mut [f32]) {
+        data.fill(0.0);
+        self.poll_handoff();
+        let output_channels = self.output_layout.channels() as usize;
+
+        let mut written = self.drain_fifo(data);
+        while written < data.len() {
+            let wanted = (data.len() - written).div_ceil(output_channels);
+            // A time-stretching pipeline turns fewer (or more) input frames
+            // into what the device wants; slowed down, the mic backlog grows
+            // until the input ring overruns.
+            let needed = self.to_device.input_frames_for(wanted) as f64 / self.current.time_scale();
+            let frames = (needed.ceil() as usize).clamp(1, MAX_BLOCK);
+            if !self.render_block(frames) {
+                break;
+            }
+            self.to_device.process(&self.block.samples, &mut self.device_fifo);
+            written += self.drain_fifo(&mut data[written..]);
+        }
+        if written < data.len() {
+            let missing = (data.len() - written) / output_channels;
+            self.output_underruns.fetch_add(missing as u64, Ordering::Relaxed);
+        }
+        self.publish_latency();
+    }
+
+    /// Publish how long mic audio now waits in the engine, and the running
+    /// pipeline's delay.
+    fn publish_latency(&self) {
+        let sample_rate = self.block.sample_rate();
+        let queued = self.input_ring.available() + self.to_device.delay_frames();
+        let fifo = self.device_fifo.len() / self.output_layout.channels() as usize;
+        let queued_ms = frames_to_ms(queued, sample_rate) + frames_to_ms(fifo, self.to_device.to_rate());
+        self.latency.queued_ms.store(queued_ms);
+        let pipeline = self.current.latency_samples(sample_rate);
+        self.latency.pipeline_ms.store(frames_to_ms(pipeline, sample_rate));
+    }
+
+    /// Move as much of the device FIFO into `out` as fits.
+    fn drain_fifo(&mut self, out: &mut [f32]) -> usize {
+        let len = self.device_fifo.len().min(out.len());
+        out[..len].copy_from_slice(&self.device_fifo[..len]);
+        self.device_fifo.copy_within(len.., 0);
+        self.device_fifo.truncate(self.device_fifo.len() - len);
+        len
+    }
+
+    /// Run up to `frames` frames of input through the pipeline into `block`.
+    /// Returns false if no input was waiting.
+    fn render_block(&mut self, frames: usize) -> bool {
+        self.block.set_sample_rate(PROCESSING_SAMPLE_RATE);
+        self.block.reshape(frames, 1);
+        let read = self.input_ring.read(&mut self.block.samples);
+        if read == 0 {
+            return false;
+        }
+        self.block.samples.truncate(read);
+        self.input_meter.process(&mut self.block);
+
+        if let Some(old) = self.fading_out.as_mut() {
+            self.fade_block.copy_from(&self.block);
+            old.process(&mut self.fade_block);
+            self.fade_block.conform(self.output_layout);
+        }
+        self.current.process(&mut self.block);
+        self.block.conform(self.output_layout);
+        if self.fading_out.is_some() {
+            // Pipelines that stretch time differently yield different lengths.
+            self.fade_block.samples.resize(self.block.samples.len(), 0.0);
+            self.crossfade();
+        }
+        self.output_meter.process(&mut self.block);
+
+        // Write to recording ring buffer if recording. Blocks the writer
+        // thread has no room for are dropped whole, keeping channels aligned,
+        // and show up in the ring's overrun count.
+        if self.is_recording.load(Ordering::Relaxed) && self.record_ring.write_all(&self.block.samples) {
+            self.samples_recorded
+                .fetch_add(self.block.frames() as u64, Ordering::Relaxed);
+        }
+        true
+    }
+
+    /// Blend the old pipeline's output into `block` with a linear ramp.
+    fn crossfade(&mut self) {
+        let ch = self.output_layout.channels() as usize;
+        for (i, (frame, old)) in self
+            .block
+            .samples
+            .chunks_exact_mut(ch)
+            .zip(self.fade_block.samples.chunks_exact(ch))
+            .enumerate()
+        {
+            let t = ((self.fade_pos + i) as f32 / self.fade_len as f32).min(1.0);
+            for (s, &o) in frame.iter_mut().zip(old.iter()) {
+                *s = o + (*s - o) * t;
+            }
+        }
+
+        self.fade_pos += self.block.frames();
+        if self.fade_pos >= self.fade_len {
+            if let Some(old) = self.fading_out.take() {
+                self.retiring = self.pipelines.retire(old).err();
+            }
+        }
+    }
+}
+
+impl Drop for OutputRenderer {
+    fn drop(&mut self) {
+        // The stream is shutting down: keep the running pipeline for the next
+        // start unless a newer one is already waiting.
+        let current = std::mem::replace(
+            &mut self.current,
+            Box::new(Pipeline::Chain(LinearChain::new())),
+        );
+        self.pipelines.put_back(current);
+    }
 }
 
 /// Real-time audio engine: mic input → effect chain → speaker output.
@@ -39,21 +459,52 @@ impl Pipeline {
 /// - cpal input callback (audio thread): writes mic data to `input_ring`
 /// - cpal output callback (audio thread): reads `input_ring`, runs chain, outputs to speaker
 ///   and writes processed audio to `record_ring` when recording
-/// - Writer thread: drains `record_ring` to WAV file
-/// - UI thread: calls `set_chain()`, `start_recording()`, `stop_recording()`
+///
+/// Mic input is downmixed to mono. The output stream is stereo when the device
+/// supports it, so spatial nodes such as HRTF reach the speaker (and the
+/// recording) intact; mono pipeline output is copied to both channels.
+///
+/// Pipelines and recordings run at `PROCESSING_SAMPLE_RATE` whatever the
+/// devices use: mic input is converted on capture and output just before
+/// it reaches the speaker, so each device can stay at its own default rate.
+///
+/// Both audio callbacks work on buffers allocated before the streams start
+/// and never touch the allocator.
+/// - Writer thread: streams `record_ring` into the WAV file
+/// - UI thread: calls `set_chain()`, `set_param()`, `start_recording()`, `stop_recording()`
 pub struct RealtimeEngine {
-    pipeline: Arc<Mutex<Pipeline>>,
-    input_ring: Arc<SpscRingBuffer>,
-    record_ring: Arc<SpscRingBuffer>,
+    /// Pipelines built by `set_chain`/`set_graph`, waiting for the audio thread.
+    pipelines: Arc<Handoff<Pipeline>>,
+    /// Live parameters of the most recently set pipeline. Only the UI thread
+    /// locks this; the audio thread reads the values through atomics.
+    params: Mutex<Vec<(u32, ParamHandle)>>,
+    /// Meter taps of the most recently set pipeline.
+    meters: Mutex<Vec<(u32, Arc<MeterTap>)>>,
+    /// Taps the audio thread publishes input and output levels to.
+    input_meter: Arc<MeterTap>,
+    output_meter: Arc<MeterTap>,
+    input_ring: Arc<SpscRingBuffer<f32>>,
+    record_ring: Arc<SpscRingBuffer<f32>>,
     is_running: Arc<AtomicBool>,
     is_recording: Arc<AtomicBool>,
-    /// Samples successfully processed (only incremented when chain lock acquired)
+    /// Frames processed while recording
     samples_recorded: Arc<AtomicU64>,
+    /// Speaker frames left silent because no input was waiting.
+    output_underruns: Arc<AtomicU64>,
+    latency: Arc<LatencyTaps>,
+    /// Lookahead of the mic's sample rate converter.
+    capture_delay_ms: f32,
+    /// Recording ring overruns when the current take started.
+    record_overruns_at_start: u64,
+    /// Rate pipelines and recordings run at.
     sample_rate: u32,
+    /// Rate of the output device.
+    output_rate: u32,
+    /// Channel count of the output stream and the recording.
+    output_channels: u16,
     _input_stream: Option<Stream>,
     _output_stream: Option<Stream>,
-    writer_handle: Option<thread::JoinHandle<Result<(), String>>>,
-    record_path: Arc<Mutex<Option<String>>>,
+    writer_handle: Option<thread::JoinHandle<Result<(), VozooError>>>,
     /// Last error from audio callbacks or writer thread
     last_error: Arc<Mutex<Option<String>>>,
 }
@@ -61,65 +512,110 @@ pub struct RealtimeEngine {
 impl RealtimeEngine {
     pub fn new() -> Self {
         Self {
-            pipeline: Arc::new(Mutex::new(Pipeline::Chain(LinearChain::new()))),
+            pipelines: Arc::new(Handoff::new()),
+            params: Mutex::new(Vec::new()),
+            meters: Mutex::new(Vec::new()),
+            input_meter: MeterTap::new(),
+            output_meter: MeterTap::new(),
             input_ring: SpscRingBuffer::new(48000 * 2),
             record_ring: SpscRingBuffer::new(48000 * 60),
             is_running: Arc::new(AtomicBool::new(false)),
             is_recording: Arc::new(AtomicBool::new(false)),
             samples_recorded: Arc::new(AtomicU64::new(0)),
-            sample_rate: 48000,
+            output_underruns: Arc::new(AtomicU64::new(0)),
+            latency: Arc::new(LatencyTaps::new()),
+            capture_delay_ms: 0.0,
+            record_overruns_at_start: 0,
+            sample_rate: PROCESSING_SAMPLE_RATE,
+            output_rate: PROCESSING_SAMPLE_RATE,
+            output_channels: 1,
             _input_stream: None,
             _output_stream: None,
             writer_handle: None,
-            record_path: Arc::new(Mutex::new(None)),
             last_error: Arc::new(Mutex::new(None)),
         }
     }
 
-    pub fn set_chain(&self, chain_json: &str) -> Result<(), String> {
-        let chain_def = ChainDef::from_json(chain_json)
-            .map_err(|e| format!("Invalid chain JSON: {e}"))?;
-        let new_chain = chain_def.build()?;
-        let mut pipeline = self.pipeline.lock().map_err(|e| format!("Lock error: {e}"))?;
-        *pipeline = Pipeline::Chain(new_chain);
+    /// Build a chain and switch to it. The chain is built and prepared on
+    /// the calling thread; the audio thread crossfades to it without locking.
+    pub fn set_chain(&self, chain_json: &str) -> Result<(), VozooError> {
+        let mut chain = ChainDef::from_json(chain_json)?.build()?;
+        // A `resample` node changes the rate for the rest of the chain; the
+        // speaker still expects the processing rate.
+        if chain.output_sample_rate(self.sample_rate) != self.sample_rate {
+            chain.add(Box::new(Resample::new(self.sample_rate)));
+        }
+        self.publish(Pipeline::Chain(chain));
         Ok(())
     }
 
-    pub fn set_graph(&self, graph_json: &str) -> Result<(), String> {
-        let graph_def = GraphDef::from_json(graph_json)
-            .map_err(|e| format!("Invalid graph JSON: {e}"))?;
-        let new_graph = graph_def.build()?;
-        let mut pipeline = self.pipeline.lock().map_err(|e| format!("Lock error: {e}"))?;
-        *pipeline = Pipeline::Graph(new_graph);
+    /// Build a graph and switch to it, like `set_chain`.
+    pub fn set_graph(&self, graph_json: &str) -> Result<(), VozooError> {
+        self.publish(Pipeline::Graph(GraphDef::from_json(graph_json)?.build()?));
         Ok(())
     }
 
-    pub fn start(&mut self) -> Result<(), String> {
+    /// Change a parameter of the running pipeline without rebuilding it.
+    ///
+    /// `node` is the node's index for chains and its ID for graphs. The value
+    /// is clamped to the parameter's range and the node glides to it over
+    /// a few milliseconds. Parameters that can't change live (e.g. a
+    /// convolution reverb's room size) return an error; callers fall back
+    /// to `set_chain`.
+    pub fn set_param(&self, node: u32, key: &str, value: f32) -> Result<(), VozooError> {
+        let params = self.params.lock().map_err(|_| VozooError::Engine("Param lock poisoned".into()))?;
+        let (_, handle) = params
+            .iter()
+            .find(|(n, p)| *n == node && p.descriptor.key == key)
+            .ok_or_else(|| VozooError::InvalidParam { node, key: key.to_string() })?;
+        handle.set(value);
+        Ok(())
+    }
+
+    fn publish(&self, mut pipeline: Pipeline) {
+        pipeline.prepare(self.sample_rate, MAX_BLOCK);
+        if let Ok(mut params) = self.params.lock() {
+            *params = pipeline.params();
+        }
+        if let Ok(mut meters) = self.meters.lock() {
+            *meters = pipeline.meters();
+        }
+        let latency = pipeline.latency_samples(self.sample_rate);
+        self.latency.pipeline_ms.store(frames_to_ms(latency, self.sample_rate));
+        self.pipelines.publish(Box::new(pipeline));
+    }
+
+    pub fn start(&mut self) -> Result<(), VozooError> {
         if self.is_running.load(Ordering::Relaxed) {
-            return Err("Engine already running".into());
+            return Err(VozooError::Engine("Engine already running".into()));
         }
 
         let host = cpal::default_host();
 
         let input_device = host.default_input_device()
-            .ok_or("No input device available")?;
+            .ok_or_else(|| VozooError::Device("No input device available".into()))?;
         let input_config = input_device.default_input_config()
-            .map_err(|e| format!("Input config error: {e}"))?;
-
-        self.sample_rate = input_config.sample_rate().0;
+            .map_err(|e| VozooError::Device(format!("Input config error: {e}")))?;
 
         let output_device = host.default_output_device()
-            .ok_or("No output device available")?;
+            .ok_or_else(|| VozooError::Device("No output device available".into()))?;
+
+        let output_default = output_device.default_output_config().ok();
+        self.output_channels = output_default.as_ref().map_or(1, |c| c.channels().min(2)).max(1);
+        self.output_rate = output_default.map_or(self.sample_rate, |c| c.sample_rate().0);
 
         let output_config = cpal::StreamConfig {
-            channels: 1,
-            sample_rate: cpal::SampleRate(self.sample_rate),
+            channels: self.output_channels,
+            sample_rate: cpal::SampleRate(self.output_rate),
             buffer_size: cpal::BufferSize::Default,
         };
 
         let input_channels = input_config.channels() as usize;
-        let input_ring = Arc::clone(&self.input_ring);
+        let input_rate = input_config.sample_rate().0;
+        let mut capture = InputCapture::new(Arc::clone(&self.input_ring), input_channels, input_rate);
+        self.capture_delay_ms = frames_to_ms(capture.to_processing.delay_frames(), input_rate);
         let is_running = Arc::clone(&self.is_running);
+        let input_latency = Arc::clone(&self.latency);
 
         // Input stream: capture mic → input_ring
         let input_stream = match input_config.sample_format() {
@@ -127,19 +623,12 @@ impl RealtimeEngine {
                 let config: cpal::StreamConfig = input_config.into();
                 input_device.build_input_stream(
                     &config,
-                    move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                    move |data: &[f32], info: &cpal::InputCallbackInfo| {
                         if !is_running.load(Ordering::Relaxed) {
                             return;
                         }
-                        if input_channels == 1 {
-                            input_ring.write(data);
-                        } else {
-                            let mono: Vec<f32> = data
-                                .chunks_exact(input_channels)
-                                .map(|frame| frame.iter().sum::<f32>() / input_channels as f32)
-                                .collect();
-                            input_ring.write(&mono);
-                        }
+                        input_latency.record_input(info);
+                        capture.push(data, |s| s);
                     },
                     {
                         let err_state = Arc::clone(&self.last_error);
@@ -150,30 +639,18 @@ impl RealtimeEngine {
                         }
                     },
                     None,
-                ).map_err(|e| format!("Build input stream error: {e}"))?
+                ).map_err(|e| VozooError::Device(format!("Build input stream error: {e}")))?
             }
             SampleFormat::I16 => {
                 let config: cpal::StreamConfig = input_config.into();
-                let input_ring_i16 = Arc::clone(&self.input_ring);
-                let is_running_i16 = Arc::clone(&self.is_running);
                 input_device.build_input_stream(
                     &config,
-                    move |data: &[i16], _: &cpal::InputCallbackInfo| {
-                        if !is_running_i16.load(Ordering::Relaxed) {
+                    move |data: &[i16], info: &cpal::InputCallbackInfo| {
+                        if !is_running.load(Ordering::Relaxed) {
                             return;
                         }
-                        let float_data: Vec<f32> = data.iter()
-                            .map(|s| *s as f32 / 32768.0)
-                            .collect();
-                        if input_channels == 1 {
-                            input_ring_i16.write(&float_data);
-                        } else {
-                            let mono: Vec<f32> = float_data
-                                .chunks_exact(input_channels)
-                                .map(|frame| frame.iter().sum::<f32>() / input_channels as f32)
-                                .collect();
-                            input_ring_i16.write(&mono);
-                        }
+                        input_latency.record_input(info);
+                        capture.push(data, |s| s as f32 / 32768.0);
                     },
                     {
                         let err_state = Arc::clone(&self.last_error);
@@ -184,58 +661,37 @@ impl RealtimeEngine {
                         }
                     },
                     None,
-                ).map_err(|e| format!("Build input stream error: {e}"))?
+                ).map_err(|e| VozooError::Device(format!("Build input stream error: {e}")))?
             }
-            format => return Err(format!("Unsupported input format: {format:?}")),
+            format => return Err(VozooError::Device(format!("Unsupported input format: {format:?}"))),
         };
 
         // Output stream: input_ring → pipeline → speaker (+ record_ring)
-        let chain = Arc::clone(&self.pipeline);
-        let input_ring_out = Arc::clone(&self.input_ring);
-        let record_ring = Arc::clone(&self.record_ring);
         let is_running_out = Arc::clone(&self.is_running);
-        let is_recording = Arc::clone(&self.is_recording);
-        let samples_recorded = Arc::clone(&self.samples_recorded);
-        let sample_rate = self.sample_rate;
+        let output_latency = Arc::clone(&self.latency);
+        // Taken last: from here on the renderer owns the pipeline and puts it
+        // back when dropped, so a start that fails keeps it for the next try.
+        let mut pipeline = self
+            .pipelines
+            .take()
+            .unwrap_or_else(|| Box::new(Pipeline::Chain(LinearChain::new())));
+        pipeline.prepare(self.sample_rate, MAX_BLOCK);
+        let mut renderer = OutputRenderer::new(self, pipeline);
+        let mut warmed = false;
 
         let output_stream = output_device.build_output_stream(
             &output_config,
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+            move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+                if !warmed {
+                    renderer.warm();
+                    warmed = true;
+                }
                 if !is_running_out.load(Ordering::Relaxed) {
                     data.fill(0.0);
                     return;
                 }
-
-                let len = data.len();
-                let read = input_ring_out.read(data);
-
-                if read < len {
-                    data[read..].fill(0.0);
-                }
-
-                if read > 0 {
-                    // Try to process through the chain. On lock failure,
-                    // output silence instead of raw mic audio to prevent feedback.
-                    if let Ok(mut chain) = chain.try_lock() {
-                        let mut buffer = AudioBuffer::new(data[..read].to_vec(), sample_rate);
-                        chain.process(&mut buffer);
-
-                        let copy_len = buffer.samples.len().min(len);
-                        data[..copy_len].copy_from_slice(&buffer.samples[..copy_len]);
-                        if copy_len < len {
-                            data[copy_len..].fill(0.0);
-                        }
-
-                        // Write to recording ring buffer if recording
-                        if is_recording.load(Ordering::Relaxed) {
-                            record_ring.write(&buffer.samples[..copy_len]);
-                            samples_recorded.fetch_add(copy_len as u64, Ordering::Relaxed);
-                        }
-                    } else {
-                        // Lock contention (chain swap in progress) — output silence
-                        data[..read].fill(0.0);
-                    }
-                }
+                output_latency.record_output(info);
+                renderer.render(data);
             },
             {
                 let err_state = Arc::clone(&self.last_error);
@@ -246,10 +702,10 @@ impl RealtimeEngine {
                 }
             },
             None,
-        ).map_err(|e| format!("Build output stream error: {e}"))?;
+        ).map_err(|e| VozooError::Device(format!("Build output stream error: {e}")))?;
 
-        input_stream.play().map_err(|e| format!("Input play error: {e}"))?;
-        output_stream.play().map_err(|e| format!("Output play error: {e}"))?;
+        input_stream.play().map_err(|e| VozooError::Device(format!("Input play error: {e}")))?;
+        output_stream.play().map_err(|e| VozooError::Device(format!("Output play error: {e}")))?;
 
         self.is_running.store(true, Ordering::Release);
         self._input_stream = Some(input_stream);
@@ -264,48 +720,57 @@ impl RealtimeEngine {
         self.stop_recording_internal();
         self._input_stream = None;
         self._output_stream = None;
+        self.pipelines.collect();
     }
 
-    pub fn start_recording(&mut self, output_path: &str) -> Result<(), String> {
+    pub fn start_recording(&mut self, output_path: &str) -> Result<(), VozooError> {
         if !self.is_running.load(Ordering::Relaxed) {
-            return Err("Engine not running".into());
+            return Err(VozooError::Engine("Engine not running".into()));
         }
         if self.is_recording.load(Ordering::Relaxed) {
-            return Err("Already recording".into());
+            return Err(VozooError::Engine("Already recording".into()));
         }
 
-        self.record_ring.drain();
+        self.record_ring.clear();
+        self.record_overruns_at_start = self.record_ring.overruns();
 
-        *self.record_path.lock().map_err(|e| format!("{e}"))? = Some(output_path.to_string());
+        let channels = self.output_channels;
+        let mut writer = WavWriter::create(output_path, channels, self.sample_rate, &WavWriteOptions::default())
+            .map_err(|source| VozooError::Write { path: output_path.to_string(), source })?;
         self.samples_recorded.store(0, Ordering::Relaxed);
         self.is_recording.store(true, Ordering::Release);
 
         let record_ring = Arc::clone(&self.record_ring);
         let is_recording = Arc::clone(&self.is_recording);
-        let record_path = Arc::clone(&self.record_path);
-        let sample_rate = self.sample_rate;
+        let sync_interval = self.sample_rate as u64 * RECORD_SYNC_SECS;
+        let wake_samples = self.sample_rate as usize * RECORD_WAKE_MS / 1000 * channels as usize;
 
-        let handle = thread::spawn(move || -> Result<(), String> {
-            let mut all_samples: Vec<f32> = Vec::new();
-            let mut read_buf = vec![0.0f32; 4800];
+        let path = output_path.to_string();
+        let handle = thread::spawn(move || -> Result<(), VozooError> {
+            let write_err = |source| VozooError::Write { path: path.clone(), source };
+            // Write straight out of the ring; the audio thread only ever
+            // pushes whole blocks, so channels stay aligned.
+            let drain = |writer: &mut WavWriter<BufWriter<File>>| -> std::io::Result<()> {
+                let mut pending = record_ring.read_slices();
+                let (first, second) = pending.slices();
+                writer.write_samples(first)?;
+                writer.write_samples(second)?;
+                pending.consume_all();
+                Ok(())
+            };
+            let mut synced_frames = 0;
 
             while is_recording.load(Ordering::Acquire) {
-                let read = record_ring.read(&mut read_buf);
-                if read > 0 {
-                    all_samples.extend_from_slice(&read_buf[..read]);
-                } else {
-                    thread::sleep(std::time::Duration::from_millis(50));
+                record_ring.wait_available(wake_samples, Duration::from_millis(RECORD_WAKE_MS as u64 * 2));
+                drain(&mut writer).map_err(write_err)?;
+                if writer.frames_written() - synced_frames >= sync_interval {
+                    writer.flush().map_err(write_err)?;
+                    synced_frames = writer.frames_written();
                 }
             }
 
-            let remaining = record_ring.drain();
-            all_samples.extend_from_slice(&remaining);
-
-            let path_lock = record_path.lock().map_err(|e| format!("Lock error: {e}"))?;
-            let path = path_lock.as_ref().ok_or("No recording path set")?;
-            let buffer = AudioBuffer::new(all_samples, sample_rate);
-            vozoo_core::write_wav(path, &buffer)
-                .map_err(|e| format!("Failed to write recording: {e}"))
+            drain(&mut writer).map_err(write_err)?;
+            writer.finalize().map_err(write_err)
         });
 
         self.writer_handle = Some(handle);
@@ -329,7 +794,7 @@ impl RealtimeEngine {
             match handle.join() {
                 Ok(Err(e)) => {
                     if let Ok(mut err) = self.last_error.lock() {
-                        *err = Some(e);
+                        *err = Some(e.to_string());
                     }
                 }
                 Err(_) => {
@@ -337,7 +802,14 @@ impl RealtimeEngine {
                         *err = Some("Writer thread panicked".into());
                     }
                 }
-                Ok(Ok(())) => {}
+                Ok(Ok(())) => {
+                    let dropped = self.record_ring.overruns() - self.record_overruns_at_start;
+                    if dropped > 0 {
+                        if let Ok(mut err) = self.last_error.lock() {
+                            *err = Some(format!("Recording dropped {dropped} samples: the writer fell behind"));
+                        }
+                    }
+                }
             }
         }
 
@@ -366,18 +838,466 @@ impl RealtimeEngine {
         self.is_recording.load(Ordering::Relaxed)
     }
 
+    /// Rate pipelines and recordings run at, independent of the devices.
     pub fn sample_rate(&self) -> u32 {
         self.sample_rate
     }
 
+    /// Channel count of the output stream (and of recordings).
+    pub fn output_channels(&self) -> u16 {
+        self.output_channels
+    }
+
+    /// Glitches since the engine was created.
+    pub fn xruns(&self) -> Xruns {
+        Xruns {
+            input_overruns: self.input_ring.overruns(),
+            output_underruns: self.output_underruns.load(Ordering::Relaxed),
+            dropped_recording_samples: self.record_ring.overruns(),
+        }
+    }
+
+    /// Current input, output and per-node meter readings. Lock-free with
+    /// respect to the audio thread.
+    pub fn meters(&self) -> EngineMeters {
+        let nodes = self
+            .meters
+            .lock()
+            .map(|meters| meters.iter().map(|(node, tap)| (*node, tap.snapshot())).collect())
+            .unwrap_or_default();
+        EngineMeters {
+            input: self.input_meter.snapshot(),
+            output: self.output_meter.snapshot(),
+            nodes,
+        }
+    }
+
+    /// Current delay from the mic to the speaker. The device figures are
+    /// only known once the streams are running.
+    pub fn latency(&self) -> EngineLatency {
+        EngineLatency {
+            input_device_ms: self.latency.input_device_ms.load(),
+            buffering_ms: self.capture_delay_ms + self.latency.queued_ms.load(),
+            pipeline_ms: self.latency.pipeline_ms.load(),
+            output_device_ms: self.latency.output_device_ms.load(),
+        }
+    }
+
     /// Take the last error, if any. Returns None if no error occurred.
     pub fn take_last_error(&self) -> Option<String> {
         self.last_error.lock().ok().and_then(|mut e| e.take())
     }
 }
 
+impl Default for RealtimeEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Drop for RealtimeEngine {
     fn drop(&mut self) {
         self.stop();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// Counts allocator calls made by the current thread while enabled.
+    struct CountingAllocator;
+
+    thread_local! {
+        static COUNTING: Cell<bool> = const { Cell::new(false) };
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn record_allocation() {
+        let _ = COUNTING.try_with(|counting| {
+            if counting.get() {
+                let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+            }
+        });
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            record_allocation();
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            record_allocation();
+            unsafe { System.alloc_zeroed(layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            record_allocation();
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            record_allocation();
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// Number of allocator calls (including frees) made by `f`.
+    fn count_allocations(f: impl FnOnce()) -> usize {
+        ALLOCATIONS.with(|a| a.set(0));
+        COUNTING.with(|c| c.set(true));
+        f();
+        COUNTING.with(|c| c.set(false));
+        ALLOCATIONS.with(|a| a.get())
+    }
+
+    /// Engine with a stereo output that is recording, without any streams.
+    fn test_engine(output_channels: u16) -> RealtimeEngine {
+        let mut engine = RealtimeEngine::new();
+        engine.output_channels = output_channels;
+        engine.is_recording.store(true, Ordering::Relaxed);
+        engine
+    }
+
+    fn renderer(engine: &RealtimeEngine, mut pipeline: Pipeline) -> OutputRenderer {
+        pipeline.prepare(engine.sample_rate, MAX_BLOCK);
+        let mut renderer = OutputRenderer::new(engine, Box::new(pipeline));
+        renderer.warm();
+        renderer
+    }
+
+    /// Feed mic audio and render callbacks of several sizes, including one
+    /// larger than `MAX_BLOCK`. Returns the allocator calls made.
+    fn render_callbacks(renderer: &mut OutputRenderer) -> usize {
+        let mic: Vec<f32> = (0..4096)
+            .map(|i| (i as f32 / 48000.0 * 440.0 * std::f32::consts::TAU).sin() * 0.5)
+            .collect();
+        let channels = renderer.output_layout.channels() as usize;
+        let mut data = vec![0.0f32; 1500 * channels];
+        let mut total = 0;
+
+        for frames in [128, 480, 1500, 441] {
+            renderer.input_ring.write(&mic[..frames]);
+            let out = &mut data[..frames * channels];
+            total += count_allocations(|| renderer.render(out));
+        }
+        total
+    }
+
+    fn all_nodes_chain() -> LinearChain {
+        // Every node, with non-default settings for nodes that are a no-op by default.
+        let nodes: Vec<String> = vozoo_nodes::available_nodes()
+            .iter()
+            .map(|info| {
+                let params = match info.node_type.as_str() {
+                    "pitch_shift" => r#"{"semitones": 5.0, "quality": 1, "preserve_formants": 1}"#,
+                    "formant_shift" => r#"{"shift_factor": 1.3}"#,
+                    "pitch_shift_resample" => r#"{"factor": 0.8}"#,
+                    "time_stretch" => r#"{"rate": 0.8}"#,
+                    "loudness_norm" => r#"{"ceiling_dbtp": -6.0}"#,
+                    _ => "{}",
+                };
+                format!(r#"{{"type": "{}", "params": {params}}}"#, info.node_type)
+            })
+            .collect();
+        let json = format!(r#"{{"name": "all", "nodes": [{}]}}"#, nodes.join(","));
+        ChainDef::from_json(&json).unwrap().build().unwrap()
+    }
+
+    #[test]
+    fn test_chain_render_does_not_allocate() {
+        let engine = test_engine(2);
+        let mut renderer = renderer(&engine, Pipeline::Chain(all_nodes_chain()));
+        assert_eq!(render_callbacks(&mut renderer), 0);
+        assert!(renderer.samples_recorded.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn test_graph_render_does_not_allocate() {
+        for def in vozoo_nodes::preset_graph_defs() {
+            let engine = test_engine(2);
+            let mut renderer = renderer(&engine, Pipeline::Graph(def.build().unwrap()));
+            assert_eq!(render_callbacks(&mut renderer), 0, "graph '{}' allocated", def.name);
+        }
+    }
+
+    #[test]
+    fn test_set_chain_crossfades_without_allocating() {
+        let engine = test_engine(1);
+        let muted = ChainDef::from_json(r#"{"name":"mute","nodes":[{"type":"gain","params":{"factor":0.0}}]}"#)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut renderer = renderer(&engine, Pipeline::Chain(muted));
+
+        engine
+            .set_chain(r#"{"name":"unity","nodes":[{"type":"gain","params":{"factor":1.0}}]}"#)
+            .unwrap();
+
+        // Constant input: the output ramps from the muted chain to the new one.
+        let fade_len = (48000 * CROSSFADE_MS / 1000) as usize;
+        let mut output = Vec::new();
+        let mut data = vec![0.0f32; 256];
+        let mut allocations = 0;
+        while output.len() < fade_len * 2 {
+            engine.input_ring.write(&[0.5; 256]);
+            allocations += count_allocations(|| renderer.render(&mut data));
+            output.extend_from_slice(&data);
+        }
+
+        assert_eq!(allocations, 0, "pipeline switch must not allocate or free");
+        assert!(output[0].abs() < 1e-6, "fade starts at the old pipeline");
+        for pair in output.windows(2) {
+            assert!(pair[1] >= pair[0] && pair[1] - pair[0] < 0.01, "fade must be smooth");
+        }
+        assert!(output[fade_len..].iter().all(|&s| (s - 0.5).abs() < 1e-6));
+
+        // The muted chain was handed back rather than dropped on the audio thread.
+        assert!(renderer.fading_out.is_none() && renderer.retiring.is_none());
+        assert!(engine.pipelines.take().is_none());
+    }
+
+    #[test]
+    fn test_noise_reduction_switched_in_does_not_allocate() {
+        // The renderer starts without noise reduction; warming must still
+        // cover it once a chain with it is published.
+        let engine = test_engine(2);
+        let unity = ChainDef::from_json(r#"{"name":"unity","nodes":[{"type":"gain","params":{"factor":1.0}}]}"#)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut renderer = renderer(&engine, Pipeline::Chain(unity));
+        assert_eq!(render_callbacks(&mut renderer), 0);
+
+        engine.set_chain(r#"{"name":"nr","nodes":[{"type":"noise_reduction"}]}"#).unwrap();
+        assert_eq!(render_callbacks(&mut renderer), 0);
+        assert_eq!(render_callbacks(&mut renderer), 0);
+        // The denoiser is running, not still fading in.
+        assert!(renderer.fading_out.is_none() && renderer.current.latency_samples(48000) > 0);
+    }
+
+    #[test]
+    fn test_set_param_glides_without_allocating() {
+        let engine = test_engine(1);
+        engine
+            .set_chain(r#"{"name":"unity","nodes":[{"type":"gain","params":{"factor":1.0}}]}"#)
+            .unwrap();
+        let mut renderer = OutputRenderer::new(&engine, engine.pipelines.take().unwrap());
+
+        engine.set_param(0, "factor", 0.0).unwrap();
+        assert!(engine.set_param(0, "freq", 1.0).is_err());
+        assert!(engine.set_param(1, "factor", 1.0).is_err());
+
+        let mut output = Vec::new();
+        let mut data = vec![0.0f32; 256];
+        let mut allocations = 0;
+        for _ in 0..40 {
+            engine.input_ring.write(&[0.5; 256]);
+            allocations += count_allocations(|| renderer.render(&mut data));
+            output.extend_from_slice(&data);
+        }
+
+        assert_eq!(allocations, 0, "parameter changes must not allocate");
+        assert!(output[0] > 0.49, "no jump at the start of the change");
+        for pair in output.windows(2) {
+            assert!(pair[1] <= pair[0] && pair[0] - pair[1] < 0.005, "change must be smooth");
+        }
+        assert_eq!(*output.last().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_graph_latency_change_does_not_allocate() {
+        // A pitch shifter built at 0 semitones has no latency until moved;
+        // moving it re-plans the dry path's delay on the audio thread.
+        let engine = test_engine(2);
+        engine
+            .set_graph(
+                r#"{"name":"split","nodes":[{"id":0,"type":"input"},{"id":1,"type":"pitch_shift"},{"id":2,"type":"mix"},{"id":3,"type":"output"}],
+                "edges":[{"from":0,"to":1},{"from":1,"to":2},{"from":0,"to":2},{"from":2,"to":3}]}"#,
+            )
+            .unwrap();
+        let mut renderer = OutputRenderer::new(&engine, engine.pipelines.take().unwrap());
+        renderer.warm();
+        assert_eq!(render_callbacks(&mut renderer), 0);
+
+        engine.set_param(1, "semitones", 4.0).unwrap();
+        assert_eq!(render_callbacks(&mut renderer), 0);
+        assert!(renderer.current.latency_samples(48000) > 0);
+    }
+
+    #[test]
+    fn test_xruns_are_counted() {
+        let engine = test_engine(2);
+        let mut renderer = renderer(&engine, Pipeline::Chain(LinearChain::new()));
+        let mut data = vec![0.0f32; 256 * 2];
+
+        // No mic input yet: the whole callback is silence.
+        renderer.render(&mut data);
+        assert_eq!(engine.xruns().output_underruns, 256);
+
+        // A full recording ring drops the block whole rather than splitting a frame.
+        engine.record_ring.write(&vec![0.0; engine.record_ring.free_space() - 1]);
+        engine.input_ring.write(&[0.5; 256]);
+        renderer.render(&mut data);
+        assert_eq!(engine.xruns().dropped_recording_samples, 512);
+        assert_eq!(engine.record_ring.free_space(), 1);
+
+        let capacity = engine.input_ring.capacity();
+        engine.input_ring.write(&vec![0.5; capacity + 100]);
+        assert_eq!(
+            engine.xruns(),
+            Xruns { input_overruns: 100, output_underruns: 256, dropped_recording_samples: 512 }
+        );
+    }
+
+    #[test]
+    fn test_meters_report_without_allocating() {
+        let engine = test_engine(2);
+        engine
+            .set_chain(r#"{"name":"quiet","nodes":[{"type":"meter"},{"type":"gain","params":{"factor":0.5}}]}"#)
+            .unwrap();
+        let mut renderer = OutputRenderer::new(&engine, engine.pipelines.take().unwrap());
+        assert_eq!(engine.meters().nodes.len(), 1);
+
+        let mic: Vec<f32> = (0..512)
+            .map(|i| (i as f32 / 48000.0 * 1000.0 * std::f32::consts::TAU).sin() * 0.5)
+            .collect();
+        let mut data = vec![0.0f32; 512 * 2];
+        let mut allocations = 0;
+        for _ in 0..50 {
+            engine.input_ring.write(&mic);
+            allocations += count_allocations(|| renderer.render(&mut data));
+        }
+        assert_eq!(allocations, 0, "metering must not allocate");
+
+        let meters = engine.meters();
+        assert!((meters.input.peak_db + 6.0).abs() < 0.1, "{:?}", meters.input);
+        assert!((meters.output.peak_db + 12.0).abs() < 0.1, "{:?}", meters.output);
+        assert_eq!(meters.nodes[0].0, 0);
+        assert!((meters.nodes[0].1.peak_db - meters.input.peak_db).abs() < 1e-3);
+        // Half the level, but on two channels: BS.1770 sums their loudness.
+        let difference = meters.output.momentary_lufs - meters.input.momentary_lufs;
+        assert!((difference + 6.02 - 3.01).abs() < 0.2, "{difference}");
+    }
+
+    #[test]
+    fn test_latency_includes_pipeline_and_queued_input() {
+        let engine = test_engine(2);
+        assert_eq!(engine.latency(), EngineLatency::default());
+
+        engine
+            .set_chain(r#"{"name":"limited","nodes":[{"type":"lookahead_limiter","params":{"ceiling_db":-1.0}}]}"#)
+            .unwrap();
+        let pipeline_ms = (240 + 7) as f32 / 48.0;
+        assert!((engine.latency().pipeline_ms - pipeline_ms).abs() < 1e-3);
+
+        // Half of what the mic delivered is still waiting after a callback.
+        let mut renderer = OutputRenderer::new(&engine, engine.pipelines.take().unwrap());
+        engine.input_ring.write(&[0.25; 960]);
+        let mut data = vec![0.0f32; 480 * 2];
+        renderer.render(&mut data);
+
+        let latency = engine.latency();
+        assert!((latency.buffering_ms - 10.0).abs() < 1e-3, "{latency:?}");
+        assert!((latency.round_trip_ms() - 10.0 - pipeline_ms).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_input_capture_does_not_allocate() {
+        let ring = SpscRingBuffer::new(48000);
+        let mut capture = InputCapture::new(Arc::clone(&ring), 2, 48000);
+        let stereo = vec![0.25f32; 3000 * 2];
+        let allocations = count_allocations(|| capture.push(&stereo, |s| s));
+        assert_eq!(allocations, 0);
+        assert_eq!(ring.available(), 3000);
+    }
+
+    #[test]
+    fn test_device_rate_conversion_does_not_allocate() {
+        let mut engine = test_engine(2);
+        engine.output_rate = 44100;
+        let mut renderer = renderer(&engine, Pipeline::Chain(all_nodes_chain()));
+        let mut capture = InputCapture::new(Arc::clone(&engine.input_ring), 1, 44100);
+
+        let mic: Vec<f32> = (0..1500)
+            .map(|i| (i as f32 / 44100.0 * 440.0 * std::f32::consts::TAU).sin() * 0.5)
+            .collect();
+        let mut data = vec![0.0f32; 1500 * 2];
+        let mut allocations = 0;
+        for frames in [128, 441, 1500, 512, 1500] {
+            let out = &mut data[..frames * 2];
+            allocations += count_allocations(|| {
+                capture.push(&mic[..frames], |s| s);
+                renderer.render(out);
+            });
+        }
+        assert_eq!(allocations, 0);
+        assert!(renderer.samples_recorded.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn test_output_is_converted_to_device_rate() {
+        let mut engine = test_engine(1);
+        engine.output_rate = 44100;
+        let mut renderer = renderer(&engine, Pipeline::Chain(LinearChain::new()));
+
+        // One second at the processing rate comes out as one second at the
+        // device rate, apart from the converter's lookahead.
+        engine.input_ring.write(&[0.5; 48000]);
+        let mut output = vec![0.0f32; 44100];
+        renderer.render(&mut output);
+        let lookahead = 40;
+        assert!(output[lookahead..44100 - lookahead].iter().all(|s| (s - 0.5).abs() < 0.01));
+        // Recordings keep the processing rate.
+        let consumed = 48000 - engine.input_ring.available();
+        assert_eq!(renderer.samples_recorded.load(Ordering::Relaxed) as usize, consumed);
+    }
+
+    #[test]
+    fn test_time_stretch_consumes_input_at_its_rate() {
+        let engine = test_engine(1);
+        engine
+            .set_chain(r#"{"name":"slow","nodes":[{"type":"time_stretch","params":{"rate":0.5}}]}"#)
+            .unwrap();
+        let mut renderer = OutputRenderer::new(&engine, engine.pipelines.take().unwrap());
+
+        let mut data = vec![0.0f32; 480];
+        let mut allocations = 0;
+        for _ in 0..50 {
+            engine.input_ring.write(&[0.5; 480]);
+            allocations += count_allocations(|| renderer.render(&mut data));
+        }
+        assert_eq!(allocations, 0);
+        assert_eq!(engine.xruns().output_underruns, 0);
+        // Every callback plays 480 frames made from about 240 input frames,
+        // so the rest of the mic input waits. The first callbacks run ahead
+        // while the stretcher fills its window.
+        let consumed = 50 * 480 - engine.input_ring.available();
+        assert!(consumed.abs_diff(50 * 240) <= 2 * 480, "consumed {consumed}");
+    }
+
+    #[test]
+    fn test_resample_node_returns_to_processing_rate() {
+        let engine = test_engine(1);
+        engine
+            .set_chain(r#"{"name":"phone","nodes":[{"type":"resample","params":{"sample_rate":8000}}]}"#)
+            .unwrap();
+        let mut renderer = OutputRenderer::new(&engine, engine.pipelines.take().unwrap());
+
+        let mut data = vec![0.0f32; 480];
+        let mut output = Vec::new();
+        for _ in 0..10 {
+            engine.input_ring.write(&[0.5; 480]);
+            renderer.render(&mut data);
+            output.extend_from_slice(&data);
+        }
+        assert_eq!(renderer.block.sample_rate(), PROCESSING_SAMPLE_RATE);
+        assert!(output[1000..].iter().all(|s| (s - 0.5).abs() < 0.01));
+    }
+}
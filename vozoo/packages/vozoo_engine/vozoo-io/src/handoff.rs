@@ -0,0 +1,155 @@
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// Retired values the audio thread can hand back before a control thread
+/// collects them. Each publish collects first, so two would be enough.
+const RETIRED_SLOTS: usize = 4;
+
+/// Wait-free handoff of boxed values from a control thread to the audio thread.
+///
+/// The control thread `publish`es a fully built value; the audio thread
+/// `take`s it with a single atomic swap. Values the audio thread is done with
+/// go back through `retire` and are dropped by the next `collect` on a
+/// control thread, so the audio thread never frees memory.
+pub(crate) struct Handoff<T> {
+    pending: AtomicPtr<T>,
+    retired: [AtomicPtr<T>; RETIRED_SLOTS],
+}
+
+impl<T> Handoff<T> {
+    pub fn new() -> Self {
+        Self {
+            pending: AtomicPtr::new(ptr::null_mut()),
+            retired: std::array::from_fn(|_| AtomicPtr::new(ptr::null_mut())),
+        }
+    }
+
+    /// Control thread: make `value` the next one the audio thread picks up.
+    /// A previously published value that was never taken is dropped here.
+    pub fn publish(&self, value: Box<T>) {
+        self.collect();
+        let old = self.pending.swap(Box::into_raw(value), Ordering::AcqRel);
+        if !old.is_null() {
+            // Safety: the pointer came from `Box::into_raw` and the swap made
+            // this thread its only owner.
+            drop(unsafe { Box::from_raw(old) });
+        }
+    }
+
+    /// Audio thread: take the most recently published value, if any.
+    pub fn take(&self) -> Option<Box<T>> {
+        let value = self.pending.swap(ptr::null_mut(), Ordering::AcqRel);
+        // Safety: as in `publish`, the swap transfers sole ownership.
+        (!value.is_null()).then(|| unsafe { Box::from_raw(value) })
+    }
+
+    /// Return a value to the pending slot unless a newer one is waiting, in
+    /// which case it is dropped. Used when the audio stream shuts down.
+    pub fn put_back(&self, value: Box<T>) {
+        let raw = Box::into_raw(value);
+        if self
+            .pending
+            .compare_exchange(ptr::null_mut(), raw, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // Safety: the exchange failed, so `raw` is still owned here.
+            drop(unsafe { Box::from_raw(raw) });
+        }
+    }
+
+    /// Audio thread: hand `value` back to be dropped on a control thread.
+    /// Gives it back if every slot is still occupied.
+    pub fn retire(&self, value: Box<T>) -> Result<(), Box<T>> {
+        let raw = Box::into_raw(value);
+        for slot in &self.retired {
+            if slot
+                .compare_exchange(ptr::null_mut(), raw, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+        // Safety: no slot accepted the pointer, so it is still owned here.
+        Err(unsafe { Box::from_raw(raw) })
+    }
+
+    /// Control thread: drop every retired value.
+    pub fn collect(&self) {
+        for slot in &self.retired {
+            let value = slot.swap(ptr::null_mut(), Ordering::AcqRel);
+            if !value.is_null() {
+                // Safety: the swap transferred sole ownership.
+                drop(unsafe { Box::from_raw(value) });
+            }
+        }
+    }
+}
+
+impl<T> Drop for Handoff<T> {
+    fn drop(&mut self) {
+        self.collect();
+        drop(self.take());
+    }
+}
+
+// Safety: values only ever move between threads as whole boxes through
+// atomic swaps, so sharing the handoff is sound whenever `T` can be sent.
+unsafe impl<T: Send> Send for Handoff<T> {}
+unsafe impl<T: Send> Sync for Handoff<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_take_returns_latest_publish() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let handoff = Handoff::new();
+        handoff.publish(Box::new((1, DropCounter(Arc::clone(&drops)))));
+        handoff.publish(Box::new((2, DropCounter(Arc::clone(&drops)))));
+
+        // The value that was never taken is dropped by the second publish.
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        assert_eq!(handoff.take().unwrap().0, 2);
+        assert!(handoff.take().is_none());
+    }
+
+    #[test]
+    fn test_retired_values_drop_on_collect() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let handoff = Handoff::new();
+
+        for _ in 0..RETIRED_SLOTS {
+            assert!(handoff.retire(Box::new(DropCounter(Arc::clone(&drops)))).is_ok());
+        }
+        let rejected = handoff.retire(Box::new(DropCounter(Arc::clone(&drops))));
+        assert!(rejected.is_err(), "retire must hand the value back when slots are full");
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+
+        handoff.collect();
+        assert_eq!(drops.load(Ordering::Relaxed), RETIRED_SLOTS);
+        drop(rejected);
+        assert_eq!(drops.load(Ordering::Relaxed), RETIRED_SLOTS + 1);
+    }
+
+    #[test]
+    fn test_put_back_yields_to_newer_value() {
+        let handoff = Handoff::new();
+        handoff.put_back(Box::new(1));
+        assert_eq!(*handoff.take().unwrap(), 1);
+
+        handoff.publish(Box::new(2));
+        handoff.put_back(Box::new(1));
+        assert_eq!(*handoff.take().unwrap(), 2);
+    }
+}
@@ -1,3 +1,4 @@
 mod engine;
+mod handoff;
 
-pub use engine::RealtimeEngine;
+pub use engine::{EngineLatency, EngineMeters, RealtimeEngine, Xruns};
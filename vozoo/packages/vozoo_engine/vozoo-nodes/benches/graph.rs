@@ -0,0 +1,176 @@
+use std::time::{Duration, Instant};
+
+use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_nodes::effects::biquad::{BiquadFilter, FilterType};
+use vozoo_nodes::effects::chorus::Chorus;
+use vozoo_nodes::effects::gain::Gain;
+use vozoo_nodes::effects::pitch_shift::PitchShift;
+use vozoo_nodes::effects::ring_mod::RingMod;
+use vozoo_nodes::graph::{AudioGraph, MixNode, PassThrough};
+use vozoo_nodes::WorkerPool;
+
+const BLOCK: usize = 256;
+const BLOCKS: usize = 2000;
+
+/// The graph as it ran before it was compiled: every node owns its output
+/// buffer and scans the whole edge list for its inputs.
+struct ReferenceGraph {
+    slots: Vec<(Box<dyn AudioNode>, AudioBuffer)>,
+    edges: Vec<(usize, usize, f32)>,
+    order: Vec<usize>,
+    input: usize,
+    output: usize,
+}
+
+impl ReferenceGraph {
+    fn new(nodes: Vec<Box<dyn AudioNode>>, edges: &[(u32, u32, f32)]) -> Self {
+        let edges: Vec<(usize, usize, f32)> = edges.iter().map(|&(f, t, g)| (f as usize, t as usize, g)).collect();
+        let mut in_degree = vec![0; nodes.len()];
+        for &(_, to, _) in &edges {
+            in_degree[to] += 1;
+        }
+        let mut order: Vec<usize> = (0..nodes.len()).filter(|&n| in_degree[n] == 0).collect();
+        let mut next = 0;
+        while next < order.len() {
+            let node = order[next];
+            next += 1;
+            for &(from, to, _) in &edges {
+                if from == node {
+                    in_degree[to] -= 1;
+                    if in_degree[to] == 0 {
+                        order.push(to);
+                    }
+                }
+            }
+        }
+        let output = nodes.len() - 1;
+        let slots = nodes.into_iter().map(|node| (node, AudioBuffer::empty(48000))).collect();
+        Self { slots, edges, order, input: 0, output }
+    }
+
+    fn prepare(&mut self) {
+        for (node, buffer) in &mut self.slots {
+            node.prepare(48000, BLOCK);
+            buffer.samples.reserve(BLOCK * 2);
+        }
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        let frames = buffer.frames();
+        for &idx in &self.order {
+            let storage = std::mem::take(&mut self.slots[idx].1.samples);
+            let mut node_buffer = AudioBuffer::with_channels(storage, 1, buffer.sample_rate());
+            let fed = self.edges.iter().any(|e| e.1 == idx);
+            if idx == self.input && !fed {
+                node_buffer.copy_from(buffer);
+            } else {
+                let ch = self.edges.iter().filter(|e| e.1 == idx).map(|e| self.slots[e.0].1.channels()).max();
+                let ch = ch.unwrap_or(1);
+                node_buffer.samples.clear();
+                node_buffer.reshape(frames, ch);
+                for &(from, _, gain) in self.edges.iter().filter(|e| e.1 == idx) {
+                    let src = &self.slots[from].1;
+                    let src_ch = src.channels() as usize;
+                    for (dst, frame) in node_buffer.samples.chunks_exact_mut(ch as usize).zip(src.samples.chunks_exact(src_ch)) {
+                        for (c, d) in dst.iter_mut().enumerate() {
+                            *d += frame[c % src_ch] * gain;
+                        }
+                    }
+                }
+            }
+            let (node, slot_buffer) = &mut self.slots[idx];
+            if let Some(layout) = node.input_layout() {
+                node_buffer.conform(layout);
+            }
+            node.process(&mut node_buffer);
+            let ch = node_buffer.channels() as usize;
+            node_buffer.samples.resize(frames * ch, 0.0);
+            *slot_buffer = node_buffer;
+        }
+        buffer.copy_from(&self.slots[self.output].1);
+    }
+}
+
+/// Nodes by ID, and edges between them.
+type Topology = (Vec<Box<dyn AudioNode>>, Vec<(u32, u32, f32)>);
+
+/// input → a few heavy independent branches → mix → output.
+fn wide() -> Topology {
+    let nodes: Vec<Box<dyn AudioNode>> = vec![
+        Box::new(PassThrough),
+        Box::new(PitchShift::new(4.0)),
+        Box::new(PitchShift::new(-5.0)),
+        Box::new(Chorus::new(25.0, 6.0, 1.2, 0.6)),
+        Box::new(RingMod::new(50.0, 8.0)),
+        Box::new(BiquadFilter::new(FilterType::LowPass, 800.0, 0.7)),
+        Box::new(PitchShift::new(7.0)),
+        Box::new(MixNode),
+        Box::new(PassThrough),
+    ];
+    let mut edges: Vec<(u32, u32, f32)> = (1..=6).flat_map(|id| [(0, id, 1.0), (id, 7, 1.0 / 6.0)]).collect();
+    edges.push((7, 8, 1.0));
+    (nodes, edges)
+}
+
+/// A long chain of cheap nodes, each also tapped into a final mix, so
+/// routing rather than DSP dominates.
+fn deep() -> Topology {
+    const LENGTH: u32 = 128;
+    let mut nodes: Vec<Box<dyn AudioNode>> = vec![Box::new(PassThrough)];
+    nodes.extend((0..LENGTH).map(|_| Box::new(Gain::new(0.99)) as Box<dyn AudioNode>));
+    nodes.push(Box::new(MixNode));
+    nodes.push(Box::new(PassThrough));
+    let mix = LENGTH + 1;
+    let mut edges: Vec<(u32, u32, f32)> = (0..LENGTH).map(|id| (id, id + 1, 1.0)).collect();
+    edges.extend((1..=LENGTH).map(|id| (id, mix, 1.0 / LENGTH as f32)));
+    edges.push((mix, mix + 1, 1.0));
+    (nodes, edges)
+}
+
+fn compiled(nodes: Vec<Box<dyn AudioNode>>, edges: &[(u32, u32, f32)]) -> AudioGraph {
+    let output = nodes.len() as u32 - 1;
+    let slots = nodes.into_iter().enumerate().map(|(id, node)| (id as u32, node)).collect();
+    let mut graph = AudioGraph::new(slots, edges.to_vec(), 0, output).unwrap();
+    graph.prepare(48000, BLOCK);
+    graph
+}
+
+/// Time per block of running `BLOCKS` blocks of a sine through `process`.
+fn time(mut process: impl FnMut(&mut AudioBuffer)) -> Duration {
+    let input: Vec<f32> = (0..BLOCK).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+    let mut buffer = AudioBuffer::new(input.clone(), 48000);
+    let start = Instant::now();
+    for _ in 0..BLOCKS {
+        buffer.samples.clear();
+        buffer.samples.extend_from_slice(&input);
+        buffer.reshape(BLOCK, 1);
+        process(&mut buffer);
+    }
+    start.elapsed() / BLOCKS as u32
+}
+
+fn main() {
+    // One pool for the whole run, as `process_file_as` keeps one per file.
+    let workers = WorkerPool::for_available_cores();
+    println!(
+        "{BLOCKS} blocks of {BLOCK} frames, time per block ({} pool threads + caller for parallel)",
+        workers.threads()
+    );
+
+    for (name, build) in [("wide", wide as fn() -> Topology), ("deep", deep)] {
+        let (nodes, edges) = build();
+        let mut reference = ReferenceGraph::new(nodes, &edges);
+        reference.prepare();
+        let reference = time(|block| reference.process(block));
+
+        let (nodes, edges) = build();
+        let mut graph = compiled(nodes, &edges);
+        let serial = time(|block| graph.process(block));
+
+        let (nodes, edges) = build();
+        let mut graph = compiled(nodes, &edges);
+        let parallel = time(|block| graph.process_parallel(block, &workers));
+
+        println!("{name:>5}: reference {reference:>10.2?}  compiled {serial:>10.2?}  parallel {parallel:>10.2?}");
+    }
+}
@@ -1,6 +1,18 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use std::sync::Arc;
+
+use vozoo_core::{AudioBuffer, AudioNode, ChannelLayout, MeterTap, ParamHandle};
+
+use crate::effects::resample::NativeRate;
 
 /// Linear effect chain: processes nodes sequentially.
+///
+/// Before each node the buffer is conformed to the node's declared input
+/// layout, so mono-only effects can follow stereo sources or spatial nodes.
+///
+/// Nodes run at the rate of the block they receive, which a `resample`
+/// node changes for everything after it. Nodes tied to one rate are wrapped
+/// in `NativeRate` when added.
+#[derive(Default)]
 pub struct LinearChain {
     nodes: Vec<Box<dyn AudioNode>>,
 }
@@ -11,11 +23,28 @@ impl LinearChain {
     }
 
     pub fn add(&mut self, node: Box<dyn AudioNode>) {
-        self.nodes.push(node);
+        self.nodes.push(NativeRate::wrap(node));
+    }
+
+    /// Prepare every node for streaming blocks of at most `max_block` frames.
+    /// Nodes after a length-changing effect are prepared for its larger
+    /// output, and nodes after a sample rate converter for its output rate.
+    pub fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        let mut block = max_block;
+        let mut rate = sample_rate;
+        for node in &mut self.nodes {
+            node.prepare(rate, block);
+            block = node.max_output_frames(block);
+            rate = node.output_sample_rate(rate);
+        }
     }
 
+    /// Process one block through every node in order.
     pub fn process(&mut self, buffer: &mut AudioBuffer) {
         for node in &mut self.nodes {
+            if let Some(layout) = node.input_layout() {
+                buffer.conform(layout);
+            }
             node.process(buffer);
         }
     }
@@ -25,4 +54,65 @@ impl LinearChain {
             node.reset();
         }
     }
+
+    /// Live parameters of every node, keyed by the node's position in the chain.
+    pub fn params(&self) -> Vec<(u32, ParamHandle)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, node)| node.params().into_iter().map(move |p| (i as u32, p)))
+            .collect()
+    }
+
+    /// Meter taps of every node, keyed by the node's position in the chain.
+    pub fn meters(&self) -> Vec<(u32, Arc<MeterTap>)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, node)| node.meters().into_iter().map(move |m| (i as u32, m)))
+            .collect()
+    }
+
+    /// Sample rate of the chain's output for a given input rate.
+    pub fn output_sample_rate(&self, input_rate: u32) -> u32 {
+        self.nodes.iter().fold(input_rate, |rate, node| node.output_sample_rate(rate))
+    }
+
+    /// Frames the chain's output lags its input by, at the chain's output
+    /// rate, once prepared for `input_rate`. Delays before a sample rate
+    /// converter are scaled to the rate after it.
+    pub fn latency_samples(&self, input_rate: u32) -> usize {
+        self.total_latency(input_rate, |node| node.latency_samples())
+    }
+
+    /// The most `latency_samples` can become while streaming.
+    pub fn max_latency_samples(&self, input_rate: u32) -> usize {
+        self.total_latency(input_rate, |node| node.max_latency_samples())
+    }
+
+    fn total_latency(&self, input_rate: u32, latency_of: impl Fn(&dyn AudioNode) -> usize) -> usize {
+        let (latency, _) = self.nodes.iter().fold((0, input_rate), |(latency, rate), node| {
+            let out_rate = node.output_sample_rate(rate);
+            let carried = (latency as u64 * out_rate as u64).div_ceil(rate.max(1) as u64) as usize;
+            (carried + latency_of(node.as_ref()), out_rate)
+        });
+        latency
+    }
+
+    /// Output frames the chain produces per input frame.
+    pub fn time_scale(&self) -> f64 {
+        self.nodes.iter().map(|node| node.time_scale()).product()
+    }
+
+    /// Whether any node can change the length of its audio.
+    pub fn changes_length(&self) -> bool {
+        self.nodes.iter().any(|node| node.changes_length())
+    }
+
+    /// Channel layout the chain produces for a given input layout.
+    pub fn output_layout(&self, input: ChannelLayout) -> ChannelLayout {
+        self.nodes.iter().fold(input, |layout, node| {
+            node.output_layout(node.input_layout().unwrap_or(layout))
+        })
+    }
 }
@@ -1,30 +1,44 @@
 use serde::{Deserialize, Serialize};
+use vozoo_core::{ParamDescriptor, VozooError};
 
 use crate::chain::LinearChain;
 use crate::effects::biquad::{BiquadFilter, FilterType};
+use crate::effects::channel_vocoder::{Carrier, ChannelVocoder};
 use crate::effects::chorus::Chorus;
 use crate::effects::dc_blocker::DcBlocker;
 use crate::effects::gain::Gain;
 use crate::effects::limiter::{HardLimiter, LookaheadLimiter};
 use crate::effects::loudness_norm::LoudnessNorm;
+use crate::effects::meter::Meter;
+use crate::effects::monotone::Monotone;
 use crate::effects::noise_reduction::NoiseReduction;
 use crate::effects::normalizer::Normalizer;
+use crate::effects::parametric_eq::{EqBand, ParametricEq};
 use crate::effects::compressor::Compressor;
 use crate::effects::convolution_reverb::ConvolutionReverb;
 use crate::effects::deesser::DeEsser;
 use crate::effects::formant_shift::FormantShift;
 use crate::effects::hrtf::Hrtf;
-use crate::effects::pitch_shift::PitchShift;
+use crate::effects::pitch_correct::PitchCorrect;
+use crate::effects::pitch_shift::{PitchQuality, PitchShift};
 use crate::effects::pitch_shift_resample::PitchShiftResample;
+use crate::effects::resample::Resample;
 use crate::effects::reverb::Reverb;
 use crate::effects::ring_mod::RingMod;
+use crate::effects::time_stretch::{StretchMode, TimeStretch};
 use crate::effects::vad::Vad;
+use crate::subgraph::{MacroDef, MacroTarget};
+use crate::validate::Diagnostic;
 
 /// JSON-serializable chain definition.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChainDef {
     pub name: String,
     pub nodes: Vec<NodeDef>,
+    /// Parameters a `subgraph` node running this chain exposes; targets
+    /// name nodes by index.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub macros: Vec<MacroDef>,
 }
 
 /// JSON-serializable node definition.
@@ -57,24 +71,35 @@ pub struct ParamInfo {
 
 impl ChainDef {
     /// Build a LinearChain from the JSON definition.
-    /// Returns an error if any node type is unknown.
-    pub fn build(&self) -> Result<LinearChain, String> {
+    /// Returns an error naming the first node whose type is unknown.
+    pub fn build(&self) -> Result<LinearChain, VozooError> {
         let mut chain = LinearChain::new();
-        for node_def in &self.nodes {
+        for (index, node_def) in self.nodes.iter().enumerate() {
             match build_node(node_def) {
                 Some(node) => chain.add(node),
-                None => return Err(format!("Unknown node type: '{}'", node_def.node_type)),
+                None => {
+                    return Err(VozooError::UnknownNode {
+                        index,
+                        node_type: node_def.node_type.clone(),
+                    })
+                }
             }
         }
         Ok(chain)
     }
 
+    /// Check the definition for unknown node types and parameters that
+    /// will be ignored or clamped. Empty if it's clean.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        crate::validate::validate_chain(self)
+    }
+
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).unwrap_or_default()
     }
 
-    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(json)
+    pub fn from_json(json: &str) -> Result<Self, VozooError> {
+        serde_json::from_str(json).map_err(json_error)
     }
 
     /// Ensure the chain ends with a limiter so output can't clip or get too
@@ -96,6 +121,18 @@ impl ChainDef {
     }
 }
 
+/// Convert a serde error into `VozooError::InvalidJson`, keeping its position.
+pub(crate) fn json_error(err: serde_json::Error) -> VozooError {
+    let (line, column) = (err.line(), err.column());
+    let text = err.to_string();
+    // serde_json appends the position to its message; it's reported separately.
+    let message = text
+        .strip_suffix(&format!(" at line {line} column {column}"))
+        .unwrap_or(&text)
+        .to_string();
+    VozooError::InvalidJson { line, column, message }
+}
+
 fn get_f32(params: &serde_json::Value, key: &str, default: f32) -> f32 {
     params.get(key).and_then(|v| v.as_f64()).map(|v| v as f32).unwrap_or(default)
 }
@@ -144,18 +181,40 @@ fn build_node(def: &NodeDef) -> Option<Box<dyn vozoo_core::AudioNode>> {
         // Core Processing
         "pitch_shift" => {
             // Phase vocoder: prefer "semitones" param, fall back to "factor" for compat
-            if let Some(semitones) = p.get("semitones").and_then(|v| v.as_f64()) {
-                let semitones = clamp_param("pitch_shift", "semitones", semitones as f32);
-                Some(Box::new(PitchShift::new(semitones)))
-            } else {
-                let factor = get_f32(p, "factor", 1.0);
-                Some(Box::new(PitchShift::from_factor(factor)))
-            }
+            let semitones = match p.get("semitones").and_then(|v| v.as_f64()) {
+                Some(semitones) => clamp_param("pitch_shift", "semitones", semitones as f32),
+                None => PitchShift::factor_to_semitones(get_f32(p, "factor", 1.0)),
+            };
+            let quality = match get_f32c(p, "pitch_shift", "quality", 0.0).round() as i32 {
+                0 => PitchQuality::Standard,
+                _ => PitchQuality::High,
+            };
+            let preserve_formants = get_f32c(p, "pitch_shift", "preserve_formants", 0.0) >= 0.5;
+            Some(Box::new(PitchShift::with_options(semitones, quality, preserve_formants)))
         }
         "pitch_shift_resample" => {
             let factor = get_f32c(p, "pitch_shift_resample", "factor", 1.0);
             Some(Box::new(PitchShiftResample::new(factor)))
         }
+        "time_stretch" => {
+            let rate = get_f32c(p, "time_stretch", "rate", 1.0);
+            let mode = match get_f32c(p, "time_stretch", "mode", 0.0).round() as i32 {
+                0 => StretchMode::Wsola,
+                _ => StretchMode::PhaseVocoder,
+            };
+            Some(Box::new(TimeStretch::new(rate, mode)))
+        }
+        "pitch_correct" => {
+            let key = get_f32c(p, "pitch_correct", "key", 0.0);
+            let scale = get_f32c(p, "pitch_correct", "scale", 0.0);
+            let retune_ms = get_f32c(p, "pitch_correct", "retune_ms", 0.0);
+            Some(Box::new(PitchCorrect::new(key, scale, retune_ms)))
+        }
+        "monotone" => {
+            let pitch_hz = get_f32c(p, "monotone", "pitch_hz", 120.0);
+            let amount = get_f32c(p, "monotone", "amount", 1.0);
+            Some(Box::new(Monotone::new(pitch_hz, amount)))
+        }
         "formant_shift" => {
             let shift_factor = get_f32c(p, "formant_shift", "shift_factor", 1.0);
             Some(Box::new(FormantShift::new(shift_factor)))
@@ -170,10 +229,61 @@ fn build_node(def: &NodeDef) -> Option<Box<dyn vozoo_core::AudioNode>> {
             let q = get_f32c(p, "highpass", "q", 0.707);
             Some(Box::new(BiquadFilter::new(FilterType::HighPass, freq, q)))
         }
+        "bandpass" => {
+            let freq = get_f32c(p, "bandpass", "freq", 1000.0);
+            let q = get_f32c(p, "bandpass", "q", 0.707);
+            Some(Box::new(BiquadFilter::new(FilterType::BandPass, freq, q)))
+        }
+        "notch" => {
+            let freq = get_f32c(p, "notch", "freq", 1000.0);
+            let q = get_f32c(p, "notch", "q", 2.0);
+            Some(Box::new(BiquadFilter::new(FilterType::Notch, freq, q)))
+        }
+        "peaking" => {
+            let freq = get_f32c(p, "peaking", "freq", 1000.0);
+            let q = get_f32c(p, "peaking", "q", 1.0);
+            let gain_db = get_f32c(p, "peaking", "gain_db", 0.0);
+            Some(Box::new(BiquadFilter::with_gain(FilterType::Peaking, freq, q, gain_db)))
+        }
+        "lowshelf" => {
+            let freq = get_f32c(p, "lowshelf", "freq", 200.0);
+            let q = get_f32c(p, "lowshelf", "q", 0.707);
+            let gain_db = get_f32c(p, "lowshelf", "gain_db", 0.0);
+            Some(Box::new(BiquadFilter::with_gain(FilterType::LowShelf, freq, q, gain_db)))
+        }
+        "highshelf" => {
+            let freq = get_f32c(p, "highshelf", "freq", 4000.0);
+            let q = get_f32c(p, "highshelf", "q", 0.707);
+            let gain_db = get_f32c(p, "highshelf", "gain_db", 0.0);
+            Some(Box::new(BiquadFilter::with_gain(FilterType::HighShelf, freq, q, gain_db)))
+        }
+        "allpass" => {
+            let freq = get_f32c(p, "allpass", "freq", 1000.0);
+            let q = get_f32c(p, "allpass", "q", 0.707);
+            Some(Box::new(BiquadFilter::new(FilterType::AllPass, freq, q)))
+        }
+        "parametric_eq" => {
+            // Flat keys per band ("band1_freq", ...), as listed in PARAMS.
+            let value = |d: &ParamDescriptor| get_f32c(p, "parametric_eq", d.key, d.default);
+            let bands: Vec<EqBand> = ParametricEq::PARAMS[1..]
+                .chunks(4)
+                .map(|band| EqBand {
+                    filter_type: EqBand::type_from_index(value(&band[0])),
+                    freq: value(&band[1]),
+                    q: value(&band[2]),
+                    gain_db: value(&band[3]),
+                })
+                .collect();
+            Some(Box::new(ParametricEq::new(value(&ParametricEq::PARAMS[0]), &bands)))
+        }
         "gain" => {
             let factor = get_f32c(p, "gain", "factor", 1.0);
             Some(Box::new(Gain::new(factor)))
         }
+        "resample" => {
+            let sample_rate = get_f32c(p, "resample", "sample_rate", 48000.0);
+            Some(Box::new(Resample::new(sample_rate.round() as u32)))
+        }
 
         // Character / Spatial
         "ring_mod" => {
@@ -182,6 +292,18 @@ fn build_node(def: &NodeDef) -> Option<Box<dyn vozoo_core::AudioNode>> {
             let mix = get_f32c(p, "ring_mod", "mix", 1.0);
             Some(Box::new(RingMod::with_mix(mod_freq, quantize_steps, mix)))
         }
+        "channel_vocoder" => {
+            let bands = get_f32c(p, "channel_vocoder", "bands", 16.0);
+            let bandwidth = get_f32c(p, "channel_vocoder", "bandwidth", 1.0);
+            let carrier = match get_f32c(p, "channel_vocoder", "carrier", 0.0).round() as i32 {
+                0 => Carrier::Saw,
+                1 => Carrier::Pulse,
+                _ => Carrier::Noise,
+            };
+            let pitch_hz = get_f32c(p, "channel_vocoder", "pitch_hz", 110.0);
+            let track_pitch = get_f32c(p, "channel_vocoder", "track_pitch", 0.0) >= 0.5;
+            Some(Box::new(ChannelVocoder::new(bands, bandwidth, carrier, pitch_hz, track_pitch)))
+        }
         "chorus" => {
             let delay_ms = get_f32c(p, "chorus", "delay_ms", 25.0);
             let depth_ms = get_f32c(p, "chorus", "depth_ms", 5.0);
@@ -228,9 +350,20 @@ fn build_node(def: &NodeDef) -> Option<Box<dyn vozoo_core::AudioNode>> {
         }
         "loudness_norm" => {
             let target_lufs = get_f32c(p, "loudness_norm", "target_lufs", -14.0);
-            Some(Box::new(LoudnessNorm::new(target_lufs)))
+            let norm = LoudnessNorm::new(target_lufs);
+            // The ceiling is opt-in so existing chains keep their sound.
+            match p.get("ceiling_dbtp").and_then(|v| v.as_f64()) {
+                Some(ceiling) => {
+                    let ceiling = clamp_param("loudness_norm", "ceiling_dbtp", ceiling as f32);
+                    Some(Box::new(norm.with_ceiling(ceiling)))
+                }
+                None => Some(Box::new(norm)),
+            }
         }
 
+        // Analysis
+        "meter" => Some(Box::new(Meter::new())),
+
         _ => None,
     }
 }
@@ -262,13 +395,42 @@ pub fn available_nodes() -> Vec<NodeInfo> {
         NodeInfo {
             node_type: "pitch_shift".into(), name: "Pitch Shift".into(),
             category: "Core Processing".into(),
-            params: vec![ParamInfo { key: "semitones".into(), name: "Semitones".into(), min: -24.0, max: 24.0, default: 0.0 }],
+            params: vec![
+                ParamInfo { key: "semitones".into(), name: "Semitones".into(), min: -24.0, max: 24.0, default: 0.0 },
+                ParamInfo { key: "quality".into(), name: "Quality (0 Standard, 1 High)".into(), min: 0.0, max: 1.0, default: 0.0 },
+                ParamInfo { key: "preserve_formants".into(), name: "Preserve Formants".into(), min: 0.0, max: 1.0, default: 0.0 },
+            ],
         },
         NodeInfo {
             node_type: "pitch_shift_resample".into(), name: "Pitch Shift (Legacy)".into(),
             category: "Core Processing".into(),
             params: vec![ParamInfo { key: "factor".into(), name: "Speed Factor".into(), min: 0.25, max: 4.0, default: 1.0 }],
         },
+        NodeInfo {
+            node_type: "time_stretch".into(), name: "Time Stretch".into(),
+            category: "Core Processing".into(),
+            params: vec![
+                ParamInfo { key: "rate".into(), name: "Speed".into(), min: 0.25, max: 4.0, default: 1.0 },
+                ParamInfo { key: "mode".into(), name: "Mode (0 WSOLA, 1 Vocoder)".into(), min: 0.0, max: 1.0, default: 0.0 },
+            ],
+        },
+        NodeInfo {
+            node_type: "pitch_correct".into(), name: "Pitch Correction".into(),
+            category: "Core Processing".into(),
+            params: vec![
+                ParamInfo { key: "key".into(), name: "Key (0 C .. 11 B)".into(), min: 0.0, max: 11.0, default: 0.0 },
+                ParamInfo { key: "scale".into(), name: "Scale (0 Chromatic, 1 Major, 2 Minor)".into(), min: 0.0, max: 2.0, default: 0.0 },
+                ParamInfo { key: "retune_ms".into(), name: "Retune Speed (ms)".into(), min: 0.0, max: 500.0, default: 0.0 },
+            ],
+        },
+        NodeInfo {
+            node_type: "monotone".into(), name: "Monotone".into(),
+            category: "Core Processing".into(),
+            params: vec![
+                ParamInfo { key: "pitch_hz".into(), name: "Pitch (Hz)".into(), min: 60.0, max: 400.0, default: 120.0 },
+                ParamInfo { key: "amount".into(), name: "Amount".into(), min: 0.0, max: 1.0, default: 1.0 },
+            ],
+        },
         NodeInfo {
             node_type: "formant_shift".into(), name: "Formant Shift".into(),
             category: "Core Processing".into(),
@@ -290,11 +452,75 @@ pub fn available_nodes() -> Vec<NodeInfo> {
                 ParamInfo { key: "q".into(), name: "Q Factor".into(), min: 0.1, max: 10.0, default: 0.707 },
             ],
         },
+        NodeInfo {
+            node_type: "bandpass".into(), name: "Band-Pass Filter".into(),
+            category: "Core Processing".into(),
+            params: vec![
+                ParamInfo { key: "freq".into(), name: "Frequency (Hz)".into(), min: 20.0, max: 20000.0, default: 1000.0 },
+                ParamInfo { key: "q".into(), name: "Q Factor".into(), min: 0.1, max: 10.0, default: 0.707 },
+            ],
+        },
+        NodeInfo {
+            node_type: "notch".into(), name: "Notch Filter".into(),
+            category: "Core Processing".into(),
+            params: vec![
+                ParamInfo { key: "freq".into(), name: "Frequency (Hz)".into(), min: 20.0, max: 20000.0, default: 1000.0 },
+                ParamInfo { key: "q".into(), name: "Q Factor".into(), min: 0.1, max: 10.0, default: 2.0 },
+            ],
+        },
+        NodeInfo {
+            node_type: "peaking".into(), name: "Peaking EQ".into(),
+            category: "Core Processing".into(),
+            params: vec![
+                ParamInfo { key: "freq".into(), name: "Frequency (Hz)".into(), min: 20.0, max: 20000.0, default: 1000.0 },
+                ParamInfo { key: "q".into(), name: "Q Factor".into(), min: 0.1, max: 10.0, default: 1.0 },
+                ParamInfo { key: "gain_db".into(), name: "Gain (dB)".into(), min: -24.0, max: 24.0, default: 0.0 },
+            ],
+        },
+        NodeInfo {
+            node_type: "lowshelf".into(), name: "Low Shelf".into(),
+            category: "Core Processing".into(),
+            params: vec![
+                ParamInfo { key: "freq".into(), name: "Frequency (Hz)".into(), min: 20.0, max: 20000.0, default: 200.0 },
+                ParamInfo { key: "q".into(), name: "Q Factor".into(), min: 0.1, max: 10.0, default: 0.707 },
+                ParamInfo { key: "gain_db".into(), name: "Gain (dB)".into(), min: -24.0, max: 24.0, default: 0.0 },
+            ],
+        },
+        NodeInfo {
+            node_type: "highshelf".into(), name: "High Shelf".into(),
+            category: "Core Processing".into(),
+            params: vec![
+                ParamInfo { key: "freq".into(), name: "Frequency (Hz)".into(), min: 20.0, max: 20000.0, default: 4000.0 },
+                ParamInfo { key: "q".into(), name: "Q Factor".into(), min: 0.1, max: 10.0, default: 0.707 },
+                ParamInfo { key: "gain_db".into(), name: "Gain (dB)".into(), min: -24.0, max: 24.0, default: 0.0 },
+            ],
+        },
+        NodeInfo {
+            node_type: "allpass".into(), name: "All-Pass Filter".into(),
+            category: "Core Processing".into(),
+            params: vec![
+                ParamInfo { key: "freq".into(), name: "Frequency (Hz)".into(), min: 20.0, max: 20000.0, default: 1000.0 },
+                ParamInfo { key: "q".into(), name: "Q Factor".into(), min: 0.1, max: 10.0, default: 0.707 },
+            ],
+        },
+        NodeInfo {
+            node_type: "parametric_eq".into(), name: "Parametric EQ".into(),
+            category: "Core Processing".into(),
+            params: ParametricEq::PARAMS
+                .iter()
+                .map(|d| ParamInfo { key: d.key.into(), name: d.name.into(), min: d.min.into(), max: d.max.into(), default: d.default.into() })
+                .collect(),
+        },
         NodeInfo {
             node_type: "gain".into(), name: "Gain".into(),
             category: "Core Processing".into(),
             params: vec![ParamInfo { key: "factor".into(), name: "Volume".into(), min: 0.0, max: 4.0, default: 1.0 }],
         },
+        NodeInfo {
+            node_type: "resample".into(), name: "Resample".into(),
+            category: "Core Processing".into(),
+            params: vec![ParamInfo { key: "sample_rate".into(), name: "Sample Rate (Hz)".into(), min: 8000.0, max: 192000.0, default: 48000.0 }],
+        },
 
         // Character / Spatial
         NodeInfo {
@@ -306,6 +532,17 @@ pub fn available_nodes() -> Vec<NodeInfo> {
                 ParamInfo { key: "mix".into(), name: "Wet/Dry Mix".into(), min: 0.0, max: 1.0, default: 1.0 },
             ],
         },
+        NodeInfo {
+            node_type: "channel_vocoder".into(), name: "Channel Vocoder".into(),
+            category: "Character".into(),
+            params: vec![
+                ParamInfo { key: "bands".into(), name: "Bands".into(), min: 4.0, max: 32.0, default: 16.0 },
+                ParamInfo { key: "bandwidth".into(), name: "Bandwidth (x Band Spacing)".into(), min: 0.5, max: 2.0, default: 1.0 },
+                ParamInfo { key: "carrier".into(), name: "Carrier (0 Saw, 1 Pulse, 2 Noise)".into(), min: 0.0, max: 2.0, default: 0.0 },
+                ParamInfo { key: "pitch_hz".into(), name: "Carrier Pitch (Hz)".into(), min: 40.0, max: 400.0, default: 110.0 },
+                ParamInfo { key: "track_pitch".into(), name: "Track Pitch".into(), min: 0.0, max: 1.0, default: 0.0 },
+            ],
+        },
         NodeInfo {
             node_type: "chorus".into(), name: "Chorus".into(),
             category: "Character".into(),
@@ -377,75 +614,93 @@ pub fn available_nodes() -> Vec<NodeInfo> {
         NodeInfo {
             node_type: "loudness_norm".into(), name: "Loudness Normalizer".into(),
             category: "Post Processing".into(),
-            params: vec![ParamInfo { key: "target_lufs".into(), name: "Target LUFS".into(), min: -30.0, max: -6.0, default: -14.0 }],
+            params: vec![
+                ParamInfo { key: "target_lufs".into(), name: "Target LUFS".into(), min: -30.0, max: -6.0, default: -14.0 },
+                ParamInfo { key: "ceiling_dbtp".into(), name: "True Peak Ceiling (dBTP)".into(), min: -12.0, max: 0.0, default: -1.0 },
+            ],
+        },
+
+        // Analysis
+        NodeInfo {
+            node_type: "meter".into(), name: "Level Meter".into(),
+            category: "Analysis".into(), params: vec![],
         },
     ]
 }
 
+/// The clean-up every voice preset starts with.
+fn voice_cleanup() -> Vec<NodeDef> {
+    vec![
+        NodeDef { node_type: "dc_blocker".into(), params: serde_json::json!({}) },
+        NodeDef { node_type: "noise_reduction".into(), params: serde_json::json!({}) },
+        NodeDef { node_type: "normalizer".into(), params: serde_json::json!({"target_rms": 0.2}) },
+    ]
+}
+
+/// The loudness and peak control every voice preset ends with.
+fn finishing() -> Vec<NodeDef> {
+    vec![
+        NodeDef { node_type: "loudness_norm".into(), params: serde_json::json!({"target_lufs": -14.0}) },
+        NodeDef { node_type: "lookahead_limiter".into(), params: serde_json::json!({"ceiling_db": -1.0}) },
+    ]
+}
+
+/// A preset: clean-up, the character nodes, then finishing.
+fn voice_preset(name: &str, character: Vec<NodeDef>) -> ChainDef {
+    ChainDef { name: name.into(), nodes: [voice_cleanup(), character, finishing()].concat(), macros: Vec::new() }
+}
+
 /// Get built-in preset definitions as ChainDefs.
 /// All presets now include pre/post processing stages.
 pub fn preset_chain_defs() -> Vec<ChainDef> {
     vec![
-        ChainDef {
-            name: "Gorilla".into(),
-            nodes: vec![
-                // Pre Processing
-                NodeDef { node_type: "dc_blocker".into(), params: serde_json::json!({}) },
-                NodeDef { node_type: "noise_reduction".into(), params: serde_json::json!({}) },
-                NodeDef { node_type: "normalizer".into(), params: serde_json::json!({"target_rms": 0.2}) },
-                // Core + Character
-                NodeDef { node_type: "pitch_shift".into(), params: serde_json::json!({"factor": 0.75}) },
+        voice_preset(
+            "Gorilla",
+            vec![
+                NodeDef { node_type: "pitch_shift".into(), params: serde_json::json!({"factor": 0.75, "quality": 1, "preserve_formants": 1}) },
                 NodeDef { node_type: "lowpass".into(), params: serde_json::json!({"freq": 800.0, "q": 0.707}) },
                 NodeDef { node_type: "gain".into(), params: serde_json::json!({"factor": 1.2}) },
-                // Post Processing
-                NodeDef { node_type: "loudness_norm".into(), params: serde_json::json!({"target_lufs": -14.0}) },
-                NodeDef { node_type: "lookahead_limiter".into(), params: serde_json::json!({"ceiling_db": -1.0}) },
             ],
-        },
-        ChainDef {
-            name: "Cat".into(),
-            nodes: vec![
-                NodeDef { node_type: "dc_blocker".into(), params: serde_json::json!({}) },
-                NodeDef { node_type: "noise_reduction".into(), params: serde_json::json!({}) },
-                NodeDef { node_type: "normalizer".into(), params: serde_json::json!({"target_rms": 0.2}) },
-                NodeDef { node_type: "pitch_shift".into(), params: serde_json::json!({"factor": 1.4}) },
+        ),
+        voice_preset(
+            "Cat",
+            vec![
+                NodeDef { node_type: "pitch_shift".into(), params: serde_json::json!({"factor": 1.4, "quality": 1, "preserve_formants": 1}) },
                 NodeDef { node_type: "highpass".into(), params: serde_json::json!({"freq": 500.0, "q": 0.707}) },
-                NodeDef { node_type: "loudness_norm".into(), params: serde_json::json!({"target_lufs": -14.0}) },
-                NodeDef { node_type: "lookahead_limiter".into(), params: serde_json::json!({"ceiling_db": -1.0}) },
             ],
-        },
+        ),
+        voice_preset(
+            "Robot",
+            vec![NodeDef {
+                node_type: "channel_vocoder".into(),
+                params: serde_json::json!({"bands": 16, "carrier": 0, "pitch_hz": 110.0}),
+            }],
+        ),
+        voice_preset(
+            "Chorus",
+            vec![NodeDef { node_type: "chorus".into(), params: serde_json::json!({"delay_ms": 25.0, "depth_ms": 5.0, "rate_hz": 1.5, "mix": 0.5}) }],
+        ),
+        voice_preset("Reverb", vec![NodeDef { node_type: "reverb".into(), params: serde_json::json!({}) }]),
+    ]
+}
+
+/// Reusable sections that `subgraph` nodes in graphs can name.
+pub fn building_block_defs() -> Vec<ChainDef> {
+    let exposed = |key: &str, name: &str, node: u32| MacroDef {
+        key: key.into(),
+        name: name.into(),
+        targets: vec![MacroTarget { node, param: key.into() }],
+    };
+    vec![
         ChainDef {
-            name: "Robot".into(),
-            nodes: vec![
-                NodeDef { node_type: "dc_blocker".into(), params: serde_json::json!({}) },
-                NodeDef { node_type: "noise_reduction".into(), params: serde_json::json!({}) },
-                NodeDef { node_type: "normalizer".into(), params: serde_json::json!({"target_rms": 0.2}) },
-                NodeDef { node_type: "ring_mod".into(), params: serde_json::json!({"mod_freq": 50.0, "quantize_steps": 8.0}) },
-                NodeDef { node_type: "loudness_norm".into(), params: serde_json::json!({"target_lufs": -14.0}) },
-                NodeDef { node_type: "lookahead_limiter".into(), params: serde_json::json!({"ceiling_db": -1.0}) },
-            ],
+            name: "Voice Cleanup".into(),
+            nodes: voice_cleanup(),
+            macros: vec![exposed("target_rms", "Target RMS", 2)],
         },
         ChainDef {
-            name: "Chorus".into(),
-            nodes: vec![
-                NodeDef { node_type: "dc_blocker".into(), params: serde_json::json!({}) },
-                NodeDef { node_type: "noise_reduction".into(), params: serde_json::json!({}) },
-                NodeDef { node_type: "normalizer".into(), params: serde_json::json!({"target_rms": 0.2}) },
-                NodeDef { node_type: "chorus".into(), params: serde_json::json!({"delay_ms": 25.0, "depth_ms": 5.0, "rate_hz": 1.5, "mix": 0.5}) },
-                NodeDef { node_type: "loudness_norm".into(), params: serde_json::json!({"target_lufs": -14.0}) },
-                NodeDef { node_type: "lookahead_limiter".into(), params: serde_json::json!({"ceiling_db": -1.0}) },
-            ],
-        },
-        ChainDef {
-            name: "Reverb".into(),
-            nodes: vec![
-                NodeDef { node_type: "dc_blocker".into(), params: serde_json::json!({}) },
-                NodeDef { node_type: "noise_reduction".into(), params: serde_json::json!({}) },
-                NodeDef { node_type: "normalizer".into(), params: serde_json::json!({"target_rms": 0.2}) },
-                NodeDef { node_type: "reverb".into(), params: serde_json::json!({}) },
-                NodeDef { node_type: "loudness_norm".into(), params: serde_json::json!({"target_lufs": -14.0}) },
-                NodeDef { node_type: "lookahead_limiter".into(), params: serde_json::json!({"ceiling_db": -1.0}) },
-            ],
+            name: "Finishing".into(),
+            nodes: finishing(),
+            macros: vec![exposed("target_lufs", "Target LUFS", 0), exposed("ceiling_db", "Ceiling (dB)", 1)],
         },
     ]
 }
@@ -507,6 +762,85 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_every_node_is_block_size_invariant() {
+        use vozoo_core::AudioBuffer;
+        use std::f32::consts::TAU;
+
+        let samples: Vec<f32> = (0..24000)
+            .map(|i| {
+                let t = i as f32 / 48000.0;
+                ((t * 440.0 * TAU).sin() * 0.5 + (t * 3100.0 * TAU).sin() * 0.2) * (t * 3.0 * TAU).sin()
+            })
+            .collect();
+
+        for info in available_nodes() {
+            // Non-default settings for nodes that are a no-op by default.
+            let params = match info.node_type.as_str() {
+                "pitch_shift" => serde_json::json!({ "semitones": 5.0, "quality": 1, "preserve_formants": 1 }),
+                "formant_shift" => serde_json::json!({ "shift_factor": 1.3 }),
+                "pitch_shift_resample" => serde_json::json!({ "factor": 0.8 }),
+                "pitch_correct" => serde_json::json!({ "key": 2, "scale": 1, "retune_ms": 50.0 }),
+                "channel_vocoder" => serde_json::json!({ "carrier": 1, "track_pitch": 1 }),
+                "peaking" | "lowshelf" => serde_json::json!({ "gain_db": 6.0 }),
+                "highshelf" => serde_json::json!({ "gain_db": -6.0 }),
+                "parametric_eq" => serde_json::json!({ "band1_type": 1, "band1_gain_db": 6.0, "band3_gain_db": -4.0 }),
+                "loudness_norm" => serde_json::json!({ "ceiling_dbtp": -6.0 }),
+                _ => serde_json::json!({}),
+            };
+            let def = NodeDef { node_type: info.node_type.clone(), params };
+
+            let mut node = build_node(&def).unwrap();
+            node.prepare(48000, samples.len());
+            let mut whole = AudioBuffer::new(samples.clone(), 48000);
+            node.process(&mut whole);
+
+            for block in [37, 256, 1000] {
+                let mut node = build_node(&def).unwrap();
+                node.prepare(48000, block);
+                let mut blocked = AudioBuffer::new(samples.clone(), 48000);
+                blocked.process_blocks(block, |b| node.process(b));
+
+                assert_eq!(
+                    blocked.samples.len(),
+                    whole.samples.len(),
+                    "'{}' output length depends on block size {block}",
+                    info.node_type
+                );
+                let max_diff = whole
+                    .samples
+                    .iter()
+                    .zip(blocked.samples.iter())
+                    .map(|(a, b)| (a - b).abs())
+                    .fold(0.0f32, f32::max);
+                assert!(
+                    max_diff < 1e-4,
+                    "'{}' output depends on block size {block}: max diff {max_diff}",
+                    info.node_type
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_chain_latency_adds_up() {
+        let json = r#"{
+            "name": "Latency",
+            "nodes": [
+                {"type": "lookahead_limiter", "params": {"ceiling_db": -1.0}},
+                {"type": "vad", "params": {}},
+                {"type": "gain", "params": {"factor": 0.8}},
+                {"type": "resample", "params": {"sample_rate": 24000}},
+                {"type": "pitch_shift", "params": {"semitones": 3.0}}
+            ]
+        }"#;
+        let mut chain = ChainDef::from_json(json).unwrap().build().unwrap();
+        chain.prepare(48000, 512);
+
+        // (247 + 480) frames at 48 kHz carry over as 364 at 24 kHz.
+        assert_eq!(chain.latency_samples(48000), 364 + 2048);
+    }
+
     #[test]
     fn test_custom_chain_with_preprocess() {
         let json = r#"{
@@ -556,6 +890,7 @@ mod tests {
         let mut def = ChainDef {
             name: "x".into(),
             nodes: vec![NodeDef { node_type: "gain".into(), params: serde_json::json!({"factor": 2.0}) }],
+            macros: Vec::new(),
         };
         def.ensure_trailing_limiter();
         assert_eq!(def.nodes.last().unwrap().node_type, "lookahead_limiter");
@@ -574,6 +909,7 @@ mod tests {
                 NodeDef { node_type: "gain".into(), params: serde_json::json!({"factor": 2.0}) },
                 NodeDef { node_type: "limiter".into(), params: serde_json::json!({}) },
             ],
+            macros: Vec::new(),
         };
         def.ensure_trailing_limiter();
         assert_eq!(def.nodes.len(), 2, "existing trailing limiter should be kept as-is");
@@ -621,6 +957,98 @@ mod tests {
         assert!(def.build().is_ok());
     }
 
+    #[test]
+    fn test_parametric_eq_bands_from_json() {
+        use vozoo_core::AudioBuffer;
+
+        // A telephone band: highpass at 300 Hz, lowpass at 3.4 kHz.
+        let json = r#"{"name":"phone","nodes":[{"type":"parametric_eq","params":{
+            "bands": 2, "band1_type": 4, "band1_freq": 300, "band1_q": 0.707,
+            "band2_type": 3, "band2_freq": 3400, "band2_q": 0.707}}]}"#;
+        let level = |freq: f32| {
+            let mut chain = ChainDef::from_json(json).unwrap().build().unwrap();
+            chain.prepare(48000, 4800);
+            let input = (0..24000).map(|i| (std::f32::consts::TAU * freq * i as f32 / 48000.0).sin()).collect();
+            let mut buffer = AudioBuffer::new(input, 48000);
+            chain.process(&mut buffer);
+            buffer.samples[12000..].iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+        };
+        assert!((level(1000.0) - 1.0).abs() < 0.05);
+        assert!(level(80.0) < 0.1);
+        assert!(level(12000.0) < 0.1);
+    }
+
+    #[test]
+    fn test_errors_point_at_the_problem() {
+        let json = r#"{"name":"x","nodes":[{"type":"gain"},{"type":"wobble"}]}"#;
+        match ChainDef::from_json(json).unwrap().build() {
+            Err(VozooError::UnknownNode { index, node_type }) => assert_eq!((index, node_type.as_str()), (1, "wobble")),
+            other => panic!("expected UnknownNode, got {:?}", other.err()),
+        }
+
+        let json = "{\n  \"name\": \"x\",\n  \"nodes\": [,]\n}";
+        match ChainDef::from_json(json) {
+            Err(VozooError::InvalidJson { line, column, message }) => {
+                assert_eq!((line, column), (3, 13));
+                assert!(!message.contains("line"), "position repeated in message: {message}");
+            }
+            other => panic!("expected InvalidJson, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_live_params_match_available_nodes() {
+        for info in available_nodes() {
+            let def = NodeDef { node_type: info.node_type.clone(), params: serde_json::json!({}) };
+            let node = build_node(&def).unwrap();
+            for handle in node.params() {
+                let d = handle.descriptor;
+                let param = info
+                    .params
+                    .iter()
+                    .find(|p| p.key == d.key)
+                    .unwrap_or_else(|| panic!("'{}' has no param '{}'", info.node_type, d.key));
+                assert_eq!(info.params[d.id as usize].key, d.key, "'{}' param ids out of order", info.node_type);
+                assert_eq!(param.name, d.name);
+                assert_eq!(
+                    (param.min as f32, param.max as f32, param.default as f32),
+                    (d.min, d.max, d.default),
+                    "'{}.{}' range differs from available_nodes()",
+                    info.node_type,
+                    d.key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_live_param_change_glides() {
+        use vozoo_core::AudioBuffer;
+
+        let json = r#"{"name":"g","nodes":[{"type":"gain","params":{"factor":1.0}}]}"#;
+        let mut chain = ChainDef::from_json(json).unwrap().build().unwrap();
+        chain.prepare(48000, 256);
+
+        let params = chain.params();
+        let (node, factor) = params.iter().find(|(_, p)| p.descriptor.key == "factor").unwrap();
+        assert_eq!(*node, 0);
+        factor.set(0.0);
+
+        let mut output = Vec::new();
+        for _ in 0..40 {
+            let mut block = AudioBuffer::new(vec![1.0; 256], 48000);
+            chain.process(&mut block);
+            output.extend_from_slice(&block.samples);
+        }
+
+        // A smooth fade rather than an instant jump to silence.
+        assert!(output[0] > 0.99);
+        for pair in output.windows(2) {
+            assert!(pair[1] <= pair[0] && pair[0] - pair[1] < 0.01);
+        }
+        assert_eq!(*output.last().unwrap(), 0.0);
+    }
+
     #[test]
     fn test_available_nodes_has_categories() {
         let nodes = available_nodes();
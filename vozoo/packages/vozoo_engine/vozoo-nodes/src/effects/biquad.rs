@@ -1,23 +1,83 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
 use std::f32::consts::PI;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum FilterType {
     LowPass,
     HighPass,
+    /// Constant 0 dB peak gain at `freq`, with `q` setting the bandwidth.
+    BandPass,
+    Notch,
+    /// Bell boosting or cutting `gain_db` around `freq`.
+    Peaking,
+    /// Shelf boosting or cutting `gain_db` below `freq`.
+    LowShelf,
+    /// Shelf boosting or cutting `gain_db` above `freq`.
+    HighShelf,
+    /// Flat magnitude; the phase turns through 180 degrees at `freq`.
+    AllPass,
 }
 
-/// Biquad filter (Direct Form II Transposed).
-pub struct BiquadFilter {
-    filter_type: FilterType,
-    freq: f32,
-    q: f32,
-    // coefficients
+impl FilterType {
+    /// Whether the `gain_db` parameter shapes the response.
+    pub fn uses_gain(self) -> bool {
+        matches!(self, FilterType::Peaking | FilterType::LowShelf | FilterType::HighShelf)
+    }
+}
+
+/// Samples between coefficient updates while `freq`, `q` or `gain_db` glides.
+const COEFF_UPDATE_INTERVAL: usize = 32;
+/// Glide time of the coefficients towards each update, so neither the
+/// update steps nor a change of filter type click.
+const COEFF_SMOOTHING_MS: f32 = 2.0;
+
+#[derive(Clone, Copy, PartialEq, Default)]
+struct Coefficients {
     b0: f32,
     b1: f32,
     b2: f32,
     a1: f32,
     a2: f32,
+}
+
+impl Coefficients {
+    /// Move a fraction `amount` of the way to `target`. Any mix of two
+    /// stable biquads is stable, so the glide can't blow up.
+    fn approach(&mut self, target: &Coefficients, amount: f32) {
+        self.b0 += (target.b0 - self.b0) * amount;
+        self.b1 += (target.b1 - self.b1) * amount;
+        self.b2 += (target.b2 - self.b2) * amount;
+        self.a1 += (target.a1 - self.a1) * amount;
+        self.a2 += (target.a2 - self.a2) * amount;
+    }
+
+    fn distance(&self, other: &Coefficients) -> f32 {
+        [
+            self.b0 - other.b0,
+            self.b1 - other.b1,
+            self.b2 - other.b2,
+            self.a1 - other.a1,
+            self.a2 - other.a2,
+        ]
+        .iter()
+        .fold(0.0, |max, d| max.max(d.abs()))
+    }
+}
+
+/// Biquad filter (Direct Form II Transposed), with the RBJ cookbook
+/// responses.
+pub struct BiquadFilter {
+    filter_type: FilterType,
+    freq: SmoothedParam,
+    q: SmoothedParam,
+    gain_db: SmoothedParam,
+    /// Samples until the next coefficient update while gliding.
+    update_countdown: usize,
+    coeffs: Coefficients,
+    /// Coefficients for the current settings, which `coeffs` glides to.
+    target: Coefficients,
+    /// Per-sample glide factor, from `COEFF_SMOOTHING_MS`.
+    coeff_glide: f32,
     // state
     z1: f32,
     z2: f32,
@@ -25,76 +85,303 @@ pub struct BiquadFilter {
 }
 
 impl BiquadFilter {
+    pub const LOWPASS_PARAMS: [ParamDescriptor; 2] = [
+        ParamDescriptor { id: 0, key: "freq", name: "Frequency (Hz)", min: 20.0, max: 20000.0, default: 1000.0 },
+        ParamDescriptor { id: 1, key: "q", name: "Q Factor", min: 0.1, max: 10.0, default: 0.707 },
+    ];
+    pub const HIGHPASS_PARAMS: [ParamDescriptor; 2] = [
+        ParamDescriptor { id: 0, key: "freq", name: "Frequency (Hz)", min: 20.0, max: 20000.0, default: 500.0 },
+        ParamDescriptor { id: 1, key: "q", name: "Q Factor", min: 0.1, max: 10.0, default: 0.707 },
+    ];
+    pub const BANDPASS_PARAMS: [ParamDescriptor; 2] = [
+        ParamDescriptor { id: 0, key: "freq", name: "Frequency (Hz)", min: 20.0, max: 20000.0, default: 1000.0 },
+        ParamDescriptor { id: 1, key: "q", name: "Q Factor", min: 0.1, max: 10.0, default: 0.707 },
+    ];
+    pub const NOTCH_PARAMS: [ParamDescriptor; 2] = [
+        ParamDescriptor { id: 0, key: "freq", name: "Frequency (Hz)", min: 20.0, max: 20000.0, default: 1000.0 },
+        ParamDescriptor { id: 1, key: "q", name: "Q Factor", min: 0.1, max: 10.0, default: 2.0 },
+    ];
+    pub const PEAKING_PARAMS: [ParamDescriptor; 3] = [
+        ParamDescriptor { id: 0, key: "freq", name: "Frequency (Hz)", min: 20.0, max: 20000.0, default: 1000.0 },
+        ParamDescriptor { id: 1, key: "q", name: "Q Factor", min: 0.1, max: 10.0, default: 1.0 },
+        ParamDescriptor { id: 2, key: "gain_db", name: "Gain (dB)", min: -24.0, max: 24.0, default: 0.0 },
+    ];
+    pub const LOWSHELF_PARAMS: [ParamDescriptor; 3] = [
+        ParamDescriptor { id: 0, key: "freq", name: "Frequency (Hz)", min: 20.0, max: 20000.0, default: 200.0 },
+        ParamDescriptor { id: 1, key: "q", name: "Q Factor", min: 0.1, max: 10.0, default: 0.707 },
+        ParamDescriptor { id: 2, key: "gain_db", name: "Gain (dB)", min: -24.0, max: 24.0, default: 0.0 },
+    ];
+    pub const HIGHSHELF_PARAMS: [ParamDescriptor; 3] = [
+        ParamDescriptor { id: 0, key: "freq", name: "Frequency (Hz)", min: 20.0, max: 20000.0, default: 4000.0 },
+        ParamDescriptor { id: 1, key: "q", name: "Q Factor", min: 0.1, max: 10.0, default: 0.707 },
+        ParamDescriptor { id: 2, key: "gain_db", name: "Gain (dB)", min: -24.0, max: 24.0, default: 0.0 },
+    ];
+    pub const ALLPASS_PARAMS: [ParamDescriptor; 2] = [
+        ParamDescriptor { id: 0, key: "freq", name: "Frequency (Hz)", min: 20.0, max: 20000.0, default: 1000.0 },
+        ParamDescriptor { id: 1, key: "q", name: "Q Factor", min: 0.1, max: 10.0, default: 0.707 },
+    ];
+
     pub fn new(filter_type: FilterType, freq: f32, q: f32) -> Self {
+        Self::with_gain(filter_type, freq, q, 0.0)
+    }
+
+    /// A filter with a `gain_db` setting, for the peaking and shelf types.
+    pub fn with_gain(filter_type: FilterType, freq: f32, q: f32, gain_db: f32) -> Self {
+        Self::with_params(filter_type, Self::descriptors(filter_type), freq, q, gain_db)
+    }
+
+    /// A filter whose frequency, Q and gain parameters use `descriptors`,
+    /// for nodes that expose several filters' parameters as their own.
+    pub(super) fn with_params(filter_type: FilterType, descriptors: [ParamDescriptor; 3], freq: f32, q: f32, gain_db: f32) -> Self {
+        let [freq_desc, q_desc, gain_desc] = descriptors;
         let mut f = Self {
             filter_type,
-            freq,
-            q,
-            b0: 1.0,
-            b1: 0.0,
-            b2: 0.0,
-            a1: 0.0,
-            a2: 0.0,
+            freq: SmoothedParam::new(freq_desc, freq),
+            q: SmoothedParam::new(q_desc, q),
+            gain_db: SmoothedParam::new(gain_desc, gain_db),
+            update_countdown: 0,
+            coeffs: Coefficients::default(),
+            target: Coefficients::default(),
+            coeff_glide: 0.0,
             z1: 0.0,
             z2: 0.0,
             configured_sr: 0,
         };
-        f.compute_coefficients(48000);
+        f.configure(48000);
         f
     }
 
-    fn compute_coefficients(&mut self, sample_rate: u32) {
+    /// Frequency, Q and gain descriptors for `filter_type`. Types without
+    /// a gain still get one, so `set_filter_type` can switch to them.
+    fn descriptors(filter_type: FilterType) -> [ParamDescriptor; 3] {
+        let gain = Self::PEAKING_PARAMS[2];
+        match filter_type {
+            FilterType::LowPass => [Self::LOWPASS_PARAMS[0], Self::LOWPASS_PARAMS[1], gain],
+            FilterType::HighPass => [Self::HIGHPASS_PARAMS[0], Self::HIGHPASS_PARAMS[1], gain],
+            FilterType::BandPass => [Self::BANDPASS_PARAMS[0], Self::BANDPASS_PARAMS[1], gain],
+            FilterType::Notch => [Self::NOTCH_PARAMS[0], Self::NOTCH_PARAMS[1], gain],
+            FilterType::Peaking => Self::PEAKING_PARAMS,
+            FilterType::LowShelf => Self::LOWSHELF_PARAMS,
+            FilterType::HighShelf => Self::HIGHSHELF_PARAMS,
+            FilterType::AllPass => [Self::ALLPASS_PARAMS[0], Self::ALLPASS_PARAMS[1], gain],
+        }
+    }
+
+    pub fn filter_type(&self) -> FilterType {
+        self.filter_type
+    }
+
+    /// Switch response; the coefficients glide to the new one.
+    pub fn set_filter_type(&mut self, filter_type: FilterType) {
+        if filter_type != self.filter_type {
+            self.filter_type = filter_type;
+            self.compute_coefficients();
+        }
+    }
+
+    /// Handles of the frequency, Q and gain parameters, whatever the type.
+    pub(super) fn handles(&self) -> [ParamHandle; 3] {
+        [self.freq.handle(), self.q.handle(), self.gain_db.handle()]
+    }
+
+    /// Set up for `sample_rate` and jump straight to the coefficients.
+    fn configure(&mut self, sample_rate: u32) {
         self.configured_sr = sample_rate;
-        let w0 = 2.0 * PI * self.freq / sample_rate as f32;
-        let alpha = w0.sin() / (2.0 * self.q);
+        self.coeff_glide = 1.0 - (-1000.0 / (COEFF_SMOOTHING_MS * sample_rate as f32)).exp();
+        self.compute_coefficients();
+        self.coeffs = self.target;
+    }
+
+    fn compute_coefficients(&mut self) {
+        let sample_rate = self.configured_sr as f32;
+        // Keep the cutoff below Nyquist whatever the sample rate.
+        let freq = self.freq.value().min(sample_rate * 0.49);
+        let w0 = 2.0 * PI * freq / sample_rate;
+        let alpha = w0.sin() / (2.0 * self.q.value());
         let cos_w0 = w0.cos();
+        let a = 10.0f32.powf(self.gain_db.value() / 40.0);
+        let shelf = 2.0 * a.sqrt() * alpha;
 
-        let (b0, b1, b2) = match self.filter_type {
+        let (b0, b1, b2, a0, a1, a2) = match self.filter_type {
             FilterType::LowPass => {
                 let b0 = (1.0 - cos_w0) / 2.0;
                 let b1 = 1.0 - cos_w0;
                 let b2 = (1.0 - cos_w0) / 2.0;
-                (b0, b1, b2)
+                (b0, b1, b2, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha)
             }
             FilterType::HighPass => {
                 let b0 = (1.0 + cos_w0) / 2.0;
                 let b1 = -(1.0 + cos_w0);
                 let b2 = (1.0 + cos_w0) / 2.0;
-                (b0, b1, b2)
+                (b0, b1, b2, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha)
             }
+            FilterType::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha),
+            FilterType::Notch => (1.0, -2.0 * cos_w0, 1.0, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha),
+            FilterType::Peaking => {
+                (1.0 + alpha * a, -2.0 * cos_w0, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos_w0, 1.0 - alpha / a)
+            }
+            FilterType::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 - shelf),
+                (a + 1.0) + (a - 1.0) * cos_w0 + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+                (a + 1.0) + (a - 1.0) * cos_w0 - shelf,
+            ),
+            FilterType::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 - shelf),
+                (a + 1.0) - (a - 1.0) * cos_w0 + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+                (a + 1.0) - (a - 1.0) * cos_w0 - shelf,
+            ),
+            FilterType::AllPass => (1.0 - alpha, -2.0 * cos_w0, 1.0 + alpha, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha),
         };
-        let a0 = 1.0 + alpha;
-        self.b0 = b0 / a0;
-        self.b1 = b1 / a0;
-        self.b2 = b2 / a0;
-        self.a1 = -2.0 * cos_w0 / a0;
-        self.a2 = (1.0 - alpha) / a0;
+        self.target = Coefficients { b0: b0 / a0, b1: b1 / a0, b2: b2 / a0, a1: a1 / a0, a2: a2 / a0 };
+    }
+
+    /// Filter one sample at the rate given to `prepare`, for nodes that run
+    /// filters inside their own sample loop.
+    pub fn process_sample(&mut self, x: f32) -> f32 {
+        if self.update_countdown == 0 {
+            if self.freq.is_smoothing() || self.q.is_smoothing() || self.gain_db.is_smoothing() {
+                self.freq.advance(COEFF_UPDATE_INTERVAL);
+                self.q.advance(COEFF_UPDATE_INTERVAL);
+                self.gain_db.advance(COEFF_UPDATE_INTERVAL);
+                self.compute_coefficients();
+            }
+            self.update_countdown = COEFF_UPDATE_INTERVAL;
+        }
+        self.update_countdown -= 1;
+        if self.coeffs != self.target {
+            self.coeffs.approach(&self.target, self.coeff_glide);
+            if self.coeffs.distance(&self.target) < 1e-6 {
+                self.coeffs = self.target;
+            }
+        }
+
+        let c = &self.coeffs;
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
     }
 }
 
 impl AudioNode for BiquadFilter {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.freq.prepare(sample_rate);
+        self.q.prepare(sample_rate);
+        self.gain_db.prepare(sample_rate);
+        self.configure(sample_rate);
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
-        if buffer.sample_rate() != self.configured_sr {
-            self.compute_coefficients(buffer.sample_rate());
+        let sample_rate = buffer.sample_rate();
+        if sample_rate != self.configured_sr {
+            self.configure(sample_rate);
         }
         for s in &mut buffer.samples {
-            let x = *s;
-            let y = self.b0 * x + self.z1;
-            self.z1 = self.b1 * x - self.a1 * y + self.z2;
-            self.z2 = self.b2 * x - self.a2 * y;
-            *s = y;
+            *s = self.process_sample(*s);
         }
     }
 
     fn reset(&mut self) {
         self.z1 = 0.0;
         self.z2 = 0.0;
+        self.update_countdown = 0;
+        self.freq.snap();
+        self.q.snap();
+        self.gain_db.snap();
+        self.configure(self.configured_sr.max(1));
     }
 
     fn name(&self) -> &str {
         match self.filter_type {
             FilterType::LowPass => "LowPass Filter",
             FilterType::HighPass => "HighPass Filter",
+            FilterType::BandPass => "BandPass Filter",
+            FilterType::Notch => "Notch Filter",
+            FilterType::Peaking => "Peaking EQ",
+            FilterType::LowShelf => "LowShelf EQ",
+            FilterType::HighShelf => "HighShelf EQ",
+            FilterType::AllPass => "AllPass Filter",
+        }
+    }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        let [freq, q, gain_db] = self.handles();
+        if self.filter_type.uses_gain() {
+            vec![freq, q, gain_db]
+        } else {
+            vec![freq, q]
         }
     }
 }
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Steady-state gain in dB of `filter` for a sine at `freq`.
+    pub(in crate::effects) fn response_db(filter: &mut dyn AudioNode, freq: f32) -> f32 {
+        filter.prepare(48000, 4800);
+        filter.reset();
+        let input: Vec<f32> = (0..24000).map(|i| (2.0 * PI * freq * i as f32 / 48000.0).sin()).collect();
+        let mut buffer = AudioBuffer::new(input, 48000);
+        filter.process(&mut buffer);
+        let tail = &buffer.samples[12000..];
+        let rms = (tail.iter().map(|s| s * s).sum::<f32>() / tail.len() as f32).sqrt();
+        20.0 * (rms * 2.0f32.sqrt()).log10()
+    }
+
+    #[test]
+    fn test_filter_responses() {
+        let cases = [
+            (FilterType::BandPass, 0.0, [(1000.0, 0.0), (100.0, -15.0), (10000.0, -15.0)]),
+            (FilterType::Notch, 0.0, [(1000.0, -40.0), (100.0, 0.0), (10000.0, 0.0)]),
+            (FilterType::Peaking, 6.0, [(1000.0, 6.0), (100.0, 0.0), (10000.0, 0.0)]),
+            (FilterType::LowShelf, 6.0, [(50.0, 6.0), (1000.0, 3.0), (10000.0, 0.0)]),
+            (FilterType::HighShelf, -6.0, [(50.0, 0.0), (1000.0, -3.0), (15000.0, -6.0)]),
+            (FilterType::AllPass, 0.0, [(100.0, 0.0), (1000.0, 0.0), (10000.0, 0.0)]),
+        ];
+        for (filter_type, gain_db, points) in cases {
+            let mut filter = BiquadFilter::with_gain(filter_type, 1000.0, 0.707, gain_db);
+            for (freq, expected) in points {
+                let measured = response_db(&mut filter, freq);
+                if expected <= -15.0 {
+                    assert!(measured < expected, "{filter_type:?} at {freq} Hz: {measured} dB");
+                } else {
+                    assert!((measured - expected).abs() < 0.5, "{filter_type:?} at {freq} Hz: {measured} dB");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_type_switch_glides() {
+        // Switching a 100 Hz tone from a lowpass to a highpass fades it out
+        // instead of jumping.
+        let mut filter = BiquadFilter::new(FilterType::LowPass, 1000.0, 0.707);
+        filter.prepare(48000, 4800);
+        let input: Vec<f32> = (0..9600).map(|i| 0.5 * (2.0 * PI * 100.0 * i as f32 / 48000.0).sin()).collect();
+        let mut output = Vec::new();
+        for (i, &x) in input.iter().enumerate() {
+            if i == 4800 {
+                filter.set_filter_type(FilterType::HighPass);
+            }
+            output.push(filter.process_sample(x));
+        }
+        let step = output.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0, f32::max);
+        assert!(step < 0.02, "{step}");
+        assert!(output[9000..].iter().all(|s| s.abs() < 0.01));
+    }
+
+    #[test]
+    fn test_gain_types_expose_gain() {
+        assert_eq!(BiquadFilter::new(FilterType::LowPass, 1000.0, 0.707).params().len(), 2);
+        let peaking = BiquadFilter::with_gain(FilterType::Peaking, 1000.0, 1.0, 3.0).params();
+        assert_eq!(peaking.iter().map(|p| p.descriptor.key).collect::<Vec<_>>(), ["freq", "q", "gain_db"]);
+    }
+}
@@ -0,0 +1,376 @@
+use std::f32::consts::LN_2;
+
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
+
+use super::biquad::{BiquadFilter, FilterType};
+use super::pitch_detect::{PitchMethod, PitchTracker};
+
+/// Bands allocated up front, so the band count can change live.
+const MAX_BANDS: usize = 32;
+/// Centre of the lowest band's lower edge and the highest band's upper edge.
+const LOW_HZ: f32 = 100.0;
+const HIGH_HZ: f32 = 8000.0;
+/// Bandpass biquads cascaded per band and signal.
+const STAGES: usize = 2;
+const ATTACK_MS: f32 = 2.0;
+const RELEASE_MS: f32 = 20.0;
+/// Carrier band level below which a band is no longer normalized, so
+/// near-silent carrier bands aren't blown up.
+const CARRIER_FLOOR: f32 = 1e-3;
+/// Duty cycle of the pulse carrier.
+const PULSE_WIDTH: f32 = 0.25;
+
+/// Built-in carrier oscillator.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Carrier {
+    Saw,
+    Pulse,
+    Noise,
+}
+
+/// One analysis/synthesis band: the same bandpass on the modulator (the
+/// voice) and on the carrier, with an envelope follower on each.
+struct Band {
+    modulator: [BiquadFilter; STAGES],
+    carrier: [BiquadFilter; STAGES],
+    /// Frequency and Q handles of every filter, in filter order.
+    tuning: Vec<ParamHandle>,
+    modulator_env: f32,
+    carrier_env: f32,
+}
+
+impl Band {
+    fn new() -> Self {
+        let filter = || BiquadFilter::new(FilterType::BandPass, 1000.0, 1.0);
+        let modulator = [filter(), filter()];
+        let carrier = [filter(), filter()];
+        let tuning = modulator.iter().chain(&carrier).flat_map(|f| f.params()).collect();
+        Self { modulator, carrier, tuning, modulator_env: 0.0, carrier_env: 0.0 }
+    }
+
+    fn filters(&mut self) -> impl Iterator<Item = &mut BiquadFilter> {
+        self.modulator.iter_mut().chain(&mut self.carrier)
+    }
+
+    /// Move the band; its filters glide to the new setting.
+    fn tune(&self, freq: f32, q: f32) {
+        for handles in self.tuning.chunks(2) {
+            handles[0].set(freq);
+            handles[1].set(q);
+        }
+    }
+
+    /// Carrier band `carrier` shaped by the modulator band's envelope.
+    fn process(&mut self, modulator: f32, carrier: f32, attack: f32, release: f32) -> f32 {
+        let m = self.modulator.iter_mut().fold(modulator, |s, f| f.process_sample(s));
+        let c = self.carrier.iter_mut().fold(carrier, |s, f| f.process_sample(s));
+        follow(&mut self.modulator_env, m.abs(), attack, release);
+        follow(&mut self.carrier_env, c.abs(), attack, release);
+        c * self.modulator_env / self.carrier_env.max(CARRIER_FLOOR)
+    }
+
+    fn reset(&mut self) {
+        for filter in self.filters() {
+            filter.reset();
+        }
+        self.modulator_env = 0.0;
+        self.carrier_env = 0.0;
+    }
+}
+
+fn follow(env: &mut f32, level: f32, attack: f32, release: f32) {
+    let coeff = if level > *env { attack } else { release };
+    *env += (level - *env) * coeff;
+}
+
+fn follower_coeff(ms: f32, sample_rate: u32) -> f32 {
+    1.0 - (-1000.0 / (ms * sample_rate as f32)).exp()
+}
+
+/// Correction for the step of a band-limited saw or pulse at phase `t`.
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let t = t / dt;
+        2.0 * t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Classic channel vocoder: splits the input into log-spaced bands and
+/// imposes each band's envelope on the same band of a carrier.
+///
+/// The carrier is a built-in saw, pulse or noise oscillator at
+/// `pitch_hz`, or at the input's own pitch with `track_pitch` on (held
+/// through unvoiced sounds). In a graph, a signal connected to the
+/// "carrier" input replaces the oscillator (e.g. a synth pad).
+pub struct ChannelVocoder {
+    bands: SmoothedParam,
+    bandwidth: SmoothedParam,
+    carrier: SmoothedParam,
+    pitch_hz: SmoothedParam,
+    track_pitch: SmoothedParam,
+    filters: Vec<Band>,
+    /// Band count and bandwidth the filters are tuned for.
+    layout: (usize, f32),
+    tracker: PitchTracker,
+    /// Carrier pitch while tracking, held when the input is unvoiced.
+    tracked_hz: f32,
+    phase: f32,
+    noise: u32,
+    attack: f32,
+    release: f32,
+    sample_rate: u32,
+}
+
+impl ChannelVocoder {
+    pub const PARAMS: [ParamDescriptor; 5] = [
+        ParamDescriptor { id: 0, key: "bands", name: "Bands", min: 4.0, max: MAX_BANDS as f32, default: 16.0 },
+        ParamDescriptor { id: 1, key: "bandwidth", name: "Bandwidth (x Band Spacing)", min: 0.5, max: 2.0, default: 1.0 },
+        ParamDescriptor { id: 2, key: "carrier", name: "Carrier (0 Saw, 1 Pulse, 2 Noise)", min: 0.0, max: 2.0, default: 0.0 },
+        ParamDescriptor { id: 3, key: "pitch_hz", name: "Carrier Pitch (Hz)", min: 40.0, max: 400.0, default: 110.0 },
+        ParamDescriptor { id: 4, key: "track_pitch", name: "Track Pitch", min: 0.0, max: 1.0, default: 0.0 },
+    ];
+
+    pub fn new(bands: f32, bandwidth: f32, carrier: Carrier, pitch_hz: f32, track_pitch: bool) -> Self {
+        let mut v = Self {
+            bands: SmoothedParam::new(Self::PARAMS[0], bands),
+            bandwidth: SmoothedParam::new(Self::PARAMS[1], bandwidth),
+            carrier: SmoothedParam::new(Self::PARAMS[2], carrier as u8 as f32),
+            pitch_hz: SmoothedParam::new(Self::PARAMS[3], pitch_hz),
+            track_pitch: SmoothedParam::new(Self::PARAMS[4], if track_pitch { 1.0 } else { 0.0 }),
+            filters: (0..MAX_BANDS).map(|_| Band::new()).collect(),
+            layout: (0, 0.0),
+            tracker: PitchTracker::new(48000, PitchMethod::Pyin),
+            tracked_hz: pitch_hz,
+            phase: 0.0,
+            noise: 1,
+            attack: 0.0,
+            release: 0.0,
+            sample_rate: 0,
+        };
+        v.prepare(48000, 0);
+        v
+    }
+
+    fn carrier_type(&self) -> Carrier {
+        match self.carrier.target().round() as i32 {
+            0 => Carrier::Saw,
+            1 => Carrier::Pulse,
+            _ => Carrier::Noise,
+        }
+    }
+
+    /// Tune the bands for the current band count and bandwidth. Bands that
+    /// weren't running (or all of them, with `snap`) start from silence.
+    fn retune(&mut self, snap: bool) {
+        let count = (self.bands.target().round() as usize).clamp(1, MAX_BANDS);
+        let width = self.bandwidth.target();
+        let high = HIGH_HZ.min(self.sample_rate as f32 * 0.45);
+        let octaves = (high / LOW_HZ).log2() / count as f32;
+        let q = 1.0 / (2.0 * (LN_2 / 2.0 * octaves * width).sinh());
+        let running = if snap { 0 } else { self.layout.0 };
+        for (i, band) in self.filters[..count].iter_mut().enumerate() {
+            band.tune(LOW_HZ * 2.0f32.powf(octaves * (i as f32 + 0.5)), q);
+            if i >= running {
+                band.reset();
+            }
+        }
+        self.layout = (count, width);
+    }
+
+    /// Next sample of the built-in oscillator.
+    fn oscillate(&mut self, carrier: Carrier, freq: f32) -> f32 {
+        let dt = (freq / self.sample_rate as f32).min(0.5);
+        let t = self.phase;
+        self.phase += dt;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+        match carrier {
+            Carrier::Saw => 2.0 * t - 1.0 - poly_blep(t, dt),
+            Carrier::Pulse => {
+                let high = if t < PULSE_WIDTH { 1.0 } else { -1.0 };
+                let fall = (t - PULSE_WIDTH).rem_euclid(1.0);
+                high + poly_blep(t, dt) - poly_blep(fall, dt) - (2.0 * PULSE_WIDTH - 1.0)
+            }
+            Carrier::Noise => {
+                self.noise = self.noise.wrapping_mul(1664525).wrapping_add(1013904223);
+                (self.noise >> 8) as f32 / (1 << 23) as f32 - 1.0
+            }
+        }
+    }
+
+    /// Vocode `buffer`, taking the carrier from `external` if given.
+    fn vocode(&mut self, buffer: &mut AudioBuffer, external: Option<&AudioBuffer>) {
+        if self.layout != (self.bands.target().round() as usize, self.bandwidth.target()) {
+            self.retune(false);
+        }
+        let carrier = self.carrier_type();
+        let track = self.track_pitch.target() >= 0.5;
+        let count = self.layout.0;
+        for i in 0..buffer.samples.len() {
+            let x = buffer.samples[i];
+            let pitch_hz = self.pitch_hz.tick();
+            let c = match external {
+                Some(external) => external.samples[i],
+                None if track => {
+                    self.tracker.process(std::slice::from_ref(&x));
+                    let estimate = self.tracker.estimate();
+                    if estimate.is_voiced() {
+                        self.tracked_hz = estimate.frequency_hz;
+                    }
+                    self.oscillate(carrier, self.tracked_hz)
+                }
+                None => self.oscillate(carrier, pitch_hz),
+            };
+            let (attack, release) = (self.attack, self.release);
+            buffer.samples[i] = self.filters[..count].iter_mut().map(|band| band.process(x, c, attack, release)).sum();
+        }
+    }
+}
+
+impl AudioNode for ChannelVocoder {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.bands.prepare(sample_rate);
+        self.bandwidth.prepare(sample_rate);
+        self.carrier.prepare(sample_rate);
+        self.pitch_hz.prepare(sample_rate);
+        self.track_pitch.prepare(sample_rate);
+        for filter in self.filters.iter_mut().flat_map(Band::filters) {
+            filter.prepare(sample_rate, 0);
+        }
+        if self.tracker.sample_rate() != sample_rate {
+            self.tracker = PitchTracker::new(sample_rate, PitchMethod::Pyin);
+        }
+        self.attack = follower_coeff(ATTACK_MS, sample_rate);
+        self.release = follower_coeff(RELEASE_MS, sample_rate);
+        self.sample_rate = sample_rate;
+        self.retune(true);
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        self.vocode(buffer, None);
+    }
+
+    fn input_ports(&self) -> &[&'static str] {
+        &["in", "carrier"]
+    }
+
+    fn process_ports(&mut self, buffer: &mut AudioBuffer, inputs: &[AudioBuffer], _outputs: &mut [AudioBuffer]) {
+        let external = inputs.first().filter(|carrier| carrier.frames() >= buffer.frames());
+        self.vocode(buffer, external);
+    }
+
+    fn reset(&mut self) {
+        self.pitch_hz.snap();
+        self.retune(true);
+        self.tracker.reset();
+        self.tracked_hz = self.pitch_hz.value();
+        self.phase = 0.0;
+        self.noise = 1;
+    }
+
+    fn name(&self) -> &str {
+        "Channel Vocoder"
+    }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![
+            self.bands.handle(),
+            self.bandwidth.handle(),
+            self.carrier.handle(),
+            self.pitch_hz.handle(),
+            self.track_pitch.handle(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::pitch_correct::tests::{pitch, voice};
+    use std::f32::consts::TAU;
+    use vozoo_core::test_signals::sine;
+
+    fn noise(frames: usize) -> Vec<f32> {
+        let mut seed = 7u32;
+        (0..frames)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                0.5 * ((seed >> 8) as f32 / (1 << 23) as f32 - 1.0)
+            })
+            .collect()
+    }
+
+    /// Amplitude of the `freq` component of `samples`.
+    fn level_at(samples: &[f32], freq: f32) -> f32 {
+        let (re, im) = samples.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, &s)| {
+            let w = TAU * freq * i as f32 / 48000.0;
+            (re + s * w.cos(), im + s * w.sin())
+        });
+        2.0 * (re * re + im * im).sqrt() / samples.len() as f32
+    }
+
+    fn vocode(node: &mut ChannelVocoder, input: &[f32]) -> Vec<f32> {
+        node.prepare(48000, 480);
+        let mut buffer = AudioBuffer::new(input.to_vec(), 48000);
+        buffer.process_blocks(480, |b| node.process(b));
+        buffer.samples
+    }
+
+    #[test]
+    fn test_follows_the_modulator_spectrum() {
+        // A 990 Hz tone lets through the carrier's 9th harmonic, not its
+        // neighbours an octave or more away.
+        let mut node = ChannelVocoder::new(16.0, 1.0, Carrier::Saw, 110.0, false);
+        let output = vocode(&mut node, &sine(990.0, 0.5, 48000, 48000));
+        let tail = &output[9600..];
+        let passed = level_at(tail, 990.0);
+        assert!(passed > 0.1, "{passed}");
+        for far in [220.0, 440.0, 1980.0, 3960.0] {
+            assert!(level_at(tail, far) < passed * 0.15, "{far} Hz: {}", level_at(tail, far));
+        }
+    }
+
+    #[test]
+    fn test_silence_in_silence_out() {
+        for carrier in [Carrier::Saw, Carrier::Pulse, Carrier::Noise] {
+            let output = vocode(&mut ChannelVocoder::new(16.0, 1.0, carrier, 110.0, false), &[0.0; 4800]);
+            assert!(output.iter().all(|s| s.abs() < 1e-6), "{carrier:?}");
+        }
+    }
+
+    #[test]
+    fn test_carrier_pitch() {
+        for carrier in [Carrier::Saw, Carrier::Pulse] {
+            let output = vocode(&mut ChannelVocoder::new(16.0, 1.0, carrier, 200.0, false), &noise(24000));
+            let measured = pitch(&output, 4800, 24000);
+            assert!((measured / 200.0 - 1.0).abs() < 0.01, "{carrier:?}: {measured} Hz");
+        }
+    }
+
+    #[test]
+    fn test_tracks_the_input_pitch() {
+        let input = voice(|_| 150.0, 24000);
+        let output = vocode(&mut ChannelVocoder::new(16.0, 1.0, Carrier::Saw, 110.0, true), &input);
+        let measured = pitch(&output, 9600, 24000);
+        assert!((measured / 150.0 - 1.0).abs() < 0.01, "{measured} Hz");
+    }
+
+    #[test]
+    fn test_external_carrier() {
+        // Noise through a 2 kHz sine carrier comes out as that sine.
+        let mut node = ChannelVocoder::new(16.0, 1.0, Carrier::Saw, 110.0, false);
+        node.prepare(48000, 480);
+        let mut buffer = AudioBuffer::new(noise(24000), 48000);
+        let carrier = AudioBuffer::new(sine(2000.0, 0.5, 48000, 24000), 48000);
+        node.process_ports(&mut buffer, std::slice::from_ref(&carrier), &mut []);
+        let tail = &buffer.samples[4800..];
+        let rms = (tail.iter().map(|s| s * s).sum::<f32>() / tail.len() as f32).sqrt();
+        assert!(level_at(tail, 2000.0) > 1.2 * rms, "{} vs rms {rms}", level_at(tail, 2000.0));
+    }
+}
@@ -1,64 +1,112 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
 use std::f32::consts::TAU;
 
 /// Chorus effect: delay line modulated by LFO.
 pub struct Chorus {
-    delay_ms: f32,
-    depth_ms: f32,
-    rate_hz: f32,
-    mix: f32,
+    delay_ms: SmoothedParam,
+    depth_ms: SmoothedParam,
+    rate_hz: SmoothedParam,
+    mix: SmoothedParam,
     lfo_phase: f32,
+    /// Dry input history, long enough for the maximum modulated delay.
+    history: Vec<f32>,
+    write_pos: usize,
+    configured_sr: u32,
 }
 
 impl Chorus {
+    pub const PARAMS: [ParamDescriptor; 4] = [
+        ParamDescriptor { id: 0, key: "delay_ms", name: "Delay (ms)", min: 1.0, max: 100.0, default: 25.0 },
+        ParamDescriptor { id: 1, key: "depth_ms", name: "Depth (ms)", min: 0.1, max: 20.0, default: 5.0 },
+        ParamDescriptor { id: 2, key: "rate_hz", name: "Rate (Hz)", min: 0.1, max: 10.0, default: 1.5 },
+        ParamDescriptor { id: 3, key: "mix", name: "Wet/Dry Mix", min: 0.0, max: 1.0, default: 0.5 },
+    ];
+
     pub fn new(delay_ms: f32, depth_ms: f32, rate_hz: f32, mix: f32) -> Self {
         Self {
-            delay_ms,
-            depth_ms,
-            rate_hz,
-            mix,
+            delay_ms: SmoothedParam::new(Self::PARAMS[0], delay_ms),
+            depth_ms: SmoothedParam::new(Self::PARAMS[1], depth_ms),
+            rate_hz: SmoothedParam::new(Self::PARAMS[2], rate_hz),
+            mix: SmoothedParam::new(Self::PARAMS[3], mix),
             lfo_phase: 0.0,
+            history: Vec::new(),
+            write_pos: 0,
+            configured_sr: 0,
         }
     }
+
+    fn configure(&mut self, sample_rate: u32) {
+        // Size the history for the longest delay the parameters can reach,
+        // so moving them while streaming never needs a reallocation.
+        let delay_ms = self.delay_ms.value().max(self.delay_ms.descriptor().max);
+        let depth_ms = self.depth_ms.value().abs().max(self.depth_ms.descriptor().max);
+        let max_delay = (delay_ms + depth_ms) * sample_rate as f32 / 1000.0;
+        self.history = vec![0.0; max_delay.max(0.0) as usize + 2];
+        self.write_pos = 0;
+        self.configured_sr = sample_rate;
+    }
 }
 
 impl AudioNode for Chorus {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        for param in [&mut self.delay_ms, &mut self.depth_ms, &mut self.rate_hz, &mut self.mix] {
+            param.prepare(sample_rate);
+        }
+        self.configure(sample_rate);
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
+        if buffer.sample_rate() != self.configured_sr {
+            self.configure(buffer.sample_rate());
+        }
+
         let sr = buffer.sample_rate() as f32;
-        let delay_samples = self.delay_ms * sr / 1000.0;
-        let depth_samples = self.depth_ms * sr / 1000.0;
-        let lfo_inc = TAU * self.rate_hz / sr;
+        let len = self.history.len();
 
-        // We need the original samples for reading delayed values
-        let dry = buffer.samples.clone();
+        for s in &mut buffer.samples {
+            let delay_samples = self.delay_ms.tick() * sr / 1000.0;
+            let depth_samples = self.depth_ms.tick() * sr / 1000.0;
+            let lfo_inc = TAU * self.rate_hz.tick() / sr;
+            let mix = self.mix.tick();
+            let dry = *s;
+            self.history[self.write_pos] = dry;
 
-        for (i, s) in buffer.samples.iter_mut().enumerate() {
             let lfo = self.lfo_phase.sin();
             self.lfo_phase += lfo_inc;
             if self.lfo_phase > TAU {
                 self.lfo_phase -= TAU;
             }
 
-            let current_delay = delay_samples + lfo * depth_samples;
-            let read_idx = i as f32 - current_delay;
-
-            let delayed = if read_idx >= 0.0 && (read_idx as usize) < dry.len() - 1 {
-                let base = read_idx as usize;
-                let frac = read_idx - base as f32;
-                dry[base] * (1.0 - frac) + dry[base + 1] * frac
-            } else {
-                0.0
-            };
+            // Linear interpolation between the two samples around the read point
+            let current_delay = (delay_samples + lfo * depth_samples).clamp(0.0, (len - 2) as f32);
+            let base = current_delay as usize;
+            let frac = current_delay - base as f32;
+            let newer = self.history[(self.write_pos + len - base) % len];
+            let older = self.history[(self.write_pos + len - base - 1) % len];
+            let delayed = newer * (1.0 - frac) + older * frac;
 
-            *s = dry[i] * (1.0 - self.mix) + delayed * self.mix;
+            self.write_pos = (self.write_pos + 1) % len;
+            *s = dry * (1.0 - mix) + delayed * mix;
         }
     }
 
     fn reset(&mut self) {
         self.lfo_phase = 0.0;
+        self.history.fill(0.0);
+        self.write_pos = 0;
+        for param in [&mut self.delay_ms, &mut self.depth_ms, &mut self.rate_hz, &mut self.mix] {
+            param.snap();
+        }
     }
 
     fn name(&self) -> &str {
         "Chorus"
     }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        [&self.delay_ms, &self.depth_ms, &self.rate_hz, &self.mix]
+            .into_iter()
+            .map(SmoothedParam::handle)
+            .collect()
+    }
 }
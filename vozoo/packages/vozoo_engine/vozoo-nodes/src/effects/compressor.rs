@@ -1,17 +1,37 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
 
 /// Feed-forward compressor with peak detection, soft knee, and makeup gain.
+///
+/// In a graph, a signal connected to the "sidechain" input drives the
+/// detector instead of the input itself (e.g. music ducking under a voice).
 pub struct Compressor {
+    threshold_db: SmoothedParam,
+    ratio: SmoothedParam,
+    attack_ms: SmoothedParam,
+    release_ms: SmoothedParam,
+    knee_db: SmoothedParam,
+    makeup_db: SmoothedParam,
+    envelope_db: f32,
+}
+
+/// Gain computer settings for one sample.
+struct Curve {
     threshold_db: f32,
     ratio: f32,
-    attack_ms: f32,
-    release_ms: f32,
     knee_db: f32,
     makeup_db: f32,
-    envelope_db: f32,
 }
 
 impl Compressor {
+    pub const PARAMS: [ParamDescriptor; 6] = [
+        ParamDescriptor { id: 0, key: "threshold_db", name: "Threshold (dB)", min: -60.0, max: 0.0, default: -20.0 },
+        ParamDescriptor { id: 1, key: "ratio", name: "Ratio", min: 1.0, max: 20.0, default: 4.0 },
+        ParamDescriptor { id: 2, key: "attack_ms", name: "Attack (ms)", min: 0.1, max: 100.0, default: 10.0 },
+        ParamDescriptor { id: 3, key: "release_ms", name: "Release (ms)", min: 10.0, max: 1000.0, default: 100.0 },
+        ParamDescriptor { id: 4, key: "knee_db", name: "Knee (dB)", min: 0.0, max: 12.0, default: 6.0 },
+        ParamDescriptor { id: 5, key: "makeup_db", name: "Makeup Gain (dB)", min: 0.0, max: 24.0, default: 0.0 },
+    ];
+
     pub fn new(
         threshold_db: f32,
         ratio: f32,
@@ -20,17 +40,69 @@ impl Compressor {
         knee_db: f32,
         makeup_db: f32,
     ) -> Self {
+        let [threshold, ratio_desc, attack, release, knee, makeup] = Self::PARAMS;
         Self {
-            threshold_db,
-            ratio: ratio.max(1.0),
-            attack_ms,
-            release_ms,
-            knee_db: knee_db.max(0.0),
-            makeup_db,
+            threshold_db: SmoothedParam::new(threshold, threshold_db),
+            ratio: SmoothedParam::new(ratio_desc, ratio.max(1.0)),
+            attack_ms: SmoothedParam::new(attack, attack_ms),
+            release_ms: SmoothedParam::new(release, release_ms),
+            knee_db: SmoothedParam::new(knee, knee_db.max(0.0)),
+            makeup_db: SmoothedParam::new(makeup, makeup_db),
             envelope_db: -96.0,
         }
     }
 
+    /// Compress `buffer`, detecting the level of `sidechain` if given.
+    fn compress(&mut self, buffer: &mut AudioBuffer, sidechain: Option<&AudioBuffer>) {
+        let sr = buffer.sample_rate() as f32;
+        // Time constants only shape the envelope, so once per block is enough.
+        let attack_ms = self.attack_ms.advance(buffer.samples.len());
+        let release_ms = self.release_ms.advance(buffer.samples.len());
+        let attack_coeff = (-1.0 / (attack_ms * 0.001 * sr)).exp();
+        let release_coeff = (-1.0 / (release_ms * 0.001 * sr)).exp();
+
+        for (i, s) in buffer.samples.iter_mut().enumerate() {
+            let curve = Curve {
+                threshold_db: self.threshold_db.tick(),
+                ratio: self.ratio.tick().max(1.0),
+                knee_db: self.knee_db.tick().max(0.0),
+                makeup_db: self.makeup_db.tick(),
+            };
+            let level = match sidechain {
+                // Loudest channel of the key signal.
+                Some(key) => key.frame(i).iter().fold(0.0f32, |peak, k| peak.max(k.abs())),
+                None => s.abs(),
+            };
+            let input_db = 20.0 * level.max(1e-10).log10();
+
+            // Smooth envelope
+            if input_db > self.envelope_db {
+                self.envelope_db =
+                    attack_coeff * self.envelope_db + (1.0 - attack_coeff) * input_db;
+            } else {
+                self.envelope_db =
+                    release_coeff * self.envelope_db + (1.0 - release_coeff) * input_db;
+            }
+
+            let gain_db = curve.gain_reduction_db(self.envelope_db);
+            let gain = 10.0f32.powf(gain_db / 20.0);
+            *s *= gain;
+        }
+    }
+
+    fn smoothed_params(&mut self) -> [&mut SmoothedParam; 6] {
+        [
+            &mut self.threshold_db,
+            &mut self.ratio,
+            &mut self.attack_ms,
+            &mut self.release_ms,
+            &mut self.knee_db,
+            &mut self.makeup_db,
+        ]
+    }
+}
+
+impl Curve {
     /// Compute gain reduction in dB for a given input level in dB.
     fn gain_reduction_db(&self, input_db: f32) -> f32 {
         let half_knee = self.knee_db / 2.0;
@@ -53,36 +125,49 @@ impl Compressor {
 }
 
 impl AudioNode for Compressor {
-    fn process(&mut self, buffer: &mut AudioBuffer) {
-        let sr = buffer.sample_rate() as f32;
-        let attack_coeff = (-1.0 / (self.attack_ms * 0.001 * sr)).exp();
-        let release_coeff = (-1.0 / (self.release_ms * 0.001 * sr)).exp();
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        for param in self.smoothed_params() {
+            param.prepare(sample_rate);
+        }
+    }
 
-        for s in &mut buffer.samples {
-            let input_db = 20.0 * s.abs().max(1e-10).log10();
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        self.compress(buffer, None);
+    }
 
-            // Smooth envelope
-            if input_db > self.envelope_db {
-                self.envelope_db =
-                    attack_coeff * self.envelope_db + (1.0 - attack_coeff) * input_db;
-            } else {
-                self.envelope_db =
-                    release_coeff * self.envelope_db + (1.0 - release_coeff) * input_db;
-            }
+    fn input_ports(&self) -> &[&'static str] {
+        &["in", "sidechain"]
+    }
 
-            let gain_db = self.gain_reduction_db(self.envelope_db);
-            let gain = 10.0f32.powf(gain_db / 20.0);
-            *s *= gain;
-        }
+    fn process_ports(&mut self, buffer: &mut AudioBuffer, inputs: &[AudioBuffer], _outputs: &mut [AudioBuffer]) {
+        let sidechain = inputs.first().filter(|key| key.frames() >= buffer.frames());
+        self.compress(buffer, sidechain);
     }
 
     fn reset(&mut self) {
         self.envelope_db = -96.0;
+        for param in self.smoothed_params() {
+            param.snap();
+        }
     }
 
     fn name(&self) -> &str {
         "Compressor"
     }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        [
+            &self.threshold_db,
+            &self.ratio,
+            &self.attack_ms,
+            &self.release_ms,
+            &self.knee_db,
+            &self.makeup_db,
+        ]
+        .into_iter()
+        .map(SmoothedParam::handle)
+        .collect()
+    }
 }
 
 #[cfg(test)]
@@ -118,4 +203,36 @@ mod tests {
         comp.process(&mut buffer);
         assert!(buffer.samples.iter().all(|s| s.is_finite()));
     }
+
+    #[test]
+    fn test_sidechain_drives_gain_reduction() {
+        let quiet: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.05).sin() * 0.05).collect();
+        let loud = AudioBuffer::new(vec![0.9; 4800], 48000);
+        let silent = AudioBuffer::new(vec![0.0; 4800], 48000);
+        let mut comp = Compressor::new(-20.0, 8.0, 1.0, 100.0, 0.0, 0.0);
+
+        // A loud key ducks a signal far below the threshold...
+        let mut buffer = AudioBuffer::new(quiet.clone(), 48000);
+        comp.process_ports(&mut buffer, &[loud], &mut []);
+        let tail = &buffer.samples[2400..];
+        let peak = tail.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak < 0.02, "quiet input should duck under a loud key, peak {peak}");
+
+        // ...and a silent key leaves a loud signal untouched.
+        comp.reset();
+        let mut buffer = AudioBuffer::new(vec![0.9; 4800], 48000);
+        comp.process_ports(&mut buffer, &[silent], &mut []);
+        assert!(buffer.samples.iter().all(|&s| (s - 0.9).abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_unconnected_sidechain_detects_input() {
+        let samples: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.05).sin() * 0.9).collect();
+        let mut a = AudioBuffer::new(samples.clone(), 48000);
+        let mut b = AudioBuffer::new(samples, 48000);
+        Compressor::new(-20.0, 4.0, 10.0, 100.0, 6.0, 0.0).process(&mut a);
+        let empty = AudioBuffer::empty(48000);
+        Compressor::new(-20.0, 4.0, 10.0, 100.0, 6.0, 0.0).process_ports(&mut b, &[empty], &mut []);
+        assert_eq!(a.samples, b.samples);
+    }
 }
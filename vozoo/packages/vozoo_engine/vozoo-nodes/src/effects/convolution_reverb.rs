@@ -1,39 +1,69 @@
 use rustfft::num_complex::Complex;
 use rustfft::Fft;
 use std::sync::Arc;
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
 
 use super::fft_utils;
+use super::frame_buffer::FrameBuffer;
+
+/// Rate the impulse response is generated at; hosts resample around the
+/// node at other rates.
+const IR_SAMPLE_RATE: u32 = 48000;
 
 /// Convolution reverb using FFT overlap-add with a synthetic impulse response.
 /// The IR is generated from Schroeder parameters (4 comb + 2 allpass filters).
+///
+/// The wet signal is processed in fixed partitions of `block_size` samples,
+/// which delays it by one partition. The dry signal is delayed to match, so
+/// the IR's direct sound lines up with it; the node reports the partition
+/// as its latency.
+///
+/// Only `dry_wet` can change while streaming; `room_size` and `damping`
+/// shape the impulse response and need a rebuilt node.
 pub struct ConvolutionReverb {
+    dry_wet: SmoothedParam,
+    frames: FrameBuffer,
+    /// Delays the dry signal by one partition, like the wet.
+    dry: FrameBuffer,
+    convolver: Convolver,
+    /// Wet signal of the current host block.
+    wet: Vec<f32>,
+}
+
+/// Uniformly partitioned FFT convolution state.
+struct Convolver {
     ir_partitions: Vec<Vec<Complex<f32>>>,
     block_size: usize,
-    fft_size: usize,
-    dry_wet: f32,
-    // Processing state
-    input_buf: Vec<f32>,
     output_tail: Vec<f32>,
+    /// Spectra of the most recent input partitions; `fdl_head` is the newest.
     freq_delay_line: Vec<Vec<Complex<f32>>>,
+    fdl_head: usize,
+    accum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
     fft_forward: Arc<dyn Fft<f32>>,
     fft_inverse: Arc<dyn Fft<f32>>,
 }
 
 impl ConvolutionReverb {
+    pub const PARAMS: [ParamDescriptor; 3] = [
+        ParamDescriptor { id: 0, key: "room_size", name: "Room Size", min: 0.1, max: 2.0, default: 0.5 },
+        ParamDescriptor { id: 1, key: "damping", name: "Damping", min: 0.0, max: 1.0, default: 0.5 },
+        ParamDescriptor { id: 2, key: "dry_wet", name: "Dry/Wet Mix", min: 0.0, max: 1.0, default: 0.3 },
+    ];
+
     pub fn new(room_size: f32, damping: f32, dry_wet: f32) -> Self {
         let block_size = 1024;
         let fft_size = block_size * 2;
         let (fft_forward, fft_inverse) = fft_utils::create_fft_pair(fft_size);
 
         // Generate synthetic IR
-        let sr = 48000.0f32;
+        let sr = IR_SAMPLE_RATE as f32;
         let ir_duration = room_size.clamp(0.1, 2.0) * 2.0; // seconds
         let ir_len = (sr * ir_duration) as usize;
         let ir = Self::generate_ir(ir_len, sr, room_size, damping);
 
         // Partition IR into FFT blocks
-        let num_partitions = (ir.len() + block_size - 1) / block_size;
+        let num_partitions = ir.len().div_ceil(block_size);
         let mut ir_partitions = Vec::with_capacity(num_partitions);
 
         for p in 0..num_partitions {
@@ -49,17 +79,26 @@ impl ConvolutionReverb {
         }
 
         let freq_delay_line = vec![vec![Complex::new(0.0f32, 0.0); fft_size]; num_partitions];
+        let scratch_len = fft_forward
+            .get_inplace_scratch_len()
+            .max(fft_inverse.get_inplace_scratch_len());
 
         Self {
-            ir_partitions,
-            block_size,
-            fft_size,
-            dry_wet: dry_wet.clamp(0.0, 1.0),
-            input_buf: Vec::new(),
-            output_tail: vec![0.0; block_size],
-            freq_delay_line,
-            fft_forward,
-            fft_inverse,
+            dry_wet: SmoothedParam::new(Self::PARAMS[2], dry_wet.clamp(0.0, 1.0)),
+            frames: FrameBuffer::new(block_size),
+            dry: FrameBuffer::new(block_size),
+            convolver: Convolver {
+                ir_partitions,
+                block_size,
+                output_tail: vec![0.0; block_size],
+                freq_delay_line,
+                fdl_head: 0,
+                accum: vec![Complex::new(0.0, 0.0); fft_size],
+                scratch: vec![Complex::new(0.0, 0.0); scratch_len],
+                fft_forward,
+                fft_inverse,
+            },
+            wet: Vec::new(),
         }
     }
 
@@ -149,95 +188,107 @@ impl ConvolutionReverb {
     }
 }
 
-impl AudioNode for ConvolutionReverb {
-    fn process(&mut self, buffer: &mut AudioBuffer) {
-        if buffer.samples.is_empty() || self.ir_partitions.is_empty() {
-            return;
-        }
-
-        let dry = buffer.samples.clone();
-        let input_len = buffer.samples.len();
+impl Convolver {
+    /// Convolve one input partition in place.
+    fn process_block(&mut self, block: &mut [f32]) {
         let block_size = self.block_size;
-        let fft_size = self.fft_size;
         let num_partitions = self.ir_partitions.len();
 
-        // Accumulate input
-        self.input_buf.extend_from_slice(&buffer.samples);
-
-        let mut wet_output = Vec::with_capacity(input_len);
-        let mut produced = 0;
-
-        while self.input_buf.len() >= block_size && produced < input_len {
-            // Take one block from input buffer
-            let block: Vec<f32> = self.input_buf.drain(..block_size).collect();
-
-            // Zero-pad and FFT
-            let mut fft_buf = vec![Complex::new(0.0f32, 0.0); fft_size];
-            for (i, &s) in block.iter().enumerate() {
-                fft_buf[i] = Complex::new(s, 0.0);
+        // Advance the frequency delay line and zero-pad + FFT the new block into it
+        self.fdl_head = (self.fdl_head + num_partitions - 1) % num_partitions;
+        let fft_buf = &mut self.freq_delay_line[self.fdl_head];
+        for (c, &s) in fft_buf.iter_mut().zip(block.iter()) {
+            *c = Complex::new(s, 0.0);
+        }
+        fft_buf[block_size..].fill(Complex::new(0.0, 0.0));
+        self.fft_forward.process_with_scratch(fft_buf, &mut self.scratch);
+
+        // Multiply-accumulate all partitions; partition i pairs with the
+        // input spectrum from i blocks ago
+        self.accum.fill(Complex::new(0.0, 0.0));
+        for (i, ir_part) in self.ir_partitions.iter().enumerate() {
+            let fdl = &self.freq_delay_line[(self.fdl_head + i) % num_partitions];
+            for ((a, &x), &h) in self.accum.iter_mut().zip(fdl.iter()).zip(ir_part.iter()) {
+                *a += x * h;
             }
-            self.fft_forward.process(&mut fft_buf);
+        }
 
-            // Shift frequency delay line
-            for i in (1..num_partitions).rev() {
-                // Move older entries back
-                let (left, right) = self.freq_delay_line.split_at_mut(i);
-                right[0].copy_from_slice(&left[i - 1]);
-            }
-            self.freq_delay_line[0].copy_from_slice(&fft_buf);
-
-            // Multiply-accumulate all partitions
-            let mut accum = vec![Complex::new(0.0f32, 0.0); fft_size];
-            for (i, ir_part) in self.ir_partitions.iter().enumerate() {
-                if i < self.freq_delay_line.len() {
-                    for j in 0..fft_size {
-                        accum[j] += self.freq_delay_line[i][j] * ir_part[j];
-                    }
-                }
-            }
+        // IFFT
+        self.fft_inverse
+            .process_with_scratch(&mut self.accum, &mut self.scratch);
+        let inv = 1.0 / self.accum.len() as f32;
+
+        // First half: add overlap tail and output
+        for ((out, c), tail) in block
+            .iter_mut()
+            .zip(self.accum[..block_size].iter())
+            .zip(self.output_tail.iter())
+        {
+            *out = c.re * inv + tail;
+        }
 
-            // IFFT
-            self.fft_inverse.process(&mut accum);
-            let inv = 1.0 / fft_size as f32;
+        // Second half: save as new overlap tail
+        for (tail, c) in self.output_tail.iter_mut().zip(self.accum[block_size..].iter()) {
+            *tail = c.re * inv;
+        }
+    }
 
-            // First half: add overlap tail and output
-            let mut out_block = Vec::with_capacity(block_size);
-            for i in 0..block_size {
-                out_block.push(accum[i].re * inv + self.output_tail[i]);
-            }
+    fn reset(&mut self) {
+        self.output_tail.fill(0.0);
+        for fdl in &mut self.freq_delay_line {
+            fdl.fill(Complex::new(0.0, 0.0));
+        }
+        self.fdl_head = 0;
+    }
+}
 
-            // Second half: save as new overlap tail
-            for i in 0..block_size {
-                self.output_tail[i] = accum[block_size + i].re * inv;
-            }
+impl AudioNode for ConvolutionReverb {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        self.wet.reserve(max_block.saturating_sub(self.wet.len()));
+        self.dry_wet.prepare(sample_rate);
+    }
 
-            let remaining = (input_len - produced).min(block_size);
-            wet_output.extend_from_slice(&out_block[..remaining]);
-            produced += remaining;
-        }
+    fn latency_samples(&self) -> usize {
+        self.frames.frame_size()
+    }
 
-        // If we didn't produce enough (input shorter than block_size), pad with silence
-        while wet_output.len() < input_len {
-            wet_output.push(0.0);
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        if buffer.samples.is_empty() || self.convolver.ir_partitions.is_empty() {
+            return;
         }
 
+        self.wet.clear();
+        self.wet.extend_from_slice(&buffer.samples);
+        let convolver = &mut self.convolver;
+        self.frames
+            .process(&mut self.wet, |block| convolver.process_block(block));
+        self.dry.process(&mut buffer.samples, |_| {});
+
         // Mix dry and wet
-        for i in 0..input_len {
-            buffer.samples[i] = dry[i] * (1.0 - self.dry_wet) + wet_output[i] * self.dry_wet;
+        for (s, &wet) in buffer.samples.iter_mut().zip(self.wet.iter()) {
+            let dry_wet = self.dry_wet.tick();
+            *s = *s * (1.0 - dry_wet) + wet * dry_wet;
         }
     }
 
     fn reset(&mut self) {
-        self.input_buf.clear();
-        self.output_tail.fill(0.0);
-        for fdl in &mut self.freq_delay_line {
-            fdl.fill(Complex::new(0.0, 0.0));
-        }
+        self.frames.reset();
+        self.dry.reset();
+        self.convolver.reset();
+        self.dry_wet.snap();
     }
 
     fn name(&self) -> &str {
         "Convolution Reverb"
     }
+
+    fn native_sample_rate(&self) -> Option<u32> {
+        Some(IR_SAMPLE_RATE)
+    }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.dry_wet.handle()]
+    }
 }
 
 #[cfg(test)]
@@ -281,12 +332,16 @@ mod tests {
             .collect();
         let dry_copy = samples.clone();
 
-        // Full dry (wet=0) should preserve original signal
+        // Full dry (wet=0) should preserve original signal, delayed by the
+        // reported latency
         let mut buffer = AudioBuffer::new(samples, sr);
         let mut rev = ConvolutionReverb::new(0.5, 0.5, 0.0);
         rev.process(&mut buffer);
 
-        for (i, (&orig, &proc)) in dry_copy.iter().zip(buffer.samples.iter()).enumerate() {
+        let latency = rev.latency_samples();
+        assert_eq!(latency, 1024);
+        assert!(buffer.samples[..latency].iter().all(|&s| s == 0.0));
+        for (i, (&orig, &proc)) in dry_copy.iter().zip(buffer.samples[latency..].iter()).enumerate() {
             assert!(
                 (orig - proc).abs() < 1e-6,
                 "Sample {i}: dry mismatch {orig} vs {proc}"
@@ -294,6 +349,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_wet_direct_sound_lines_up_with_dry() {
+        let first_sound = |dry_wet: f32| {
+            let mut impulse = vec![0.0f32; 4096];
+            impulse[0] = 1.0;
+            let mut buffer = AudioBuffer::new(impulse, 48000);
+            let mut rev = ConvolutionReverb::new(0.5, 0.5, dry_wet);
+            buffer.process_blocks(100, |block| rev.process(block));
+            buffer.samples.iter().position(|s| s.abs() > 1e-3)
+        };
+
+        assert_eq!(first_sound(0.0), Some(1024));
+        assert_eq!(first_sound(1.0), Some(1024));
+    }
+
     #[test]
     fn test_generate_ir_valid() {
         let ir = ConvolutionReverb::generate_ir(48000, 48000.0, 0.5, 0.5);
@@ -19,6 +19,12 @@ impl DcBlocker {
     }
 }
 
+impl Default for DcBlocker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AudioNode for DcBlocker {
     fn process(&mut self, buffer: &mut AudioBuffer) {
         for s in &mut buffer.samples {
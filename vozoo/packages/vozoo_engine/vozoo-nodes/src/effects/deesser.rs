@@ -1,11 +1,19 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
+
+/// Samples between sidechain filter updates while `frequency` glides.
+const COEFF_UPDATE_INTERVAL: usize = 32;
 
 /// Wideband de-esser: highpass sidechain detects sibilance,
 /// then applies gain reduction to the full signal.
+///
+/// In a graph, a signal connected to the "sidechain" input is filtered and
+/// detected instead of the input (e.g. a dry voice keying a processed one).
 pub struct DeEsser {
-    frequency: f32,
-    threshold_db: f32,
-    ratio: f32,
+    frequency: SmoothedParam,
+    threshold_db: SmoothedParam,
+    ratio: SmoothedParam,
+    /// Samples until the next filter update while gliding.
+    update_countdown: usize,
     attack_ms: f32,
     release_ms: f32,
     // Sidechain biquad state (highpass)
@@ -21,11 +29,18 @@ pub struct DeEsser {
 }
 
 impl DeEsser {
+    pub const PARAMS: [ParamDescriptor; 3] = [
+        ParamDescriptor { id: 0, key: "frequency", name: "Frequency (Hz)", min: 2000.0, max: 10000.0, default: 5000.0 },
+        ParamDescriptor { id: 1, key: "threshold_db", name: "Threshold (dB)", min: -40.0, max: 0.0, default: -20.0 },
+        ParamDescriptor { id: 2, key: "ratio", name: "Ratio", min: 1.0, max: 20.0, default: 6.0 },
+    ];
+
     pub fn new(frequency: f32, threshold_db: f32, ratio: f32) -> Self {
         let mut d = Self {
-            frequency,
-            threshold_db,
-            ratio: ratio.max(1.0),
+            frequency: SmoothedParam::new(Self::PARAMS[0], frequency),
+            threshold_db: SmoothedParam::new(Self::PARAMS[1], threshold_db),
+            ratio: SmoothedParam::new(Self::PARAMS[2], ratio.max(1.0)),
+            update_countdown: 0,
             attack_ms: 0.5,
             release_ms: 50.0,
             b0: 1.0,
@@ -44,7 +59,8 @@ impl DeEsser {
 
     fn compute_coefficients(&mut self, sample_rate: u32) {
         self.configured_sr = sample_rate;
-        let w0 = 2.0 * std::f32::consts::PI * self.frequency / sample_rate as f32;
+        let frequency = self.frequency.value().min(sample_rate as f32 * 0.49);
+        let w0 = 2.0 * std::f32::consts::PI * frequency / sample_rate as f32;
         let alpha = w0.sin() / (2.0 * 0.707); // Q = 0.707 (Butterworth)
         let cos_w0 = w0.cos();
 
@@ -68,18 +84,36 @@ impl DeEsser {
     }
 }
 
-impl AudioNode for DeEsser {
-    fn process(&mut self, buffer: &mut AudioBuffer) {
-        if buffer.sample_rate() != self.configured_sr {
-            self.compute_coefficients(buffer.sample_rate());
+impl DeEsser {
+    /// De-ess `buffer`, detecting sibilance in `sidechain` if given.
+    fn deess(&mut self, buffer: &mut AudioBuffer, sidechain: Option<&AudioBuffer>) {
+        let sample_rate = buffer.sample_rate();
+        if sample_rate != self.configured_sr {
+            self.compute_coefficients(sample_rate);
         }
 
-        let sr = buffer.sample_rate() as f32;
+        let sr = sample_rate as f32;
         let attack_coeff = (-1.0 / (self.attack_ms * 0.001 * sr)).exp();
         let release_coeff = (-1.0 / (self.release_ms * 0.001 * sr)).exp();
 
-        for s in &mut buffer.samples {
-            let sc = self.sidechain_filter(*s);
+        for (i, s) in buffer.samples.iter_mut().enumerate() {
+            if self.update_countdown == 0 {
+                if self.frequency.is_smoothing() {
+                    self.frequency.advance(COEFF_UPDATE_INTERVAL);
+                    self.compute_coefficients(sample_rate);
+                }
+                self.update_countdown = COEFF_UPDATE_INTERVAL;
+            }
+            self.update_countdown -= 1;
+            let threshold_db = self.threshold_db.tick();
+            let ratio = self.ratio.tick().max(1.0);
+
+            let key = match sidechain {
+                // Mono sum of the key signal.
+                Some(key) => key.frame(i).iter().sum::<f32>() / key.channels() as f32,
+                None => *s,
+            };
+            let sc = self.sidechain_filter(key);
             let sc_db = 20.0 * sc.abs().max(1e-10).log10();
 
             // Smooth envelope
@@ -92,24 +126,55 @@ impl AudioNode for DeEsser {
             }
 
             // Compute gain reduction when sidechain exceeds threshold
-            let over_db = self.envelope_db - self.threshold_db;
+            let over_db = self.envelope_db - threshold_db;
             if over_db > 0.0 {
-                let gain_reduction_db = over_db * (1.0 - 1.0 / self.ratio);
+                let gain_reduction_db = over_db * (1.0 - 1.0 / ratio);
                 let gain = 10.0f32.powf(-gain_reduction_db / 20.0);
                 *s *= gain;
             }
         }
     }
+}
+
+impl AudioNode for DeEsser {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.frequency.prepare(sample_rate);
+        self.threshold_db.prepare(sample_rate);
+        self.ratio.prepare(sample_rate);
+        self.compute_coefficients(sample_rate);
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        self.deess(buffer, None);
+    }
+
+    fn input_ports(&self) -> &[&'static str] {
+        &["in", "sidechain"]
+    }
+
+    fn process_ports(&mut self, buffer: &mut AudioBuffer, inputs: &[AudioBuffer], _outputs: &mut [AudioBuffer]) {
+        let sidechain = inputs.first().filter(|key| key.frames() >= buffer.frames());
+        self.deess(buffer, sidechain);
+    }
 
     fn reset(&mut self) {
         self.z1 = 0.0;
         self.z2 = 0.0;
         self.envelope_db = -96.0;
+        self.update_countdown = 0;
+        self.frequency.snap();
+        self.threshold_db.snap();
+        self.ratio.snap();
+        self.compute_coefficients(self.configured_sr.max(1));
     }
 
     fn name(&self) -> &str {
         "De-Esser"
     }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.frequency.handle(), self.threshold_db.handle(), self.ratio.handle()]
+    }
 }
 
 #[cfg(test)]
@@ -163,4 +228,23 @@ mod tests {
             "De-esser should preserve 200Hz signal: ratio {ratio}"
         );
     }
+
+    #[test]
+    fn test_deesser_detects_sidechain() {
+        // A low tone keyed by a sibilant sidechain gets turned down, although
+        // it has nothing above the crossover itself.
+        let sr = 48000u32;
+        let tone = |freq: f32, amp: f32| -> Vec<f32> {
+            (0..sr).map(|i| (i as f32 / sr as f32 * freq * std::f32::consts::TAU).sin() * amp).collect()
+        };
+        let key = AudioBuffer::new(tone(8000.0, 0.8), sr);
+
+        let mut buffer = AudioBuffer::new(tone(200.0, 0.3), sr);
+        let mut deesser = DeEsser::new(5000.0, -20.0, 6.0);
+        deesser.process_ports(&mut buffer, &[key], &mut []);
+
+        let rms = |s: &[f32]| (s.iter().map(|x| x * x).sum::<f32>() / s.len() as f32).sqrt();
+        let ratio = rms(&buffer.samples) / rms(&tone(200.0, 0.3));
+        assert!(ratio < 0.5, "sibilant key should reduce the 200Hz signal: ratio {ratio}");
+    }
 }
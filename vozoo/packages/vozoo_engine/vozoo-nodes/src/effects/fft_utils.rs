@@ -9,6 +9,11 @@ pub fn create_fft_pair(size: usize) -> (Arc<dyn Fft<f32>>, Arc<dyn Fft<f32>>) {
     (forward, inverse)
 }
 
+/// Create a forward FFT of the given size, for analysis-only uses.
+pub fn create_fft_forward(size: usize) -> Arc<dyn Fft<f32>> {
+    FftPlanner::new().plan_fft_forward(size)
+}
+
 /// Generate a Hann window of the given size.
 pub fn hann_window(size: usize) -> Vec<f32> {
     (0..size)
@@ -19,6 +24,11 @@ pub fn hann_window(size: usize) -> Vec<f32> {
         .collect()
 }
 
+/// Map a phase to [-π, π].
+pub fn wrap_phase(phase: f32) -> f32 {
+    phase - std::f32::consts::TAU * (phase / std::f32::consts::TAU).round()
+}
+
 /// Convert real samples to complex (imaginary = 0).
 pub fn real_to_complex(samples: &[f32]) -> Vec<Complex<f32>> {
     samples.iter().map(|&s| Complex::new(s, 0.0)).collect()
@@ -1,24 +1,46 @@
 use rustfft::num_complex::Complex;
 use rustfft::Fft;
 use std::sync::Arc;
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
 
 use super::fft_utils;
+use super::stft::Stft;
 
 /// Formant shifter using LPC (Linear Predictive Coding) analysis-resynthesis.
 /// Separates the spectral envelope (formants) from the excitation (residual),
 /// shifts the envelope, and recombines.
+///
+/// Streams with a latency of `fft_size` samples. Like `PitchShift`, a
+/// shifter built at unity passes audio through until the factor first moves.
 pub struct FormantShift {
-    shift_factor: f32,
-    lpc_order: usize,
-    fft_size: usize,
-    hop_size: usize,
-    window: Vec<f32>,
-    fft_forward: Arc<dyn Fft<f32>>,
+    shift_factor: SmoothedParam,
+    active: bool,
+    stft: Stft,
+    analysis: LpcAnalysis,
+}
+
+/// LPC envelope estimation state and scratch buffers.
+pub(super) struct LpcAnalysis {
     fft_inverse: Arc<dyn Fft<f32>>,
+    autocorr_buf: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    autocorr: Vec<f32>,
+    lpc_coeffs: Vec<f32>,
+    lpc_temp: Vec<f32>,
+    original_env: Vec<f32>,
+    shifted_env: Vec<f32>,
 }
 
 impl FormantShift {
+    pub const PARAMS: [ParamDescriptor; 1] = [ParamDescriptor {
+        id: 0,
+        key: "shift_factor",
+        name: "Shift Factor",
+        min: 0.5,
+        max: 2.0,
+        default: 1.0,
+    }];
+
     pub fn new(shift_factor: f32) -> Self {
         Self::with_order(shift_factor, 16)
     }
@@ -26,27 +48,25 @@ impl FormantShift {
     pub fn with_order(shift_factor: f32, lpc_order: usize) -> Self {
         let fft_size = 2048;
         let hop_size = fft_size / 4;
-        let (fft_forward, fft_inverse) = fft_utils::create_fft_pair(fft_size);
 
         Self {
-            shift_factor,
-            lpc_order,
-            fft_size,
-            hop_size,
-            window: fft_utils::hann_window(fft_size),
-            fft_forward,
-            fft_inverse,
+            shift_factor: SmoothedParam::new(Self::PARAMS[0], shift_factor),
+            active: false,
+            stft: Stft::new(fft_size, hop_size),
+            analysis: LpcAnalysis::new(fft_size, lpc_order),
         }
     }
 
     /// Levinson-Durbin recursion: compute LPC coefficients from autocorrelation.
-    fn levinson_durbin(autocorr: &[f32], order: usize) -> Vec<f32> {
-        let mut coeffs = vec![0.0f32; order + 1];
-        let mut temp = vec![0.0f32; order + 1];
+    ///
+    /// `coeffs` and `temp` must hold `order + 1` values; the result is
+    /// written to `coeffs`.
+    fn levinson_durbin(autocorr: &[f32], order: usize, coeffs: &mut [f32], temp: &mut [f32]) {
+        coeffs.fill(0.0);
         coeffs[0] = 1.0;
 
         if autocorr[0].abs() < 1e-10 {
-            return coeffs;
+            return;
         }
 
         let mut error = autocorr[0];
@@ -71,16 +91,11 @@ impl FormantShift {
                 break;
             }
         }
-
-        coeffs
     }
 
     /// Compute LPC spectral envelope magnitude at each FFT bin.
-    fn lpc_envelope(&self, coeffs: &[f32], num_bins: usize) -> Vec<f32> {
-        let mut envelope = vec![0.0f32; num_bins];
-        let fft_size = self.fft_size;
-
-        for k in 0..num_bins {
+    fn lpc_envelope(coeffs: &[f32], fft_size: usize, envelope: &mut [f32]) {
+        for (k, env) in envelope.iter_mut().enumerate() {
             let freq = 2.0 * std::f32::consts::PI * k as f32 / fft_size as f32;
             let mut re = 0.0f32;
             let mut im = 0.0f32;
@@ -91,132 +106,142 @@ impl FormantShift {
             }
             let mag_sq = re * re + im * im;
             // Envelope = 1 / |A(z)| — clamp to avoid division by zero
-            envelope[k] = 1.0 / mag_sq.sqrt().max(1e-6);
+            *env = 1.0 / mag_sq.sqrt().max(1e-6);
         }
-
-        envelope
     }
 
     /// Shift envelope by resampling in frequency domain.
-    fn shift_envelope(&self, envelope: &[f32]) -> Vec<f32> {
+    pub(super) fn shift_envelope(envelope: &[f32], shift_factor: f32, shifted: &mut [f32]) {
         let len = envelope.len();
-        let mut shifted = vec![0.0f32; len];
 
-        for k in 0..len {
-            let src = k as f32 / self.shift_factor;
+        for (k, out) in shifted.iter_mut().enumerate() {
+            let src = k as f32 / shift_factor;
             let src_idx = src as usize;
             let frac = src - src_idx as f32;
 
-            if src_idx + 1 < len {
-                shifted[k] = envelope[src_idx] * (1.0 - frac) + envelope[src_idx + 1] * frac;
+            *out = if src_idx + 1 < len {
+                envelope[src_idx] * (1.0 - frac) + envelope[src_idx + 1] * frac
             } else if src_idx < len {
-                shifted[k] = envelope[src_idx];
-            }
-            // else: leave as 0.0 (frequency beyond original range)
+                envelope[src_idx]
+            } else {
+                0.0 // frequency beyond original range
+            };
         }
-
-        shifted
     }
 }
 
-impl AudioNode for FormantShift {
-    fn process(&mut self, buffer: &mut AudioBuffer) {
-        if (self.shift_factor - 1.0).abs() < 0.01 || buffer.samples.is_empty() {
-            return;
-        }
-
-        let fft_size = self.fft_size;
-        let hop_size = self.hop_size;
+impl LpcAnalysis {
+    pub(super) fn new(fft_size: usize, lpc_order: usize) -> Self {
         let num_bins = fft_size / 2 + 1;
-        let inv_fft_size = 1.0 / fft_size as f32;
-        let input_len = buffer.samples.len();
+        let (_, fft_inverse) = fft_utils::create_fft_pair(fft_size);
+        let scratch_len = fft_inverse.get_inplace_scratch_len();
 
-        // Pad input
-        let padded_len = input_len + fft_size;
-        let mut padded = vec![0.0f32; padded_len];
-        padded[..input_len].copy_from_slice(&buffer.samples);
+        Self {
+            fft_inverse,
+            autocorr_buf: vec![Complex::new(0.0, 0.0); fft_size],
+            scratch: vec![Complex::new(0.0, 0.0); scratch_len],
+            autocorr: vec![0.0; lpc_order + 1],
+            lpc_coeffs: vec![0.0; lpc_order + 1],
+            lpc_temp: vec![0.0; lpc_order + 1],
+            original_env: vec![0.0; num_bins],
+            shifted_env: vec![0.0; num_bins],
+        }
+    }
 
-        let mut output = vec![0.0f32; padded_len];
-        let mut window_sum = vec![0.0f32; padded_len];
+    /// Estimate the spectral envelope of one full `fft_size`-point STFT
+    /// frame, one magnitude per bin up to Nyquist.
+    pub(super) fn envelope(&mut self, fft_buf: &[Complex<f32>]) -> &[f32] {
+        let lpc_order = self.lpc_coeffs.len() - 1;
+        let fft_size = fft_buf.len();
+        let inv_fft_size = 1.0 / fft_size as f32;
 
-        let mut fft_buf = vec![Complex::new(0.0f32, 0.0); fft_size];
-        let mut autocorr_buf = vec![Complex::new(0.0f32, 0.0); fft_size];
+        // Compute autocorrelation via FFT: autocorr = IFFT(|FFT(x)|^2)
+        for (a, c) in self.autocorr_buf.iter_mut().zip(fft_buf.iter()) {
+            *a = Complex::new(c.norm_sqr(), 0.0);
+        }
+        self.fft_inverse
+            .process_with_scratch(&mut self.autocorr_buf, &mut self.scratch);
 
-        let mut pos = 0;
-        while pos + fft_size <= padded_len {
-            // Window the frame
-            let mut frame = vec![0.0f32; fft_size];
-            for i in 0..fft_size {
-                frame[i] = padded[pos + i] * self.window[i];
-            }
+        // Extract autocorrelation values (normalized)
+        for (r, c) in self.autocorr.iter_mut().zip(self.autocorr_buf.iter()) {
+            *r = c.re * inv_fft_size * inv_fft_size;
+        }
 
-            // Compute autocorrelation via FFT: autocorr = IFFT(|FFT(x)|^2)
-            for i in 0..fft_size {
-                autocorr_buf[i] = Complex::new(frame[i], 0.0);
-            }
-            self.fft_forward.process(&mut autocorr_buf);
-            for c in &mut autocorr_buf {
-                *c = Complex::new(c.norm_sqr(), 0.0);
-            }
-            self.fft_inverse.process(&mut autocorr_buf);
+        // LPC analysis
+        FormantShift::levinson_durbin(
+            &self.autocorr,
+            lpc_order,
+            &mut self.lpc_coeffs,
+            &mut self.lpc_temp,
+        );
 
-            // Extract autocorrelation values (normalized)
-            let autocorr: Vec<f32> = (0..=self.lpc_order)
-                .map(|i| autocorr_buf[i].re * inv_fft_size * inv_fft_size)
-                .collect();
+        FormantShift::lpc_envelope(&self.lpc_coeffs, fft_size, &mut self.original_env);
+        &self.original_env
+    }
 
-            // LPC analysis
-            let lpc_coeffs = Self::levinson_durbin(&autocorr, self.lpc_order);
+    /// Replace the spectral envelope of one STFT frame with a shifted copy.
+    fn shift_frame(&mut self, fft_buf: &mut [Complex<f32>], shift_factor: f32) {
+        let fft_size = fft_buf.len();
+        let num_bins = fft_size / 2 + 1;
 
-            // Compute original and shifted envelopes
-            let original_env = self.lpc_envelope(&lpc_coeffs, num_bins);
-            let shifted_env = self.shift_envelope(&original_env);
+        // Compute original and shifted envelopes
+        self.envelope(fft_buf);
+        FormantShift::shift_envelope(&self.original_env, shift_factor, &mut self.shifted_env);
 
-            // FFT the windowed frame
-            for i in 0..fft_size {
-                fft_buf[i] = Complex::new(frame[i], 0.0);
-            }
-            self.fft_forward.process(&mut fft_buf);
-
-            // Apply envelope modification: divide by original, multiply by shifted
-            for k in 0..num_bins {
-                let ratio = shifted_env[k] / original_env[k].max(1e-6);
-                fft_buf[k] *= ratio;
-                if k > 0 && k < fft_size / 2 {
-                    fft_buf[fft_size - k] = fft_buf[k].conj();
-                }
+        // Apply envelope modification: divide by original, multiply by shifted
+        for k in 0..num_bins {
+            let ratio = self.shifted_env[k] / self.original_env[k].max(1e-6);
+            fft_buf[k] *= ratio;
+            if k > 0 && k < fft_size / 2 {
+                fft_buf[fft_size - k] = fft_buf[k].conj();
             }
+        }
+    }
+}
 
-            // IFFT
-            self.fft_inverse.process(&mut fft_buf);
-
-            // Overlap-add
-            for i in 0..fft_size {
-                output[pos + i] += fft_buf[i].re * inv_fft_size * self.window[i];
-                window_sum[pos + i] += self.window[i] * self.window[i];
-            }
+impl AudioNode for FormantShift {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.shift_factor.prepare(sample_rate);
+    }
 
-            pos += hop_size;
+    fn latency_samples(&self) -> usize {
+        if self.active || (self.shift_factor.target() - 1.0).abs() >= 0.01 {
+            self.max_latency_samples()
+        } else {
+            0
         }
+    }
 
-        // Normalize and write back
-        let mut result = Vec::with_capacity(input_len);
-        for i in 0..input_len {
-            if window_sum[i] > 1e-6 {
-                result.push(output[i] / window_sum[i]);
-            } else {
-                result.push(0.0);
-            }
+    fn max_latency_samples(&self) -> usize {
+        self.stft.fft_size()
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        self.active |= (self.shift_factor.target() - 1.0).abs() >= 0.01;
+        if !self.active || buffer.samples.is_empty() {
+            return;
         }
 
-        buffer.samples = result;
+        let hop_size = self.stft.hop_size();
+        let shift_factor = &mut self.shift_factor;
+        let analysis = &mut self.analysis;
+        self.stft.process(&mut buffer.samples, |spectrum| {
+            analysis.shift_frame(spectrum, shift_factor.advance(hop_size));
+        });
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        self.stft.reset();
+        self.shift_factor.snap();
+    }
 
     fn name(&self) -> &str {
         "Formant Shift"
     }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.shift_factor.handle()]
+    }
 }
 
 #[cfg(test)]
@@ -271,7 +296,9 @@ mod tests {
     fn test_levinson_durbin_basic() {
         // White noise autocorrelation: R[0] = 1.0, R[k>0] = 0.0
         let autocorr = vec![1.0, 0.0, 0.0, 0.0, 0.0];
-        let coeffs = FormantShift::levinson_durbin(&autocorr, 4);
+        let mut coeffs = vec![0.0; 5];
+        let mut temp = vec![0.0; 5];
+        FormantShift::levinson_durbin(&autocorr, 4, &mut coeffs, &mut temp);
         // All reflection coefficients should be 0 for white noise
         assert!((coeffs[0] - 1.0).abs() < 1e-6);
         for c in &coeffs[1..] {
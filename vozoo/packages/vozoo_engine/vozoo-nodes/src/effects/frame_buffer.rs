@@ -0,0 +1,71 @@
+/// Re-blocks a stream of arbitrary-sized buffers into fixed-size frames.
+///
+/// Nodes that work on whole frames (RNNoise, STFT hops, FFT convolution)
+/// use this so their output doesn't depend on the host's block size.
+/// Output lags input by exactly `frame_size` samples.
+pub struct FrameBuffer {
+    frame_size: usize,
+    /// Frame currently being collected from the input.
+    input: Vec<f32>,
+    /// Last processed frame, played out while the next one is collected.
+    output: Vec<f32>,
+    pos: usize,
+}
+
+impl FrameBuffer {
+    pub fn new(frame_size: usize) -> Self {
+        assert!(frame_size > 0, "frame_size must be non-zero");
+        Self {
+            frame_size,
+            input: vec![0.0; frame_size],
+            output: vec![0.0; frame_size],
+            pos: 0,
+        }
+    }
+
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// Push `samples` through the frame buffer in place.
+    ///
+    /// `process_frame` is called with each completed input frame and must
+    /// overwrite it with the processed frame.
+    pub fn process(&mut self, samples: &mut [f32], mut process_frame: impl FnMut(&mut [f32])) {
+        for s in samples.iter_mut() {
+            self.input[self.pos] = *s;
+            *s = self.output[self.pos];
+            self.pos += 1;
+
+            if self.pos == self.frame_size {
+                process_frame(&mut self.input);
+                std::mem::swap(&mut self.input, &mut self.output);
+                self.pos = 0;
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.input.fill(0.0);
+        self.output.fill(0.0);
+        self.pos = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_buffer_delays_by_frame_size() {
+        let mut fb = FrameBuffer::new(4);
+        let mut samples: Vec<f32> = (1..=10).map(|i| i as f32).collect();
+
+        // Identity processing in uneven blocks.
+        let (a, b) = samples.split_at_mut(3);
+        fb.process(a, |_| {});
+        fb.process(b, |_| {});
+
+        assert_eq!(samples, vec![0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    }
+}
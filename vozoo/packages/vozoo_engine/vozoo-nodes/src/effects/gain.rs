@@ -1,26 +1,55 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{AudioBuffer, AudioNode, ChannelLayout, ParamDescriptor, ParamHandle, SmoothedParam};
 
 /// Simple gain (volume) node.
 pub struct Gain {
-    factor: f32,
+    factor: SmoothedParam,
 }
 
 impl Gain {
+    pub const PARAMS: [ParamDescriptor; 1] = [ParamDescriptor {
+        id: 0,
+        key: "factor",
+        name: "Volume",
+        min: 0.0,
+        max: 4.0,
+        default: 1.0,
+    }];
+
     pub fn new(factor: f32) -> Self {
-        Self { factor }
+        Self {
+            factor: SmoothedParam::new(Self::PARAMS[0], factor),
+        }
     }
 }
 
 impl AudioNode for Gain {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.factor.prepare(sample_rate);
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
-        for s in &mut buffer.samples {
-            *s *= self.factor;
+        let ch = buffer.channels() as usize;
+        for frame in buffer.samples.chunks_exact_mut(ch) {
+            let factor = self.factor.tick();
+            for s in frame {
+                *s *= factor;
+            }
         }
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        self.factor.snap();
+    }
 
     fn name(&self) -> &str {
         "Gain"
     }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.factor.handle()]
+    }
+
+    fn input_layout(&self) -> Option<ChannelLayout> {
+        None
+    }
 }
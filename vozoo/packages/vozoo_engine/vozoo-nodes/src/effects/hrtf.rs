@@ -1,38 +1,73 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{AudioBuffer, AudioNode, ChannelLayout, ParamDescriptor, ParamHandle, SmoothedParam};
+
+/// Samples between position updates while the source moves.
+const POSITION_UPDATE_INTERVAL: usize = 32;
+
+/// Rate the ITD delay line and head-shadow filter are tuned for; hosts
+/// resample around the node at other rates.
+const DESIGN_SAMPLE_RATE: u32 = 48000;
 
 /// Simple HRTF-based 3D audio positioning.
 ///
 /// Uses a synthetic Head-Related Transfer Function to position audio
-/// in 3D space around the listener. The output is stereo (interleaved L/R)
-/// packed into the mono buffer: [L0, R0, L1, R1, ...].
+/// in 3D space around the listener. Takes mono input and produces a stereo
+/// buffer. In a graph, the "left" and "right" outputs carry each ear on its
+/// own as mono.
 ///
 /// Parameters:
 /// - `azimuth`: horizontal angle in degrees, 0=front, 90=right, -90=left, 180=behind
 /// - `elevation`: vertical angle in degrees, -90=below, 0=level, 90=above
 /// - `distance`: distance in arbitrary units, affects gain and delay
 pub struct Hrtf {
-    azimuth: f32,
-    elevation: f32,
-    distance: f32,
+    azimuth: SmoothedParam,
+    elevation: SmoothedParam,
+    distance: SmoothedParam,
+    /// Gains and delays for the current (smoothed) position.
+    position: Position,
+    /// Samples until the next position update while moving.
+    update_countdown: usize,
     /// Delay line for ITD (Interaural Time Difference)
     delay_buf_l: Vec<f32>,
     delay_buf_r: Vec<f32>,
     delay_write_pos: usize,
     /// Simple low-pass state for head shadow on far ear
     lpf_state: f32,
+    /// Copy of the mono input while the buffer is rewritten as stereo.
+    input: Vec<f32>,
+}
+
+/// Per-ear delays and gains derived from the source position.
+#[derive(Clone, Copy, Default)]
+struct Position {
+    left_delay: usize,
+    right_delay: usize,
+    gain_l: f32,
+    gain_r: f32,
+    dist_gain: f32,
+    shadow_alpha: f32,
+    azimuth: f32,
 }
 
 impl Hrtf {
+    pub const PARAMS: [ParamDescriptor; 3] = [
+        ParamDescriptor { id: 0, key: "azimuth", name: "Azimuth (deg)", min: -180.0, max: 180.0, default: 0.0 },
+        ParamDescriptor { id: 1, key: "elevation", name: "Elevation (deg)", min: -90.0, max: 90.0, default: 0.0 },
+        ParamDescriptor { id: 2, key: "distance", name: "Distance", min: 0.1, max: 10.0, default: 1.0 },
+    ];
+
     pub fn new(azimuth: f32, elevation: f32, distance: f32) -> Self {
         let max_delay = 128; // samples, enough for ~2.7ms ITD at 48kHz
         Self {
-            azimuth: azimuth.clamp(-180.0, 180.0),
-            elevation: elevation.clamp(-90.0, 90.0),
-            distance: distance.max(0.1),
+            azimuth: SmoothedParam::new(Self::PARAMS[0], azimuth.clamp(-180.0, 180.0)),
+            elevation: SmoothedParam::new(Self::PARAMS[1], elevation.clamp(-90.0, 90.0)),
+            distance: SmoothedParam::new(Self::PARAMS[2], distance.max(0.1)),
+            position: Position::default(),
+            update_countdown: 0,
             delay_buf_l: vec![0.0; max_delay],
             delay_buf_r: vec![0.0; max_delay],
             delay_write_pos: 0,
             lpf_state: 0.0,
+            input: Vec::new(),
         }
     }
 
@@ -43,10 +78,10 @@ impl Hrtf {
         // Simplified: max ITD ~0.7ms at 90°, head radius ~8.75cm
         let head_radius_m = 0.0875;
         let speed_of_sound = 343.0;
-        let theta = self.azimuth.to_radians();
+        let theta = self.azimuth.value().to_radians();
         let itd_sec = (head_radius_m / speed_of_sound) * (theta.abs() + theta.abs().sin());
         let itd_samples = itd_sec * sample_rate as f32;
-        if self.azimuth >= 0.0 { itd_samples } else { -itd_samples }
+        if self.azimuth.value() >= 0.0 { itd_samples } else { -itd_samples }
     }
 
     /// Compute ILD (Interaural Level Difference) based on azimuth.
@@ -55,56 +90,89 @@ impl Hrtf {
         // Simple panning model with head shadow.
         // At azimuth=0 (front), both ears equal.
         // At azimuth=90 (right), left ear attenuated.
-        let theta = self.azimuth.to_radians();
+        let theta = self.azimuth.value().to_radians();
         let pan = theta.sin(); // -1 (left) to +1 (right)
 
         let left_gain = ((1.0 - pan) / 2.0).sqrt();
         let right_gain = ((1.0 + pan) / 2.0).sqrt();
 
         // Elevation affects overall gain slightly (sources above are louder).
-        let elev_factor = 1.0 + self.elevation.to_radians().sin() * 0.1;
+        let elev_factor = 1.0 + self.elevation.value().to_radians().sin() * 0.1;
 
         (left_gain * elev_factor, right_gain * elev_factor)
     }
 
     /// Distance attenuation (inverse distance law).
     fn distance_gain(&self) -> f32 {
-        (1.0 / self.distance).min(1.0)
+        (1.0 / self.distance.value()).min(1.0)
     }
 
     /// Head shadow low-pass coefficient for the far ear.
     /// Higher azimuth = more filtering on far ear.
     fn shadow_coeff(&self) -> f32 {
-        let shadow_amount = self.azimuth.to_radians().sin().abs();
+        let shadow_amount = self.azimuth.value().to_radians().sin().abs();
         // 0.0 = no filtering, approaching 1.0 = heavy filtering
         1.0 - shadow_amount * 0.6
     }
-}
-
-impl AudioNode for Hrtf {
-    fn process(&mut self, buffer: &mut AudioBuffer) {
-        let sample_rate = buffer.sample_rate();
-        let input = buffer.samples.clone();
-        let num_samples = input.len();
 
+    fn position(&self, sample_rate: u32) -> Position {
         let itd = self.itd_samples(sample_rate);
         let (gain_l, gain_r) = self.ild_gains();
-        let dist_gain = self.distance_gain();
-        let shadow_alpha = self.shadow_coeff();
-
         let delay_len = self.delay_buf_l.len();
 
         // Determine which ear is delayed.
         let left_delay = if itd < 0.0 { itd.abs() as usize } else { 0 };
         let right_delay = if itd >= 0.0 { itd as usize } else { 0 };
-        let left_delay = left_delay.min(delay_len - 1);
-        let right_delay = right_delay.min(delay_len - 1);
+
+        Position {
+            left_delay: left_delay.min(delay_len - 1),
+            right_delay: right_delay.min(delay_len - 1),
+            gain_l,
+            gain_r,
+            dist_gain: self.distance_gain(),
+            shadow_alpha: self.shadow_coeff(),
+            azimuth: self.azimuth.value(),
+        }
+    }
+}
+
+impl AudioNode for Hrtf {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        self.input.reserve(max_block.saturating_sub(self.input.len()));
+        for param in [&mut self.azimuth, &mut self.elevation, &mut self.distance] {
+            param.prepare(sample_rate);
+        }
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        let sample_rate = buffer.sample_rate();
+        self.input.clear();
+        self.input.extend_from_slice(&buffer.samples);
+        let num_samples = self.input.len();
+        let delay_len = self.delay_buf_l.len();
 
         // Output: interleaved stereo [L, R, L, R, ...]
-        let mut stereo = vec![0.0f32; num_samples * 2];
+        buffer.reshape(num_samples, 2);
+
+        for (&input, out) in self.input.iter().zip(buffer.samples.chunks_exact_mut(2)) {
+            if self.update_countdown == 0 {
+                let moving = [&self.azimuth, &self.elevation, &self.distance]
+                    .iter()
+                    .any(|p| p.is_smoothing());
+                if moving {
+                    for param in [&mut self.azimuth, &mut self.elevation, &mut self.distance] {
+                        param.advance(POSITION_UPDATE_INTERVAL);
+                    }
+                }
+                // Also refreshes after a sample rate change.
+                self.position = self.position(sample_rate);
+                self.update_countdown = POSITION_UPDATE_INTERVAL;
+            }
+            self.update_countdown -= 1;
 
-        for i in 0..num_samples {
-            let sample = input[i] * dist_gain;
+            let Position { left_delay, right_delay, gain_l, gain_r, dist_gain, shadow_alpha, azimuth } =
+                self.position;
+            let sample = input * dist_gain;
 
             // Write to delay buffers.
             let wp = self.delay_write_pos % delay_len;
@@ -119,24 +187,35 @@ impl AudioNode for Hrtf {
             let mut right_sample = self.delay_buf_r[read_r] * gain_r;
 
             // Apply head shadow (simple LPF) to the far ear.
-            if self.azimuth > 0.0 {
+            if azimuth > 0.0 {
                 // Source on right, shadow on left ear.
                 self.lpf_state = self.lpf_state * (1.0 - shadow_alpha) + left_sample * shadow_alpha;
                 left_sample = self.lpf_state;
-            } else if self.azimuth < 0.0 {
+            } else if azimuth < 0.0 {
                 // Source on left, shadow on right ear.
                 self.lpf_state = self.lpf_state * (1.0 - shadow_alpha) + right_sample * shadow_alpha;
                 right_sample = self.lpf_state;
             }
 
-            stereo[i * 2] = left_sample;
-            stereo[i * 2 + 1] = right_sample;
+            out[0] = left_sample;
+            out[1] = right_sample;
 
             self.delay_write_pos += 1;
         }
+    }
 
-        // Replace buffer with interleaved stereo.
-        buffer.samples = stereo;
+    fn output_ports(&self) -> &[&'static str] {
+        &["out", "left", "right"]
+    }
+
+    fn process_ports(&mut self, buffer: &mut AudioBuffer, _inputs: &[AudioBuffer], outputs: &mut [AudioBuffer]) {
+        self.process(buffer);
+        for (channel, ear) in outputs.iter_mut().enumerate() {
+            ear.set_sample_rate(buffer.sample_rate());
+            ear.samples.clear();
+            ear.samples.extend(buffer.channel(channel as u16));
+            ear.reshape(buffer.frames(), 1);
+        }
     }
 
     fn reset(&mut self) {
@@ -144,11 +223,30 @@ impl AudioNode for Hrtf {
         self.delay_buf_r.fill(0.0);
         self.delay_write_pos = 0;
         self.lpf_state = 0.0;
+        self.update_countdown = 0;
+        for param in [&mut self.azimuth, &mut self.elevation, &mut self.distance] {
+            param.snap();
+        }
     }
 
     fn name(&self) -> &str {
         "HRTF 3D Audio"
     }
+
+    fn native_sample_rate(&self) -> Option<u32> {
+        Some(DESIGN_SAMPLE_RATE)
+    }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        [&self.azimuth, &self.elevation, &self.distance]
+            .into_iter()
+            .map(SmoothedParam::handle)
+            .collect()
+    }
+
+    fn output_layout(&self, _input: ChannelLayout) -> ChannelLayout {
+        ChannelLayout::Stereo
+    }
 }
 
 #[cfg(test)]
@@ -161,7 +259,9 @@ mod tests {
         let input = vec![1.0; 100];
         let mut buffer = AudioBuffer::new(input, 48000);
         hrtf.process(&mut buffer);
-        // Output should be 2x the input length (stereo interleaved).
+        // Output should be stereo with the same number of frames.
+        assert_eq!(buffer.channels(), 2);
+        assert_eq!(buffer.frames(), 100);
         assert_eq!(buffer.samples.len(), 200);
     }
 
@@ -224,4 +324,19 @@ mod tests {
         hrtf.process(&mut buffer);
         assert!(buffer.samples.iter().all(|s| s.is_finite()));
     }
+
+    #[test]
+    fn test_hrtf_ear_ports_split_the_stereo_output() {
+        let mut hrtf = Hrtf::new(60.0, 0.0, 1.0);
+        let input: Vec<f32> = (0..480).map(|i| (i as f32 * 0.1).sin()).collect();
+        let mut buffer = AudioBuffer::new(input, 48000);
+        let mut ears = vec![AudioBuffer::empty(48000), AudioBuffer::empty(48000)];
+        hrtf.process_ports(&mut buffer, &[], &mut ears);
+
+        assert_eq!(hrtf.output_ports(), ["out", "left", "right"]);
+        for (channel, ear) in ears.iter().enumerate() {
+            assert_eq!(ear.channels(), 1);
+            assert!(ear.samples.iter().eq(buffer.channel(channel as u16)));
+        }
+    }
 }
@@ -1,4 +1,10 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use std::collections::VecDeque;
+
+use vozoo_core::{
+    AudioBuffer, AudioNode, ChannelLayout, ParamDescriptor, ParamHandle, SmoothedParam, PROCESSING_SAMPLE_RATE,
+};
+
+use super::loudness::TruePeak;
 
 /// Hard limiter: clamps samples to [-1.0, 1.0].
 pub struct HardLimiter;
@@ -13,77 +19,235 @@ impl AudioNode for HardLimiter {
     fn name(&self) -> &str {
         "Hard Limiter"
     }
+
+    fn input_layout(&self) -> Option<ChannelLayout> {
+        None
+    }
 }
 
-/// Lookahead limiter with attack/release envelope.
-/// Prevents clipping by looking ahead and smoothly reducing gain.
+/// Lookahead limiter that never lets a sample past the ceiling.
+///
+/// Each frame's peak (across channels, including inter-sample peaks found
+/// by 4x oversampling) sets the gain it needs. The limiter takes the
+/// smallest needed gain over the next `lookahead_ms` with a sliding-window
+/// maximum (a monotonic deque, O(1) per frame), lets it recover with a
+/// `release_ms` time constant, then averages it over the lookahead window.
+/// The average only ever ramps down towards a peak across the window that
+/// already contains it, so the gain has fully arrived when the peak does.
+/// Multichannel input is limited with one linked gain so the image doesn't
+/// shift, and a final clamp absorbs rounding.
+///
+/// The signal is delayed by `latency_samples()` frames: the lookahead plus
+/// what the true-peak detector needs to see past each sample. The delay
+/// runs across calls, so peaks straddling block boundaries are caught at
+/// any block size.
 pub struct LookaheadLimiter {
-    ceiling_db: f32,
-    attack_ms: f32,
+    ceiling_db: SmoothedParam,
     release_ms: f32,
     lookahead_ms: f32,
-    gain: f32,
+    /// Window length in frames, from `lookahead_ms`.
+    lookahead: usize,
+    /// Interleaved delay line of `latency + 1` frames.
+    delay: Vec<f32>,
+    /// Frames processed since the last reset; also indexes the delay line.
+    frame: usize,
+    detectors: Vec<TruePeak>,
+    /// Inter-sample peak of the span before the frame being measured.
+    last_span: f32,
+    /// (frame, peak) pairs with decreasing peaks: the window maximum is
+    /// at the front.
+    window: VecDeque<(usize, f32)>,
+    /// Needed gain after release smoothing.
+    held: f32,
+    /// The last `lookahead + 1` held gains and their sum.
+    ramp: Vec<f32>,
+    ramp_pos: usize,
+    ramp_sum: f64,
+    configured_sr: u32,
+    configured_channels: usize,
 }
 
+/// Frames between a sample arriving and its peak being known: the true-peak
+/// detector's latency plus the span after the sample.
+const PEAK_DELAY: usize = TruePeak::LATENCY + 1;
+
 impl LookaheadLimiter {
+    pub const PARAMS: [ParamDescriptor; 1] = [ParamDescriptor {
+        id: 0,
+        key: "ceiling_db",
+        name: "Ceiling (dB)",
+        min: -12.0,
+        max: 0.0,
+        default: -1.0,
+    }];
+
     pub fn new(ceiling_db: f32) -> Self {
         Self {
-            ceiling_db,
-            attack_ms: 5.0,
+            ceiling_db: SmoothedParam::new(Self::PARAMS[0], ceiling_db),
             release_ms: 50.0,
             lookahead_ms: 5.0,
-            gain: 1.0,
+            lookahead: 0,
+            delay: Vec::new(),
+            frame: 0,
+            detectors: Vec::new(),
+            last_span: 0.0,
+            window: VecDeque::new(),
+            held: 1.0,
+            ramp: Vec::new(),
+            ramp_pos: 0,
+            ramp_sum: 0.0,
+            configured_sr: 0,
+            configured_channels: 0,
         }
     }
+
+    fn lookahead_frames(&self, sample_rate: u32) -> usize {
+        ((self.lookahead_ms * 0.001 * sample_rate as f32) as usize).max(1)
+    }
+
+    /// Frames the signal is delayed by at `sample_rate`.
+    fn latency_at(&self, sample_rate: u32) -> usize {
+        self.lookahead_frames(sample_rate) + PEAK_DELAY
+    }
+
+    fn configure(&mut self, sample_rate: u32, channels: usize) {
+        self.lookahead = self.lookahead_frames(sample_rate);
+        let latency = self.latency_at(sample_rate);
+        self.delay.clear();
+        self.delay.resize((latency + 1) * channels, 0.0);
+        self.detectors.clear();
+        self.detectors.resize(channels, TruePeak::new(sample_rate));
+        self.window.clear();
+        self.window.reserve(self.lookahead + 1);
+        self.ramp.clear();
+        self.ramp.resize(self.lookahead + 1, 1.0);
+        self.configured_sr = sample_rate;
+        self.configured_channels = channels;
+        self.clear();
+    }
+
+    fn clear(&mut self) {
+        self.delay.fill(0.0);
+        self.frame = 0;
+        for detector in &mut self.detectors {
+            detector.reset();
+        }
+        self.last_span = 0.0;
+        self.window.clear();
+        self.held = 1.0;
+        self.ramp.fill(1.0);
+        self.ramp_pos = 0;
+        self.ramp_sum = self.ramp.len() as f64;
+    }
+
+    /// Largest peak of the last `lookahead + 1` measured frames, after
+    /// adding `peak` for frame `index`.
+    fn window_peak(&mut self, index: usize, peak: f32) -> f32 {
+        while self.window.back().is_some_and(|&(_, p)| p <= peak) {
+            self.window.pop_back();
+        }
+        self.window.push_back((index, peak));
+        while self.window.front().is_some_and(|&(i, _)| i + self.lookahead < index) {
+            self.window.pop_front();
+        }
+        self.window.front().map_or(0.0, |&(_, p)| p)
+    }
+
+    /// Average of the last `lookahead + 1` held gains, after adding `held`.
+    fn ramp_gain(&mut self, held: f32) -> f32 {
+        self.ramp_sum += (held - self.ramp[self.ramp_pos]) as f64;
+        self.ramp[self.ramp_pos] = held;
+        self.ramp_pos = (self.ramp_pos + 1) % self.ramp.len();
+        if self.ramp_pos == 0 {
+            // Resync so rounding can't accumulate.
+            self.ramp_sum = self.ramp.iter().map(|&g| g as f64).sum();
+        }
+        (self.ramp_sum / self.ramp.len() as f64) as f32
+    }
 }
 
 impl AudioNode for LookaheadLimiter {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        // The channel count is only known once audio arrives; leave room for
+        // stereo so switching from mono doesn't allocate on the audio thread.
+        let latency = self.latency_at(sample_rate);
+        self.delay.reserve((latency + 1) * 2);
+        self.detectors.reserve(2);
+        self.ceiling_db.prepare(sample_rate);
+        self.configure(sample_rate, self.configured_channels.max(1));
+    }
+
+    /// At the prepared sample rate, or the processing rate before `prepare`.
+    fn latency_samples(&self) -> usize {
+        match self.configured_sr {
+            0 => self.latency_at(PROCESSING_SAMPLE_RATE),
+            sr => self.latency_at(sr),
+        }
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
-        let ceiling = 10.0f32.powf(self.ceiling_db / 20.0);
+        let channels = buffer.channels() as usize;
+        if buffer.sample_rate() != self.configured_sr || channels != self.configured_channels {
+            self.configure(buffer.sample_rate(), channels);
+        }
+
         let sr = buffer.sample_rate() as f32;
-        let attack_coeff = (-1.0 / (self.attack_ms * 0.001 * sr)).exp();
-        let release_coeff = (-1.0 / (self.release_ms * 0.001 * sr)).exp();
-        let lookahead = (self.lookahead_ms * 0.001 * sr) as usize;
-
-        // Pass 1: find peak envelope with lookahead
-        let len = buffer.samples.len();
-        let mut peaks = vec![0.0f32; len];
-        for i in 0..len {
-            let end = (i + lookahead).min(len);
-            let mut peak = 0.0f32;
-            for j in i..end {
-                peak = peak.max(buffer.samples[j].abs());
+        let release_coeff = 1.0 - (-1.0 / (self.release_ms * 0.001 * sr)).exp();
+        let len = self.delay.len() / channels;
+
+        for frame in buffer.samples.chunks_exact_mut(channels) {
+            let slot = self.frame % len;
+            self.delay[slot * channels..(slot + 1) * channels].copy_from_slice(frame);
+
+            // Peak of the frame `PEAK_DELAY` back: its samples and the
+            // interpolated spans either side of it.
+            let mut span = 0.0f32;
+            for (detector, &s) in self.detectors.iter_mut().zip(frame.iter()) {
+                span = span.max(detector.process(s));
             }
-            peaks[i] = peak;
-        }
+            let measured = (self.frame + len - PEAK_DELAY) % len;
+            let sample_peak = self.delay[measured * channels..(measured + 1) * channels]
+                .iter()
+                .fold(0.0f32, |m, s| m.max(s.abs()));
+            let peak = sample_peak.max(span).max(self.last_span);
+            self.last_span = span;
 
-        // Pass 2: apply gain reduction
-        for (i, s) in buffer.samples.iter_mut().enumerate() {
-            let peak = peaks[i];
-            let target_gain = if peak > ceiling {
-                ceiling / peak
+            let ceiling = 10.0f32.powf(self.ceiling_db.tick() / 20.0);
+            let window_peak = self.window_peak(self.frame, peak);
+            let needed = if window_peak > ceiling {
+                ceiling / window_peak
             } else {
                 1.0
             };
+            self.held = (self.held + release_coeff * (1.0 - self.held)).min(needed);
+            let gain = self.ramp_gain(self.held);
 
-            // Smooth gain changes
-            if target_gain < self.gain {
-                self.gain = attack_coeff * self.gain + (1.0 - attack_coeff) * target_gain;
-            } else {
-                self.gain = release_coeff * self.gain + (1.0 - release_coeff) * target_gain;
+            // The oldest frame leaves the delay line.
+            let out = (self.frame + 1) % len;
+            let delayed = &self.delay[out * channels..(out + 1) * channels];
+            for (s, &d) in frame.iter_mut().zip(delayed.iter()) {
+                *s = (d * gain).clamp(-ceiling, ceiling);
             }
-
-            *s *= self.gain;
+            self.frame += 1;
         }
     }
 
     fn reset(&mut self) {
-        self.gain = 1.0;
+        self.clear();
+        self.ceiling_db.snap();
     }
 
     fn name(&self) -> &str {
         "Lookahead Limiter"
     }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.ceiling_db.handle()]
+    }
+
+    fn input_layout(&self) -> Option<ChannelLayout> {
+        None
+    }
 }
 
 #[cfg(test)]
@@ -111,4 +275,95 @@ mod tests {
             "Limiter failed: max peak {max_peak} > ceiling {ceiling}"
         );
     }
+
+    /// Loud noise with sudden bursts, the hardest case for a limiter.
+    fn bursts(frames: usize, channels: usize) -> AudioBuffer {
+        let mut state = 0x2545_f491u32;
+        let samples = (0..frames * channels)
+            .map(|i| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                let noise = state as f32 / u32::MAX as f32 * 2.0 - 1.0;
+                let burst = if (i / channels / 700).is_multiple_of(3) { 4.0 } else { 0.3 };
+                noise * burst
+            })
+            .collect();
+        AudioBuffer::with_channels(samples, channels as u16, 48000)
+    }
+
+    #[test]
+    fn test_ceiling_holds_at_any_block_size() {
+        let ceiling = 10.0f32.powf(-1.0 / 20.0);
+        let mut reference: Option<Vec<f32>> = None;
+        for block in [1, 7, 64, 480, 4096] {
+            let mut buffer = bursts(24000, 2);
+            let mut limiter = LookaheadLimiter::new(-1.0);
+            limiter.prepare(48000, block);
+            buffer.process_blocks(block, |b| limiter.process(b));
+
+            let max_peak = buffer.samples.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+            assert!(max_peak <= ceiling, "block {block}: {max_peak} > {ceiling}");
+            match &reference {
+                Some(reference) => assert_eq!(&buffer.samples, reference, "block {block}"),
+                None => reference = Some(buffer.samples),
+            }
+        }
+    }
+
+    #[test]
+    fn test_gain_arrives_before_the_peak() {
+        let mut samples = vec![0.1f32; 4800];
+        samples.extend(std::iter::repeat_n(2.0, 4800));
+        let mut buffer = AudioBuffer::new(samples, 48000);
+        let mut limiter = LookaheadLimiter::new(-6.0);
+        limiter.prepare(48000, 256);
+        buffer.process_blocks(256, |b| limiter.process(b));
+
+        let ceiling = 10.0f32.powf(-6.0 / 20.0);
+        let step = 4800 + limiter.latency_samples();
+        let lookahead = limiter.latency_samples() - PEAK_DELAY;
+        // Untouched until the step enters the window, then ramped down
+        // across it: the quiet sample just before the step already carries
+        // nearly the full reduction.
+        assert_eq!(buffer.samples[step - lookahead - 3], 0.1);
+        assert!(buffer.samples[step - 1] < 0.1 * ceiling / 1.8, "{}", buffer.samples[step - 1]);
+        assert!(buffer.samples[step - 1] > 0.0);
+        // The step itself never needs the clamp by much, and settles at the ceiling.
+        assert!(buffer.samples[step] <= ceiling);
+        assert!(buffer.samples[step + 2400..].iter().all(|&s| (s - ceiling).abs() < 1e-3));
+    }
+
+    #[test]
+    fn test_latency_is_reported() {
+        let mut limiter = LookaheadLimiter::new(-1.0);
+        limiter.prepare(48000, 512);
+        let latency = limiter.latency_samples();
+        assert_eq!(latency, 240 + PEAK_DELAY);
+
+        let mut samples = vec![0.0f32; 1024];
+        samples[10] = 0.5;
+        let mut buffer = AudioBuffer::new(samples, 48000);
+        limiter.process(&mut buffer);
+        assert_eq!(buffer.samples[10 + latency], 0.5, "quiet audio passes through unchanged");
+    }
+
+    #[test]
+    fn test_inter_sample_peaks_are_limited() {
+        // Quarter-rate sine sampled off its crests: samples at 0.64 but the
+        // waveform between them reaches 0.9, above the -3 dB ceiling.
+        let samples: Vec<f32> = (0..9600)
+            .map(|i| (std::f32::consts::FRAC_PI_2 * i as f32 + std::f32::consts::FRAC_PI_4).sin() * 0.9)
+            .collect();
+        let mut buffer = AudioBuffer::new(samples, 48000);
+        let mut limiter = LookaheadLimiter::new(-3.0);
+        limiter.prepare(48000, 512);
+        limiter.process(&mut buffer);
+
+        // Measure the whole output so the detector has settled by the tail.
+        let mut detector = TruePeak::new(48000);
+        let peaks: Vec<f32> = buffer.samples.iter().map(|&s| detector.process(s)).collect();
+        let true_peak = 20.0 * peaks[4800..].iter().fold(0.0f32, |m, &p| m.max(p)).log10();
+        assert!(true_peak < -2.95, "{true_peak} dBTP");
+    }
 }
@@ -0,0 +1,561 @@
+use std::f64::consts::PI;
+
+use vozoo_core::AudioBuffer;
+
+/// Loudness reported for silence.
+pub const SILENCE_LUFS: f32 = f32::NEG_INFINITY;
+
+/// One second-order section, run in f64: the K-weighting shelf sits far
+/// below Nyquist at high sample rates, where f32 coefficients drift.
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Section {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// ITU-R BS.1770 K-weighting for one channel: a +4 dB high shelf modelling
+/// the head, followed by the RLB high-pass.
+///
+/// Coefficients are derived for any sample rate and match the tables in
+/// the standard at 48 kHz.
+#[derive(Debug, Clone, Copy)]
+pub struct KWeighting {
+    shelf: Section,
+    highpass: Section,
+}
+
+impl KWeighting {
+    pub fn new(sample_rate: u32) -> Self {
+        let fs = sample_rate as f64;
+
+        let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / fs).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Section {
+            b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / fs).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let highpass = Section {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        Self { shelf, highpass }
+    }
+
+    pub fn process(&mut self, x: f32) -> f64 {
+        self.highpass.process(self.shelf.process(x as f64))
+    }
+
+    pub fn reset(&mut self) {
+        self.shelf.z = [0.0; 2];
+        self.highpass.z = [0.0; 2];
+    }
+}
+
+/// Loudness of a K-weighted mean square summed over channels, in LUFS.
+pub fn lufs(mean_square: f64) -> f32 {
+    if mean_square > 0.0 {
+        (-0.691 + 10.0 * mean_square.log10()) as f32
+    } else {
+        SILENCE_LUFS
+    }
+}
+
+/// BS.1770 channel weight: 1.0 for front channels, 1.41 for surrounds and
+/// 0 for the LFE of a 5.1 layout (L, R, C, LFE, Ls, Rs).
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4) | (6, 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Blocks quieter than this never count towards integrated loudness or LRA.
+const ABSOLUTE_GATE_LUFS: f32 = -70.0;
+/// Relative gates, below the ungated loudness of what passed the absolute gate.
+const RELATIVE_GATE_LU: f32 = -10.0;
+const LRA_RELATIVE_GATE_LU: f32 = -20.0;
+
+/// Blocks start every 100 ms; momentary blocks span 4 steps, short-term 30.
+const STEPS_PER_SECOND: u32 = 10;
+const MOMENTARY_STEPS: usize = 4;
+const SHORT_TERM_STEPS: usize = 30;
+
+/// Histogram resolution: 0.1 LU bins from the absolute gate up to +10 LUFS.
+const BIN_LU: f32 = 0.1;
+const BINS: usize = 800;
+
+/// Gated block loudness kept as a histogram, so memory stays fixed however
+/// long the programme runs. Each bin also sums its blocks' exact energy, so
+/// only the gate thresholds are quantized.
+#[derive(Debug, Clone)]
+struct Histogram {
+    counts: Vec<u64>,
+    energy: Vec<f64>,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self { counts: vec![0; BINS], energy: vec![0.0; BINS] }
+    }
+
+    fn add(&mut self, mean_square: f64) {
+        let loudness = lufs(mean_square);
+        if loudness < ABSOLUTE_GATE_LUFS {
+            return;
+        }
+        let bin = (((loudness - ABSOLUTE_GATE_LUFS) / BIN_LU) as usize).min(BINS - 1);
+        self.counts[bin] += 1;
+        self.energy[bin] += mean_square;
+    }
+
+    /// First bin whose blocks are at or above `threshold`.
+    fn bin_at(threshold: f32) -> usize {
+        (((threshold - ABSOLUTE_GATE_LUFS) / BIN_LU).round().max(0.0) as usize).min(BINS)
+    }
+
+    /// Loudness of the energy-average of the blocks from bin `from` up.
+    fn gated_lufs(&self, from: usize) -> f32 {
+        let count: u64 = self.counts[from..].iter().sum();
+        if count == 0 {
+            return SILENCE_LUFS;
+        }
+        lufs(self.energy[from..].iter().sum::<f64>() / count as f64)
+    }
+
+    /// Loudness at which `fraction` of the blocks from bin `from` up lie below.
+    fn percentile(&self, from: usize, fraction: f64) -> f32 {
+        let total: u64 = self.counts[from..].iter().sum();
+        let wanted = (fraction * total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bin, &count) in self.counts.iter().enumerate().skip(from) {
+            seen += count;
+            if seen >= wanted {
+                return ABSOLUTE_GATE_LUFS + (bin as f32 + 0.5) * BIN_LU;
+            }
+        }
+        SILENCE_LUFS
+    }
+
+    fn clear(&mut self) {
+        self.counts.fill(0);
+        self.energy.fill(0.0);
+    }
+}
+
+/// Taps per polyphase branch of the true-peak interpolator.
+const TRUE_PEAK_TAPS: usize = 12;
+/// Largest oversampling factor, used below 96 kHz.
+const MAX_OVERSAMPLING: usize = 4;
+
+/// Inter-sample peak detector for one channel (BS.1770-4 Annex 2).
+///
+/// Oversamples to at least 176.4 kHz with a windowed-sinc interpolator and
+/// reports the largest absolute value between consecutive samples. The
+/// interpolator is linear-phase, so `process` describes the span ending
+/// `TruePeak::LATENCY` samples before the one just pushed.
+///
+/// Holds no heap memory, so detectors can be created on the audio thread.
+#[derive(Debug, Clone, Copy)]
+pub struct TruePeak {
+    /// `factor` branches of `TRUE_PEAK_TAPS` coefficients, oldest tap first.
+    phases: [f32; TRUE_PEAK_TAPS * MAX_OVERSAMPLING],
+    factor: usize,
+    /// The last `TRUE_PEAK_TAPS` inputs, stored twice so they can be read
+    /// as one slice starting at `pos`.
+    history: [f32; 2 * TRUE_PEAK_TAPS],
+    pos: usize,
+}
+
+impl TruePeak {
+    /// Samples between an input sample and the span `process` reports.
+    pub const LATENCY: usize = TRUE_PEAK_TAPS / 2;
+
+    pub fn new(sample_rate: u32) -> Self {
+        let factor = match sample_rate {
+            0..=95_999 => 4,
+            96_000..=191_999 => 2,
+            _ => 1,
+        };
+        let taps = TRUE_PEAK_TAPS * factor;
+        // Hann-windowed sinc with its cutoff at the input Nyquist frequency,
+        // centred on a tap: branch 0 passes the samples through unchanged
+        // and the others land between them.
+        let half = (taps / 2) as f64;
+        let prototype = |i: usize| {
+            let x = (i as f64 - half) / factor as f64;
+            let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            let window = 0.5 + 0.5 * (PI * (i as f64 - half) / half).cos();
+            sinc * window
+        };
+        // Unity gain per branch.
+        let gain = (0..taps).map(prototype).sum::<f64>() / factor as f64;
+        let mut phases = [0.0; TRUE_PEAK_TAPS * MAX_OVERSAMPLING];
+        for phase in 0..factor {
+            for tap in 0..TRUE_PEAK_TAPS {
+                let oldest_first = TRUE_PEAK_TAPS - 1 - tap;
+                phases[phase * TRUE_PEAK_TAPS + oldest_first] = (prototype(phase + tap * factor) / gain) as f32;
+            }
+        }
+        Self { phases, factor, history: [0.0; 2 * TRUE_PEAK_TAPS], pos: 0 }
+    }
+
+    /// Push one sample; returns the largest absolute interpolated value in
+    /// the span ending `LATENCY` samples ago.
+    pub fn process(&mut self, x: f32) -> f32 {
+        self.history[self.pos] = x;
+        self.history[self.pos + TRUE_PEAK_TAPS] = x;
+        self.pos = (self.pos + 1) % TRUE_PEAK_TAPS;
+        let window = &self.history[self.pos..self.pos + TRUE_PEAK_TAPS];
+        let mut peak = 0.0f32;
+        for phase in self.phases[..self.factor * TRUE_PEAK_TAPS].chunks_exact(TRUE_PEAK_TAPS) {
+            let y: f32 = phase.iter().zip(window).map(|(c, x)| c * x).sum();
+            peak = peak.max(y.abs());
+        }
+        peak
+    }
+
+    pub fn reset(&mut self) {
+        self.history = [0.0; 2 * TRUE_PEAK_TAPS];
+        self.pos = 0;
+    }
+}
+
+/// Results of a BS.1770-4 / EBU R128 measurement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessStats {
+    /// Gated programme loudness.
+    pub integrated_lufs: f32,
+    /// Loudness range (EBU Tech 3342), in LU.
+    pub loudness_range_lu: f32,
+    /// Largest inter-sample peak, in dBTP.
+    pub true_peak_db: f32,
+    pub max_momentary_lufs: f32,
+    pub max_short_term_lufs: f32,
+}
+
+/// Streaming ITU-R BS.1770-4 loudness meter: K-weighted, channel-weighted
+/// 400 ms blocks at 75% overlap, absolute (-70 LUFS) and relative (-10 LU)
+/// gating for integrated loudness, EBU Tech 3342 loudness range over 3 s
+/// short-term blocks, and 4x oversampled true peak.
+///
+/// Block loudness is kept in fixed-size histograms, so `process` never
+/// allocates and hours of audio cost no more memory than a second.
+#[derive(Debug, Clone)]
+pub struct LoudnessAnalyzer {
+    sample_rate: u32,
+    channels: usize,
+    filters: Vec<KWeighting>,
+    peaks: Vec<TruePeak>,
+    weights: Vec<f64>,
+    step_frames: usize,
+    step_pos: usize,
+    step_energy: f64,
+    /// Channel-weighted energy of the last `SHORT_TERM_STEPS` steps.
+    steps: [f64; SHORT_TERM_STEPS],
+    steps_seen: usize,
+    momentary: Histogram,
+    short_term: Histogram,
+    momentary_lufs: f32,
+    short_term_lufs: f32,
+    integrated_lufs: f32,
+    max_momentary_lufs: f32,
+    max_short_term_lufs: f32,
+    true_peak: f32,
+}
+
+impl LoudnessAnalyzer {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            sample_rate,
+            channels,
+            filters: vec![KWeighting::new(sample_rate); channels],
+            peaks: vec![TruePeak::new(sample_rate); channels],
+            weights: (0..channels).map(|c| channel_weight(c, channels)).collect(),
+            step_frames: (sample_rate as usize).div_ceil(STEPS_PER_SECOND as usize).max(1),
+            step_pos: 0,
+            step_energy: 0.0,
+            steps: [0.0; SHORT_TERM_STEPS],
+            steps_seen: 0,
+            momentary: Histogram::new(),
+            short_term: Histogram::new(),
+            momentary_lufs: SILENCE_LUFS,
+            short_term_lufs: SILENCE_LUFS,
+            integrated_lufs: SILENCE_LUFS,
+            max_momentary_lufs: SILENCE_LUFS,
+            max_short_term_lufs: SILENCE_LUFS,
+            true_peak: 0.0,
+        }
+    }
+
+    /// Measure a whole buffer.
+    pub fn measure(buffer: &AudioBuffer) -> LoudnessStats {
+        let mut analyzer = Self::new(buffer.sample_rate(), buffer.channels());
+        analyzer.process(&buffer.samples);
+        analyzer.stats()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    /// Switch to a new channel count and start the measurement over.
+    /// Doesn't allocate when shrinking or regrowing to the original count.
+    pub fn set_channels(&mut self, channels: u16) {
+        let channels = channels.max(1) as usize;
+        self.channels = channels;
+        self.filters.resize(channels, KWeighting::new(self.sample_rate));
+        self.peaks.resize(channels, TruePeak::new(self.sample_rate));
+        self.weights.clear();
+        self.weights.extend((0..channels).map(|c| channel_weight(c, channels)));
+        self.reset();
+    }
+
+    /// Feed interleaved samples with the analyzer's channel count.
+    pub fn process(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (c, &s) in frame.iter().enumerate() {
+                let z = self.filters[c].process(s);
+                self.step_energy += self.weights[c] * z * z;
+                self.true_peak = self.true_peak.max(self.peaks[c].process(s));
+            }
+            self.step_pos += 1;
+            if self.step_pos == self.step_frames {
+                self.finish_step();
+            }
+        }
+    }
+
+    fn finish_step(&mut self) {
+        self.steps[self.steps_seen % SHORT_TERM_STEPS] = self.step_energy;
+        self.steps_seen += 1;
+        self.step_pos = 0;
+        self.step_energy = 0.0;
+
+        let window = |count: usize| {
+            let energy: f64 = (0..count)
+                .map(|i| self.steps[(self.steps_seen - 1 - i) % SHORT_TERM_STEPS])
+                .sum();
+            energy / (count * self.step_frames) as f64
+        };
+        if self.steps_seen >= MOMENTARY_STEPS {
+            let mean_square = window(MOMENTARY_STEPS);
+            self.momentary.add(mean_square);
+            self.momentary_lufs = lufs(mean_square);
+            self.max_momentary_lufs = self.max_momentary_lufs.max(self.momentary_lufs);
+            self.integrated_lufs = self.gated(&self.momentary, RELATIVE_GATE_LU).1;
+        }
+        if self.steps_seen >= SHORT_TERM_STEPS {
+            let mean_square = window(SHORT_TERM_STEPS);
+            self.short_term.add(mean_square);
+            self.short_term_lufs = lufs(mean_square);
+            self.max_short_term_lufs = self.max_short_term_lufs.max(self.short_term_lufs);
+        }
+    }
+
+    /// First bin past the relative gate, and the loudness of what passes it.
+    fn gated(&self, histogram: &Histogram, relative_gate: f32) -> (usize, f32) {
+        let ungated = histogram.gated_lufs(0);
+        if ungated == SILENCE_LUFS {
+            return (BINS, SILENCE_LUFS);
+        }
+        let from = Histogram::bin_at(ungated + relative_gate);
+        (from, histogram.gated_lufs(from))
+    }
+
+    /// Loudness of the last 400 ms.
+    pub fn momentary_lufs(&self) -> f32 {
+        self.momentary_lufs
+    }
+
+    /// Loudness of the last 3 s.
+    pub fn short_term_lufs(&self) -> f32 {
+        self.short_term_lufs
+    }
+
+    /// Gated loudness of everything since the last reset, updated every
+    /// 100 ms. `SILENCE_LUFS` until a block passes the gates.
+    pub fn integrated_lufs(&self) -> f32 {
+        self.integrated_lufs
+    }
+
+    /// Spread between the 10th and 95th percentile of gated short-term
+    /// loudness, in LU. 0 before the first 3 s.
+    pub fn loudness_range_lu(&self) -> f32 {
+        let (from, gated) = self.gated(&self.short_term, LRA_RELATIVE_GATE_LU);
+        if gated == SILENCE_LUFS {
+            return 0.0;
+        }
+        self.short_term.percentile(from, 0.95) - self.short_term.percentile(from, 0.10)
+    }
+
+    /// Largest inter-sample peak since the last reset, in dBTP.
+    pub fn true_peak_db(&self) -> f32 {
+        if self.true_peak > 0.0 {
+            20.0 * self.true_peak.log10()
+        } else {
+            SILENCE_LUFS
+        }
+    }
+
+    pub fn stats(&self) -> LoudnessStats {
+        LoudnessStats {
+            integrated_lufs: self.integrated_lufs(),
+            loudness_range_lu: self.loudness_range_lu(),
+            true_peak_db: self.true_peak_db(),
+            max_momentary_lufs: self.max_momentary_lufs,
+            max_short_term_lufs: self.max_short_term_lufs,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for filter in &mut self.filters {
+            filter.reset();
+        }
+        for peak in &mut self.peaks {
+            peak.reset();
+        }
+        self.step_pos = 0;
+        self.step_energy = 0.0;
+        self.steps = [0.0; SHORT_TERM_STEPS];
+        self.steps_seen = 0;
+        self.momentary.clear();
+        self.short_term.clear();
+        self.momentary_lufs = SILENCE_LUFS;
+        self.short_term_lufs = SILENCE_LUFS;
+        self.integrated_lufs = SILENCE_LUFS;
+        self.max_momentary_lufs = SILENCE_LUFS;
+        self.max_short_term_lufs = SILENCE_LUFS;
+        self.true_peak = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+
+    /// Loudness of a steady sine after the filter settles.
+    fn sine_lufs(freq: f64, amplitude: f64, sample_rate: u32) -> f32 {
+        let mut filter = KWeighting::new(sample_rate);
+        let n = sample_rate as usize;
+        let mut sum = 0.0;
+        for i in 0..2 * n {
+            let x = (TAU * freq * i as f64 / sample_rate as f64).sin() * amplitude;
+            let y = filter.process(x as f32);
+            if i >= n {
+                sum += y * y;
+            }
+        }
+        lufs(sum / n as f64)
+    }
+
+    #[test]
+    fn test_full_scale_1k_sine_reads_about_minus_3_lufs() {
+        // BS.1770's reference point: a 0 dBFS 997 Hz sine on one channel
+        // measures -3.01 LUFS.
+        for rate in [44100, 48000, 96000] {
+            let loudness = sine_lufs(997.0, 1.0, rate);
+            assert!((loudness + 3.01).abs() < 0.05, "{rate} Hz: {loudness}");
+        }
+    }
+
+    #[test]
+    fn test_low_frequencies_are_attenuated() {
+        assert!(sine_lufs(20.0, 1.0, 48000) < sine_lufs(997.0, 1.0, 48000) - 10.0);
+        assert_eq!(lufs(0.0), SILENCE_LUFS);
+    }
+
+    /// Stereo 1 kHz sine segments of (seconds, dBFS) at `rate`.
+    fn segments(parts: &[(f32, f32)], rate: u32) -> AudioBuffer {
+        let mut samples = Vec::new();
+        let mut t = 0usize;
+        for &(seconds, db) in parts {
+            let amplitude = 10f64.powf(db as f64 / 20.0);
+            for _ in 0..(seconds * rate as f32) as usize {
+                let s = ((TAU * 1000.0 * t as f64 / rate as f64).sin() * amplitude) as f32;
+                samples.extend([s, s]);
+                t += 1;
+            }
+        }
+        AudioBuffer::with_channels(samples, 2, rate)
+    }
+
+    // Reference cases from EBU Tech 3341 and 3342, shortened.
+
+    #[test]
+    fn test_integrated_loudness_of_steady_tones() {
+        for (db, rate) in [(-23.0, 48000), (-33.0, 44100)] {
+            let stats = LoudnessAnalyzer::measure(&segments(&[(5.0, db)], rate));
+            assert!((stats.integrated_lufs - db).abs() < 0.1, "{db} dBFS at {rate} Hz: {stats:?}");
+            assert!((stats.max_momentary_lufs - db).abs() < 0.1, "{stats:?}");
+            assert!(stats.loudness_range_lu < 0.2, "{stats:?}");
+        }
+    }
+
+    #[test]
+    fn test_gating_ignores_quiet_passages() {
+        // Relative gate: the -36 dBFS parts fall more than 10 LU below.
+        let stats = LoudnessAnalyzer::measure(&segments(&[(5.0, -36.0), (30.0, -23.0), (5.0, -36.0)], 8000));
+        assert!((stats.integrated_lufs + 23.0).abs() < 0.1, "{stats:?}");
+
+        // Absolute gate: silence-level audio doesn't pull the result down.
+        let stats = LoudnessAnalyzer::measure(&segments(&[(5.0, -72.0), (20.0, -20.0), (5.0, -72.0)], 8000));
+        assert!((stats.integrated_lufs + 20.0).abs() < 0.1, "{stats:?}");
+
+        // Both parts pass the gates and average in the energy domain.
+        let stats = LoudnessAnalyzer::measure(&segments(&[(10.0, -26.0), (10.05, -20.0), (10.0, -26.0)], 8000));
+        assert!((stats.integrated_lufs + 23.0).abs() < 0.1, "{stats:?}");
+    }
+
+    #[test]
+    fn test_loudness_range() {
+        let stats = LoudnessAnalyzer::measure(&segments(&[(10.0, -20.0), (10.0, -30.0)], 8000));
+        assert!((stats.loudness_range_lu - 10.0).abs() < 1.0, "{stats:?}");
+        let stats = LoudnessAnalyzer::measure(&segments(&[(10.0, -20.0), (10.0, -15.0)], 8000));
+        assert!((stats.loudness_range_lu - 5.0).abs() < 1.0, "{stats:?}");
+    }
+
+    #[test]
+    fn test_true_peak_finds_inter_sample_peaks() {
+        // A quarter-rate sine sampled 45 degrees off its crests: every
+        // sample sits 3 dB below the real peak.
+        let samples: Vec<f32> = (0..4800)
+            .map(|i| (std::f32::consts::FRAC_PI_2 * i as f32 + std::f32::consts::FRAC_PI_4).sin() * 0.5)
+            .collect();
+        let sample_peak = samples.iter().fold(0.0f32, |p, s| p.max(s.abs()));
+        assert!((20.0 * sample_peak.log10() + 9.03).abs() < 0.01);
+
+        let stats = LoudnessAnalyzer::measure(&AudioBuffer::new(samples, 48000));
+        assert!((stats.true_peak_db + 6.02).abs() < 0.3, "{stats:?}");
+
+        // Ordinary material reads its sample peak.
+        let stats = LoudnessAnalyzer::measure(&segments(&[(1.0, -6.0)], 48000));
+        assert!((stats.true_peak_db + 6.0).abs() < 0.1, "{stats:?}");
+    }
+}
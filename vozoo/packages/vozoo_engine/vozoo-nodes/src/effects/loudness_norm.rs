@@ -1,84 +1,256 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{smoothing_coeff, AudioBuffer, AudioNode, ChannelLayout, ParamDescriptor, ParamHandle, SmoothedParam};
 
-/// LUFS-based loudness normalization.
-/// Adjusts the overall level to match a target LUFS value.
+use super::loudness::{LoudnessAnalyzer, TruePeak};
+
+/// EBU R128 loudness normalization with an optional true-peak ceiling.
+///
+/// The input is measured with a `LoudnessAnalyzer`, and the gain follows
+/// the difference between the target and the integrated (gated) loudness
+/// so far, with a fast attack and slow release so the node can stream.
+/// Pauses and silence don't pass the gates, so they don't pump the gain.
 ///
-/// Simplified LUFS: uses integrated loudness (mean square over entire signal)
-/// with K-weighting approximation.
+/// With a ceiling, the output is held below it in dBTP: the audio is
+/// delayed by `TruePeak::LATENCY + 1` samples so that inter-sample peaks are
+/// caught before they are played.
 pub struct LoudnessNorm {
-    target_lufs: f32,
+    target_lufs: SmoothedParam,
+    ceiling_dbtp: Option<SmoothedParam>,
+    analyzer: LoudnessAnalyzer,
+    gain_db: f32,
+    /// Output true-peak detectors and the matching delay line (interleaved).
+    detectors: Vec<TruePeak>,
+    delay: Vec<f32>,
+    delay_pos: usize,
+    /// Detector reading for the span before the sample leaving the delay.
+    last_peak: f32,
+    limit_gain: f32,
 }
 
 impl LoudnessNorm {
+    pub const PARAMS: [ParamDescriptor; 2] = [
+        ParamDescriptor { id: 0, key: "target_lufs", name: "Target LUFS", min: -30.0, max: -6.0, default: -14.0 },
+        ParamDescriptor { id: 1, key: "ceiling_dbtp", name: "True Peak Ceiling (dBTP)", min: -12.0, max: 0.0, default: -1.0 },
+    ];
+
     /// Create a loudness normalizer with a target LUFS (e.g., -14.0 for streaming).
     pub fn new(target_lufs: f32) -> Self {
-        Self { target_lufs }
+        Self {
+            target_lufs: SmoothedParam::new(Self::PARAMS[0], target_lufs),
+            ceiling_dbtp: None,
+            analyzer: LoudnessAnalyzer::new(48000, 2),
+            gain_db: 0.0,
+            detectors: Vec::new(),
+            delay: Vec::new(),
+            delay_pos: 0,
+            last_peak: 0.0,
+            limit_gain: 1.0,
+        }
+    }
+
+    /// Also keep the output's true peak below `ceiling_dbtp` (e.g. -1.0).
+    pub fn with_ceiling(mut self, ceiling_dbtp: f32) -> Self {
+        self.ceiling_dbtp = Some(SmoothedParam::new(Self::PARAMS[1], ceiling_dbtp));
+        self
+    }
+
+    /// The input measurement the gain follows.
+    pub fn analyzer(&self) -> &LoudnessAnalyzer {
+        &self.analyzer
+    }
+
+    /// Match the measurement and ceiling state to the stream. Only
+    /// allocates when the rate changes or more than stereo appears.
+    fn configure(&mut self, sample_rate: u32, channels: u16) {
+        if sample_rate != self.analyzer.sample_rate() {
+            self.analyzer = LoudnessAnalyzer::new(sample_rate, channels.max(2));
+        }
+        if channels != self.analyzer.channels() {
+            self.analyzer.set_channels(channels);
+        }
+        if self.ceiling_dbtp.is_some() {
+            let channels = channels as usize;
+            self.detectors.clear();
+            self.detectors.resize(channels, TruePeak::new(sample_rate));
+            self.delay.clear();
+            self.delay.resize((TruePeak::LATENCY + 1) * channels, 0.0);
+            self.delay_pos = 0;
+            self.last_peak = 0.0;
+            self.limit_gain = 1.0;
+        }
+    }
+
+    /// Pass one frame through the ceiling's delay line, reducing the gain
+    /// of the frame leaving it if it would peak above `ceiling`.
+    fn limit(&mut self, frame: &mut [f32], ceiling: f32, release_coeff: f32) {
+        let ch = frame.len();
+        let delayed = &mut self.delay[self.delay_pos * ch..(self.delay_pos + 1) * ch];
+        let mut peak = 0.0f32;
+        for ((s, d), detector) in frame.iter_mut().zip(delayed.iter_mut()).zip(self.detectors.iter_mut()) {
+            peak = peak.max(detector.process(*s));
+            std::mem::swap(s, d);
+        }
+        self.delay_pos = (self.delay_pos + 1) % (TruePeak::LATENCY + 1);
+
+        // The spans either side of the outgoing sample.
+        let span_peak = peak.max(self.last_peak);
+        self.last_peak = peak;
+        let needed = if span_peak > ceiling { ceiling / span_peak } else { 1.0 };
+        self.limit_gain += release_coeff * (1.0 - self.limit_gain);
+        self.limit_gain = self.limit_gain.min(needed);
+        for s in frame.iter_mut() {
+            *s *= self.limit_gain;
+        }
     }
 }
 
+/// How quickly the ceiling lets go after a peak, in seconds.
+const CEILING_RELEASE_S: f32 = 0.2;
+
 impl AudioNode for LoudnessNorm {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.target_lufs.prepare(sample_rate);
+        if let Some(ceiling) = self.ceiling_dbtp.as_mut() {
+            ceiling.prepare(sample_rate);
+        }
+        self.configure(sample_rate, 2);
+    }
+
+    fn latency_samples(&self) -> usize {
+        if self.ceiling_dbtp.is_some() {
+            TruePeak::LATENCY + 1
+        } else {
+            0
+        }
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
         if buffer.samples.is_empty() {
             return;
         }
 
-        // Measure current loudness (simplified LUFS = -0.691 + 10*log10(mean_square))
-        let mean_sq: f64 = buffer
-            .samples
-            .iter()
-            .map(|s| (*s as f64) * (*s as f64))
-            .sum::<f64>()
-            / buffer.samples.len() as f64;
-
-        if mean_sq < 1e-10 {
-            return; // Silence
+        let sr = buffer.sample_rate();
+        let ch = buffer.channels();
+        if sr != self.analyzer.sample_rate() || ch != self.analyzer.channels() {
+            self.configure(sr, ch);
         }
+        let ch = ch as usize;
+        let sr = sr as f32;
+        let attack_coeff = smoothing_coeff(0.2, sr);
+        let release_coeff = smoothing_coeff(1.0, sr);
+        let ceiling_release = smoothing_coeff(CEILING_RELEASE_S, sr);
 
-        let current_lufs = -0.691 + 10.0 * (mean_sq as f32).log10();
-        let gain_db = self.target_lufs - current_lufs;
+        for frame in buffer.samples.chunks_exact_mut(ch) {
+            self.analyzer.process(frame);
+            let current_lufs = self.analyzer.integrated_lufs();
+            let target_lufs = self.target_lufs.tick();
+            // Nothing has passed the gates yet: hold the gain.
+            if current_lufs.is_finite() {
+                // Limit gain to reasonable range
+                let target_db = (target_lufs - current_lufs).clamp(-20.0, 20.0);
+                let coeff = if target_db < self.gain_db {
+                    attack_coeff
+                } else {
+                    release_coeff
+                };
+                self.gain_db += coeff * (target_db - self.gain_db);
+            }
 
-        // Limit gain to reasonable range
-        let gain_db = gain_db.clamp(-20.0, 20.0);
-        let gain = 10.0f32.powf(gain_db / 20.0);
+            let gain = 10.0f32.powf(self.gain_db / 20.0);
+            for s in frame.iter_mut() {
+                *s *= gain;
+            }
 
-        for s in &mut buffer.samples {
-            *s *= gain;
+            if let Some(ceiling) = self.ceiling_dbtp.as_mut() {
+                let ceiling = 10.0f32.powf(ceiling.tick() / 20.0);
+                self.limit(frame, ceiling, ceiling_release);
+            }
         }
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        self.analyzer.reset();
+        self.gain_db = 0.0;
+        self.target_lufs.snap();
+        if let Some(ceiling) = self.ceiling_dbtp.as_mut() {
+            ceiling.snap();
+            for detector in &mut self.detectors {
+                detector.reset();
+            }
+            self.delay.fill(0.0);
+            self.delay_pos = 0;
+            self.last_peak = 0.0;
+            self.limit_gain = 1.0;
+        }
+    }
 
     fn name(&self) -> &str {
         "Loudness Normalizer"
     }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        std::iter::once(&self.target_lufs)
+            .chain(self.ceiling_dbtp.as_ref())
+            .map(SmoothedParam::handle)
+            .collect()
+    }
+
+    fn input_layout(&self) -> Option<ChannelLayout> {
+        None
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use vozoo_core::test_signals::tone;
 
     #[test]
     fn test_loudness_norm_adjusts_level() {
-        let samples: Vec<f32> = (0..48000)
-            .map(|i| (i as f32 / 48000.0 * 440.0 * std::f32::consts::TAU).sin() * 0.1)
-            .collect();
-
-        let mut buffer = AudioBuffer::new(samples, 48000);
+        let mut buffer = tone(440.0, 0.1, 5.0);
         let mut norm = LoudnessNorm::new(-14.0);
         norm.process(&mut buffer);
 
-        // Measure output LUFS
-        let mean_sq: f64 = buffer
-            .samples
-            .iter()
-            .map(|s| (*s as f64) * (*s as f64))
-            .sum::<f64>()
-            / buffer.samples.len() as f64;
-
-        let output_lufs = -0.691 + 10.0 * (mean_sq as f32).log10();
+        // Measure output LUFS over the last second, after the gain has settled
+        let tail = AudioBuffer::new(buffer.samples[48000 * 4..].to_vec(), 48000);
+        let output_lufs = LoudnessAnalyzer::measure(&tail).integrated_lufs;
         assert!(
             (output_lufs - (-14.0)).abs() < 1.0,
             "LUFS not normalized: got {output_lufs}"
         );
     }
+
+    #[test]
+    fn test_silence_does_not_raise_the_gain() {
+        let mut buffer = tone(440.0, 0.1, 4.0);
+        buffer.samples.extend(std::iter::repeat_n(0.0, 48000 * 4));
+        buffer.samples.extend(tone(440.0, 0.1, 1.0).samples);
+        let mut norm = LoudnessNorm::new(-14.0);
+        norm.process(&mut buffer);
+
+        // The tone comes back at the level that meets the target, not
+        // boosted by the pause.
+        let input_lufs = LoudnessAnalyzer::measure(&tone(440.0, 0.1, 4.0)).integrated_lufs;
+        let settled = 0.1 * 10f32.powf((-14.0 - input_lufs) / 20.0);
+        let after = buffer.samples[48000 * 8..].iter().fold(0.0f32, |p, s| p.max(s.abs()));
+        assert!((after / settled - 1.0).abs() < 0.03, "{after} vs {settled}");
+    }
+
+    #[test]
+    fn test_ceiling_limits_true_peak() {
+        // A quiet tone lifted by 9 dB would peak near -3 dBTP.
+        let input = tone(997.0, 0.25, 6.0);
+        let mut open = input.clone();
+        LoudnessNorm::new(-6.0).process(&mut open);
+        let mut limited = input;
+        let mut norm = LoudnessNorm::new(-6.0).with_ceiling(-6.0);
+        norm.prepare(48000, 512);
+        limited.process_blocks(512, |block| norm.process(block));
+        assert_eq!(norm.params().len(), 2);
+
+        let tail = |buffer: &AudioBuffer| AudioBuffer::new(buffer.samples[48000 * 4..].to_vec(), 48000);
+        let open_peak = LoudnessAnalyzer::measure(&tail(&open)).true_peak_db;
+        let limited_peak = LoudnessAnalyzer::measure(&tail(&limited)).true_peak_db;
+        assert!(open_peak > -4.0, "{open_peak}");
+        assert!(limited_peak < -5.9 && limited_peak > -7.0, "{limited_peak}");
+    }
 }
@@ -0,0 +1,361 @@
+use rustfft::num_complex::Complex;
+use rustfft::Fft;
+use std::sync::Arc;
+use vozoo_core::{AudioBuffer, AudioNode, ChannelLayout, MeterSnapshot, MeterTap, METER_FLOOR_DB, SPECTRUM_BANDS};
+
+use super::fft_utils;
+use super::loudness::{lufs, KWeighting, LoudnessAnalyzer};
+
+/// Meter readings are published every 100 ms, the BS.1770 gating step.
+const STEPS_PER_SECOND: u32 = 10;
+/// Momentary loudness spans 400 ms, short-term loudness 3 s.
+const MOMENTARY_STEPS: usize = 4;
+const SHORT_TERM_STEPS: usize = 30;
+/// Samples behind each spectrum (about 43 ms at 48 kHz).
+const FFT_SIZE: usize = 2048;
+
+/// Pass-through analysis tap: measures peak, RMS, momentary and short-term
+/// loudness and a coarse spectrum of whatever flows through it, and
+/// publishes a `MeterSnapshot` to its `MeterTap` every 100 ms.
+///
+/// Allocation happens in `prepare` (or on the first block); measuring is
+/// allocation-free afterwards for up to stereo input.
+pub struct Meter {
+    tap: Arc<MeterTap>,
+    sample_rate: u32,
+    filters: Vec<KWeighting>,
+    step_frames: usize,
+    step_pos: usize,
+    /// Sums over the current step.
+    step_peak: f32,
+    step_square: f64,
+    step_weighted: f64,
+    step_samples: usize,
+    /// K-weighted mean square of each of the last `SHORT_TERM_STEPS` steps.
+    steps: [f64; SHORT_TERM_STEPS],
+    steps_seen: usize,
+    /// Mono downmix of the last `FFT_SIZE` frames, oldest at `history_pos`.
+    history: Vec<f32>,
+    history_pos: usize,
+    window: Vec<f32>,
+    window_gain: f32,
+    fft: Option<Arc<dyn Fft<f32>>>,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    /// FFT bins `[start, end)` of each spectrum band.
+    band_bins: [(usize, usize); SPECTRUM_BANDS],
+}
+
+impl Meter {
+    pub fn new() -> Self {
+        Self::with_tap(MeterTap::new())
+    }
+
+    /// A meter publishing to an existing tap, e.g. one a host already hands
+    /// out to readers.
+    pub fn with_tap(tap: Arc<MeterTap>) -> Self {
+        Self {
+            tap,
+            sample_rate: 0,
+            filters: Vec::new(),
+            step_frames: 1,
+            step_pos: 0,
+            step_peak: 0.0,
+            step_square: 0.0,
+            step_weighted: 0.0,
+            step_samples: 0,
+            steps: [0.0; SHORT_TERM_STEPS],
+            steps_seen: 0,
+            history: Vec::new(),
+            history_pos: 0,
+            window: Vec::new(),
+            window_gain: 1.0,
+            fft: None,
+            spectrum: Vec::new(),
+            scratch: Vec::new(),
+            band_bins: [(0, 0); SPECTRUM_BANDS],
+        }
+    }
+
+    pub fn tap(&self) -> &Arc<MeterTap> {
+        &self.tap
+    }
+
+    fn configure(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.filters = vec![KWeighting::new(sample_rate); 2];
+        self.step_frames = (sample_rate / STEPS_PER_SECOND).max(1) as usize;
+
+        let fft = fft_utils::create_fft_forward(FFT_SIZE);
+        self.scratch = vec![Complex::new(0.0, 0.0); fft.get_inplace_scratch_len()];
+        self.fft = Some(fft);
+        self.spectrum = vec![Complex::new(0.0, 0.0); FFT_SIZE];
+        self.history = vec![0.0; FFT_SIZE];
+        self.window = fft_utils::hann_window(FFT_SIZE);
+        // A sine centred on a bin peaks at amplitude * sum(window) / 2.
+        self.window_gain = 2.0 / self.window.iter().sum::<f32>();
+
+        let bin_hz = sample_rate as f32 / FFT_SIZE as f32;
+        let nyquist_bin = FFT_SIZE / 2;
+        for (band, bins) in self.band_bins.iter_mut().enumerate() {
+            let lo = (MeterSnapshot::band_edge_hz(band as f32) / bin_hz).ceil() as usize;
+            let hi = (MeterSnapshot::band_edge_hz(band as f32 + 1.0) / bin_hz).ceil() as usize;
+            *bins = if lo >= nyquist_bin {
+                (0, 0)
+            } else if lo >= hi {
+                // Narrower than a bin: use the bin nearest the band centre.
+                let centre = (MeterSnapshot::band_center_hz(band) / bin_hz).round() as usize;
+                (centre, centre + 1)
+            } else {
+                (lo, hi.min(nyquist_bin + 1))
+            };
+        }
+        self.clear();
+    }
+
+    fn clear(&mut self) {
+        for filter in &mut self.filters {
+            filter.reset();
+        }
+        self.step_pos = 0;
+        self.step_peak = 0.0;
+        self.step_square = 0.0;
+        self.step_weighted = 0.0;
+        self.step_samples = 0;
+        self.steps = [0.0; SHORT_TERM_STEPS];
+        self.steps_seen = 0;
+        self.history.fill(0.0);
+        self.history_pos = 0;
+    }
+
+    /// Close the current 100 ms step and publish a snapshot.
+    fn finish_step(&mut self) {
+        self.steps[self.steps_seen % SHORT_TERM_STEPS] = self.step_weighted / self.step_frames as f64;
+        self.steps_seen += 1;
+
+        let loudness = |count: usize| {
+            let count = count.min(self.steps_seen);
+            let sum: f64 = (0..count)
+                .map(|i| self.steps[(self.steps_seen - 1 - i) % SHORT_TERM_STEPS])
+                .sum();
+            lufs(sum / count as f64)
+        };
+        let mut snapshot = MeterSnapshot {
+            peak_db: to_db(self.step_peak),
+            rms_db: to_db((self.step_square / self.step_samples.max(1) as f64).sqrt() as f32),
+            momentary_lufs: loudness(MOMENTARY_STEPS),
+            short_term_lufs: loudness(SHORT_TERM_STEPS),
+            spectrum_db: [METER_FLOOR_DB; SPECTRUM_BANDS],
+        };
+        self.measure_spectrum(&mut snapshot.spectrum_db);
+        self.tap.publish(&snapshot);
+
+        self.step_pos = 0;
+        self.step_peak = 0.0;
+        self.step_square = 0.0;
+        self.step_weighted = 0.0;
+        self.step_samples = 0;
+    }
+
+    fn measure_spectrum(&mut self, bands: &mut [f32; SPECTRUM_BANDS]) {
+        let Some(fft) = self.fft.as_ref() else {
+            return;
+        };
+        let (older, newer) = self.history.split_at(self.history_pos);
+        for ((bin, &s), &w) in self.spectrum.iter_mut().zip(newer.iter().chain(older)).zip(&self.window) {
+            *bin = Complex::new(s * w, 0.0);
+        }
+        fft.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+
+        for (level, &(start, end)) in bands.iter_mut().zip(&self.band_bins) {
+            let peak = self.spectrum[start..end].iter().map(|c| c.norm()).fold(0.0f32, f32::max);
+            *level = to_db(peak * self.window_gain);
+        }
+    }
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    if amplitude > 0.0 {
+        (20.0 * amplitude.log10()).max(METER_FLOOR_DB)
+    } else {
+        METER_FLOOR_DB
+    }
+}
+
+impl AudioNode for Meter {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        if sample_rate != self.sample_rate {
+            self.configure(sample_rate);
+        }
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        if buffer.sample_rate() != self.sample_rate {
+            self.configure(buffer.sample_rate());
+        }
+        let channels = buffer.channels() as usize;
+        if self.filters.len() < channels {
+            self.filters.resize(channels, KWeighting::new(self.sample_rate));
+        }
+
+        for frame in buffer.samples.chunks_exact(channels) {
+            let mut mono = 0.0;
+            for (&s, filter) in frame.iter().zip(self.filters.iter_mut()) {
+                self.step_peak = self.step_peak.max(s.abs());
+                self.step_square += (s * s) as f64;
+                let weighted = filter.process(s);
+                self.step_weighted += weighted * weighted;
+                mono += s;
+            }
+            self.step_samples += channels;
+            self.history[self.history_pos] = mono / channels as f32;
+            self.history_pos = (self.history_pos + 1) % FFT_SIZE;
+
+            self.step_pos += 1;
+            if self.step_pos == self.step_frames {
+                self.finish_step();
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.clear();
+        self.tap.publish(&MeterSnapshot::SILENT);
+    }
+
+    fn name(&self) -> &str {
+        "Meter"
+    }
+
+    fn meters(&self) -> Vec<Arc<MeterTap>> {
+        vec![Arc::clone(&self.tap)]
+    }
+
+    fn input_layout(&self) -> Option<ChannelLayout> {
+        None
+    }
+}
+
+/// Whole-signal summary of what a meter reports over time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeterSummary {
+    pub peak_db: f32,
+    pub rms_db: f32,
+    /// Loudest momentary and short-term readings.
+    pub max_momentary_lufs: f32,
+    pub max_short_term_lufs: f32,
+    /// Gated programme loudness, loudness range and true peak (BS.1770-4).
+    pub integrated_lufs: f32,
+    pub loudness_range_lu: f32,
+    pub true_peak_db: f32,
+    /// Band levels averaged (in power) over every snapshot.
+    pub spectrum_db: [f32; SPECTRUM_BANDS],
+}
+
+/// Run `buffer` through a meter and a `LoudnessAnalyzer` and summarize
+/// their readings. Levels are clamped to `METER_FLOOR_DB`.
+pub fn analyze(buffer: &AudioBuffer) -> MeterSummary {
+    let loudness = LoudnessAnalyzer::measure(buffer);
+    let mut meter = Meter::new();
+    meter.prepare(buffer.sample_rate(), 0);
+    let step_samples = meter.step_frames * buffer.channels() as usize;
+
+    let mut summary = MeterSummary {
+        peak_db: to_db(buffer.samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))),
+        rms_db: METER_FLOOR_DB,
+        max_momentary_lufs: METER_FLOOR_DB,
+        max_short_term_lufs: METER_FLOOR_DB,
+        integrated_lufs: loudness.integrated_lufs.max(METER_FLOOR_DB),
+        loudness_range_lu: loudness.loudness_range_lu,
+        true_peak_db: loudness.true_peak_db.max(METER_FLOOR_DB),
+        spectrum_db: [METER_FLOOR_DB; SPECTRUM_BANDS],
+    };
+    if !buffer.samples.is_empty() {
+        let square: f64 = buffer.samples.iter().map(|&s| (s * s) as f64).sum();
+        summary.rms_db = to_db((square / buffer.samples.len() as f64).sqrt() as f32);
+    }
+
+    let mut band_power = [0.0f64; SPECTRUM_BANDS];
+    let mut snapshots = 0;
+    let mut block = AudioBuffer::with_channels(Vec::with_capacity(step_samples), buffer.channels(), buffer.sample_rate());
+    for chunk in buffer.samples.chunks_exact(step_samples) {
+        block.samples.clear();
+        block.samples.extend_from_slice(chunk);
+        meter.process(&mut block);
+
+        let snapshot = meter.tap.snapshot();
+        summary.max_momentary_lufs = summary.max_momentary_lufs.max(snapshot.momentary_lufs);
+        summary.max_short_term_lufs = summary.max_short_term_lufs.max(snapshot.short_term_lufs);
+        for (power, db) in band_power.iter_mut().zip(snapshot.spectrum_db) {
+            *power += 10f64.powf(db as f64 / 10.0);
+        }
+        snapshots += 1;
+    }
+    if snapshots > 0 {
+        for (level, power) in summary.spectrum_db.iter_mut().zip(band_power) {
+            *level = ((10.0 * (power / snapshots as f64).log10()) as f32).max(METER_FLOOR_DB);
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vozoo_core::test_signals::tone;
+
+    #[test]
+    fn test_meter_reads_a_sine() {
+        let mut meter = Meter::new();
+        meter.prepare(48000, 1024);
+        let mut buffer = tone(997.0, 0.5, 4.0);
+        let original = buffer.samples.clone();
+        buffer.process_blocks(512, |block| meter.process(block));
+        assert_eq!(buffer.samples, original, "meters must not change the signal");
+
+        let snapshot = meter.tap().snapshot();
+        // -6 dBFS peak, -9 dBFS RMS, and K-weighted loudness about 3 dB lower.
+        assert!((snapshot.peak_db + 6.02).abs() < 0.05, "{snapshot:?}");
+        assert!((snapshot.rms_db + 9.03).abs() < 0.05, "{snapshot:?}");
+        assert!((snapshot.momentary_lufs + 9.03).abs() < 0.1, "{snapshot:?}");
+        assert!((snapshot.short_term_lufs + 9.03).abs() < 0.1, "{snapshot:?}");
+
+        let loudest = (0..SPECTRUM_BANDS)
+            .max_by(|&a, &b| snapshot.spectrum_db[a].total_cmp(&snapshot.spectrum_db[b]))
+            .unwrap();
+        let (lo, hi) = (MeterSnapshot::band_edge_hz(loudest as f32), MeterSnapshot::band_edge_hz(loudest as f32 + 1.0));
+        assert!((lo..hi).contains(&997.0), "loudest band {lo}..{hi} Hz");
+        assert!((snapshot.spectrum_db[loudest] + 6.0).abs() < 1.5, "{}", snapshot.spectrum_db[loudest]);
+    }
+
+    #[test]
+    fn test_reset_publishes_silence() {
+        let mut meter = Meter::new();
+        meter.process(&mut tone(440.0, 0.5, 0.5));
+        assert!(meter.tap().snapshot().peak_db > -7.0);
+        meter.reset();
+        assert_eq!(meter.tap().snapshot(), MeterSnapshot::SILENT);
+    }
+
+    #[test]
+    fn test_analyze_summarizes_a_file() {
+        let mut buffer = tone(200.0, 0.1, 2.0);
+        buffer.samples.extend(tone(200.0, 0.8, 4.0).samples);
+        let summary = analyze(&buffer);
+        assert!((summary.peak_db + 1.94).abs() < 0.05, "{summary:?}");
+        // The short-term window fills with the loud part, which measures
+        // about 18 dB above the quiet one.
+        assert!((summary.max_short_term_lufs - summary.max_momentary_lufs).abs() < 0.1, "{summary:?}");
+        assert!(summary.max_short_term_lufs > -7.0 && summary.max_short_term_lufs < -5.0, "{summary:?}");
+        assert!(summary.rms_db < summary.peak_db - 3.0);
+        assert!(summary.loudness_range_lu > 1.0, "{summary:?}");
+        assert!(summary.integrated_lufs < summary.max_short_term_lufs, "{summary:?}");
+        assert!((summary.true_peak_db - summary.peak_db).abs() < 0.1, "{summary:?}");
+    }
+}
@@ -1,4 +1,5 @@
 pub mod biquad;
+pub mod channel_vocoder;
 pub mod chorus;
 pub mod compressor;
 pub mod convolution_reverb;
@@ -6,14 +7,25 @@ pub mod dc_blocker;
 pub mod deesser;
 pub mod fft_utils;
 pub mod formant_shift;
+pub mod frame_buffer;
 pub mod gain;
 pub mod hrtf;
 pub mod limiter;
+pub mod loudness;
 pub mod loudness_norm;
+pub mod meter;
+pub mod monotone;
 pub mod noise_reduction;
 pub mod normalizer;
+pub mod parametric_eq;
+pub mod pitch_correct;
+pub mod pitch_detect;
 pub mod pitch_shift;
 pub mod pitch_shift_resample;
+pub mod resample;
 pub mod reverb;
 pub mod ring_mod;
+pub mod stft;
+pub mod time_stretch;
 pub mod vad;
+pub mod vocoder;
@@ -0,0 +1,104 @@
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
+
+use super::pitch_correct::{glide, Retuner};
+
+/// Time constant that keeps the held pitch from stepping between hops.
+const GLIDE_MS: f32 = 10.0;
+
+/// Robot voice: holds every voiced sound at one pitch, flattening the
+/// intonation while keeping the words and the voice's formants.
+///
+/// With `amount` below 1 part of the original melody stays. Unvoiced
+/// sounds keep the last shift. Streams with a latency of 2048 samples.
+pub struct Monotone {
+    pitch_hz: SmoothedParam,
+    amount: SmoothedParam,
+    sample_rate: u32,
+    retuner: Retuner,
+}
+
+impl Monotone {
+    pub const PARAMS: [ParamDescriptor; 2] = [
+        ParamDescriptor { id: 0, key: "pitch_hz", name: "Pitch (Hz)", min: 60.0, max: 400.0, default: 120.0 },
+        ParamDescriptor { id: 1, key: "amount", name: "Amount", min: 0.0, max: 1.0, default: 1.0 },
+    ];
+
+    pub fn new(pitch_hz: f32, amount: f32) -> Self {
+        Self {
+            pitch_hz: SmoothedParam::new(Self::PARAMS[0], pitch_hz),
+            amount: SmoothedParam::new(Self::PARAMS[1], amount),
+            sample_rate: 48000,
+            retuner: Retuner::new(),
+        }
+    }
+}
+
+impl AudioNode for Monotone {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.pitch_hz.prepare(sample_rate);
+        self.amount.prepare(sample_rate);
+        self.sample_rate = sample_rate;
+        self.retuner.prepare(sample_rate);
+    }
+
+    fn latency_samples(&self) -> usize {
+        self.retuner.latency_samples()
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        let target_hz = self.pitch_hz.target();
+        let amount = self.amount.target();
+        let sample_rate = self.sample_rate;
+        self.retuner.process(&mut buffer.samples, |estimate, shift| {
+            if !estimate.is_voiced() {
+                return shift;
+            }
+            let flat = 12.0 * (target_hz / estimate.frequency_hz).log2();
+            glide(shift, amount * flat, GLIDE_MS, sample_rate)
+        });
+    }
+
+    fn reset(&mut self) {
+        self.retuner.reset();
+    }
+
+    fn name(&self) -> &str {
+        "Monotone"
+    }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.pitch_hz.handle(), self.amount.handle()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::pitch_correct::tests::{pitch, voice};
+
+    fn flatten(pitch_hz: f32, amount: f32, input: &[f32]) -> Vec<f32> {
+        let mut node = Monotone::new(pitch_hz, amount);
+        node.prepare(48000, 480);
+        let mut buffer = AudioBuffer::new(input.to_vec(), 48000);
+        buffer.process_blocks(480, |b| node.process(b));
+        buffer.samples
+    }
+
+    #[test]
+    fn test_flattens_intonation() {
+        // 200 Hz swinging two semitones either way twice a second.
+        let input = voice(|t| 200.0 * 2.0f32.powf((t * 2.0 * std::f32::consts::TAU).sin() / 6.0), 48000);
+        let output = flatten(120.0, 1.0, &input);
+        for from in (9600..48000).step_by(4800) {
+            let measured = pitch(&output, from, from + 2400);
+            assert!((measured / 120.0 - 1.0).abs() < 0.03, "at {from}: {measured} Hz");
+        }
+    }
+
+    #[test]
+    fn test_amount_zero_keeps_the_melody() {
+        let input = voice(|_| 210.0, 24000);
+        let measured = pitch(&flatten(120.0, 0.0, &input), 9600, 24000);
+        assert!((measured / 210.0 - 1.0).abs() < 0.005, "{measured} Hz");
+    }
+}
@@ -1,69 +1,148 @@
+use std::sync::OnceLock;
+
 use vozoo_core::{AudioBuffer, AudioNode};
-use nnnoiseless::DenoiseState;
+use nnnoiseless::{DenoiseState, RnnModel};
+
+use super::frame_buffer::FrameBuffer;
+
+const RNNOISE_SAMPLE_RATE: u32 = 48000;
+
+/// A fresh denoiser borrowing the built-in model, which every instance
+/// shares instead of owning a copy.
+fn denoiser() -> Box<DenoiseState<'static>> {
+    static MODEL: OnceLock<RnnModel> = OnceLock::new();
+    DenoiseState::with_model(MODEL.get_or_init(RnnModel::default))
+}
 
 /// Noise reduction using nnnoiseless (Rust port of Xiph's RNNoise).
-/// RNNoise operates on 480-sample frames at 48kHz.
+/// RNNoise operates on 480-sample frames at 48kHz; hosts resample around
+/// this node at other rates.
+///
+/// Input is re-blocked into RNNoise frames, which costs one frame (10ms)
+/// regardless of the host block size; RNNoise's overlap-add synthesis holds
+/// back another, so output lags input by 20ms.
 pub struct NoiseReduction {
     state: Box<DenoiseState<'static>>,
+    /// An untouched denoiser `reset` swaps in, so resetting doesn't build
+    /// one on the audio thread.
+    spare: Box<DenoiseState<'static>>,
+    /// Whether `spare` holds the state swapped out by the last reset.
+    spare_used: bool,
+    frames: FrameBuffer,
+    frame_out: Vec<f32>,
 }
 
 impl NoiseReduction {
     pub fn new() -> Self {
         Self {
-            state: DenoiseState::new(),
+            state: denoiser(),
+            spare: denoiser(),
+            spare_used: false,
+            frames: FrameBuffer::new(DenoiseState::FRAME_SIZE),
+            frame_out: vec![0.0; DenoiseState::FRAME_SIZE],
         }
     }
 }
 
+/// Builds nnnoiseless's FFT plans on the calling thread.
+///
+/// nnnoiseless plans its FFTs on first use and caches the plans per
+/// thread, so the first frame a thread denoises allocates. A real-time
+/// host builds one of these up front and calls `warm` on its audio thread
+/// before streaming; any noise reduction node that thread runs later,
+/// whenever it was added, then processes without allocating.
+pub struct DenoiseWarmup {
+    state: Box<DenoiseState<'static>>,
+}
+
+impl DenoiseWarmup {
+    pub fn new() -> Self {
+        Self { state: denoiser() }
+    }
+
+    /// Denoise one silent frame. Allocates only the thread's FFT plans,
+    /// and only the first time on each thread.
+    pub fn warm(&mut self) {
+        let silence = [0.0; DenoiseState::FRAME_SIZE];
+        let mut out = [0.0; DenoiseState::FRAME_SIZE];
+        self.state.process_frame(&mut out, &silence);
+    }
+}
+
+impl Default for DenoiseWarmup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for NoiseReduction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AudioNode for NoiseReduction {
+    fn prepare(&mut self, _sample_rate: u32, _max_block: usize) {
+        if self.spare_used {
+            self.spare = denoiser();
+            self.spare_used = false;
+        }
+    }
+
+    fn latency_samples(&self) -> usize {
+        self.frames.frame_size() + DenoiseState::FRAME_SIZE
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
-        // RNNoise expects 48kHz. If sample rate differs, skip.
-        if buffer.sample_rate() != 48000 || buffer.samples.is_empty() {
+        // RNNoise expects 48kHz. If a caller bypassed the host's conversion,
+        // skip rather than denoise at the wrong rate.
+        if buffer.sample_rate() != RNNOISE_SAMPLE_RATE || buffer.samples.is_empty() {
             return;
         }
 
-        const FRAME_SIZE: usize = DenoiseState::FRAME_SIZE; // 480
-        let mut output = Vec::with_capacity(buffer.samples.len());
-
         // RNNoise works with f32 samples scaled to roughly [-32768, 32768]
         // Our samples are [-1.0, 1.0], so scale up and back down
         let scale = 32767.0f32;
 
-        let mut frame_in = [0.0f32; FRAME_SIZE];
-        let mut frame_out = [0.0f32; FRAME_SIZE];
-
-        let mut pos = 0;
-        while pos + FRAME_SIZE <= buffer.samples.len() {
+        let Self {
+            state,
+            frames,
+            frame_out,
+            ..
+        } = self;
+        frames.process(&mut buffer.samples, |frame| {
             // Scale up to RNNoise range
-            for (i, s) in buffer.samples[pos..pos + FRAME_SIZE].iter().enumerate() {
-                frame_in[i] = s * scale;
+            for s in frame.iter_mut() {
+                *s *= scale;
             }
 
-            self.state.process_frame(&mut frame_out, &frame_in);
+            state.process_frame(frame_out, frame);
 
             // Scale back down
-            for s in &frame_out {
-                output.push(s / scale);
+            for (s, out) in frame.iter_mut().zip(frame_out.iter()) {
+                *s = out / scale;
             }
-
-            pos += FRAME_SIZE;
-        }
-
-        // Handle remaining samples (pass through unprocessed)
-        if pos < buffer.samples.len() {
-            output.extend_from_slice(&buffer.samples[pos..]);
-        }
-
-        buffer.samples = output;
+        });
     }
 
     fn reset(&mut self) {
-        self.state = DenoiseState::new();
+        if self.spare_used {
+            // Reset twice since the last prepare: nothing clean to swap in.
+            self.state = denoiser();
+        } else {
+            std::mem::swap(&mut self.state, &mut self.spare);
+            self.spare_used = true;
+        }
+        self.frames.reset();
     }
 
     fn name(&self) -> &str {
         "Noise Reduction"
     }
+
+    fn native_sample_rate(&self) -> Option<u32> {
+        Some(RNNOISE_SAMPLE_RATE)
+    }
 }
 
 #[cfg(test)]
@@ -91,4 +170,22 @@ mod tests {
         // Samples should still be in valid range
         assert!(buffer.samples.iter().all(|s| s.abs() < 2.0));
     }
+
+    #[test]
+    fn test_reset_matches_a_fresh_node() {
+        let samples: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.057).sin() * 0.3 + (i as f32 * 7.3).sin() * 0.05).collect();
+        let run = |nr: &mut NoiseReduction| {
+            let mut buffer = AudioBuffer::new(samples.clone(), 48000);
+            nr.process(&mut buffer);
+            buffer.samples
+        };
+        let mut nr = NoiseReduction::new();
+        nr.prepare(48000, 480);
+        let fresh = run(&mut nr);
+        // Twice, so the second reset has no clean spare left.
+        for _ in 0..2 {
+            nr.reset();
+            assert_eq!(run(&mut nr), fresh);
+        }
+    }
 }
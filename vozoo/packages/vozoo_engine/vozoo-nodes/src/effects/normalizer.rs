@@ -1,46 +1,93 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{smoothing_coeff, AudioBuffer, AudioNode, ChannelLayout, ParamDescriptor, ParamHandle, SmoothedParam};
 
 /// RMS normalizer — scales audio to a target RMS level.
+///
+/// Works as a slow automatic gain control so it can stream: the level is
+/// tracked over a ~200ms window and the gain follows it with a fast attack
+/// and slow release. All channels share one gain.
 pub struct Normalizer {
-    target_rms: f32,
+    target_rms: SmoothedParam,
+    mean_square: f32,
+    gain: f32,
 }
 
 impl Normalizer {
+    pub const PARAMS: [ParamDescriptor; 1] = [ParamDescriptor {
+        id: 0,
+        key: "target_rms",
+        name: "Target RMS",
+        min: 0.01,
+        max: 1.0,
+        default: 0.2,
+    }];
+
     /// Create a normalizer with a target RMS level (e.g., 0.2 for moderate volume).
     pub fn new(target_rms: f32) -> Self {
-        Self { target_rms }
+        Self {
+            target_rms: SmoothedParam::new(Self::PARAMS[0], target_rms),
+            mean_square: 0.0,
+            gain: 1.0,
+        }
     }
 }
 
 impl AudioNode for Normalizer {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.target_rms.prepare(sample_rate);
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
         if buffer.samples.is_empty() {
             return;
         }
 
-        // Calculate current RMS
-        let sum_sq: f64 = buffer.samples.iter().map(|s| (*s as f64) * (*s as f64)).sum();
-        let rms = (sum_sq / buffer.samples.len() as f64).sqrt() as f32;
-
-        if rms < 1e-6 {
-            return; // Signal is silence
-        }
+        let sr = buffer.sample_rate() as f32;
+        let ch = buffer.channels() as usize;
+        let level_coeff = smoothing_coeff(0.2, sr);
+        let attack_coeff = smoothing_coeff(0.05, sr);
+        let release_coeff = smoothing_coeff(0.5, sr);
 
-        let gain = self.target_rms / rms;
+        for frame in buffer.samples.chunks_exact_mut(ch) {
+            let frame_sq = frame.iter().map(|s| s * s).sum::<f32>() / ch as f32;
+            self.mean_square += level_coeff * (frame_sq - self.mean_square);
 
-        // Limit gain to avoid extreme amplification of quiet signals
-        let gain = gain.min(10.0);
+            let target_rms = self.target_rms.tick();
+            let rms = self.mean_square.sqrt();
+            // Hold the gain through silence
+            if rms > 1e-3 {
+                // Limit gain to avoid extreme amplification of quiet signals
+                let target_gain = (target_rms / rms).min(10.0);
+                let coeff = if target_gain < self.gain {
+                    attack_coeff
+                } else {
+                    release_coeff
+                };
+                self.gain += coeff * (target_gain - self.gain);
+            }
 
-        for s in &mut buffer.samples {
-            *s *= gain;
+            for s in frame.iter_mut() {
+                *s *= self.gain;
+            }
         }
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        self.mean_square = 0.0;
+        self.gain = 1.0;
+        self.target_rms.snap();
+    }
 
     fn name(&self) -> &str {
         "Normalizer"
     }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.target_rms.handle()]
+    }
+
+    fn input_layout(&self) -> Option<ChannelLayout> {
+        None
+    }
 }
 
 #[cfg(test)]
@@ -49,16 +96,18 @@ mod tests {
 
     #[test]
     fn test_normalizer_adjusts_level() {
-        let samples: Vec<f32> = (0..4800)
-            .map(|i| (i as f32 / 4800.0 * 440.0 * std::f32::consts::TAU).sin() * 0.1)
+        let samples: Vec<f32> = (0..48000 * 3)
+            .map(|i| (i as f32 / 48000.0 * 440.0 * std::f32::consts::TAU).sin() * 0.1)
             .collect();
 
         let mut buffer = AudioBuffer::new(samples, 48000);
         let mut normalizer = Normalizer::new(0.2);
         normalizer.process(&mut buffer);
 
-        let sum_sq: f64 = buffer.samples.iter().map(|s| (*s as f64) * (*s as f64)).sum();
-        let rms = (sum_sq / buffer.samples.len() as f64).sqrt() as f32;
+        // Measure the last second, after the gain has settled
+        let tail = &buffer.samples[48000 * 2..];
+        let sum_sq: f64 = tail.iter().map(|s| (*s as f64) * (*s as f64)).sum();
+        let rms = (sum_sq / tail.len() as f64).sqrt() as f32;
         assert!((rms - 0.2).abs() < 0.02, "RMS not normalized: got {rms}");
     }
 }
@@ -0,0 +1,199 @@
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
+
+use super::biquad::{BiquadFilter, FilterType};
+
+/// Most bands a parametric EQ runs.
+pub const MAX_EQ_BANDS: usize = 8;
+
+/// Band responses by `bandN_type` value.
+const BAND_TYPES: [FilterType; 8] = [
+    FilterType::Peaking,
+    FilterType::LowShelf,
+    FilterType::HighShelf,
+    FilterType::LowPass,
+    FilterType::HighPass,
+    FilterType::BandPass,
+    FilterType::Notch,
+    FilterType::AllPass,
+];
+
+/// Settings of one EQ band.
+#[derive(Clone, Copy, Debug)]
+pub struct EqBand {
+    pub filter_type: FilterType,
+    pub freq: f32,
+    pub q: f32,
+    pub gain_db: f32,
+}
+
+impl EqBand {
+    /// The response selected by a `bandN_type` value.
+    pub fn type_from_index(index: f32) -> FilterType {
+        BAND_TYPES[(index.round().max(0.0) as usize).min(BAND_TYPES.len() - 1)]
+    }
+
+    fn type_index(filter_type: FilterType) -> f32 {
+        BAND_TYPES.iter().position(|&t| t == filter_type).unwrap_or(0) as f32
+    }
+}
+
+/// The "bands" count, then type, frequency, Q and gain of each band, with
+/// each band's default frequency.
+macro_rules! eq_params {
+    ($($band:literal => $freq:literal),* $(,)?) => {
+        [
+            ParamDescriptor { id: 0, key: "bands", name: "Bands", min: 1.0, max: MAX_EQ_BANDS as f32, default: 4.0 },
+            $(
+                ParamDescriptor {
+                    id: 4 * $band - 3,
+                    key: concat!("band", $band, "_type"),
+                    name: concat!("Band ", $band, " Type (0 Peak, 1 Low Shelf, 2 High Shelf, 3 Low Pass, 4 High Pass, 5 Band Pass, 6 Notch, 7 All Pass)"),
+                    min: 0.0,
+                    max: 7.0,
+                    default: 0.0,
+                },
+                ParamDescriptor { id: 4 * $band - 2, key: concat!("band", $band, "_freq"), name: concat!("Band ", $band, " Frequency (Hz)"), min: 20.0, max: 20000.0, default: $freq },
+                ParamDescriptor { id: 4 * $band - 1, key: concat!("band", $band, "_q"), name: concat!("Band ", $band, " Q Factor"), min: 0.1, max: 10.0, default: 1.0 },
+                ParamDescriptor { id: 4 * $band, key: concat!("band", $band, "_gain_db"), name: concat!("Band ", $band, " Gain (dB)"), min: -24.0, max: 24.0, default: 0.0 },
+            )*
+        ]
+    };
+}
+
+/// Parametric EQ: up to eight biquad bands in series, each a bell, shelf,
+/// pass, notch or allpass response.
+///
+/// Every setting is live. Frequency, Q and gain sweeps glide through
+/// smoothed coefficients, and a band switching type crossfades to its new
+/// coefficients, so neither clicks. Bands past `bands` are bypassed.
+pub struct ParametricEq {
+    bands: SmoothedParam,
+    types: Vec<SmoothedParam>,
+    filters: Vec<BiquadFilter>,
+    /// Bands run in the last block.
+    active: usize,
+}
+
+impl ParametricEq {
+    pub const PARAMS: [ParamDescriptor; 1 + 4 * MAX_EQ_BANDS] = eq_params![
+        1 => 80.0,
+        2 => 250.0,
+        3 => 1000.0,
+        4 => 4000.0,
+        5 => 500.0,
+        6 => 2000.0,
+        7 => 8000.0,
+        8 => 12000.0,
+    ];
+
+    /// An EQ running the first `bands` of `settings`. Bands missing from
+    /// `settings` start at their parameter defaults, a flat bell.
+    pub fn new(bands: f32, settings: &[EqBand]) -> Self {
+        let (types, filters) = Self::PARAMS[1..]
+            .chunks(4)
+            .enumerate()
+            .map(|(i, d)| {
+                let band = settings.get(i).copied().unwrap_or(EqBand {
+                    filter_type: EqBand::type_from_index(d[0].default),
+                    freq: d[1].default,
+                    q: d[2].default,
+                    gain_db: d[3].default,
+                });
+                (
+                    SmoothedParam::new(d[0], EqBand::type_index(band.filter_type)),
+                    BiquadFilter::with_params(band.filter_type, [d[1], d[2], d[3]], band.freq, band.q, band.gain_db),
+                )
+            })
+            .unzip();
+        Self { bands: SmoothedParam::new(Self::PARAMS[0], bands), types, filters, active: 0 }
+    }
+
+    fn band_count(&self) -> usize {
+        (self.bands.target().round() as usize).clamp(1, MAX_EQ_BANDS)
+    }
+}
+
+impl AudioNode for ParametricEq {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        self.bands.prepare(sample_rate);
+        for (kind, filter) in self.types.iter_mut().zip(&mut self.filters) {
+            kind.prepare(sample_rate);
+            filter.prepare(sample_rate, max_block);
+        }
+        self.active = self.band_count();
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        let count = self.band_count();
+        // Bands coming out of bypass start from silence at their settings.
+        for filter in self.filters.iter_mut().take(count).skip(self.active) {
+            filter.reset();
+        }
+        self.active = count;
+        for (kind, filter) in self.types.iter().zip(&mut self.filters).take(count) {
+            filter.set_filter_type(EqBand::type_from_index(kind.target()));
+            filter.process(buffer);
+        }
+    }
+
+    fn reset(&mut self) {
+        for filter in &mut self.filters {
+            filter.reset();
+        }
+        self.active = self.band_count();
+    }
+
+    fn name(&self) -> &str {
+        "Parametric EQ"
+    }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        let bands = self.types.iter().zip(&self.filters).flat_map(|(kind, filter)| {
+            let [freq, q, gain_db] = filter.handles();
+            [kind.handle(), freq, q, gain_db]
+        });
+        std::iter::once(self.bands.handle()).chain(bands).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::biquad::tests::response_db;
+
+    fn band(filter_type: FilterType, freq: f32, q: f32, gain_db: f32) -> EqBand {
+        EqBand { filter_type, freq, q, gain_db }
+    }
+
+    #[test]
+    fn test_bands_combine_in_series() {
+        let mut eq = ParametricEq::new(
+            3.0,
+            &[
+                band(FilterType::HighPass, 300.0, 0.707, 0.0),
+                band(FilterType::Peaking, 1500.0, 1.0, 6.0),
+                band(FilterType::LowPass, 3400.0, 0.707, 0.0),
+            ],
+        );
+        assert!(response_db(&mut eq, 60.0) < -20.0);
+        assert!((response_db(&mut eq, 1500.0) - 6.0).abs() < 0.5);
+        assert!(response_db(&mut eq, 12000.0) < -20.0);
+    }
+
+    #[test]
+    fn test_bands_past_the_count_are_bypassed() {
+        let cut = [band(FilterType::Peaking, 1000.0, 1.0, 0.0), band(FilterType::Notch, 1000.0, 2.0, 0.0)];
+        assert!(response_db(&mut ParametricEq::new(1.0, &cut), 1000.0).abs() < 0.1);
+        assert!(response_db(&mut ParametricEq::new(2.0, &cut), 1000.0) < -40.0);
+    }
+
+    #[test]
+    fn test_live_type_change() {
+        let mut eq = ParametricEq::new(1.0, &[band(FilterType::Peaking, 1000.0, 1.0, 0.0)]);
+        let params = eq.params();
+        assert_eq!(params.len(), ParametricEq::PARAMS.len());
+        assert!(params.iter().enumerate().all(|(i, p)| p.descriptor.id as usize == i));
+        params[1].set(6.0);
+        assert!(response_db(&mut eq, 1000.0) < -40.0);
+    }
+}
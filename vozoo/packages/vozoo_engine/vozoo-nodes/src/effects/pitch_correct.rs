@@ -0,0 +1,223 @@
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
+
+use super::pitch_detect::{PitchEstimate, PitchMethod, PitchTracker, PITCH_HOP};
+use super::pitch_shift::LockedShifter;
+
+// The tracker reads exactly the frames the shifter shifts.
+const _: () = assert!(PITCH_HOP == LockedShifter::HOP_SIZE);
+
+/// Scale degrees in semitones above the key, by `scale` parameter value.
+const SCALES: [&[i32]; 3] = [
+    &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+    &[0, 2, 4, 5, 7, 9, 11],
+    &[0, 2, 3, 5, 7, 8, 10],
+];
+
+/// A pYIN tracker steering a phase-locked, formant-preserving pitch
+/// shifter. Each hop the tracker reads the frame the shifter is about to
+/// shift, and the owning node turns the estimate into a shift.
+pub(super) struct Retuner {
+    tracker: PitchTracker,
+    shifter: LockedShifter,
+    /// Shift applied to the last frame, in semitones.
+    shift: f32,
+}
+
+impl Retuner {
+    pub(super) fn new() -> Self {
+        Self {
+            tracker: PitchTracker::new(48000, PitchMethod::Pyin),
+            shifter: LockedShifter::with_formants(true),
+            shift: 0.0,
+        }
+    }
+
+    pub(super) fn prepare(&mut self, sample_rate: u32) {
+        if self.tracker.sample_rate() != sample_rate {
+            self.tracker = PitchTracker::new(sample_rate, PitchMethod::Pyin);
+        }
+    }
+
+    pub(super) fn latency_samples(&self) -> usize {
+        self.shifter.latency_samples()
+    }
+
+    /// Stream `samples` through in place. `retune` gets each hop's pitch
+    /// estimate and the current shift, and returns the next shift in
+    /// semitones.
+    pub(super) fn process(&mut self, samples: &mut [f32], mut retune: impl FnMut(PitchEstimate, f32) -> f32) {
+        let Self { tracker, shifter, shift } = self;
+        shifter.process(samples, |hop| {
+            tracker.process(hop);
+            *shift = retune(tracker.estimate(), *shift);
+            2.0f32.powf(*shift / 12.0)
+        });
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.tracker.reset();
+        self.shifter.reset();
+        self.shift = 0.0;
+    }
+}
+
+/// One hop of an exponential glide from `current` toward `target` with a
+/// time constant of `time_ms`; 0 ms jumps straight there.
+pub(super) fn glide(current: f32, target: f32, time_ms: f32, sample_rate: u32) -> f32 {
+    if time_ms <= 0.0 {
+        return target;
+    }
+    let hop_ms = 1000.0 * PITCH_HOP as f32 / sample_rate as f32;
+    target + (current - target) * (-hop_ms / time_ms).exp()
+}
+
+/// Autotune: pulls voiced sounds to the nearest note of a scale.
+///
+/// `retune_ms` sets how fast the pitch moves onto the note: 0 jumps
+/// straight there, giving the hard-tuned robotic sound, while a few
+/// hundred ms only straightens held notes. Unvoiced sounds keep the last
+/// correction. Streams with a latency of 2048 samples.
+pub struct PitchCorrect {
+    key: SmoothedParam,
+    scale: SmoothedParam,
+    retune_ms: SmoothedParam,
+    sample_rate: u32,
+    retuner: Retuner,
+}
+
+impl PitchCorrect {
+    pub const PARAMS: [ParamDescriptor; 3] = [
+        ParamDescriptor { id: 0, key: "key", name: "Key (0 C .. 11 B)", min: 0.0, max: 11.0, default: 0.0 },
+        ParamDescriptor { id: 1, key: "scale", name: "Scale (0 Chromatic, 1 Major, 2 Minor)", min: 0.0, max: 2.0, default: 0.0 },
+        ParamDescriptor { id: 2, key: "retune_ms", name: "Retune Speed (ms)", min: 0.0, max: 500.0, default: 0.0 },
+    ];
+
+    pub fn new(key: f32, scale: f32, retune_ms: f32) -> Self {
+        Self {
+            key: SmoothedParam::new(Self::PARAMS[0], key),
+            scale: SmoothedParam::new(Self::PARAMS[1], scale),
+            retune_ms: SmoothedParam::new(Self::PARAMS[2], retune_ms),
+            sample_rate: 48000,
+            retuner: Retuner::new(),
+        }
+    }
+}
+
+/// The note of `scale` in `key` nearest to the (fractional) MIDI note
+/// `note`.
+fn nearest_note(note: f32, key: i32, scale: &[i32]) -> f32 {
+    let octave = ((note - key as f32) / 12.0).floor() as i32;
+    (octave - 1..=octave + 1)
+        .flat_map(|o| scale.iter().map(move |&degree| (key + 12 * o + degree) as f32))
+        .min_by(|a, b| (a - note).abs().total_cmp(&(b - note).abs()))
+        .unwrap_or(note)
+}
+
+impl AudioNode for PitchCorrect {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.key.prepare(sample_rate);
+        self.scale.prepare(sample_rate);
+        self.retune_ms.prepare(sample_rate);
+        self.sample_rate = sample_rate;
+        self.retuner.prepare(sample_rate);
+    }
+
+    fn latency_samples(&self) -> usize {
+        self.retuner.latency_samples()
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        let key = self.key.target().round() as i32;
+        let scale = SCALES[(self.scale.target().round() as usize).min(SCALES.len() - 1)];
+        let retune_ms = self.retune_ms.target();
+        let sample_rate = self.sample_rate;
+        self.retuner.process(&mut buffer.samples, |estimate, shift| {
+            if !estimate.is_voiced() {
+                return shift;
+            }
+            let note = estimate.midi_note();
+            glide(shift, nearest_note(note, key, scale) - note, retune_ms, sample_rate)
+        });
+    }
+
+    fn reset(&mut self) {
+        self.retuner.reset();
+    }
+
+    fn name(&self) -> &str {
+        "Pitch Correct"
+    }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.key.handle(), self.scale.handle(), self.retune_ms.handle()]
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// A held note with a few harmonics, like a sung vowel.
+    pub(in crate::effects) fn voice(frequency: impl Fn(f32) -> f32, frames: usize) -> Vec<f32> {
+        let mut phase = 0.0f32;
+        (0..frames)
+            .map(|i| {
+                phase += std::f32::consts::TAU * frequency(i as f32 / 48000.0) / 48000.0;
+                0.4 * phase.sin() + 0.2 * (2.0 * phase).sin() + 0.1 * (3.0 * phase).sin()
+            })
+            .collect()
+    }
+
+    /// Median tracked pitch of `samples[from..to]`.
+    pub(in crate::effects) fn pitch(samples: &[f32], from: usize, to: usize) -> f32 {
+        let estimates = PitchTracker::track(&AudioBuffer::new(samples.to_vec(), 48000), PitchMethod::Pyin);
+        let mut voiced: Vec<f32> = estimates[from / PITCH_HOP..to / PITCH_HOP]
+            .iter()
+            .filter(|e| e.is_voiced())
+            .map(|e| e.frequency_hz)
+            .collect();
+        assert!(!voiced.is_empty(), "nothing voiced in {from}..{to}");
+        voiced.sort_by(f32::total_cmp);
+        voiced[voiced.len() / 2]
+    }
+
+    fn correct(node: &mut PitchCorrect, input: &[f32]) -> Vec<f32> {
+        node.prepare(48000, 480);
+        let mut buffer = AudioBuffer::new(input.to_vec(), 48000);
+        buffer.process_blocks(480, |b| node.process(b));
+        buffer.samples
+    }
+
+    #[test]
+    fn test_nearest_note() {
+        let (chromatic, major, minor) = (SCALES[0], SCALES[1], SCALES[2]);
+        assert_eq!(nearest_note(69.4, 0, chromatic), 69.0);
+        assert_eq!(nearest_note(69.6, 0, chromatic), 70.0);
+        // A#: between A and B in C major, in D minor.
+        assert_eq!(nearest_note(70.2, 0, major), 71.0);
+        assert_eq!(nearest_note(70.0, 2, minor), 70.0);
+        // Wraps below the key.
+        assert_eq!(nearest_note(58.6, 0, major), 59.0);
+    }
+
+    #[test]
+    fn test_snaps_to_the_scale() {
+        // 460 Hz sits between A (440) and A# (466.2).
+        let input = voice(|_| 460.0, 48000);
+        for (scale, expected) in [(0.0, 466.16), (1.0, 440.0)] {
+            let output = correct(&mut PitchCorrect::new(0.0, scale, 0.0), &input);
+            let measured = pitch(&output, 12000, 48000);
+            assert!((measured / expected - 1.0).abs() < 0.005, "scale {scale}: {measured} Hz");
+        }
+    }
+
+    #[test]
+    fn test_retune_speed_slows_the_correction() {
+        let input = voice(|_| 460.0, 48000);
+        let early = |retune_ms: f32| pitch(&correct(&mut PitchCorrect::new(0.0, 1.0, retune_ms), &input), 6144, 9216);
+        assert!((early(0.0) / 440.0 - 1.0).abs() < 0.005);
+        assert!(early(300.0) > 450.0);
+        let settled = pitch(&correct(&mut PitchCorrect::new(0.0, 1.0, 300.0), &input), 40000, 48000);
+        assert!((settled / 440.0 - 1.0).abs() < 0.01, "{settled} Hz");
+    }
+}
@@ -0,0 +1,463 @@
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::Fft;
+use vozoo_core::AudioBuffer;
+
+use super::fft_utils;
+
+/// Samples between estimates (about 5 ms at 48 kHz).
+pub const PITCH_HOP: usize = 256;
+/// Normalized difference a YIN dip must fall below to count as a period.
+const YIN_THRESHOLD: f32 = 0.15;
+/// pYIN threshold prior: Beta(2, 11.33), mean 0.15, over 0.01..=1.00.
+const PYIN_THRESHOLDS: usize = 100;
+const PYIN_BETA: (f32, f32) = (2.0, 11.33);
+/// Weight pYIN gives the global minimum when no dip is under a threshold.
+const PYIN_ABSOLUTE_MIN: f32 = 0.01;
+/// How much the HMM believes the candidates (pYIN's "YIN trust").
+const PYIN_TRUST: f32 = 0.5;
+const BINS_PER_SEMITONE: f32 = 5.0;
+/// Largest pitch move between estimates the HMM allows, in bins.
+const MAX_JUMP: usize = 25;
+const VOICING_STAY: f32 = 0.99;
+
+/// How `PitchTracker` decides on a period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PitchMethod {
+    /// The first dip of the normalized difference under a fixed threshold.
+    Yin,
+    /// Every threshold at once, weighted by a prior, with an HMM choosing
+    /// among the candidates over time. Far fewer octave jumps and voicing
+    /// flickers than `Yin`.
+    Pyin,
+}
+
+/// Fundamental frequency of one analysis frame.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PitchEstimate {
+    /// 0 when no period was found.
+    pub frequency_hz: f32,
+    /// How likely the frame is voiced, 0..=1.
+    pub probability: f32,
+}
+
+impl PitchEstimate {
+    pub fn is_voiced(&self) -> bool {
+        self.frequency_hz > 0.0 && self.probability >= 0.5
+    }
+
+    /// Pitch as a MIDI note number (69 = A4 = 440 Hz), fractional.
+    pub fn midi_note(&self) -> f32 {
+        69.0 + 12.0 * (self.frequency_hz / 440.0).log2()
+    }
+}
+
+/// Streaming YIN / pYIN fundamental-frequency tracker for mono audio.
+///
+/// Each `PITCH_HOP` samples it analyses the last `frame_size()` samples:
+/// the YIN difference function over lags up to one period of `min_hz`,
+/// computed with FFTs, then cumulative-mean normalized. An estimate
+/// describes the middle of that frame, `latency_samples()` back.
+///
+/// Everything is allocated in `new`; `process` doesn't allocate.
+pub struct PitchTracker {
+    method: PitchMethod,
+    sample_rate: u32,
+    min_lag: usize,
+    max_lag: usize,
+    /// Samples each lag is compared over.
+    window: usize,
+    /// The last `frame_size` input samples.
+    history: Vec<f32>,
+    hop_pos: usize,
+    fft_forward: Arc<dyn Fft<f32>>,
+    fft_inverse: Arc<dyn Fft<f32>>,
+    head: Vec<Complex<f32>>,
+    frame: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    /// Running sums of squares of `history`.
+    energy: Vec<f64>,
+    /// Cumulative mean normalized difference per lag.
+    normalized: Vec<f32>,
+    /// Local minima of `normalized` in the lag range: (lag, value).
+    dips: Vec<(usize, f32)>,
+    /// Candidate (frequency, probability) pairs of the current frame.
+    candidates: Vec<(f32, f32)>,
+    hmm: Option<Box<PitchHmm>>,
+    estimate: PitchEstimate,
+}
+
+/// pYIN's pitch HMM, decoded causally: a voiced and an unvoiced state per
+/// pitch bin, filtered forward one frame at a time.
+struct PitchHmm {
+    min_hz: f32,
+    bins: usize,
+    prior: Vec<f32>,
+    /// Transition weight by distance in bins, summing to one over ±MAX_JUMP.
+    jump: Vec<f32>,
+    /// Voiced states, then unvoiced.
+    belief: Vec<f32>,
+    predicted: Vec<f32>,
+    /// Candidate probability per bin.
+    observed: Vec<f32>,
+}
+
+impl PitchTracker {
+    /// Track 60 Hz to 1 kHz, which covers speaking and singing voices.
+    pub fn new(sample_rate: u32, method: PitchMethod) -> Self {
+        Self::with_range(sample_rate, method, 60.0, 1000.0)
+    }
+
+    pub fn with_range(sample_rate: u32, method: PitchMethod, min_hz: f32, max_hz: f32) -> Self {
+        let rate = sample_rate as f32;
+        let max_lag = (rate / min_hz).ceil() as usize;
+        let min_lag = ((rate / max_hz).floor() as usize).clamp(2, max_lag - 1);
+        let window = max_lag;
+        let size = (window + max_lag + 1).next_power_of_two();
+        let (fft_forward, fft_inverse) = fft_utils::create_fft_pair(size);
+        let scratch_len = fft_forward.get_inplace_scratch_len().max(fft_inverse.get_inplace_scratch_len());
+
+        Self {
+            method,
+            sample_rate,
+            min_lag,
+            max_lag,
+            window,
+            history: vec![0.0; size],
+            hop_pos: 0,
+            fft_forward,
+            fft_inverse,
+            head: vec![Complex::new(0.0, 0.0); size],
+            frame: vec![Complex::new(0.0, 0.0); size],
+            scratch: vec![Complex::new(0.0, 0.0); scratch_len],
+            energy: vec![0.0; size + 1],
+            normalized: vec![1.0; max_lag + 2],
+            dips: Vec::with_capacity(max_lag),
+            candidates: Vec::with_capacity(max_lag),
+            hmm: (method == PitchMethod::Pyin).then(|| Box::new(PitchHmm::new(min_hz, max_hz))),
+            estimate: PitchEstimate::default(),
+        }
+    }
+
+    /// Track a whole buffer (downmixed to mono): one estimate per
+    /// `PITCH_HOP` frames.
+    pub fn track(buffer: &AudioBuffer, method: PitchMethod) -> Vec<PitchEstimate> {
+        let channels = buffer.channels().max(1) as usize;
+        let mono: Vec<f32> = buffer
+            .samples
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+        let mut tracker = Self::new(buffer.sample_rate(), method);
+        mono.chunks_exact(PITCH_HOP)
+            .map(|hop| {
+                tracker.process(hop);
+                tracker.estimate()
+            })
+            .collect()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn method(&self) -> PitchMethod {
+        self.method
+    }
+
+    /// Samples analysed per estimate.
+    pub fn frame_size(&self) -> usize {
+        self.history.len()
+    }
+
+    /// How far behind the input the middle of the analysed frame is.
+    pub fn latency_samples(&self) -> usize {
+        self.history.len() / 2
+    }
+
+    /// The estimate for the latest complete hop.
+    pub fn estimate(&self) -> PitchEstimate {
+        self.estimate
+    }
+
+    /// Feed mono samples; the estimate updates at every hop boundary.
+    pub fn process(&mut self, samples: &[f32]) {
+        let size = self.history.len();
+        for &s in samples {
+            self.history[size - PITCH_HOP + self.hop_pos] = s;
+            self.hop_pos += 1;
+            if self.hop_pos == PITCH_HOP {
+                self.analyze();
+                self.history.copy_within(PITCH_HOP.., 0);
+                self.hop_pos = 0;
+            }
+        }
+    }
+
+    fn analyze(&mut self) {
+        self.difference();
+        self.dips.clear();
+        let d = &self.normalized;
+        self.dips
+            .extend((self.min_lag..self.max_lag).filter(|&lag| d[lag] < d[lag - 1] && d[lag] <= d[lag + 1]).map(|lag| (lag, d[lag])));
+
+        self.candidates.clear();
+        self.estimate = match self.method {
+            PitchMethod::Yin => match self.dips.iter().find(|&&(_, value)| value < YIN_THRESHOLD) {
+                Some(&(lag, value)) => PitchEstimate {
+                    frequency_hz: self.sample_rate as f32 / refine(&self.normalized, lag),
+                    probability: (1.0 - value).clamp(0.0, 1.0),
+                },
+                None => PitchEstimate::default(),
+            },
+            PitchMethod::Pyin => {
+                self.vote();
+                let hmm = self.hmm.as_mut().expect("pYIN trackers have an HMM");
+                hmm.step(&self.candidates)
+            }
+        };
+    }
+
+    /// pYIN candidates: each threshold votes for the first dip under it,
+    /// or weakly for the deepest dip if none is.
+    fn vote(&mut self) {
+        let Self { hmm, dips, candidates, normalized, sample_rate, .. } = self;
+        let prior = &hmm.as_ref().expect("pYIN trackers have an HMM").prior;
+        let deepest = dips.iter().copied().min_by(|a, b| a.1.total_cmp(&b.1));
+        for (i, &weight) in prior.iter().enumerate() {
+            let threshold = (i + 1) as f32 / PYIN_THRESHOLDS as f32;
+            let vote = match dips.iter().find(|&&(_, value)| value < threshold) {
+                Some(&(lag, _)) => Some((lag, weight)),
+                None => deepest.map(|(lag, _)| (lag, weight * PYIN_ABSOLUTE_MIN)),
+            };
+            let Some((lag, weight)) = vote else {
+                continue;
+            };
+            let frequency = *sample_rate as f32 / refine(normalized, lag);
+            match candidates.iter_mut().find(|(f, _)| *f == frequency) {
+                Some((_, p)) => *p += weight,
+                None => candidates.push((frequency, weight)),
+            }
+        }
+    }
+
+    /// Cumulative mean normalized YIN difference of the current frame.
+    fn difference(&mut self) {
+        let size = self.history.len();
+        let (window, max_lag) = (self.window, self.max_lag);
+
+        // Correlation of the first `window` samples with every lag, as
+        // IFFT(conj(FFT(head)) * FFT(frame)); lags stay short of wrapping.
+        for (i, (h, f)) in self.head.iter_mut().zip(self.frame.iter_mut()).enumerate() {
+            let x = self.history[i];
+            *h = Complex::new(if i < window { x } else { 0.0 }, 0.0);
+            *f = Complex::new(x, 0.0);
+        }
+        self.fft_forward.process_with_scratch(&mut self.head, &mut self.scratch);
+        self.fft_forward.process_with_scratch(&mut self.frame, &mut self.scratch);
+        for (h, f) in self.head.iter_mut().zip(&self.frame) {
+            *h = h.conj() * f;
+        }
+        self.fft_inverse.process_with_scratch(&mut self.head, &mut self.scratch);
+
+        for i in 0..size {
+            let x = self.history[i] as f64;
+            self.energy[i + 1] = self.energy[i] + x * x;
+        }
+        let span = |lag: usize| self.energy[lag + window] - self.energy[lag];
+        let base = span(0);
+        // Below about -90 dBFS there's nothing to track.
+        if base < 1e-9 * window as f64 {
+            self.normalized.fill(1.0);
+            return;
+        }
+
+        self.normalized[0] = 1.0;
+        let mut running = 0.0f64;
+        for lag in 1..=max_lag + 1 {
+            let correlation = self.head[lag].re as f64 / size as f64;
+            let d = (base + span(lag) - 2.0 * correlation).max(0.0);
+            running += d;
+            self.normalized[lag] = if running > 0.0 { (d * lag as f64 / running) as f32 } else { 1.0 };
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.hop_pos = 0;
+        if let Some(hmm) = self.hmm.as_mut() {
+            hmm.reset();
+        }
+        self.estimate = PitchEstimate::default();
+    }
+}
+
+/// Lag of the dip at `lag`, refined by a parabola through it and its
+/// neighbours.
+fn refine(normalized: &[f32], lag: usize) -> f32 {
+    let (a, b, c) = (normalized[lag - 1], normalized[lag], normalized[lag + 1]);
+    let curvature = a - 2.0 * b + c;
+    let offset = if curvature > 0.0 { (0.5 * (a - c) / curvature).clamp(-0.5, 0.5) } else { 0.0 };
+    lag as f32 + offset
+}
+
+impl PitchHmm {
+    fn new(min_hz: f32, max_hz: f32) -> Self {
+        let bins = (12.0 * BINS_PER_SEMITONE * (max_hz / min_hz).log2()).ceil() as usize + 1;
+
+        let (a, b) = PYIN_BETA;
+        let pdf = |x: f32| x.powf(a - 1.0) * (1.0 - x).powf(b - 1.0);
+        let mut prior: Vec<f32> = (1..=PYIN_THRESHOLDS).map(|i| pdf(i as f32 / PYIN_THRESHOLDS as f32)).collect();
+        let total: f32 = prior.iter().sum();
+        prior.iter_mut().for_each(|p| *p /= total);
+
+        let mut jump: Vec<f32> = (0..=MAX_JUMP).map(|d| (MAX_JUMP + 1 - d) as f32).collect();
+        let total = jump[0] + 2.0 * jump[1..].iter().sum::<f32>();
+        jump.iter_mut().for_each(|w| *w /= total);
+
+        let mut hmm = Self {
+            min_hz,
+            bins,
+            prior,
+            jump,
+            belief: vec![0.0; 2 * bins],
+            predicted: vec![0.0; 2 * bins],
+            observed: vec![0.0; bins],
+        };
+        hmm.reset();
+        hmm
+    }
+
+    fn bin_of(&self, frequency: f32) -> Option<usize> {
+        let bin = (12.0 * BINS_PER_SEMITONE * (frequency / self.min_hz).log2()).round();
+        (bin >= 0.0 && (bin as usize) < self.bins).then_some(bin as usize)
+    }
+
+    /// Fold one frame's candidates into the belief and read off the most
+    /// likely state.
+    fn step(&mut self, candidates: &[(f32, f32)]) -> PitchEstimate {
+        let bins = self.bins;
+        self.observed.fill(0.0);
+        for &(frequency, p) in candidates {
+            if let Some(bin) = self.bin_of(frequency) {
+                self.observed[bin] += PYIN_TRUST * p;
+            }
+        }
+        let unvoiced = ((1.0 - self.observed.iter().sum::<f32>()) / bins as f32).max(0.0);
+
+        // Predict: pitch moves a little, voicing rarely flips.
+        let (voiced, silent) = self.belief.split_at(bins);
+        for to in 0..bins {
+            let (mut from_voiced, mut from_silent) = (0.0, 0.0);
+            for from in to.saturating_sub(MAX_JUMP)..(to + MAX_JUMP + 1).min(bins) {
+                let w = self.jump[from.abs_diff(to)];
+                from_voiced += w * voiced[from];
+                from_silent += w * silent[from];
+            }
+            self.predicted[to] = VOICING_STAY * from_voiced + (1.0 - VOICING_STAY) * from_silent;
+            self.predicted[bins + to] = (1.0 - VOICING_STAY) * from_voiced + VOICING_STAY * from_silent;
+        }
+
+        // Update with the observation and renormalize.
+        for (bin, (b, &p)) in self.belief.iter_mut().zip(&self.predicted).enumerate() {
+            *b = p * if bin < bins { self.observed[bin] } else { unvoiced };
+        }
+        let total: f32 = self.belief.iter().sum();
+        if total <= 0.0 || !total.is_finite() {
+            self.reset();
+            return PitchEstimate::default();
+        }
+        self.belief.iter_mut().for_each(|b| *b /= total);
+
+        let probability: f32 = self.belief[..bins].iter().sum();
+        let best = (0..bins).max_by(|&a, &b| self.belief[a].total_cmp(&self.belief[b])).unwrap_or(0);
+        // The candidate that fed the winning bin has the exact frequency.
+        let frequency_hz = candidates
+            .iter()
+            .filter_map(|&(frequency, p)| self.bin_of(frequency).map(|bin| (frequency, p, bin.abs_diff(best))))
+            .filter(|&(_, _, distance)| distance <= 1)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(0.0, |(frequency, _, _)| frequency);
+        PitchEstimate { frequency_hz, probability }
+    }
+
+    fn reset(&mut self) {
+        // Start out unvoiced, with no idea of the pitch.
+        let (voiced, silent) = self.belief.split_at_mut(self.bins);
+        voiced.fill(0.0);
+        silent.fill(1.0 / self.bins as f32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use super::*;
+
+    /// A tone with a few harmonics, like a sung vowel.
+    fn voice(frequency: impl Fn(f32) -> f32, frames: usize) -> Vec<f32> {
+        let mut phase = 0.0f32;
+        (0..frames)
+            .map(|i| {
+                phase += TAU * frequency(i as f32 / 48000.0) / 48000.0;
+                0.4 * phase.sin() + 0.2 * (2.0 * phase).sin() + 0.1 * (3.0 * phase).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_finds_the_pitch_of_voiced_tones() {
+        for method in [PitchMethod::Yin, PitchMethod::Pyin] {
+            for hz in [82.0f32, 147.0, 220.0, 440.0, 880.0] {
+                let estimates = PitchTracker::track(&AudioBuffer::new(voice(|_| hz, 24000), 48000), method);
+                for estimate in &estimates[20..] {
+                    assert!(estimate.is_voiced(), "{method:?} {hz} Hz: {estimate:?}");
+                    assert!((estimate.frequency_hz / hz - 1.0).abs() < 0.005, "{method:?} {hz} Hz: {estimate:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_silence_and_noise_are_unvoiced() {
+        let mut seed = 1u32;
+        let noise: Vec<f32> = (0..24000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+            })
+            .collect();
+        for method in [PitchMethod::Yin, PitchMethod::Pyin] {
+            let silent = PitchTracker::track(&AudioBuffer::new(vec![0.0; 24000], 48000), method);
+            assert!(silent.iter().all(|e| !e.is_voiced() && e.probability == 0.0), "{method:?}");
+            let noisy = PitchTracker::track(&AudioBuffer::new(noise.clone(), 48000), method);
+            let voiced = noisy[20..].iter().filter(|e| e.is_voiced()).count();
+            assert!(voiced * 10 < noisy.len(), "{method:?}: {voiced} voiced noise frames");
+        }
+    }
+
+    #[test]
+    fn test_pyin_follows_a_glide_and_a_pause() {
+        // 150 Hz rising to 300 Hz over a second, then silence.
+        let mut input = voice(|t| 150.0 * 2.0f32.powf(t), 48000);
+        input.extend(std::iter::repeat_n(0.0, 24000));
+        let estimates = PitchTracker::track(&AudioBuffer::new(input, 48000), PitchMethod::Pyin);
+        let latency = 1024.0 / 48000.0;
+        for (i, estimate) in estimates.iter().enumerate().skip(20).take(150) {
+            let t = (i + 1) as f32 * PITCH_HOP as f32 / 48000.0 - latency;
+            let expected = 150.0 * 2.0f32.powf(t);
+            assert!((estimate.frequency_hz / expected - 1.0).abs() < 0.02, "hop {i}: {estimate:?}, expected {expected}");
+        }
+        assert!(estimates[200..].iter().all(|e| !e.is_voiced()));
+    }
+
+    #[test]
+    fn test_midi_note() {
+        let a4 = PitchEstimate { frequency_hz: 440.0, probability: 1.0 };
+        assert!((a4.midi_note() - 69.0).abs() < 1e-5);
+        let c4 = PitchEstimate { frequency_hz: 261.6256, probability: 1.0 };
+        assert!((c4.midi_note() - 60.0).abs() < 1e-3);
+    }
+}
@@ -1,193 +1,383 @@
 use rustfft::num_complex::Complex;
-use rustfft::Fft;
-use std::sync::Arc;
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
 
 use super::fft_utils;
+use super::formant_shift::{FormantShift, LpcAnalysis};
+use super::frame_buffer::FrameBuffer;
+use super::stft::Stft;
+use super::vocoder::Vocoder;
+
+/// How `PitchShift` moves the spectrum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PitchQuality {
+    /// Moves every bin to its scaled position. Cheap, but speech comes out
+    /// phasey and low voices land up to half a bin off pitch.
+    Standard,
+    /// Stretches each frame in time with a phase-locked vocoder, then
+    /// resamples it back to the frame length. Phases restart from the
+    /// input at onsets, so attacks stay sharp. Twice the overlap of
+    /// `Standard`.
+    High,
+}
 
 /// Phase Vocoder pitch shifter. Shifts pitch without changing duration.
-/// Uses STFT analysis-resynthesis with frequency-domain bin shifting.
+///
+/// With `preserve_formants` the LPC envelope of each frame (as in
+/// `FormantShift`) is kept where it was while the harmonics move, so
+/// voices change pitch without sounding smaller or bigger.
+///
+/// Streams with a latency of `fft_size` samples. A shifter built at zero
+/// semitones passes audio through untouched until its pitch is first moved.
 pub struct PitchShift {
-    semitones: f32,
+    semitones: SmoothedParam,
+    /// Set once the pitch leaves zero; from then on the vocoder keeps
+    /// running (even back at zero) so the latency doesn't jump.
+    active: bool,
+    shifter: Shifter,
+}
+
+enum Shifter {
+    Standard { stft: Stft, state: VocoderState },
+    High(LockedShifter),
+}
+
+/// Per-bin analysis/synthesis state and scratch buffers.
+struct VocoderState {
+    last_phase: Vec<f32>,
+    sum_phase: Vec<f32>,
+    magnitudes: Vec<f32>,
+    frequencies: Vec<f32>,
+    synth_magnitudes: Vec<f32>,
+    synth_frequencies: Vec<f32>,
+    bin_count: Vec<u32>,
+    formants: Option<Box<LpcAnalysis>>,
+}
+
+/// `PitchQuality::High` state: framing, the vocoder and the resampled
+/// grains' overlap-add accumulator.
+///
+/// `PitchCorrect` and `Monotone` drive one directly, picking the factor
+/// for each hop from a pitch tracker.
+pub(super) struct LockedShifter {
     fft_size: usize,
     hop_size: usize,
     window: Vec<f32>,
-    last_phase: Vec<f32>,
-    sum_phase: Vec<f32>,
-    fft_forward: Arc<dyn Fft<f32>>,
-    fft_inverse: Arc<dyn Fft<f32>>,
+    frames: FrameBuffer,
+    /// Sliding analysis window (most recent `fft_size` input samples).
+    analysis: Vec<f32>,
+    ola: Vec<f32>,
+    vocoder: Vocoder,
+    /// False until the first frame, which has no phases to continue.
+    primed: bool,
+    formants: Option<Box<LpcAnalysis>>,
+    shifted_env: Vec<f32>,
 }
 
 impl PitchShift {
+    pub const PARAMS: [ParamDescriptor; 1] = [ParamDescriptor {
+        id: 0,
+        key: "semitones",
+        name: "Semitones",
+        min: -24.0,
+        max: 24.0,
+        default: 0.0,
+    }];
+
     pub fn new(semitones: f32) -> Self {
+        Self::with_options(semitones, PitchQuality::Standard, false)
+    }
+
+    pub fn with_options(semitones: f32, quality: PitchQuality, preserve_formants: bool) -> Self {
         let fft_size = 2048;
-        let hop_size = fft_size / 4; // 75% overlap
-        let (fft_forward, fft_inverse) = fft_utils::create_fft_pair(fft_size);
+        let num_bins = fft_size / 2 + 1;
+        let formants = preserve_formants.then(|| Box::new(LpcAnalysis::new(fft_size, 16)));
+
+        let shifter = match quality {
+            PitchQuality::Standard => Shifter::Standard {
+                stft: Stft::new(fft_size, fft_size / 4), // 75% overlap
+                state: VocoderState {
+                    last_phase: vec![0.0; num_bins],
+                    sum_phase: vec![0.0; num_bins],
+                    magnitudes: vec![0.0; num_bins],
+                    frequencies: vec![0.0; num_bins],
+                    synth_magnitudes: vec![0.0; num_bins],
+                    synth_frequencies: vec![0.0; num_bins],
+                    bin_count: vec![0; num_bins],
+                    formants,
+                },
+            },
+            PitchQuality::High => Shifter::High(LockedShifter::new(formants)),
+        };
 
         Self {
-            semitones,
-            fft_size,
-            hop_size,
-            window: fft_utils::hann_window(fft_size),
-            last_phase: vec![0.0; fft_size],
-            sum_phase: vec![0.0; fft_size],
-            fft_forward,
-            fft_inverse,
+            semitones: SmoothedParam::new(Self::PARAMS[0], semitones),
+            active: false,
+            shifter,
         }
     }
 
     /// Legacy constructor for backward compatibility with "factor" parameter.
     /// Converts factor to semitones: factor = 2^(semitones/12).
     pub fn from_factor(factor: f32) -> Self {
-        let semitones = 12.0 * factor.max(0.01).ln() / 2.0f32.ln();
-        Self::new(semitones)
+        Self::new(Self::factor_to_semitones(factor))
     }
-}
 
-impl AudioNode for PitchShift {
-    fn process(&mut self, buffer: &mut AudioBuffer) {
-        if self.semitones.abs() < 0.01 || buffer.samples.is_empty() {
-            return;
+    pub fn factor_to_semitones(factor: f32) -> f32 {
+        12.0 * factor.max(0.01).ln() / 2.0f32.ln()
+    }
+
+    pub fn quality(&self) -> PitchQuality {
+        match self.shifter {
+            Shifter::Standard { .. } => PitchQuality::Standard,
+            Shifter::High(_) => PitchQuality::High,
+        }
+    }
+
+    pub fn preserves_formants(&self) -> bool {
+        match &self.shifter {
+            Shifter::Standard { state, .. } => state.formants.is_some(),
+            Shifter::High(shifter) => shifter.formants.is_some(),
         }
+    }
+}
 
-        let factor = 2.0f32.powf(self.semitones / 12.0);
-        let fft_size = self.fft_size;
-        let hop_size = self.hop_size;
+impl VocoderState {
+    /// Shift the spectrum of one STFT frame by `factor`.
+    fn shift_frame(&mut self, fft_buf: &mut [Complex<f32>], factor: f32, hop_size: usize) {
+        let fft_size = fft_buf.len();
         let num_bins = fft_size / 2 + 1;
         let expected_phase_diff = 2.0 * std::f32::consts::PI * hop_size as f32 / fft_size as f32;
-        let inv_fft_size = 1.0 / fft_size as f32;
-
-        let input = &buffer.samples;
-        let input_len = input.len();
-
-        // Pad input to ensure we can process full frames
-        let padded_len = input_len + fft_size;
-        let mut padded = vec![0.0f32; padded_len];
-        padded[..input_len].copy_from_slice(input);
-
-        // Output accumulator
-        let mut output = vec![0.0f32; padded_len];
-        let mut window_sum = vec![0.0f32; padded_len];
-
-        // Temporary buffers
-        let mut fft_buf = vec![Complex::new(0.0f32, 0.0); fft_size];
-        let mut magnitudes = vec![0.0f32; num_bins];
-        let mut frequencies = vec![0.0f32; num_bins];
-        let mut synth_magnitudes = vec![0.0f32; num_bins];
-        let mut synth_frequencies = vec![0.0f32; num_bins];
-
-        let mut pos = 0;
-        while pos + fft_size <= padded_len {
-            // Analysis: window and FFT
-            for i in 0..fft_size {
-                fft_buf[i] = Complex::new(padded[pos + i] * self.window[i], 0.0);
-            }
-            self.fft_forward.process(&mut fft_buf);
 
-            // Convert to magnitude/frequency
-            for k in 0..num_bins {
-                let re = fft_buf[k].re;
-                let im = fft_buf[k].im;
-                let mag = (re * re + im * im).sqrt();
-                let phase = im.atan2(re);
+        // Convert to magnitude/frequency
+        for (k, bin) in fft_buf[..num_bins].iter().enumerate() {
+            let re = bin.re;
+            let im = bin.im;
+            let mag = (re * re + im * im).sqrt();
+            let phase = im.atan2(re);
 
-                // Compute instantaneous frequency
-                let phase_diff = phase - self.last_phase[k];
-                self.last_phase[k] = phase;
+            // Compute instantaneous frequency
+            let phase_diff = phase - self.last_phase[k];
+            self.last_phase[k] = phase;
 
-                // Remove expected phase advance
-                let mut deviation = phase_diff - k as f32 * expected_phase_diff;
+            // Remove expected phase advance
+            let mut deviation = phase_diff - k as f32 * expected_phase_diff;
 
-                // Map to [-PI, PI]
-                deviation -= (deviation / std::f32::consts::PI).round() * 2.0 * std::f32::consts::PI;
+            // Map to [-PI, PI]
+            deviation -= (deviation / std::f32::consts::PI).round() * 2.0 * std::f32::consts::PI;
 
-                // True frequency of this bin
-                let true_freq = k as f32 + deviation / expected_phase_diff;
+            // True frequency of this bin
+            let true_freq = k as f32 + deviation / expected_phase_diff;
 
-                magnitudes[k] = mag;
-                frequencies[k] = true_freq;
+            self.magnitudes[k] = mag;
+            self.frequencies[k] = true_freq;
+        }
+
+        // Preserving formants: shift the flattened spectrum and put the
+        // unshifted envelope back afterwards.
+        let envelope = self.formants.as_mut().map(|lpc| lpc.envelope(fft_buf));
+        if let Some(envelope) = envelope {
+            for (mag, &env) in self.magnitudes.iter_mut().zip(envelope) {
+                *mag /= env.max(1e-6);
             }
+        }
 
-            // Pitch shift: move bins
-            synth_magnitudes.fill(0.0);
-            synth_frequencies.fill(0.0);
-            let mut bin_count = vec![0u32; num_bins];
-
-            for k in 0..num_bins {
-                let new_bin = (k as f32 * factor) as usize;
-                if new_bin < num_bins {
-                    synth_magnitudes[new_bin] += magnitudes[k];
-                    synth_frequencies[new_bin] = frequencies[k] * factor;
-                    bin_count[new_bin] += 1;
-                }
+        // Pitch shift: move bins
+        self.synth_magnitudes.fill(0.0);
+        self.synth_frequencies.fill(0.0);
+        self.bin_count.fill(0);
+
+        for k in 0..num_bins {
+            let new_bin = (k as f32 * factor) as usize;
+            if new_bin < num_bins {
+                self.synth_magnitudes[new_bin] += self.magnitudes[k];
+                self.synth_frequencies[new_bin] = self.frequencies[k] * factor;
+                self.bin_count[new_bin] += 1;
             }
+        }
 
-            // Average magnitudes when multiple bins contribute to the same output bin
-            for k in 0..num_bins {
-                if bin_count[k] > 1 {
-                    synth_magnitudes[k] /= bin_count[k] as f32;
-                }
+        // Average magnitudes when multiple bins contribute to the same output bin
+        for (mag, &count) in self.synth_magnitudes.iter_mut().zip(self.bin_count.iter()) {
+            if count > 1 {
+                *mag /= count as f32;
             }
+        }
+        if let Some(envelope) = envelope {
+            for (mag, &env) in self.synth_magnitudes.iter_mut().zip(envelope) {
+                *mag *= env;
+            }
+        }
+
+        // Resynthesize: frequency to phase
+        for (k, bin) in fft_buf[..num_bins].iter_mut().enumerate() {
+            let deviation = (self.synth_frequencies[k] - k as f32) * expected_phase_diff;
+            self.sum_phase[k] += k as f32 * expected_phase_diff + deviation;
+
+            let phase = self.sum_phase[k];
+            let mag = self.synth_magnitudes[k];
+            *bin = Complex::new(mag * phase.cos(), mag * phase.sin());
+        }
+        // Mirror for inverse FFT
+        for k in num_bins..fft_size {
+            fft_buf[k] = fft_buf[fft_size - k].conj();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.last_phase.fill(0.0);
+        self.sum_phase.fill(0.0);
+    }
+}
+
+impl LockedShifter {
+    pub(super) const FFT_SIZE: usize = 2048;
+    pub(super) const HOP_SIZE: usize = Self::FFT_SIZE / 8;
 
-            // Resynthesize: frequency to phase
-            for k in 0..num_bins {
-                let deviation = (synth_frequencies[k] - k as f32) * expected_phase_diff;
-                self.sum_phase[k] += k as f32 * expected_phase_diff + deviation;
+    fn new(formants: Option<Box<LpcAnalysis>>) -> Self {
+        let fft_size = Self::FFT_SIZE;
+        Self {
+            fft_size,
+            hop_size: Self::HOP_SIZE,
+            window: fft_utils::hann_window(fft_size),
+            frames: FrameBuffer::new(Self::HOP_SIZE),
+            analysis: vec![0.0; fft_size],
+            ola: vec![0.0; fft_size],
+            vocoder: Vocoder::new(fft_size),
+            primed: false,
+            formants,
+            shifted_env: vec![0.0; fft_size / 2 + 1],
+        }
+    }
+
+    pub(super) fn with_formants(preserve_formants: bool) -> Self {
+        Self::new(preserve_formants.then(|| Box::new(LpcAnalysis::new(Self::FFT_SIZE, 16))))
+    }
 
-                let phase = self.sum_phase[k];
-                let mag = synth_magnitudes[k];
-                fft_buf[k] = Complex::new(mag * phase.cos(), mag * phase.sin());
+    /// Latency of the shifted output, in samples.
+    pub(super) fn latency_samples(&self) -> usize {
+        self.fft_size
+    }
+
+    /// Stream `samples` through in place. `factor` is called with each
+    /// hop of input as it completes and returns the factor to shift the
+    /// frame ending there by.
+    pub(super) fn process(&mut self, samples: &mut [f32], mut factor: impl FnMut(&[f32]) -> f32) {
+        let Self { fft_size, hop_size, window, frames, analysis, ola, vocoder, primed, formants, shifted_env } = self;
+        let (n, h) = (*fft_size, *hop_size);
+
+        frames.process(samples, |hop| {
+            let factor = factor(hop);
+            analysis.copy_within(h.., 0);
+            analysis[n - h..].copy_from_slice(hop);
+            vocoder.analyze(analysis, window);
+            let onset = vocoder.onset();
+
+            // Resampling by `factor` moves the envelope too, so pre-shift
+            // it the other way.
+            if let Some(lpc) = formants {
+                let envelope = lpc.envelope(vocoder.spectrum());
+                FormantShift::shift_envelope(envelope, 1.0 / factor, shifted_env);
+                for ((mag, &env), &shifted) in vocoder.magnitudes_mut().iter_mut().zip(envelope).zip(shifted_env.iter()) {
+                    *mag *= shifted / env.max(1e-6);
+                }
             }
-            // Mirror for inverse FFT
-            for k in num_bins..fft_size {
-                fft_buf[k] = fft_buf[fft_size - k].conj();
+            // Content the resampling would push past Nyquist.
+            let magnitudes = vocoder.magnitudes_mut();
+            let limit = ((magnitudes.len() as f32 / factor) as usize).min(magnitudes.len());
+            magnitudes[limit..].fill(0.0);
+
+            // Stretched by `factor` in time, then read back at `factor`
+            // speed around the frame centre.
+            vocoder.lock_phases((*primed && !onset).then_some(h), h as f32 * factor);
+            *primed = true;
+            let frame = vocoder.resynthesize();
+            let centre = n as f32 / 2.0;
+            let read = |m: usize, source: &dyn Fn(usize) -> f32| {
+                let pos = centre + (m as f32 - centre) * factor;
+                if pos < 0.0 || pos >= (n - 1) as f32 {
+                    return 0.0;
+                }
+                let (i, frac) = (pos as usize, pos.fract());
+                source(i) * (1.0 - frac) + source(i + 1) * frac
+            };
+            // Overlapped grains sum to one whatever their length.
+            let weight: f32 = (0..n).map(|m| read(m, &|i| window[i]) * window[m]).sum();
+            let scale = h as f32 / weight.max(1e-6) / n as f32;
+            for (m, o) in ola.iter_mut().enumerate() {
+                *o += read(m, &|i| frame[i].re) * window[m] * scale;
             }
 
-            self.fft_inverse.process(&mut fft_buf);
+            hop.copy_from_slice(&ola[..h]);
+            ola.copy_within(h.., 0);
+            ola[n - h..].fill(0.0);
+        });
+    }
 
-            // Overlap-add
-            for i in 0..fft_size {
-                output[pos + i] += fft_buf[i].re * inv_fft_size * self.window[i];
-                window_sum[pos + i] += self.window[i] * self.window[i];
-            }
+    pub(super) fn reset(&mut self) {
+        self.frames.reset();
+        self.analysis.fill(0.0);
+        self.ola.fill(0.0);
+        self.vocoder.reset();
+        self.primed = false;
+    }
+}
+
+impl AudioNode for PitchShift {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.semitones.prepare(sample_rate);
+    }
 
-            pos += hop_size;
+    fn latency_samples(&self) -> usize {
+        if self.active || self.semitones.target().abs() >= 0.01 {
+            self.max_latency_samples()
+        } else {
+            0
         }
+    }
 
-        // Normalize by window sum
-        let mut result = Vec::with_capacity(input_len);
-        for i in 0..input_len {
-            if window_sum[i] > 1e-6 {
-                result.push(output[i] / window_sum[i]);
-            } else {
-                result.push(0.0);
-            }
+    fn max_latency_samples(&self) -> usize {
+        match &self.shifter {
+            Shifter::Standard { stft, .. } => stft.fft_size(),
+            Shifter::High(shifter) => shifter.latency_samples(),
         }
+    }
 
-        // Prevent amplitude explosion: scale output to match input RMS
-        let input_rms: f32 = (buffer.samples.iter().map(|s| s * s).sum::<f32>()
-            / buffer.samples.len().max(1) as f32)
-            .sqrt();
-        let output_rms: f32 =
-            (result.iter().map(|s| s * s).sum::<f32>() / result.len().max(1) as f32).sqrt();
-        if output_rms > 1e-6 && input_rms > 1e-6 {
-            let scale = input_rms / output_rms;
-            for s in &mut result {
-                *s *= scale;
-            }
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        self.active |= self.semitones.target().abs() >= 0.01;
+        if !self.active || buffer.samples.is_empty() {
+            return;
         }
 
-        buffer.samples = result;
+        let semitones = &mut self.semitones;
+        let mut factor = |hop_size: usize| 2.0f32.powf(semitones.advance(hop_size) / 12.0);
+        match &mut self.shifter {
+            Shifter::Standard { stft, state } => {
+                let hop_size = stft.hop_size();
+                stft.process(&mut buffer.samples, |spectrum| {
+                    state.shift_frame(spectrum, factor(hop_size), hop_size);
+                });
+            }
+            Shifter::High(shifter) => shifter.process(&mut buffer.samples, |hop| factor(hop.len())),
+        }
     }
 
     fn reset(&mut self) {
-        self.last_phase.fill(0.0);
-        self.sum_phase.fill(0.0);
+        match &mut self.shifter {
+            Shifter::Standard { stft, state } => {
+                stft.reset();
+                state.reset();
+            }
+            Shifter::High(shifter) => shifter.reset(),
+        }
+        self.semitones.snap();
     }
 
     fn name(&self) -> &str {
         "Pitch Shift"
     }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.semitones.handle()]
+    }
 }
 
 #[cfg(test)]
@@ -243,11 +433,89 @@ mod tests {
         assert_eq!(buffer.samples, original);
     }
 
+    /// Frequency of a tone from its rising zero crossings.
+    fn frequency(samples: &[f32]) -> f32 {
+        let crossings: Vec<usize> = samples.windows(2).enumerate().filter(|(_, w)| w[0] < 0.0 && w[1] >= 0.0).map(|(i, _)| i).collect();
+        let (first, last) = (crossings[0], *crossings.last().unwrap());
+        (crossings.len() - 1) as f32 * 48000.0 / (last - first) as f32
+    }
+
+    /// Magnitude-weighted mean frequency below 4 kHz of an 8192-sample
+    /// stretch.
+    fn centroid(samples: &[f32]) -> f32 {
+        let size = 8192;
+        let window = fft_utils::hann_window(size);
+        let mut spectrum: Vec<Complex<f32>> = samples[..size].iter().zip(&window).map(|(&x, &w)| Complex::new(x * w, 0.0)).collect();
+        fft_utils::create_fft_forward(size).process(&mut spectrum);
+        let (weighted, total) = spectrum[..size / 12]
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(weighted, total), (k, c)| (weighted + k as f32 * c.norm(), total + c.norm()));
+        weighted / total * 48000.0 / size as f32
+    }
+
+    fn shift(semitones: f32, quality: PitchQuality, preserve_formants: bool, input: &[f32]) -> Vec<f32> {
+        let mut ps = PitchShift::with_options(semitones, quality, preserve_formants);
+        let mut buffer = AudioBuffer::new(input.to_vec(), 48000);
+        buffer.process_blocks(480, |b| ps.process(b));
+        buffer.samples
+    }
+
+    #[test]
+    fn test_high_quality_shifts_pitch() {
+        let input: Vec<f32> = (0..48000).map(|i| (i as f32 / 48000.0 * 220.0 * std::f32::consts::TAU).sin() * 0.5).collect();
+        for semitones in [-12.0f32, -5.0, 7.0, 24.0] {
+            let output = shift(semitones, PitchQuality::High, false, &input);
+            assert_eq!(output.len(), input.len());
+            let steady = &output[8192..];
+            let expected = 220.0 * 2.0f32.powf(semitones / 12.0);
+            let measured = frequency(steady);
+            assert!((measured / expected - 1.0).abs() < 0.005, "{semitones}: {measured} Hz, expected {expected}");
+            let peak = steady.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+            assert!((0.45..0.55).contains(&peak), "{semitones}: peak {peak}");
+        }
+    }
+
+    #[test]
+    fn test_high_quality_does_not_depend_on_block_size() {
+        let input: Vec<f32> = (0..9600).map(|i| (i as f32 / 48000.0 * 310.0 * std::f32::consts::TAU).sin() * 0.5).collect();
+        let run = |block: usize| {
+            let mut ps = PitchShift::with_options(-3.0, PitchQuality::High, true);
+            let mut buffer = AudioBuffer::new(input.clone(), 48000);
+            buffer.process_blocks(block, |b| ps.process(b));
+            buffer.samples
+        };
+        assert_eq!(run(64), run(480));
+    }
+
+    #[test]
+    fn test_preserve_formants_keeps_the_envelope() {
+        // A 150 Hz "vowel" with one resonance at 1 kHz.
+        let input: Vec<f32> = (0..48000)
+            .map(|i| {
+                let t = i as f32 / 48000.0;
+                (1..60)
+                    .map(|h| {
+                        let f = 150.0 * h as f32;
+                        (t * f * std::f32::consts::TAU).sin() * 0.1 / (1.0 + ((f - 1000.0) / 300.0).powi(2))
+                    })
+                    .sum()
+            })
+            .collect();
+        let original = centroid(&input[16384..]);
+        for quality in [PitchQuality::Standard, PitchQuality::High] {
+            let plain = centroid(&shift(7.0, quality, false, &input)[16384..]);
+            let preserved = centroid(&shift(7.0, quality, true, &input)[16384..]);
+            assert!(plain / original > 1.3, "{quality:?}: plain {plain} vs {original}");
+            assert!((preserved / original - 1.0).abs() < 0.1, "{quality:?}: preserved {preserved} vs {original}");
+        }
+    }
+
     #[test]
     fn test_from_factor_conversion() {
         // factor 0.75 (gorilla) should be about -4.98 semitones
         let ps = PitchShift::from_factor(0.75);
         let expected = 12.0 * 0.75f32.ln() / 2.0f32.ln();
-        assert!((ps.semitones - expected).abs() < 0.01);
+        assert!((ps.semitones.value() - expected).abs() < 0.01);
     }
 }
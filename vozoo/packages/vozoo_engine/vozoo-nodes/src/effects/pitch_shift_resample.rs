@@ -2,41 +2,77 @@ use vozoo_core::{AudioBuffer, AudioNode};
 
 /// Legacy pitch shift by resampling (changes duration).
 /// factor < 1.0 = slower/deeper, factor > 1.0 = faster/higher.
+///
+/// Each block yields roughly `frames / factor` output frames; the read
+/// position carries over between blocks so the output stream doesn't depend
+/// on the block size.
 pub struct PitchShiftResample {
     factor: f32,
+    /// Read position relative to the start of the next block. Negative
+    /// values fall between `prev` and the block's first sample.
+    pos: f64,
+    /// Last input sample of the previous block.
+    prev: f32,
+    output: Vec<f32>,
 }
 
 impl PitchShiftResample {
     pub fn new(factor: f32) -> Self {
-        Self { factor }
+        Self {
+            factor,
+            pos: 0.0,
+            prev: 0.0,
+            output: Vec::new(),
+        }
     }
 }
 
 impl AudioNode for PitchShiftResample {
+    fn prepare(&mut self, _sample_rate: u32, max_block: usize) {
+        let max_out = self.max_output_frames(max_block);
+        self.output.reserve(max_out.saturating_sub(self.output.len()));
+    }
+
+    fn max_output_frames(&self, input_frames: usize) -> usize {
+        (input_frames as f32 / self.factor.max(0.01)).ceil() as usize + 1
+    }
+
+    fn time_scale(&self) -> f64 {
+        1.0 / self.factor.max(0.01) as f64
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
         if (self.factor - 1.0).abs() < f32::EPSILON || buffer.samples.is_empty() {
             return;
         }
 
         let input = &buffer.samples;
-        let out_len = (input.len() as f32 / self.factor) as usize;
-        let mut out = Vec::with_capacity(out_len);
-
-        let mut idx: f64 = 0.0;
         let last = input.len() - 1;
+        self.output.clear();
 
-        while (idx as usize) < last {
-            let i = idx as usize;
-            let frac = idx as f32 - i as f32;
-            let val = input[i] * (1.0 - frac) + input[i + 1] * frac;
-            out.push(val);
-            idx += self.factor as f64;
+        while self.pos < last as f64 {
+            let i = self.pos.floor();
+            let frac = (self.pos - i) as f32;
+            let (a, b) = if i < 0.0 {
+                (self.prev, input[0])
+            } else {
+                (input[i as usize], input[i as usize + 1])
+            };
+            self.output.push(a * (1.0 - frac) + b * frac);
+            self.pos += self.factor as f64;
         }
 
-        buffer.samples = out;
+        self.pos -= input.len() as f64;
+        self.prev = input[last];
+        // Copy back rather than swap so the host keeps its own allocation.
+        buffer.samples.clear();
+        buffer.samples.extend_from_slice(&self.output);
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        self.pos = 0.0;
+        self.prev = 0.0;
+    }
 
     fn name(&self) -> &str {
         "Pitch Shift (Resample)"
@@ -0,0 +1,388 @@
+use std::sync::Arc;
+
+use vozoo_core::{
+    AudioBuffer, AudioNode, ChannelLayout, MeterTap, ParamDescriptor, ParamHandle, Resampler,
+    BLOCK_CAPACITY_FACTOR,
+};
+
+/// Sample rate converter node: nodes after it run at `sample_rate`.
+///
+/// Each block yields about `frames * sample_rate / input_rate` frames, so
+/// it belongs in chains; graphs keep one rate throughout and reject it.
+pub struct Resample {
+    target_rate: u32,
+    resampler: Option<Resampler>,
+    output: Vec<f32>,
+}
+
+impl Resample {
+    pub const PARAMS: [ParamDescriptor; 1] = [ParamDescriptor {
+        id: 0,
+        key: "sample_rate",
+        name: "Sample Rate (Hz)",
+        min: 8000.0,
+        max: 192000.0,
+        default: 48000.0,
+    }];
+
+    pub fn new(target_rate: u32) -> Self {
+        Self {
+            target_rate: target_rate.max(1),
+            resampler: None,
+            output: Vec::new(),
+        }
+    }
+
+    /// The converter for `input_rate`, rebuilt if the rate changed.
+    fn resampler(&mut self, input_rate: u32) -> &mut Resampler {
+        if self.resampler.as_ref().is_none_or(|r| r.from_rate() != input_rate) {
+            // Stereo so that both mono and stereo blocks fit the reservation.
+            self.resampler = Some(Resampler::new(input_rate, self.target_rate, 2));
+        }
+        self.resampler.as_mut().expect("resampler was just built")
+    }
+}
+
+impl AudioNode for Resample {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        let resampler = self.resampler(sample_rate);
+        resampler.prepare(max_block);
+        let max_out = resampler.max_output_frames(max_block) * 2;
+        self.output.reserve(max_out.saturating_sub(self.output.len()));
+    }
+
+    fn max_output_frames(&self, input_frames: usize) -> usize {
+        self.resampler
+            .as_ref()
+            .map_or(input_frames, |r| r.max_output_frames(input_frames))
+    }
+
+    fn output_sample_rate(&self, _input_rate: u32) -> u32 {
+        self.target_rate
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        if buffer.sample_rate() == self.target_rate {
+            return;
+        }
+
+        let channels = buffer.channels();
+        let mut output = std::mem::take(&mut self.output);
+        let resampler = self.resampler(buffer.sample_rate());
+        resampler.set_channels(channels);
+        output.clear();
+        resampler.process(&buffer.samples, &mut output);
+        self.output = output;
+
+        // Copy back rather than swap so the host keeps its own allocation.
+        buffer.samples.clear();
+        buffer.samples.extend_from_slice(&self.output);
+        buffer.set_sample_rate(self.target_rate);
+    }
+
+    fn reset(&mut self) {
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.reset();
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Resample"
+    }
+
+    fn input_layout(&self) -> Option<ChannelLayout> {
+        None
+    }
+}
+
+/// Runs a node that only works at its `native_sample_rate` inside a host
+/// streaming at another rate.
+///
+/// Blocks are converted to the native rate, processed, and converted back.
+/// The result goes through a FIFO primed with enough silence to cover both
+/// converters' lookahead, so every block comes back with exactly the frames
+/// it went in with, at the cost of that much extra delay. At the native rate
+/// the node is called directly.
+///
+/// `LinearChain` and `AudioGraph` wrap such nodes automatically.
+pub struct NativeRate {
+    inner: Box<dyn AudioNode>,
+    native_rate: u32,
+    host_rate: u32,
+    to_native: Resampler,
+    from_native: Resampler,
+    /// The block at the native rate.
+    block: AudioBuffer,
+    /// Host-rate output not yet handed back.
+    fifo: Vec<f32>,
+    fifo_channels: u16,
+    /// Frames of silence the FIFO starts with.
+    priming: usize,
+}
+
+impl NativeRate {
+    /// Wrap `node` if it has a native rate; other nodes are returned as is.
+    pub fn wrap(node: Box<dyn AudioNode>) -> Box<dyn AudioNode> {
+        match node.native_sample_rate() {
+            Some(native_rate) => Box::new(Self::new(node, native_rate)),
+            None => node,
+        }
+    }
+
+    fn new(inner: Box<dyn AudioNode>, native_rate: u32) -> Self {
+        Self {
+            inner,
+            native_rate,
+            host_rate: native_rate,
+            to_native: Resampler::new(native_rate, native_rate, 1),
+            from_native: Resampler::new(native_rate, native_rate, 1),
+            block: AudioBuffer::empty(native_rate),
+            fifo: Vec::new(),
+            fifo_channels: 1,
+            priming: 0,
+        }
+    }
+
+    fn is_passthrough(&self) -> bool {
+        self.host_rate == self.native_rate
+    }
+
+    /// Host frames of delay for `inner` native frames of the node's own.
+    fn host_latency(&self, inner: usize) -> usize {
+        if self.is_passthrough() {
+            return inner;
+        }
+        let inner = inner as u64 * self.host_rate as u64;
+        self.priming + inner.div_ceil(self.native_rate as u64) as usize
+    }
+
+    /// Empty the FIFO and refill it with the priming silence.
+    fn prime(&mut self, channels: u16) {
+        self.fifo_channels = channels;
+        self.from_native.set_channels(channels);
+        self.fifo.clear();
+        self.fifo.resize(self.priming * channels as usize, 0.0);
+    }
+}
+
+impl AudioNode for NativeRate {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        self.host_rate = sample_rate;
+        if self.is_passthrough() {
+            self.inner.prepare(sample_rate, max_block);
+            return;
+        }
+
+        let (host, native) = (sample_rate as usize, self.native_rate as usize);
+        self.to_native = Resampler::new(sample_rate, self.native_rate, 2);
+        self.to_native.prepare(max_block);
+        let native_block = self.to_native.max_output_frames(max_block);
+        self.inner.prepare(self.native_rate, native_block);
+
+        let inner_out = self.inner.max_output_frames(native_block);
+        self.from_native = Resampler::new(self.native_rate, sample_rate, 2);
+        self.from_native.prepare(inner_out);
+
+        // Host frames held back by the converters, plus a frame of rounding
+        // per stage.
+        self.priming = self.to_native.delay_frames() + (self.from_native.delay_frames() + 1) * host / native + 3;
+
+        let reserve = |v: &mut Vec<f32>, len: usize| v.reserve(len.saturating_sub(v.len()));
+        reserve(&mut self.block.samples, native_block * BLOCK_CAPACITY_FACTOR);
+        let fifo_frames = self.priming + max_block + self.from_native.max_output_frames(inner_out);
+        reserve(&mut self.fifo, fifo_frames * 2);
+
+        let layout = self.inner.input_layout().unwrap_or(ChannelLayout::Mono);
+        self.prime(self.inner.output_layout(layout).channels());
+    }
+
+    fn max_output_frames(&self, input_frames: usize) -> usize {
+        if self.is_passthrough() {
+            self.inner.max_output_frames(input_frames)
+        } else {
+            input_frames
+        }
+    }
+
+    fn time_scale(&self) -> f64 {
+        self.inner.time_scale()
+    }
+
+    fn changes_length(&self) -> bool {
+        self.inner.changes_length()
+    }
+
+    /// The priming silence plus the node's own latency, in host frames.
+    fn latency_samples(&self) -> usize {
+        self.host_latency(self.inner.latency_samples())
+    }
+
+    fn max_latency_samples(&self) -> usize {
+        self.host_latency(self.inner.max_latency_samples())
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        if self.is_passthrough() || buffer.sample_rate() != self.host_rate {
+            // Unprepared, or fed a rate other than the prepared one: run the
+            // node as is rather than convert from the wrong rate.
+            self.inner.process(buffer);
+            return;
+        }
+
+        let frames = buffer.frames();
+        self.to_native.set_channels(buffer.channels());
+        self.block.set_sample_rate(self.native_rate);
+        self.block.reshape(0, buffer.channels());
+        self.to_native.process(&buffer.samples, &mut self.block.samples);
+
+        self.inner.process(&mut self.block);
+
+        let channels = self.block.channels();
+        if channels != self.fifo_channels {
+            self.prime(channels);
+        }
+        self.from_native.process(&self.block.samples, &mut self.fifo);
+
+        let len = (frames * channels as usize).min(self.fifo.len());
+        buffer.samples.clear();
+        buffer.samples.extend_from_slice(&self.fifo[..len]);
+        buffer.reshape(frames, channels);
+        self.fifo.copy_within(len.., 0);
+        self.fifo.truncate(self.fifo.len() - len);
+    }
+
+    fn input_ports(&self) -> &[&'static str] {
+        self.inner.input_ports()
+    }
+
+    fn output_ports(&self) -> &[&'static str] {
+        self.inner.output_ports()
+    }
+
+    /// Extra ports only reach the node when no conversion is needed; a
+    /// converted node runs on its main input alone.
+    fn process_ports(&mut self, buffer: &mut AudioBuffer, inputs: &[AudioBuffer], outputs: &mut [AudioBuffer]) {
+        if self.is_passthrough() {
+            self.inner.process_ports(buffer, inputs, outputs);
+        } else {
+            self.process(buffer);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.to_native.reset();
+        self.from_native.reset();
+        self.prime(self.fifo_channels);
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        self.inner.params()
+    }
+
+    fn meters(&self) -> Vec<Arc<MeterTap>> {
+        self.inner.meters()
+    }
+
+    fn input_layout(&self) -> Option<ChannelLayout> {
+        self.inner.input_layout()
+    }
+
+    fn output_layout(&self, input: ChannelLayout) -> ChannelLayout {
+        self.inner.output_layout(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vozoo_core::test_signals::sine;
+
+    /// Passes audio through unchanged, but only at 48 kHz.
+    struct Native48k;
+
+    impl AudioNode for Native48k {
+        fn process(&mut self, buffer: &mut AudioBuffer) {
+            assert_eq!(buffer.sample_rate(), 48000);
+        }
+
+        fn reset(&mut self) {}
+
+        fn name(&self) -> &str {
+            "Native48k"
+        }
+
+        fn native_sample_rate(&self) -> Option<u32> {
+            Some(48000)
+        }
+    }
+
+    #[test]
+    fn test_resample_node_changes_rate() {
+        let mut node = Resample::new(16000);
+        node.prepare(48000, 480);
+        assert_eq!(node.output_sample_rate(48000), 16000);
+
+        let input = sine(440.0, 0.5, 48000, 4800);
+        let mut frames = 0;
+        for block in input.chunks(480) {
+            let mut buffer = AudioBuffer::new(block.to_vec(), 48000);
+            node.process(&mut buffer);
+            assert_eq!(buffer.sample_rate(), 16000);
+            assert!(buffer.frames() <= node.max_output_frames(480));
+            frames += buffer.frames();
+        }
+        // Everything but the converter's lookahead has come out.
+        let lookahead = Resampler::new(48000, 16000, 1).delay_frames() / 3;
+        assert!((1600 - lookahead - 1..=1600).contains(&frames), "{frames} frames");
+    }
+
+    #[test]
+    fn test_native_rate_keeps_block_sizes_and_signal() {
+        let mut node = NativeRate::wrap(Box::new(Native48k));
+        node.prepare(44100, 512);
+
+        let input = sine(300.0, 0.5, 44100, 44100);
+        let mut output = Vec::new();
+        let mut pos = 0;
+        for size in [512, 7, 441, 100, 1, 333].into_iter().cycle() {
+            if pos + size > input.len() {
+                break;
+            }
+            let mut buffer = AudioBuffer::new(input[pos..pos + size].to_vec(), 44100);
+            node.process(&mut buffer);
+            assert_eq!(buffer.frames(), size);
+            output.extend_from_slice(&buffer.samples);
+            pos += size;
+        }
+
+        // The output is the input, delayed by a whole number of frames.
+        let best = (0..200)
+            .map(|delay| {
+                let err = output[delay + 2000..pos]
+                    .iter()
+                    .zip(&input[2000..])
+                    .map(|(a, b)| (a - b).abs())
+                    .fold(0.0f32, f32::max);
+                (err, delay)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap();
+        assert!(best.0 < 0.01, "max error {} at delay {}", best.0, best.1);
+        assert_eq!(best.1, node.latency_samples());
+    }
+
+    #[test]
+    fn test_native_rate_is_transparent_at_native_rate() {
+        let mut node = NativeRate::wrap(Box::new(Native48k));
+        node.prepare(48000, 256);
+        let input = sine(440.0, 0.5, 48000, 256);
+        let mut buffer = AudioBuffer::new(input.clone(), 48000);
+        node.process(&mut buffer);
+        assert_eq!(buffer.samples, input);
+    }
+}
@@ -4,6 +4,12 @@ use vozoo_core::{AudioBuffer, AudioNode};
 pub struct Reverb {
     delay_times_ms: Vec<f32>,
     decay_factors: Vec<f32>,
+    /// Dry input history, long enough for the longest delay.
+    history: Vec<f32>,
+    write_pos: usize,
+    /// Decaying peak of the output, used to keep it from clipping.
+    peak: f32,
+    configured_sr: u32,
 }
 
 impl Reverb {
@@ -11,6 +17,10 @@ impl Reverb {
         Self {
             delay_times_ms,
             decay_factors,
+            history: Vec::new(),
+            write_pos: 0,
+            peak: 0.0,
+            configured_sr: 0,
         }
     }
 
@@ -18,35 +28,58 @@ impl Reverb {
     pub fn default_comb() -> Self {
         Self::new(vec![30.0, 40.0, 50.0], vec![0.5, 0.4, 0.3])
     }
+
+    fn configure(&mut self, sample_rate: u32) {
+        let sr = sample_rate as f32;
+        let max_delay = self
+            .delay_times_ms
+            .iter()
+            .map(|ms| (ms * sr / 1000.0) as usize)
+            .max()
+            .unwrap_or(0);
+        self.history = vec![0.0; max_delay + 1];
+        self.write_pos = 0;
+        self.peak = 0.0;
+        self.configured_sr = sample_rate;
+    }
 }
 
 impl AudioNode for Reverb {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.configure(sample_rate);
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
+        if buffer.sample_rate() != self.configured_sr {
+            self.configure(buffer.sample_rate());
+        }
+
         let sr = buffer.sample_rate() as f32;
-        let dry = buffer.samples.clone();
+        let len = self.history.len();
+        // Peak follower release (~100ms)
+        let release = (-1.0 / (0.1 * sr)).exp();
 
-        for (delay_ms, decay) in self.delay_times_ms.iter().zip(self.decay_factors.iter()) {
-            let delay_samples = (*delay_ms * sr / 1000.0) as usize;
+        for s in &mut buffer.samples {
+            self.history[self.write_pos] = *s;
+            let mut out = *s;
 
-            for i in delay_samples..buffer.samples.len() {
-                buffer.samples[i] += dry[i - delay_samples] * decay;
+            for (delay_ms, decay) in self.delay_times_ms.iter().zip(self.decay_factors.iter()) {
+                let delay_samples = (*delay_ms * sr / 1000.0) as usize;
+                out += self.history[(self.write_pos + len - delay_samples) % len] * decay;
             }
-        }
+            self.write_pos = (self.write_pos + 1) % len;
 
-        // Normalize to prevent clipping
-        let max_val = buffer
-            .samples
-            .iter()
-            .map(|s| s.abs())
-            .fold(0.0f32, f32::max);
-        if max_val > 1.0 {
-            for s in &mut buffer.samples {
-                *s /= max_val;
-            }
+            // Scale down while the output would clip
+            self.peak = out.abs().max(self.peak * release);
+            *s = if self.peak > 1.0 { out / self.peak } else { out };
         }
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.write_pos = 0;
+        self.peak = 0.0;
+    }
 
     fn name(&self) -> &str {
         "Reverb"
@@ -1,16 +1,23 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
 use std::f32::consts::TAU;
 
 /// Ring modulation + bitcrush effect (Robot voice).
 pub struct RingMod {
-    mod_freq: f32,
-    quantize_steps: f32,
+    mod_freq: SmoothedParam,
+    /// Bitcrush levels; not smoothed, since the steps are discrete anyway.
+    quantize_steps: SmoothedParam,
     /// Dry/wet blend: 0.0 = dry (bypass), 1.0 = fully wet.
-    mix: f32,
+    mix: SmoothedParam,
     phase: f32,
 }
 
 impl RingMod {
+    pub const PARAMS: [ParamDescriptor; 3] = [
+        ParamDescriptor { id: 0, key: "mod_freq", name: "Mod Frequency (Hz)", min: 1.0, max: 1000.0, default: 50.0 },
+        ParamDescriptor { id: 1, key: "quantize_steps", name: "Quantize Steps", min: 0.0, max: 64.0, default: 8.0 },
+        ParamDescriptor { id: 2, key: "mix", name: "Wet/Dry Mix", min: 0.0, max: 1.0, default: 1.0 },
+    ];
+
     /// Backward-compatible constructor: fully wet (mix = 1.0).
     pub fn new(mod_freq: f32, quantize_steps: f32) -> Self {
         Self::with_mix(mod_freq, quantize_steps, 1.0)
@@ -19,28 +26,37 @@ impl RingMod {
     /// Construct with an explicit dry/wet mix (clamped to [0, 1]).
     pub fn with_mix(mod_freq: f32, quantize_steps: f32, mix: f32) -> Self {
         Self {
-            mod_freq,
-            quantize_steps,
-            mix: mix.clamp(0.0, 1.0),
+            mod_freq: SmoothedParam::new(Self::PARAMS[0], mod_freq),
+            quantize_steps: SmoothedParam::new(Self::PARAMS[1], quantize_steps),
+            mix: SmoothedParam::new(Self::PARAMS[2], mix.clamp(0.0, 1.0)),
             phase: 0.0,
         }
     }
 }
 
 impl AudioNode for RingMod {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.mod_freq.prepare(sample_rate);
+        self.mix.prepare(sample_rate);
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
-        let phase_inc = TAU * self.mod_freq / buffer.sample_rate() as f32;
+        let sr = buffer.sample_rate() as f32;
+        self.quantize_steps.snap();
+        let quantize_steps = self.quantize_steps.value();
 
         for s in &mut buffer.samples {
+            let phase_inc = TAU * self.mod_freq.tick() / sr;
+            let mix = self.mix.tick();
             let dry = *s;
             let mut wet = dry * self.phase.sin();
 
             // Bitcrush quantization (on the wet path)
-            if self.quantize_steps > 0.0 {
-                wet = (wet * self.quantize_steps).round() / self.quantize_steps;
+            if quantize_steps > 0.0 {
+                wet = (wet * quantize_steps).round() / quantize_steps;
             }
 
-            *s = dry * (1.0 - self.mix) + wet * self.mix;
+            *s = dry * (1.0 - mix) + wet * mix;
 
             self.phase += phase_inc;
             if self.phase > TAU {
@@ -51,11 +67,17 @@ impl AudioNode for RingMod {
 
     fn reset(&mut self) {
         self.phase = 0.0;
+        self.mod_freq.snap();
+        self.mix.snap();
     }
 
     fn name(&self) -> &str {
         "Ring Modulator"
     }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.mod_freq.handle(), self.quantize_steps.handle(), self.mix.handle()]
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,143 @@
+use rustfft::num_complex::Complex;
+use rustfft::Fft;
+use std::sync::Arc;
+
+use super::fft_utils;
+use super::frame_buffer::FrameBuffer;
+
+/// Streaming STFT analysis/resynthesis with Hann windows and overlap-add.
+///
+/// Input is collected into hops of `hop_size` samples. For every hop the
+/// last `fft_size` samples are windowed and transformed, handed to a
+/// spectral callback, transformed back and overlap-added. Output lags input
+/// by exactly `fft_size` samples regardless of the host block size.
+pub struct Stft {
+    fft_size: usize,
+    hop_size: usize,
+    window: Vec<f32>,
+    frames: FrameBuffer,
+    /// Sliding analysis window (most recent `fft_size` input samples).
+    analysis: Vec<f32>,
+    /// Overlap-add accumulator.
+    ola: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    fft_forward: Arc<dyn Fft<f32>>,
+    fft_inverse: Arc<dyn Fft<f32>>,
+    /// 1 / (sum of squared windows overlapping any output sample).
+    ola_norm: f32,
+}
+
+impl Stft {
+    pub fn new(fft_size: usize, hop_size: usize) -> Self {
+        assert!(hop_size > 0 && hop_size <= fft_size, "hop_size must be in 1..=fft_size");
+        let (fft_forward, fft_inverse) = fft_utils::create_fft_pair(fft_size);
+        let window = fft_utils::hann_window(fft_size);
+        let window_power: f32 = window.iter().map(|w| w * w).sum();
+        let scratch_len = fft_forward
+            .get_inplace_scratch_len()
+            .max(fft_inverse.get_inplace_scratch_len());
+
+        Self {
+            fft_size,
+            hop_size,
+            window,
+            frames: FrameBuffer::new(hop_size),
+            analysis: vec![0.0; fft_size],
+            ola: vec![0.0; fft_size],
+            spectrum: vec![Complex::new(0.0, 0.0); fft_size],
+            scratch: vec![Complex::new(0.0, 0.0); scratch_len],
+            fft_forward,
+            fft_inverse,
+            ola_norm: hop_size as f32 / window_power.max(1e-6),
+        }
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
+    pub fn hop_size(&self) -> usize {
+        self.hop_size
+    }
+
+    /// Stream `samples` through the STFT in place.
+    ///
+    /// `process_spectrum` receives the full `fft_size`-point spectrum of each
+    /// windowed frame and may modify it. It must keep the spectrum conjugate
+    /// symmetric for the output to stay real.
+    pub fn process(&mut self, samples: &mut [f32], mut process_spectrum: impl FnMut(&mut [Complex<f32>])) {
+        let Self {
+            fft_size,
+            hop_size,
+            window,
+            frames,
+            analysis,
+            ola,
+            spectrum,
+            scratch,
+            fft_forward,
+            fft_inverse,
+            ola_norm,
+        } = self;
+        let (n, h) = (*fft_size, *hop_size);
+        let inv_n = 1.0 / n as f32;
+
+        frames.process(samples, |hop| {
+            // Slide the analysis window by one hop.
+            analysis.copy_within(h.., 0);
+            analysis[n - h..].copy_from_slice(hop);
+
+            for ((c, &x), &w) in spectrum.iter_mut().zip(analysis.iter()).zip(window.iter()) {
+                *c = Complex::new(x * w, 0.0);
+            }
+            fft_forward.process_with_scratch(spectrum, scratch);
+            process_spectrum(spectrum);
+            fft_inverse.process_with_scratch(spectrum, scratch);
+
+            for ((o, c), &w) in ola.iter_mut().zip(spectrum.iter()).zip(window.iter()) {
+                *o += c.re * inv_n * w;
+            }
+
+            // The first hop of the accumulator is now complete.
+            for (out, &acc) in hop.iter_mut().zip(ola.iter()) {
+                *out = acc * *ola_norm;
+            }
+            ola.copy_within(h.., 0);
+            ola[n - h..].fill(0.0);
+        });
+    }
+
+    pub fn reset(&mut self) {
+        self.frames.reset();
+        self.analysis.fill(0.0);
+        self.ola.fill(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_stft_reconstructs_delayed_input() {
+        let mut stft = Stft::new(256, 64);
+        let input: Vec<f32> = (0..2048)
+            .map(|i| (i as f32 / 48000.0 * 440.0 * std::f32::consts::TAU).sin() * 0.5)
+            .collect();
+        let mut output = input.clone();
+        for block in output.chunks_mut(100) {
+            stft.process(block, |_| {});
+        }
+
+        // Output lags input by fft_size samples.
+        for i in 256..2048 {
+            assert!(
+                (output[i] - input[i - 256]).abs() < 1e-4,
+                "sample {i}: {} vs {}",
+                output[i],
+                input[i - 256]
+            );
+        }
+    }
+}
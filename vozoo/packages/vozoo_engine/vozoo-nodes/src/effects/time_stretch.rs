@@ -0,0 +1,365 @@
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
+
+use super::fft_utils;
+use super::vocoder::Vocoder;
+
+/// How `TimeStretch` rebuilds the signal at the new speed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StretchMode {
+    /// Waveform-similarity overlap-add: copies grains of the input, each
+    /// nudged to line up with the one before. Best for speech.
+    Wsola,
+    /// Phase vocoder with identity phase locking. Smoother on sustained
+    /// tones and music, slightly phasey on speech.
+    PhaseVocoder,
+}
+
+/// Changes speed without changing pitch: `rate` 0.5 plays at half speed
+/// (twice as long), 2.0 at double speed.
+///
+/// Each block of `n` frames yields about `n / rate` frames. Synthesized
+/// grains are queued and handed out at that pace, so block outputs don't
+/// jump by whole grains; the queue starts with `latency_samples()` frames
+/// of silence. A stretcher built at rate 1 passes audio through untouched
+/// until its rate is first moved.
+pub struct TimeStretch {
+    rate: SmoothedParam,
+    /// Set once the rate leaves 1; from then on the stretcher keeps running
+    /// (even back at 1) so the latency doesn't jump.
+    active: bool,
+    grain: Grain,
+    /// Buffered input, starting `tolerance` frames before the next frame.
+    input: Vec<f32>,
+    /// Input frames dropped from the front of `input` so far.
+    dropped: u64,
+    /// Nominal start of the next analysis frame in `input`.
+    pos: f64,
+    /// Overlap-add accumulator, one frame long.
+    ola: Vec<f32>,
+    /// Synthesized output not handed out yet.
+    queue: Vec<f32>,
+    /// Fraction of an output frame owed to the host.
+    owed: f64,
+}
+
+/// Frame geometry and the per-mode synthesis state.
+struct Grain {
+    mode: StretchMode,
+    size: usize,
+    hop: usize,
+    /// How far WSOLA may move a frame from its nominal position.
+    tolerance: usize,
+    window: Vec<f32>,
+    /// Scale that makes the overlapped windows sum to one.
+    ola_norm: f32,
+    /// Absolute input position the previous frame was taken from.
+    last_start: Option<u64>,
+    vocoder: Option<Box<Vocoder>>,
+}
+
+impl TimeStretch {
+    pub const PARAMS: [ParamDescriptor; 1] = [ParamDescriptor {
+        id: 0,
+        key: "rate",
+        name: "Speed",
+        min: 0.25,
+        max: 4.0,
+        default: 1.0,
+    }];
+
+    pub fn new(rate: f32, mode: StretchMode) -> Self {
+        let (size, hop, tolerance) = match mode {
+            StretchMode::Wsola => (1024, 512, 256),
+            StretchMode::PhaseVocoder => (2048, 512, 0),
+        };
+        let window = fft_utils::hann_window(size);
+        // WSOLA windows each grain once, the vocoder on analysis and
+        // again on synthesis.
+        let overlap: f32 = match mode {
+            StretchMode::Wsola => window.iter().sum(),
+            StretchMode::PhaseVocoder => window.iter().map(|w| w * w).sum(),
+        };
+        let vocoder = (mode == StretchMode::PhaseVocoder).then(|| Box::new(Vocoder::new(size)));
+
+        let mut stretch = Self {
+            rate: SmoothedParam::new(Self::PARAMS[0], rate),
+            active: false,
+            grain: Grain {
+                mode,
+                size,
+                hop,
+                tolerance,
+                window,
+                ola_norm: hop as f32 / overlap.max(1e-6),
+                last_start: None,
+                vocoder,
+            },
+            input: Vec::new(),
+            dropped: 0,
+            pos: 0.0,
+            ola: vec![0.0; size],
+            queue: Vec::new(),
+            owed: 0.0,
+        };
+        stretch.restart();
+        stretch
+    }
+
+    pub fn mode(&self) -> StretchMode {
+        self.grain.mode
+    }
+
+    /// Empty the buffers, leaving the input history and the queue primed
+    /// with silence.
+    fn restart(&mut self) {
+        let Grain { size, tolerance, .. } = self.grain;
+        self.input.clear();
+        self.input.resize(tolerance, 0.0);
+        self.dropped = 0;
+        self.pos = tolerance as f64;
+        self.ola.fill(0.0);
+        self.queue.clear();
+        self.queue.resize(size + tolerance, 0.0);
+        self.owed = 0.0;
+        self.grain.last_start = None;
+    }
+
+    /// Synthesize every frame the buffered input allows into the queue.
+    fn synthesize(&mut self) {
+        let Grain { size, hop, tolerance, .. } = self.grain;
+        loop {
+            let start = self.pos.round() as usize;
+            // Where the input that followed the previous frame starts.
+            let natural = self.grain.last_start.map_or(start, |last| (last + hop as u64 - self.dropped) as usize);
+            if start.max(natural) + tolerance + size > self.input.len() {
+                break;
+            }
+
+            let chosen = self.grain.place(&self.input, start, natural);
+            let absolute = self.dropped + chosen as u64;
+            let analysis_hop = self.grain.last_start.map(|last| absolute.saturating_sub(last) as usize);
+            self.grain.render(&self.input[chosen..chosen + size], analysis_hop, &mut self.ola);
+            self.grain.last_start = Some(absolute);
+
+            self.queue.extend_from_slice(&self.ola[..hop]);
+            self.ola.copy_within(hop.., 0);
+            self.ola[size - hop..].fill(0.0);
+            self.pos += hop as f64 * self.rate.advance(hop) as f64;
+
+            // Drop input neither the next frame's search nor its natural
+            // continuation can reach.
+            let keep_from = (self.pos.round() as usize).saturating_sub(tolerance).min(chosen + hop);
+            self.input.copy_within(keep_from.., 0);
+            self.input.truncate(self.input.len() - keep_from);
+            self.dropped += keep_from as u64;
+            self.pos -= keep_from as f64;
+        }
+    }
+}
+
+impl Grain {
+    /// Start of the frame to copy near `start`. WSOLA searches the
+    /// tolerance for the offset whose first hop best matches `natural`, the
+    /// input that followed the previous frame, so grains join in phase.
+    fn place(&self, input: &[f32], start: usize, natural: usize) -> usize {
+        if self.tolerance == 0 || self.last_start.is_none() {
+            return start;
+        }
+        let target = &input[natural..natural + self.hop];
+        let origin = start - self.tolerance;
+        let score = |offset: usize| {
+            let candidate = &input[origin + offset..origin + offset + self.hop];
+            let (dot, energy) = candidate
+                .iter()
+                .zip(target)
+                .step_by(2)
+                .fold((0.0f32, 0.0f32), |(dot, energy), (&c, &t)| (dot + c * t, energy + c * c));
+            dot / energy.sqrt().max(1e-9)
+        };
+        // Coarse search, then refine around the best. Ties keep the offset
+        // nearest the nominal position.
+        let span = 2 * self.tolerance;
+        let better = |best: (usize, f32), offset: usize| {
+            let s = score(offset);
+            let nearer = offset.abs_diff(self.tolerance) < best.0.abs_diff(self.tolerance);
+            if s > best.1 || (s == best.1 && nearer) {
+                (offset, s)
+            } else {
+                best
+            }
+        };
+        let coarse = (0..=span).step_by(4).fold((self.tolerance, score(self.tolerance)), better);
+        let fine = (coarse.0.saturating_sub(3)..=(coarse.0 + 3).min(span)).fold(coarse, better);
+        origin + fine.0
+    }
+
+    /// Window `frame`, run it through the vocoder in that mode, and add it
+    /// to `ola`. `analysis_hop` is how far the input moved since the
+    /// previous frame.
+    fn render(&mut self, frame: &[f32], analysis_hop: Option<usize>, ola: &mut [f32]) {
+        match self.vocoder.as_mut() {
+            None => {
+                for ((o, &x), &w) in ola.iter_mut().zip(frame).zip(&self.window) {
+                    *o += x * w * self.ola_norm;
+                }
+            }
+            Some(vocoder) => {
+                vocoder.analyze(frame, &self.window);
+                vocoder.lock_phases(analysis_hop, self.hop as f32);
+                vocoder.synthesize(ola, &self.window, self.ola_norm);
+            }
+        }
+    }
+}
+
+impl AudioNode for TimeStretch {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        self.rate.prepare(sample_rate);
+        let Grain { size, hop, tolerance, .. } = self.grain;
+        let input = max_block + 2 * (size + tolerance) + 4 * hop;
+        self.input.reserve(input.saturating_sub(self.input.len()));
+        let queue = self.max_output_frames(max_block) + 4 * (size + tolerance + hop);
+        self.queue.reserve(queue.saturating_sub(self.queue.len()));
+    }
+
+    fn max_output_frames(&self, input_frames: usize) -> usize {
+        (input_frames as f32 / Self::PARAMS[0].min).ceil() as usize + 1
+    }
+
+    fn latency_samples(&self) -> usize {
+        if self.active || (self.rate.target() - 1.0).abs() >= 1e-3 {
+            self.max_latency_samples()
+        } else {
+            0
+        }
+    }
+
+    fn max_latency_samples(&self) -> usize {
+        self.grain.size + self.grain.tolerance
+    }
+
+    fn time_scale(&self) -> f64 {
+        1.0 / self.rate.target() as f64
+    }
+
+    /// The rate is live, so even a node built at 1.0 may stretch.
+    fn changes_length(&self) -> bool {
+        true
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        self.active |= (self.rate.target() - 1.0).abs() >= 1e-3;
+        if !self.active || buffer.samples.is_empty() {
+            return;
+        }
+
+        let frames = buffer.samples.len();
+        self.input.extend_from_slice(&buffer.samples);
+        self.synthesize();
+
+        // Owing more than is queued means the queue is too shallow for the
+        // rate; the shortfall is dropped so it settles deeper.
+        self.owed += frames as f64 / self.rate.value() as f64;
+        let out = (self.owed as usize).min(self.queue.len());
+        self.owed = self.owed.fract();
+        buffer.samples.clear();
+        buffer.samples.extend_from_slice(&self.queue[..out]);
+        self.queue.copy_within(out.., 0);
+        self.queue.truncate(self.queue.len() - out);
+    }
+
+    fn reset(&mut self) {
+        self.rate.snap();
+        self.restart();
+        if let Some(vocoder) = self.grain.vocoder.as_mut() {
+            vocoder.reset();
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Time Stretch"
+    }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.rate.handle()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vozoo_core::test_signals::sine;
+
+    use super::*;
+
+    /// Frequency of a tone from its rising zero crossings.
+    fn frequency(samples: &[f32]) -> f32 {
+        let crossings: Vec<usize> = samples.windows(2).enumerate().filter(|(_, w)| w[0] < 0.0 && w[1] >= 0.0).map(|(i, _)| i).collect();
+        let (first, last) = (crossings[0], *crossings.last().unwrap());
+        (crossings.len() - 1) as f32 * 48000.0 / (last - first) as f32
+    }
+
+    fn stretch(rate: f32, mode: StretchMode, input: &[f32], block: usize) -> Vec<f32> {
+        let mut node = TimeStretch::new(rate, mode);
+        node.prepare(48000, block);
+        let mut buffer = AudioBuffer::new(input.to_vec(), 48000);
+        buffer.process_blocks(block, |b| {
+            node.process(b);
+            assert!(b.frames() <= node.max_output_frames(block));
+        });
+        buffer.samples
+    }
+
+    #[test]
+    fn test_length_follows_rate_and_pitch_stays() {
+        let input = sine(220.0, 0.5, 48000, 48000);
+        for mode in [StretchMode::Wsola, StretchMode::PhaseVocoder] {
+            for rate in [0.5f32, 1.5] {
+                let output = stretch(rate, mode, &input, 512);
+                let expected = input.len() as f32 / rate;
+                // Up to a latency's worth of the primed queue may go unused at the slow rate.
+                let shortfall = expected - output.len() as f32;
+                assert!((-1.0..4096.0).contains(&shortfall), "{mode:?} at {rate}: {} frames", output.len());
+
+                // Past the primed silence and the fade in, the tone keeps its pitch.
+                let steady = &output[4096..output.len() - 2048];
+                let f = frequency(steady);
+                assert!((f - 220.0).abs() < 3.0, "{mode:?} at {rate}: {f} Hz");
+                let peak = steady.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+                assert!((0.35..0.7).contains(&peak), "{mode:?} at {rate}: peak {peak}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_output_does_not_depend_on_block_size() {
+        let input = sine(300.0, 0.5, 48000, 24000);
+        for mode in [StretchMode::Wsola, StretchMode::PhaseVocoder] {
+            let a = stretch(0.7, mode, &input, 64);
+            let b = stretch(0.7, mode, &input, 1000);
+            let common = a.len().min(b.len()) - 1024;
+            assert_eq!(a[..common], b[..common], "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_wsola_at_rate_one_reproduces_input() {
+        // Moved away and back: the grains line up exactly, delayed by the latency.
+        let input = sine(440.0, 0.5, 48000, 9600);
+        let mut node = TimeStretch::new(1.0, StretchMode::Wsola);
+        node.active = true;
+        let mut buffer = AudioBuffer::new(input.clone(), 48000);
+        buffer.process_blocks(256, |b| node.process(b));
+        let latency = node.latency_samples();
+        assert_eq!(buffer.samples.len(), input.len());
+        for (i, (&out, &x)) in buffer.samples[latency + 512..].iter().zip(&input[512..]).enumerate() {
+            assert!((out - x).abs() < 1e-4, "sample {i}: {out} vs {x}");
+        }
+    }
+
+    #[test]
+    fn test_rate_one_passes_through() {
+        let input = sine(440.0, 0.5, 48000, 4800);
+        let output = stretch(1.0, StretchMode::PhaseVocoder, &input, 480);
+        assert_eq!(output, input);
+    }
+}
@@ -1,40 +1,69 @@
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{AudioBuffer, AudioNode, ParamDescriptor, ParamHandle, SmoothedParam};
+
+use super::frame_buffer::FrameBuffer;
 
 /// Voice Activity Detection — zeroes out frames below an energy threshold.
 /// Saves CPU by allowing downstream nodes to skip silent frames.
+///
+/// Frames are gated whole, so output lags input by one frame.
 pub struct Vad {
-    threshold_db: f32,
-    frame_size: usize,
+    threshold_db: SmoothedParam,
+    frames: FrameBuffer,
 }
 
 impl Vad {
+    pub const PARAMS: [ParamDescriptor; 1] = [ParamDescriptor {
+        id: 0,
+        key: "threshold_db",
+        name: "Threshold (dB)",
+        min: -60.0,
+        max: -10.0,
+        default: -40.0,
+    }];
+
     /// Create a VAD with the given threshold in dB (e.g., -40.0).
     pub fn new(threshold_db: f32) -> Self {
         Self {
-            threshold_db,
-            frame_size: 480, // 10ms at 48kHz
+            threshold_db: SmoothedParam::new(Self::PARAMS[0], threshold_db),
+            frames: FrameBuffer::new(480), // 10ms at 48kHz
         }
     }
 }
 
 impl AudioNode for Vad {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.threshold_db.prepare(sample_rate);
+    }
+
+    fn latency_samples(&self) -> usize {
+        self.frames.frame_size()
+    }
+
     fn process(&mut self, buffer: &mut AudioBuffer) {
-        let threshold_linear = 10.0f32.powf(self.threshold_db / 20.0);
-        let threshold_energy = threshold_linear * threshold_linear * self.frame_size as f32;
+        let frame_size = self.frames.frame_size();
+        let threshold_db = &mut self.threshold_db;
 
-        for chunk in buffer.samples.chunks_mut(self.frame_size) {
-            let energy: f32 = chunk.iter().map(|s| s * s).sum();
+        self.frames.process(&mut buffer.samples, |frame| {
+            // The threshold only matters once per frame.
+            let threshold_linear = 10.0f32.powf(threshold_db.advance(frame_size) / 20.0);
+            let threshold_energy = threshold_linear * threshold_linear * frame_size as f32;
+            let energy: f32 = frame.iter().map(|s| s * s).sum();
             if energy < threshold_energy {
-                for s in chunk.iter_mut() {
-                    *s = 0.0;
-                }
+                frame.fill(0.0);
             }
-        }
+        });
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        self.frames.reset();
+        self.threshold_db.snap();
+    }
 
     fn name(&self) -> &str {
         "Voice Activity Detection"
     }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        vec![self.threshold_db.handle()]
+    }
 }
@@ -0,0 +1,187 @@
+use std::f32::consts::TAU;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::Fft;
+
+use super::fft_utils;
+
+/// Rise in high-frequency content from one frame to the next that counts
+/// as an onset (about 6 dB).
+const ONSET_RISE: f32 = 4.0;
+
+/// Phase vocoder frame with identity phase locking, for nodes that pick
+/// their own frames and hops (`TimeStretch`, `PitchShift`).
+///
+/// Per frame: `analyze`, optionally edit `magnitudes_mut`, `lock_phases`
+/// for the new hop, then `synthesize` or `resynthesize`.
+pub struct Vocoder {
+    fft_forward: Arc<dyn Fft<f32>>,
+    fft_inverse: Arc<dyn Fft<f32>>,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+    phases: Vec<f32>,
+    last_phases: Vec<f32>,
+    synth_phases: Vec<f32>,
+    peaks: Vec<usize>,
+    /// Synthesis phase of each peak in `peaks`.
+    peak_phases: Vec<f32>,
+    /// High-frequency content of the previous frame, for `onset`.
+    last_energy: f32,
+}
+
+impl Vocoder {
+    pub fn new(size: usize) -> Self {
+        let (fft_forward, fft_inverse) = fft_utils::create_fft_pair(size);
+        let scratch_len = fft_forward.get_inplace_scratch_len().max(fft_inverse.get_inplace_scratch_len());
+        let bins = size / 2 + 1;
+        Self {
+            fft_forward,
+            fft_inverse,
+            spectrum: vec![Complex::new(0.0, 0.0); size],
+            scratch: vec![Complex::new(0.0, 0.0); scratch_len],
+            magnitudes: vec![0.0; bins],
+            phases: vec![0.0; bins],
+            last_phases: vec![0.0; bins],
+            synth_phases: vec![0.0; bins],
+            peaks: Vec::with_capacity(bins),
+            peak_phases: Vec::with_capacity(bins),
+            last_energy: 0.0,
+        }
+    }
+
+    /// Window `frame` and split its spectrum into magnitudes and phases.
+    pub fn analyze(&mut self, frame: &[f32], window: &[f32]) {
+        for ((c, &x), &w) in self.spectrum.iter_mut().zip(frame).zip(window) {
+            *c = Complex::new(x * w, 0.0);
+        }
+        self.fft_forward.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+        for (k, bin) in self.spectrum[..self.magnitudes.len()].iter().enumerate() {
+            self.magnitudes[k] = bin.norm();
+            self.phases[k] = bin.arg();
+        }
+    }
+
+    /// Full spectrum of the last analysed frame.
+    pub fn spectrum(&self) -> &[Complex<f32>] {
+        &self.spectrum
+    }
+
+    /// Magnitudes up to Nyquist, used as they are by the next synthesis.
+    pub fn magnitudes_mut(&mut self) -> &mut [f32] {
+        &mut self.magnitudes
+    }
+
+    /// Whether the high-frequency content of the last analysed frame jumped
+    /// since the frame before, which marks an onset. Call once per frame.
+    pub fn onset(&mut self) -> bool {
+        let energy: f32 = self.magnitudes.iter().enumerate().map(|(k, m)| k as f32 * m * m).sum();
+        let onset = energy > ONSET_RISE * self.last_energy + 1e-6;
+        self.last_energy = energy;
+        onset
+    }
+
+    /// Advance the synthesis phases by `synthesis_hop`. Peaks move at their
+    /// measured frequency; the bins around each peak keep their analysed
+    /// phase offset from it, which keeps the partials' shapes intact.
+    ///
+    /// Without an `analysis_hop` (the first frame, or a phase reset at an
+    /// onset) the synthesis phases restart from the analysed ones.
+    pub fn lock_phases(&mut self, analysis_hop: Option<usize>, synthesis_hop: f32) {
+        let Some(analysis_hop) = analysis_hop.filter(|&hop| hop > 0) else {
+            self.synth_phases.copy_from_slice(&self.phases);
+            self.last_phases.copy_from_slice(&self.phases);
+            return;
+        };
+        let size = self.spectrum.len() as f32;
+        let bins = self.magnitudes.len();
+
+        self.peaks.clear();
+        let m = &self.magnitudes;
+        self.peaks.extend((1..bins - 1).filter(|&k| m[k] > m[k - 1] && m[k] >= m[k + 1]));
+        if self.peaks.is_empty() {
+            self.peaks.extend(0..bins);
+        }
+
+        self.peak_phases.clear();
+        for &p in &self.peaks {
+            let expected = TAU * p as f32 / size * analysis_hop as f32;
+            let deviation = fft_utils::wrap_phase(self.phases[p] - self.last_phases[p] - expected);
+            let frequency = TAU * p as f32 / size + deviation / analysis_hop as f32;
+            self.peak_phases.push(self.synth_phases[p] + frequency * synthesis_hop);
+        }
+
+        // Each bin follows the nearest peak.
+        let mut owner = 0;
+        for k in 0..bins {
+            while owner + 1 < self.peaks.len() && 2 * k > self.peaks[owner] + self.peaks[owner + 1] {
+                owner += 1;
+            }
+            let p = self.peaks[owner];
+            self.synth_phases[k] = fft_utils::wrap_phase(self.peak_phases[owner] + self.phases[k] - self.phases[p]);
+        }
+        self.last_phases.copy_from_slice(&self.phases);
+    }
+
+    /// Inverse transform of the magnitudes at the synthesis phases. The
+    /// frame is in the real parts, scaled up by the frame size.
+    pub fn resynthesize(&mut self) -> &[Complex<f32>] {
+        let size = self.spectrum.len();
+        let bins = self.magnitudes.len();
+        for k in 0..bins {
+            self.spectrum[k] = Complex::from_polar(self.magnitudes[k], self.synth_phases[k]);
+        }
+        for k in bins..size {
+            self.spectrum[k] = self.spectrum[size - k].conj();
+        }
+        self.fft_inverse.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+        &self.spectrum
+    }
+
+    /// `resynthesize`, then window the frame and add it to `ola`.
+    pub fn synthesize(&mut self, ola: &mut [f32], window: &[f32], norm: f32) {
+        let scale = norm / self.spectrum.len() as f32;
+        let frame = self.resynthesize();
+        for ((o, c), &w) in ola.iter_mut().zip(frame).zip(window) {
+            *o += c.re * w * scale;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.last_phases.fill(0.0);
+        self.synth_phases.fill(0.0);
+        self.last_energy = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_onset_follows_rises_in_high_frequencies() {
+        let mut vocoder = Vocoder::new(256);
+        vocoder.magnitudes_mut().fill(1.0);
+        assert!(vocoder.onset(), "attack from silence");
+        assert!(!vocoder.onset(), "steady frame");
+        vocoder.magnitudes_mut().iter_mut().for_each(|m| *m *= 0.9);
+        assert!(!vocoder.onset(), "decay");
+        vocoder.magnitudes_mut().iter_mut().for_each(|m| *m *= 3.0);
+        assert!(vocoder.onset(), "attack");
+    }
+
+    #[test]
+    fn test_unlocked_frame_resynthesizes_input() {
+        let size = 256;
+        let frame: Vec<f32> = (0..size).map(|i| (i as f32 * 0.37).sin() + 0.2 * (i as f32 * 1.9).cos()).collect();
+        let flat = vec![1.0; size];
+        let mut vocoder = Vocoder::new(size);
+        vocoder.analyze(&frame, &flat);
+        vocoder.lock_phases(None, 64.0);
+        let output = vocoder.resynthesize();
+        for (c, &x) in output.iter().zip(&frame) {
+            assert!((c.re / size as f32 - x).abs() < 1e-4);
+        }
+    }
+}
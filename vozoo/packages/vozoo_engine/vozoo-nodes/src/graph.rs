@@ -1,20 +1,214 @@
-use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::Arc;
 
-use vozoo_core::{AudioBuffer, AudioNode};
+use vozoo_core::{
+    AudioBuffer, AudioNode, ChannelLayout, MeterTap, ParamHandle, VozooError, BLOCK_CAPACITY_FACTOR,
+    PROCESSING_SAMPLE_RATE,
+};
 
-/// A node slot in the audio graph, identified by a unique ID.
+use crate::effects::resample::NativeRate;
+use crate::workers::WorkerPool;
+
+/// A node slot in the compiled graph, identified by a unique ID.
 struct GraphSlot {
     id: u32,
     node: Box<dyn AudioNode>,
+    /// Edges into this node.
+    incoming: Vec<SlotEdge>,
+    /// Whether the main input is the graph input rather than a mix of edges.
+    takes_graph_input: bool,
+    /// Signals at the node's extra input ports for the current block.
+    inputs: Vec<AudioBuffer>,
+    /// Pool buffer holding each output port's signal, main port first.
+    outputs: Vec<usize>,
+    /// The output buffers, swapped out of the pool while the node runs.
+    held: Vec<AudioBuffer>,
 }
 
-/// Edge connecting two node slots: source output → destination input.
-#[derive(Debug, Clone, Copy)]
-struct GraphEdge {
-    from_id: u32,
-    to_id: u32,
+/// Connection from a node's output port to another node's input port.
+///
+/// Ports are named by the nodes (`AudioNode::input_ports` and
+/// `output_ports`); `None` is the main port. A plain `(from, to, gain)`
+/// tuple connects the main ports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: u32,
+    pub from_port: Option<String>,
+    pub to: u32,
+    pub to_port: Option<String>,
     /// Gain applied to the signal on this edge (for dry/wet mixing).
+    pub gain: f32,
+    /// Frames the signal is delayed by, for feedback. A delayed edge may
+    /// close a cycle: it only carries what its source produced earlier.
+    pub delay: Option<usize>,
+}
+
+impl Edge {
+    /// Connect the main output of `from` to the main input of `to`.
+    pub fn new(from: u32, to: u32, gain: f32) -> Self {
+        Self { from, from_port: None, to, to_port: None, gain, delay: None }
+    }
+
+    /// Delay the signal by `frames` frames, making this a feedback edge.
+    pub fn delayed(mut self, frames: usize) -> Self {
+        self.delay = Some(frames);
+        self
+    }
+
+    /// Take the signal from output port `port` instead.
+    pub fn from_port(mut self, port: &str) -> Self {
+        self.from_port = Some(port.to_string());
+        self
+    }
+
+    /// Feed input port `port` instead.
+    pub fn to_port(mut self, port: &str) -> Self {
+        self.to_port = Some(port.to_string());
+        self
+    }
+}
+
+impl From<(u32, u32, f32)> for Edge {
+    fn from((from, to, gain): (u32, u32, f32)) -> Self {
+        Self::new(from, to, gain)
+    }
+}
+
+/// Edge resolved to slot, pool and port indices for processing.
+struct SlotEdge {
+    /// Source slot.
+    from: usize,
+    /// Pool buffer holding the source port's signal.
+    source: usize,
+    to_port: usize,
     gain: f32,
+    /// Delay compensating for a shorter path than the destination's others.
+    delay: DelayLine,
+    /// History of the source for a feedback edge, which reads it instead of
+    /// the pool.
+    feedback: Option<FeedbackLine>,
+}
+
+/// Delay of whole frames, applied one frame at a time so it runs across
+/// blocks of any size.
+///
+/// The line keeps `capacity` frames of history whatever the current delay,
+/// so the delay can change within it without allocating or going silent.
+#[derive(Default)]
+struct DelayLine {
+    frames: usize,
+    capacity: usize,
+    channels: usize,
+    /// Interleaved ring of the last `capacity` frames; the next is written
+    /// at `pos`.
+    buffer: Vec<f32>,
+    pos: usize,
+    /// The frame leaving the line.
+    out: Vec<f32>,
+}
+
+impl DelayLine {
+    /// Make room for delays of up to `frames`, with room for stereo so the
+    /// first stereo frame doesn't allocate. Growing clears the history.
+    fn reserve(&mut self, frames: usize) {
+        if frames <= self.capacity {
+            return;
+        }
+        self.capacity = frames;
+        self.buffer.reserve((frames * 2).saturating_sub(self.buffer.len()));
+        self.out.reserve(2);
+        self.configure(self.channels.max(1));
+    }
+
+    /// Change the delay. Within the reserved capacity the history is kept;
+    /// beyond it the line grows, which allocates.
+    fn set_frames(&mut self, frames: usize) {
+        self.reserve(frames);
+        self.frames = frames;
+    }
+
+    fn configure(&mut self, channels: usize) {
+        self.channels = channels;
+        self.buffer.clear();
+        self.buffer.resize(self.capacity * channels, 0.0);
+        self.out.clear();
+        self.out.resize(channels, 0.0);
+        self.pos = 0;
+    }
+
+    /// Push one frame in, returning the frame from `frames` frames ago.
+    fn push<'a>(&'a mut self, frame: &'a [f32]) -> &'a [f32] {
+        if self.capacity == 0 {
+            return frame;
+        }
+        if frame.len() != self.channels {
+            self.configure(frame.len());
+        }
+        let ch = self.channels;
+        if self.frames > 0 {
+            let at = (self.pos + self.capacity - self.frames) % self.capacity;
+            self.out.copy_from_slice(&self.buffer[at * ch..(at + 1) * ch]);
+        }
+        self.buffer[self.pos * ch..(self.pos + 1) * ch].copy_from_slice(frame);
+        self.pos = (self.pos + 1) % self.capacity;
+        if self.frames == 0 {
+            return frame;
+        }
+        &self.out
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.pos = 0;
+    }
+}
+
+/// History of a feedback edge's source: yields, for each frame of a chunk,
+/// the frame produced `frames` frames before it. Chunks are never longer
+/// than the delay, so every frame read was written by an earlier chunk.
+#[derive(Default)]
+struct FeedbackLine {
+    frames: usize,
+    channels: usize,
+    /// Interleaved ring of the last `frames` frames; the oldest at `pos`.
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl FeedbackLine {
+    /// A silent line of `frames` frames, with room for stereo.
+    fn new(frames: usize) -> Self {
+        let mut line = Self { frames, channels: 1, buffer: Vec::with_capacity(frames * 2), pos: 0 };
+        line.buffer.resize(frames, 0.0);
+        line
+    }
+
+    /// Frame `index` of the current chunk.
+    fn frame(&self, index: usize) -> &[f32] {
+        let at = (self.pos + index) % self.frames;
+        &self.buffer[at * self.channels..(at + 1) * self.channels]
+    }
+
+    /// Append the chunk the source just produced. A change of channel count
+    /// restarts the history from silence.
+    fn write(&mut self, chunk: &AudioBuffer) {
+        let ch = chunk.channels() as usize;
+        if ch != self.channels {
+            self.channels = ch;
+            self.buffer.clear();
+            self.buffer.resize(self.frames * ch, 0.0);
+            self.pos = 0;
+        }
+        for frame in chunk.samples.chunks_exact(ch) {
+            self.buffer[self.pos * ch..(self.pos + 1) * ch].copy_from_slice(frame);
+            self.pos = (self.pos + 1) % self.frames;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.pos = 0;
+    }
 }
 
 /// DAG-based audio graph with topological execution order.
@@ -22,163 +216,561 @@ struct GraphEdge {
 /// Supports parallel routing (dry/wet splits, parallel compression)
 /// while maintaining the same `AudioBuffer`-based processing model.
 ///
-/// Each node receives the sum of all incoming edge buffers.
+/// Each node input port receives the sum of the edges into it. Besides the
+/// main ports, nodes may have named extra ports (e.g. a compressor's
+/// "sidechain"), which edges address by name.
 /// The final output is taken from the designated output node.
+///
+/// The graph is compiled when it is built: nodes are grouped into levels
+/// that only depend on earlier ones, each node keeps the edges into it, and
+/// output ports share a pool of buffers, a buffer being handed to another
+/// node once every node reading it has run. The pool is reserved in
+/// `prepare`, so processing a block doesn't allocate.
+///
+/// Paths through nodes with different latencies are realigned: each edge
+/// into a node is delayed so that all of them arrive as late as the
+/// slowest, so parallel branches mix without comb filtering.
+///
+/// Cycles are allowed when one of their edges is delayed (a feedback edge,
+/// see `Edge::delayed`). Scheduling ignores feedback edges, and blocks are
+/// run in chunks no longer than the shortest feedback delay, so the output
+/// is the same whatever the block size. Feedback edges take no part in
+/// latency alignment.
 pub struct AudioGraph {
+    /// Slots in execution order.
     slots: Vec<GraphSlot>,
-    edges: Vec<GraphEdge>,
-    /// Topologically sorted execution order (node IDs).
-    exec_order: Vec<u32>,
-    /// The node whose output is the graph output.
-    output_node_id: u32,
-    /// The node that receives the graph input.
-    input_node_id: u32,
+    /// Runs of `slots` that only depend on earlier runs.
+    levels: Vec<Range<usize>>,
+    /// Output buffers, shared by ports whose signals aren't needed at the
+    /// same time.
+    pool: Vec<AudioBuffer>,
+    /// The slot whose output is the graph output.
+    output_slot: Option<usize>,
+    /// Each node's latency when the edge delays were last planned.
+    node_latency: Vec<usize>,
+    /// Latency of each node's output relative to the graph input.
+    path_latency: Vec<usize>,
+    /// The most the graph's latency can become, as of the last `prepare`.
+    max_latency: usize,
+    /// Longest run of frames processed at once: the shortest feedback
+    /// delay, if there are feedback edges.
+    chunk: Option<usize>,
+    /// A chunk of the graph input, and the output gathered chunk by chunk.
+    chunk_input: AudioBuffer,
+    chunk_output: AudioBuffer,
+}
+
+/// Edge resolved to node and port indices, before scheduling.
+struct Connection {
+    from: usize,
+    from_port: usize,
+    to: usize,
+    to_port: usize,
+    gain: f32,
+    /// Feedback delay in frames; 0 for an ordinary edge.
+    delay: usize,
 }
 
 impl AudioGraph {
     /// Build a graph from slots, edges, input/output node IDs.
-    /// Performs topological sort at construction time.
+    /// Schedules the nodes and plans the buffer pool at construction time.
+    ///
+    /// Every node runs at the graph's sample rate and keeps the block
+    /// length: nodes tied to another rate are wrapped in `NativeRate`, and
+    /// sample rate converters and time stretchers are rejected.
+    ///
+    /// Edges to or from IDs without a node are ignored; naming a port the
+    /// node doesn't have is an error.
     pub fn new(
         slots: Vec<(u32, Box<dyn AudioNode>)>,
-        edges: Vec<(u32, u32, f32)>,
+        edges: impl IntoIterator<Item = impl Into<Edge>>,
         input_node_id: u32,
         output_node_id: u32,
-    ) -> Result<Self, String> {
-        let graph_slots: Vec<GraphSlot> = slots
-            .into_iter()
-            .map(|(id, node)| GraphSlot { id, node })
-            .collect();
+    ) -> Result<Self, VozooError> {
+        if let Some((id, node)) = slots
+            .iter()
+            .find(|(_, node)| node.output_sample_rate(PROCESSING_SAMPLE_RATE) != PROCESSING_SAMPLE_RATE)
+        {
+            return Err(VozooError::InvalidGraph(format!(
+                "node {id} ('{}') changes the sample rate, which only chains support",
+                node.name()
+            )));
+        }
+        if let Some((id, node)) = slots.iter().find(|(_, node)| node.changes_length()) {
+            return Err(VozooError::InvalidGraph(format!(
+                "node {id} ('{}') changes the length of its audio, which only chains support",
+                node.name()
+            )));
+        }
+
+        let nodes: Vec<(u32, Box<dyn AudioNode>)> =
+            slots.into_iter().map(|(id, node)| (id, NativeRate::wrap(node))).collect();
+        let index_of = |id: u32| nodes.iter().position(|(slot_id, _)| *slot_id == id);
+
+        let mut connections = Vec::new();
+        for e in edges.into_iter().map(Into::into) {
+            let (Some(from), Some(to)) = (index_of(e.from), index_of(e.to)) else {
+                continue;
+            };
+            let (from_node, to_node) = (&nodes[from].1, &nodes[to].1);
+            connections.push(Connection {
+                from,
+                from_port: port_index(e.from, from_node.as_ref(), from_node.output_ports(), &e.from_port, "output")?,
+                to,
+                to_port: port_index(e.to, to_node.as_ref(), to_node.input_ports(), &e.to_port, "input")?,
+                gain: e.gain,
+                delay: e.delay.unwrap_or(0),
+            });
+        }
+        let input = index_of(input_node_id);
+        let output = index_of(output_node_id);
+
+        // Order the nodes by level; the sort is stable, so ties keep the
+        // order they were given in.
+        let level = schedule(nodes.len(), &connections)?;
+        let mut scheduled: Vec<_> = nodes.into_iter().enumerate().collect();
+        scheduled.sort_by_key(|(index, _)| level[*index]);
+        let mut position = vec![0; scheduled.len()];
+        for (pos, (index, _)) in scheduled.iter().enumerate() {
+            position[*index] = pos;
+        }
+        let mut levels: Vec<Range<usize>> = Vec::new();
+        for (pos, (index, _)) in scheduled.iter().enumerate() {
+            match levels.last_mut() {
+                Some(run) if level[scheduled[run.start].0] == level[*index] => run.end = pos + 1,
+                _ => levels.push(pos..pos + 1),
+            }
+        }
 
-        let graph_edges: Vec<GraphEdge> = edges
+        // A port's signal is needed until the last level reading it; the
+        // graph output, and sources of feedback edges, until the chunk is done.
+        let last_use = |node: usize, port: usize| {
+            let outgoing = connections.iter().filter(|c| c.from == node && c.from_port == port);
+            if Some(node) == output && port == 0 || outgoing.clone().any(|c| c.delay > 0) {
+                return usize::MAX;
+            }
+            outgoing.map(|c| level[c.to]).fold(level[node], usize::max)
+        };
+        let mut pool_of: Vec<Vec<usize>> = vec![Vec::new(); scheduled.len()];
+        let mut pool_len = 0;
+        let mut live: Vec<(usize, usize)> = Vec::new();
+        let mut free: Vec<usize> = Vec::new();
+        for (current, run) in levels.iter().enumerate() {
+            live.retain(|&(buffer, last)| {
+                if last < current {
+                    free.push(buffer);
+                }
+                last >= current
+            });
+            for (index, (_, node)) in &scheduled[run.clone()] {
+                for port in 0..node.output_ports().len() {
+                    let buffer = free.pop().unwrap_or_else(|| {
+                        pool_len += 1;
+                        pool_len - 1
+                    });
+                    live.push((buffer, last_use(*index, port)));
+                    pool_of[*index].push(buffer);
+                }
+            }
+        }
+
+        let graph_slots: Vec<GraphSlot> = scheduled
             .into_iter()
-            .map(|(from_id, to_id, gain)| GraphEdge { from_id, to_id, gain })
+            .map(|(index, (id, node))| {
+                let incoming: Vec<SlotEdge> = connections
+                    .iter()
+                    .filter(|c| c.to == index)
+                    .map(|c| SlotEdge {
+                        from: position[c.from],
+                        source: pool_of[c.from][c.from_port],
+                        to_port: c.to_port,
+                        gain: c.gain,
+                        delay: DelayLine::default(),
+                        feedback: (c.delay > 0).then(|| FeedbackLine::new(c.delay)),
+                    })
+                    .collect();
+                let buffers = |count: usize| vec![AudioBuffer::empty(PROCESSING_SAMPLE_RATE); count];
+                GraphSlot {
+                    id,
+                    takes_graph_input: Some(index) == input && !incoming.iter().any(|e| e.to_port == 0),
+                    incoming,
+                    inputs: buffers(node.input_ports().len().saturating_sub(1)),
+                    held: buffers(pool_of[index].len()),
+                    outputs: pool_of[index].clone(),
+                    node,
+                }
+            })
             .collect();
 
-        let exec_order = topological_sort(&graph_slots, &graph_edges)?;
-
         Ok(Self {
+            output_slot: output.map(|index| position[index]),
+            node_latency: vec![0; graph_slots.len()],
+            path_latency: vec![0; graph_slots.len()],
+            max_latency: 0,
+            chunk: connections.iter().map(|c| c.delay).filter(|&delay| delay > 0).min(),
+            chunk_input: AudioBuffer::empty(PROCESSING_SAMPLE_RATE),
+            chunk_output: AudioBuffer::empty(PROCESSING_SAMPLE_RATE),
             slots: graph_slots,
-            edges: graph_edges,
-            exec_order,
-            output_node_id,
-            input_node_id,
+            levels,
+            pool: vec![AudioBuffer::empty(PROCESSING_SAMPLE_RATE); pool_len],
         })
     }
 
-    /// Process audio through the graph.
+    /// Frames the graph output lags its input by, including the delays
+    /// that align parallel paths.
+    pub fn latency_samples(&self) -> usize {
+        self.output_slot.map_or(0, |idx| self.path_latency[idx])
+    }
+
+    /// The most `latency_samples` can become while streaming. Valid after
+    /// `prepare`.
+    pub fn max_latency_samples(&self) -> usize {
+        self.max_latency
+    }
+
+    /// Reserve every edge delay for the longest it can get, from each
+    /// node's `max_latency_samples`: an edge is delayed by at most the
+    /// latest arrival at its destination.
+    fn reserve_paths(&mut self) {
+        let mut max_path = vec![0; self.slots.len()];
+        for (idx, slot) in self.slots.iter_mut().enumerate() {
+            let arrival = slot
+                .incoming
+                .iter()
+                .filter(|e| e.feedback.is_none())
+                .map(|e| max_path[e.from])
+                .max()
+                .unwrap_or(0);
+            for edge in slot.incoming.iter_mut().filter(|e| e.feedback.is_none()) {
+                edge.delay.reserve(arrival);
+            }
+            max_path[idx] = arrival + slot.node.max_latency_samples();
+        }
+        self.max_latency = self.output_slot.map_or(0, |idx| max_path[idx]);
+    }
+
+    /// Re-plan the edge delays if any node's latency changed since the last
+    /// plan. Edges were reserved for the worst case in `prepare`, so this
+    /// neither allocates nor drops the history of edges it doesn't change.
+    fn align_paths(&mut self) {
+        let unchanged = self
+            .slots
+            .iter()
+            .zip(&self.node_latency)
+            .all(|(slot, &latency)| slot.node.latency_samples() == latency);
+        if unchanged {
+            return;
+        }
+
+        for (latency, slot) in self.node_latency.iter_mut().zip(&self.slots) {
+            *latency = slot.node.latency_samples();
+        }
+        for (idx, slot) in self.slots.iter().enumerate() {
+            let arrival = slot
+                .incoming
+                .iter()
+                .filter(|e| e.feedback.is_none())
+                .map(|e| self.path_latency[e.from])
+                .max()
+                .unwrap_or(0);
+            self.path_latency[idx] = arrival + self.node_latency[idx];
+        }
+        for (idx, slot) in self.slots.iter_mut().enumerate() {
+            let arrival = self.path_latency[idx] - self.node_latency[idx];
+            for edge in slot.incoming.iter_mut().filter(|e| e.feedback.is_none()) {
+                edge.delay.set_frames(arrival - self.path_latency[edge.from]);
+            }
+        }
+    }
+
+    /// Prepare every node for streaming blocks of at most `max_block` frames.
+    pub fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        let capacity = max_block * BLOCK_CAPACITY_FACTOR;
+        let reserve = |buffer: &mut AudioBuffer| buffer.samples.reserve(capacity.saturating_sub(buffer.len()));
+        for slot in &mut self.slots {
+            slot.node.prepare(sample_rate, max_block);
+            slot.inputs.iter_mut().for_each(reserve);
+        }
+        self.pool.iter_mut().for_each(reserve);
+        if self.chunk.is_some() {
+            reserve(&mut self.chunk_input);
+            reserve(&mut self.chunk_output);
+        }
+        self.reserve_paths();
+        self.align_paths();
+    }
+
+    /// Process one block of audio through the graph.
     ///
     /// The input buffer is fed into the input node.
     /// After execution, the buffer is replaced with the output node's result.
+    ///
+    /// The main input of each node is conformed to its declared input
+    /// layout after mixing; extra inputs are passed as mixed.
     pub fn process(&mut self, buffer: &mut AudioBuffer) {
-        let sample_rate = buffer.sample_rate();
-        let num_samples = buffer.samples.len();
+        self.process_on(buffer, None);
+    }
 
-        // Buffer storage for each node's output, keyed by node ID.
-        let mut node_buffers: Vec<(u32, Vec<f32>)> = self
-            .slots
-            .iter()
-            .map(|s| (s.id, vec![0.0; num_samples]))
-            .collect();
+    /// Like `process`, but runs the independent nodes of each level on the
+    /// threads of `workers`. The output is identical.
+    ///
+    /// Every level with more than one node is a round trip to the pool's
+    /// threads, so this pays off for offline rendering of graphs with heavy
+    /// parallel branches, not on an audio callback.
+    pub fn process_parallel(&mut self, buffer: &mut AudioBuffer, workers: &WorkerPool) {
+        self.process_on(buffer, Some(workers));
+    }
 
-        // Set input node's buffer to the input audio.
-        if let Some((_, buf)) = node_buffers.iter_mut().find(|(id, _)| *id == self.input_node_id) {
-            buf.copy_from_slice(&buffer.samples);
-        }
-
-        // Execute nodes in topological order.
-        for &node_id in &self.exec_order {
-            // Sum all incoming edge buffers for this node.
-            let mut input_samples = vec![0.0f32; num_samples];
-            let mut has_input = false;
-
-            for edge in &self.edges {
-                if edge.to_id == node_id {
-                    if let Some((_, src_buf)) = node_buffers.iter().find(|(id, _)| *id == edge.from_id) {
-                        for (i, s) in src_buf.iter().enumerate() {
-                            if i < num_samples {
-                                input_samples[i] += s * edge.gain;
-                            }
-                        }
-                        has_input = true;
-                    }
-                }
+    fn process_on(&mut self, buffer: &mut AudioBuffer, workers: Option<&WorkerPool>) {
+        self.align_paths();
+        let output = self.output_slot.map(|slot| self.slots[slot].outputs[0]);
+
+        let frames = buffer.frames();
+        let chunk = self.chunk.unwrap_or(frames).max(1);
+        if chunk >= frames {
+            self.run_levels(buffer, workers);
+            // Replace the buffer with the output node's result.
+            if let Some(output) = output {
+                buffer.copy_from(&self.pool[output]);
             }
+            return;
+        }
 
-            // For input node, use the existing buffer (already set above).
-            if node_id == self.input_node_id && !has_input {
-                if let Some((_, buf)) = node_buffers.iter().find(|(id, _)| *id == self.input_node_id) {
-                    input_samples.copy_from_slice(buf);
-                }
+        // Feedback edges only reach back `chunk` frames, so run the block
+        // in pieces that short.
+        let ch = buffer.channels() as usize;
+        let mut input = std::mem::replace(&mut self.chunk_input, AudioBuffer::empty(buffer.sample_rate()));
+        input.set_sample_rate(buffer.sample_rate());
+        self.chunk_output.samples.clear();
+        for start in (0..frames).step_by(chunk) {
+            let end = (start + chunk).min(frames);
+            input.samples.clear();
+            input.samples.extend_from_slice(&buffer.samples[start * ch..end * ch]);
+            input.reshape(end - start, ch as u16);
+            self.run_levels(&input, workers);
+
+            if let Some(output) = output {
+                let piece = &self.pool[output];
+                self.chunk_output.samples.extend_from_slice(&piece.samples);
+                self.chunk_output.reshape(self.chunk_output.len() / piece.channels() as usize, piece.channels());
+            }
+        }
+        self.chunk_input = input;
+        if output.is_some() {
+            self.chunk_output.set_sample_rate(buffer.sample_rate());
+            buffer.copy_from(&self.chunk_output);
+        }
+    }
+
+    /// Run every level on `input`, leaving each node's outputs in the pool,
+    /// then record what feedback edges will need later.
+    fn run_levels(&mut self, input: &AudioBuffer, workers: Option<&WorkerPool>) {
+        let frames = input.frames();
+        for level in &self.levels {
+            let slots = &mut self.slots[level.clone()];
+            for slot in slots.iter_mut() {
+                slot.swap_outputs(&mut self.pool);
             }
 
-            // Process through the node.
-            let mut node_buffer = AudioBuffer::new(input_samples, sample_rate);
-            if let Some(slot) = self.slots.iter_mut().find(|s| s.id == node_id) {
-                slot.node.process(&mut node_buffer);
+            let pool = &self.pool;
+            match workers {
+                Some(workers) => workers.for_each(slots, |slot| slot.run(pool, input, frames)),
+                None => slots.iter_mut().for_each(|slot| slot.run(pool, input, frames)),
             }
 
-            // Store this node's output.
-            if let Some((_, buf)) = node_buffers.iter_mut().find(|(id, _)| *id == node_id) {
-                let copy_len = node_buffer.samples.len().min(num_samples);
-                buf[..copy_len].copy_from_slice(&node_buffer.samples[..copy_len]);
+            for slot in slots.iter_mut() {
+                slot.swap_outputs(&mut self.pool);
             }
         }
 
-        // Copy output node's buffer to the result.
-        if let Some((_, buf)) = node_buffers.iter().find(|(id, _)| *id == self.output_node_id) {
-            let copy_len = buf.len().min(buffer.samples.len());
-            buffer.samples[..copy_len].copy_from_slice(&buf[..copy_len]);
+        for edge in self.slots.iter_mut().flat_map(|slot| &mut slot.incoming) {
+            if let Some(line) = &mut edge.feedback {
+                line.write(&self.pool[edge.source]);
+            }
         }
     }
 
+    /// Channel layout at the output node for a given graph input layout.
+    pub fn output_layout(&self, input: ChannelLayout) -> ChannelLayout {
+        let mut layouts: Vec<Option<ChannelLayout>> = vec![None; self.slots.len()];
+        for (idx, slot) in self.slots.iter().enumerate() {
+            let incoming = slot
+                .incoming
+                .iter()
+                .filter(|e| e.to_port == 0)
+                .filter_map(|e| layouts[e.from])
+                .map(|l| l.channels())
+                .max();
+            let mixed = match incoming {
+                Some(ch) => ChannelLayout::from_channels(ch),
+                None if slot.takes_graph_input => input,
+                None => ChannelLayout::Mono,
+            };
+            let node = &slot.node;
+            layouts[idx] = Some(node.output_layout(node.input_layout().unwrap_or(mixed)));
+        }
+        self.output_slot.and_then(|idx| layouts[idx]).unwrap_or(input)
+    }
+
     pub fn reset(&mut self) {
         for slot in &mut self.slots {
             slot.node.reset();
+            for edge in &mut slot.incoming {
+                edge.delay.reset();
+                if let Some(line) = &mut edge.feedback {
+                    line.reset();
+                }
+            }
         }
     }
-}
 
-/// Topological sort using Kahn's algorithm.
-fn topological_sort(slots: &[GraphSlot], edges: &[GraphEdge]) -> Result<Vec<u32>, String> {
-    let ids: Vec<u32> = slots.iter().map(|s| s.id).collect();
+    /// Live parameters of every node, keyed by node ID.
+    pub fn params(&self) -> Vec<(u32, ParamHandle)> {
+        self.slots
+            .iter()
+            .flat_map(|slot| slot.node.params().into_iter().map(move |p| (slot.id, p)))
+            .collect()
+    }
+
+    /// Meter taps of every node, keyed by node ID.
+    pub fn meters(&self) -> Vec<(u32, Arc<MeterTap>)> {
+        self.slots
+            .iter()
+            .flat_map(|slot| slot.node.meters().into_iter().map(move |m| (slot.id, m)))
+            .collect()
+    }
+}
 
-    // Build in-degree map.
-    let mut in_degree: Vec<(u32, usize)> = ids.iter().map(|&id| (id, 0)).collect();
-    for edge in edges {
-        if let Some((_, deg)) = in_degree.iter_mut().find(|(id, _)| *id == edge.to_id) {
-            *deg += 1;
+impl GraphSlot {
+    /// Exchange the held output buffers with their pool entries.
+    fn swap_outputs(&mut self, pool: &mut [AudioBuffer]) {
+        for (held, &buffer) in self.held.iter_mut().zip(&self.outputs) {
+            std::mem::swap(held, &mut pool[buffer]);
         }
     }
 
-    // Start with nodes that have zero in-degree.
-    let mut queue: VecDeque<u32> = in_degree
-        .iter()
-        .filter(|(_, deg)| *deg == 0)
-        .map(|(id, _)| *id)
-        .collect();
+    /// Mix this node's inputs from the pool and run it, leaving its outputs
+    /// in `held`.
+    fn run(&mut self, pool: &[AudioBuffer], graph_input: &AudioBuffer, frames: usize) {
+        let sample_rate = graph_input.sample_rate();
+        let (main, extra) = self.held.split_first_mut().expect("nodes have a main output");
+        main.set_sample_rate(sample_rate);
+        if self.takes_graph_input {
+            main.copy_from(graph_input);
+        } else {
+            mix_port(&mut self.incoming, pool, 0, main, frames);
+        }
+        for (port, input) in self.inputs.iter_mut().enumerate() {
+            input.set_sample_rate(sample_rate);
+            if !mix_port(&mut self.incoming, pool, port + 1, input, frames) {
+                input.reshape(0, 1);
+            }
+        }
+        // Pool buffers come from other nodes; outputs the node leaves
+        // alone must not carry their signal.
+        for output in extra.iter_mut() {
+            output.reshape(0, 1);
+            output.set_sample_rate(sample_rate);
+        }
+
+        if let Some(layout) = self.node.input_layout() {
+            main.conform(layout);
+        }
+        self.node.process_ports(main, &self.inputs, extra);
 
-    let mut result = Vec::with_capacity(ids.len());
+        // Keep every output at the graph's frame count.
+        for output in &mut self.held {
+            let ch = output.channels() as usize;
+            output.samples.resize(frames * ch, 0.0);
+        }
+    }
+}
 
-    while let Some(node_id) = queue.pop_front() {
-        result.push(node_id);
+/// Index of the port named `name` (the main port for `None`) among `ports`.
+fn port_index(
+    id: u32,
+    node: &dyn AudioNode,
+    ports: &[&str],
+    name: &Option<String>,
+    direction: &str,
+) -> Result<usize, VozooError> {
+    let Some(name) = name else {
+        return Ok(0);
+    };
+    ports.iter().position(|p| p == name).ok_or_else(|| {
+        VozooError::InvalidGraph(format!(
+            "node {id} ('{}') has no {direction} port '{name}' (it has: {})",
+            node.name(),
+            ports.join(", ")
+        ))
+    })
+}
+
+/// Sum the edges into input `port` into `target`, at the widest channel
+/// count among their sources (mono sources are copied to every channel).
+/// Returns false if nothing is connected, leaving `frames` frames of mono
+/// silence.
+fn mix_port(edges: &mut [SlotEdge], pool: &[AudioBuffer], port: usize, target: &mut AudioBuffer, frames: usize) -> bool {
+    let source_channels = |e: &SlotEdge| match &e.feedback {
+        Some(line) => line.channels as u16,
+        None => pool[e.source].channels(),
+    };
+    let channels = edges.iter().filter(|e| e.to_port == port).map(source_channels).max();
+    let ch = channels.unwrap_or(1) as usize;
+    target.samples.clear();
+    target.reshape(frames, ch as u16);
 
-        for edge in edges {
-            if edge.from_id == node_id {
-                if let Some((_, deg)) = in_degree.iter_mut().find(|(id, _)| *id == edge.to_id) {
-                    *deg -= 1;
-                    if *deg == 0 {
-                        queue.push_back(edge.to_id);
-                    }
+    for e in edges.iter_mut().filter(|e| e.to_port == port) {
+        if let Some(line) = &e.feedback {
+            for (i, dst) in target.samples.chunks_exact_mut(ch).enumerate() {
+                let frame = line.frame(i);
+                for (c, d) in dst.iter_mut().enumerate() {
+                    *d += frame[c % frame.len()] * e.gain;
                 }
             }
+            continue;
+        }
+        let src = &pool[e.source];
+        let src_ch = src.channels() as usize;
+        for (dst, frame) in target.samples.chunks_exact_mut(ch).zip(src.samples.chunks_exact(src_ch)) {
+            let frame = e.delay.push(frame);
+            for (c, d) in dst.iter_mut().enumerate() {
+                *d += frame[c % src_ch] * e.gain;
+            }
         }
     }
+    channels.is_some()
+}
+
+/// Level of every node: one past the deepest node feeding it, so each
+/// level only depends on earlier ones. Kahn's algorithm over adjacency
+/// lists, ignoring feedback edges; nodes left over sit on a cycle.
+fn schedule(count: usize, connections: &[Connection]) -> Result<Vec<usize>, VozooError> {
+    let mut successors = vec![Vec::new(); count];
+    let mut in_degree = vec![0; count];
+    for c in connections.iter().filter(|c| c.delay == 0) {
+        successors[c.from].push(c.to);
+        in_degree[c.to] += 1;
+    }
 
-    if result.len() != ids.len() {
-        return Err("Graph contains a cycle".into());
+    let mut level = vec![0; count];
+    let mut ready: Vec<usize> = (0..count).filter(|&node| in_degree[node] == 0).collect();
+    let mut visited = 0;
+    while let Some(node) = ready.pop() {
+        visited += 1;
+        for &next in &successors[node] {
+            level[next] = level[next].max(level[node] + 1);
+            in_degree[next] -= 1;
+            if in_degree[next] == 0 {
+                ready.push(next);
+            }
+        }
     }
 
-    Ok(result)
+    if visited != count {
+        return Err(VozooError::GraphCycle);
+    }
+    Ok(level)
 }
 
 // ── Utility nodes for graph routing ────────────────────────────────
@@ -190,6 +782,7 @@ impl AudioNode for PassThrough {
     fn process(&mut self, _buffer: &mut AudioBuffer) {}
     fn reset(&mut self) {}
     fn name(&self) -> &str { "PassThrough" }
+    fn input_layout(&self) -> Option<ChannelLayout> { None }
 }
 
 /// Mix node: sums incoming signals (handled by graph routing).
@@ -200,11 +793,13 @@ impl AudioNode for MixNode {
     fn process(&mut self, _buffer: &mut AudioBuffer) {}
     fn reset(&mut self) {}
     fn name(&self) -> &str { "Mix" }
+    fn input_layout(&self) -> Option<ChannelLayout> { None }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::VecDeque;
     use crate::effects::gain::Gain;
 
     #[test]
@@ -297,6 +892,343 @@ mod tests {
         ];
 
         let result = AudioGraph::new(slots, edges, 0, 1);
-        assert!(result.is_err());
+        assert!(matches!(result, Err(VozooError::GraphCycle)));
+    }
+
+    #[test]
+    fn test_time_stretch_is_rejected() {
+        use crate::effects::time_stretch::{StretchMode, TimeStretch};
+
+        // Even at rate 1.0: the rate can move once the graph runs.
+        for rate in [1.0, 0.8] {
+            let slots: Vec<(u32, Box<dyn AudioNode>)> = vec![
+                (0, Box::new(PassThrough)),
+                (1, Box::new(TimeStretch::new(rate, StretchMode::Wsola))),
+            ];
+            let err = AudioGraph::new(slots, vec![(0, 1, 1.0)], 0, 1).err().expect("time_stretch in a graph");
+            assert!(err.to_string().contains("changes the length"), "{err}");
+        }
+
+        // Also inside a chain subgraph.
+        let json = r#"{
+            "name": "Outer",
+            "definitions": [{ "name": "Slow", "nodes": [{ "type": "time_stretch", "params": { "rate": 0.5 } }] }],
+            "nodes": [
+                { "id": 0, "type": "input" },
+                { "id": 1, "type": "subgraph", "params": { "chain": "Slow" } },
+                { "id": 2, "type": "output" }
+            ],
+            "edges": [{ "from": 0, "to": 1 }, { "from": 1, "to": 2 }]
+        }"#;
+        let result = crate::GraphDef::from_json(json).unwrap().build();
+        assert!(matches!(result, Err(VozooError::InvalidGraph(_))), "{:?}", result.err());
+    }
+
+    #[test]
+    fn test_stereo_branch_mixes_with_mono_dry() {
+        use crate::effects::hrtf::Hrtf;
+
+        // input(0) → hrtf(1) → mix(2) → output(3), plus input(0) → mix(2)
+        let slots: Vec<(u32, Box<dyn AudioNode>)> = vec![
+            (0, Box::new(PassThrough)),
+            (1, Box::new(Hrtf::new(90.0, 0.0, 1.0))),
+            (2, Box::new(MixNode)),
+            (3, Box::new(PassThrough)),
+        ];
+        let edges = vec![
+            (0, 1, 1.0),
+            (1, 2, 0.5),
+            (0, 2, 0.5),
+            (2, 3, 1.0),
+        ];
+
+        let mut graph = AudioGraph::new(slots, edges, 0, 3).unwrap();
+        assert_eq!(graph.output_layout(ChannelLayout::Mono), ChannelLayout::Stereo);
+
+        let mut buffer = AudioBuffer::new(vec![0.5; 256], 48000);
+        graph.process(&mut buffer);
+
+        assert_eq!(buffer.channels(), 2);
+        assert_eq!(buffer.frames(), 256);
+        let left: f32 = buffer.channel(0).map(|s| s * s).sum();
+        let right: f32 = buffer.channel(1).map(|s| s * s).sum();
+        assert!(right > left, "azimuth=90 should be louder on the right");
+    }
+
+    /// Delays its input by a fixed number of frames and reports it.
+    struct FixedDelay(VecDeque<f32>);
+
+    impl FixedDelay {
+        fn new(frames: usize) -> Self {
+            Self(std::iter::repeat_n(0.0, frames).collect())
+        }
+    }
+
+    impl AudioNode for FixedDelay {
+        fn latency_samples(&self) -> usize {
+            self.0.len()
+        }
+        fn process(&mut self, buffer: &mut AudioBuffer) {
+            for s in &mut buffer.samples {
+                self.0.push_back(*s);
+                *s = self.0.pop_front().unwrap_or(0.0);
+            }
+        }
+        fn reset(&mut self) {
+            self.0.iter_mut().for_each(|s| *s = 0.0);
+        }
+        fn name(&self) -> &str { "Fixed Delay" }
+    }
+
+    #[test]
+    fn test_parallel_paths_are_time_aligned() {
+        // input(0) → delay 5(1) → delay 7(2) → mix(4), input(0) → delay 3(3)
+        // → mix(4), plus a dry edge input(0) → mix(4): all arrive at 12.
+        let slots: Vec<(u32, Box<dyn AudioNode>)> = vec![
+            (0, Box::new(PassThrough)),
+            (1, Box::new(FixedDelay::new(5))),
+            (2, Box::new(FixedDelay::new(7))),
+            (3, Box::new(FixedDelay::new(3))),
+            (4, Box::new(MixNode)),
+            (5, Box::new(FixedDelay::new(2))),
+        ];
+        let edges = vec![
+            (0, 1, 1.0),
+            (1, 2, 1.0),
+            (2, 4, 1.0),
+            (0, 3, 1.0),
+            (3, 4, 1.0),
+            (0, 4, 1.0),
+            (4, 5, 1.0),
+        ];
+
+        let mut graph = AudioGraph::new(slots, edges, 0, 5).unwrap();
+        graph.prepare(48000, 16);
+        assert_eq!(graph.latency_samples(), 14);
+
+        let mut impulse = vec![0.0; 64];
+        impulse[1] = 1.0;
+        let mut buffer = AudioBuffer::new(impulse, 48000);
+        buffer.process_blocks(5, |block| graph.process(block));
+
+        let mut expected = vec![0.0; 64];
+        expected[15] = 3.0;
+        assert_eq!(buffer.samples, expected);
+    }
+
+    #[test]
+    fn test_paths_realign_when_a_latency_changes() {
+        use crate::effects::pitch_shift::PitchShift;
+
+        // input(0) → pitch shift(1) → output(2), plus input(0) → output(2).
+        let shift = PitchShift::new(0.0);
+        let semitones = shift.params().remove(0);
+        let slots: Vec<(u32, Box<dyn AudioNode>)> = vec![
+            (0, Box::new(PassThrough)),
+            (1, Box::new(shift)),
+            (2, Box::new(PassThrough)),
+        ];
+        let edges = vec![(0, 1, 0.5), (1, 2, 1.0), (0, 2, 0.5)];
+
+        let mut graph = AudioGraph::new(slots, edges, 0, 2).unwrap();
+        graph.prepare(48000, 256);
+        assert_eq!(graph.latency_samples(), 0);
+        assert_eq!(graph.max_latency_samples(), 2048);
+        let lines = |graph: &AudioGraph| -> Vec<(*const f32, usize)> {
+            let edges = graph.slots.iter().flat_map(|slot| &slot.incoming);
+            edges.map(|e| (e.delay.buffer.as_ptr(), e.delay.buffer.capacity())).collect()
+        };
+        let reserved = lines(&graph);
+
+        semitones.set(3.0);
+        let mut buffer = AudioBuffer::new(vec![0.5; 256], 48000);
+        graph.process(&mut buffer);
+        assert_eq!(graph.latency_samples(), 2048);
+        // The dry edge is now delayed too, so nothing has come out yet.
+        assert!(buffer.samples.iter().all(|&s| s == 0.0));
+        // Prepare reserved for this, so the re-plan didn't reallocate.
+        assert_eq!(lines(&graph), reserved);
+    }
+
+    #[test]
+    fn test_delay_change_keeps_history() {
+        let mut line = DelayLine::default();
+        line.reserve(4);
+        line.set_frames(2);
+        let out: Vec<f32> = (1..=5).map(|i| line.push(&[i as f32])[0]).collect();
+        assert_eq!(out, [0.0, 0.0, 1.0, 2.0, 3.0]);
+        // Lengthening reads further back into what was already pushed.
+        line.set_frames(4);
+        assert_eq!(line.push(&[6.0]), [2.0]);
+        line.set_frames(0);
+        assert_eq!(line.push(&[7.0]), [7.0]);
+        line.set_frames(1);
+        assert_eq!(line.push(&[8.0]), [7.0]);
+    }
+
+    /// Multiplies its input by the "key" input (or passes it if nothing is
+    /// connected) and copies the raw input to a "dry" output.
+    struct KeyedGain;
+
+    impl AudioNode for KeyedGain {
+        fn process(&mut self, _buffer: &mut AudioBuffer) {}
+
+        fn input_ports(&self) -> &[&'static str] {
+            &["in", "key"]
+        }
+
+        fn output_ports(&self) -> &[&'static str] {
+            &["out", "dry"]
+        }
+
+        fn process_ports(&mut self, buffer: &mut AudioBuffer, inputs: &[AudioBuffer], outputs: &mut [AudioBuffer]) {
+            outputs[0].copy_from(buffer);
+            if !inputs[0].is_empty() {
+                for (s, k) in buffer.samples.iter_mut().zip(&inputs[0].samples) {
+                    *s *= k;
+                }
+            }
+        }
+
+        fn reset(&mut self) {}
+
+        fn name(&self) -> &str {
+            "Keyed Gain"
+        }
+    }
+
+    #[test]
+    fn test_edges_address_named_ports() {
+        // input(0) → keyed(2).in, and gain(1) and input(0) both into
+        // keyed(2).key; keyed.out and keyed.dry are mixed at output(3).
+        let slots: Vec<(u32, Box<dyn AudioNode>)> = vec![
+            (0, Box::new(PassThrough)),
+            (1, Box::new(Gain::new(2.0))),
+            (2, Box::new(KeyedGain)),
+            (3, Box::new(PassThrough)),
+        ];
+        let edges = vec![
+            Edge::new(0, 1, 1.0),
+            Edge::new(0, 2, 1.0),
+            Edge::new(1, 2, 1.0).to_port("key"),
+            Edge::new(0, 2, 1.0).to_port("key"),
+            Edge::new(2, 3, 1.0),
+            Edge::new(2, 3, 10.0).from_port("dry"),
+        ];
+
+        let mut graph = AudioGraph::new(slots, edges, 0, 3).unwrap();
+        let mut buffer = AudioBuffer::new(vec![0.5, -0.25], 48000);
+        graph.process(&mut buffer);
+
+        // key = 2x + x = 3x, so out = 3x², plus 10x from the dry port.
+        let expected = [3.0 * 0.25 + 5.0, 3.0 * 0.0625 - 2.5];
+        for (out, want) in buffer.samples.iter().zip(expected) {
+            assert!((out - want).abs() < 1e-6, "{out} vs {want}");
+        }
+    }
+
+    #[test]
+    fn test_unconnected_port_is_empty() {
+        let slots: Vec<(u32, Box<dyn AudioNode>)> = vec![
+            (0, Box::new(PassThrough)),
+            (1, Box::new(KeyedGain)),
+            (2, Box::new(PassThrough)),
+        ];
+        let mut graph = AudioGraph::new(slots, vec![(0, 1, 1.0), (1, 2, 1.0)], 0, 2).unwrap();
+        let mut buffer = AudioBuffer::new(vec![0.5, -0.25], 48000);
+        graph.process(&mut buffer);
+        assert_eq!(buffer.samples, vec![0.5, -0.25]);
+    }
+
+    #[test]
+    fn test_unknown_port_is_an_error() {
+        let slots = || -> Vec<(u32, Box<dyn AudioNode>)> {
+            vec![(0, Box::new(PassThrough)), (1, Box::new(Gain::new(1.0)))]
+        };
+        let err = AudioGraph::new(slots(), vec![Edge::new(0, 1, 1.0).to_port("key")], 0, 1).err().unwrap();
+        assert!(err.to_string().contains("no input port 'key'"), "{err}");
+        let err = AudioGraph::new(slots(), vec![Edge::new(0, 1, 1.0).from_port("left")], 0, 1).err().unwrap();
+        assert!(err.to_string().contains("no output port 'left'"), "{err}");
+    }
+
+    #[test]
+    fn test_chain_reuses_pool_buffers() {
+        // input → six gains → output: each buffer is free again once the
+        // next node has read it.
+        let mut slots: Vec<(u32, Box<dyn AudioNode>)> = vec![(0, Box::new(PassThrough))];
+        slots.extend((1..=6).map(|id| (id, Box::new(Gain::new(2.0)) as Box<dyn AudioNode>)));
+        slots.push((7, Box::new(PassThrough)));
+        let edges: Vec<(u32, u32, f32)> = (0..7).map(|id| (id, id + 1, 1.0)).collect();
+
+        let mut graph = AudioGraph::new(slots, edges, 0, 7).unwrap();
+        assert_eq!(graph.levels.len(), 8);
+        assert_eq!(graph.pool.len(), 2);
+
+        let mut buffer = AudioBuffer::new(vec![0.5, -0.25], 48000);
+        graph.process(&mut buffer);
+        assert_eq!(buffer.samples, vec![32.0, -16.0]);
+    }
+
+    #[test]
+    fn test_parallel_matches_serial() {
+        use crate::effects::chorus::Chorus;
+        use crate::effects::pitch_shift::PitchShift;
+        use crate::effects::ring_mod::RingMod;
+
+        // input → four independent branches → mix → output.
+        let build = || {
+            let slots: Vec<(u32, Box<dyn AudioNode>)> = vec![
+                (0, Box::new(PassThrough)),
+                (1, Box::new(PitchShift::new(4.0))),
+                (2, Box::new(Chorus::new(20.0, 5.0, 1.5, 0.5))),
+                (3, Box::new(RingMod::new(60.0, 0.0))),
+                (4, Box::new(Gain::new(0.5))),
+                (5, Box::new(MixNode)),
+                (6, Box::new(PassThrough)),
+            ];
+            let mut edges: Vec<(u32, u32, f32)> = (1..=4).flat_map(|id| [(0, id, 1.0), (id, 5, 0.25)]).collect();
+            edges.push((5, 6, 1.0));
+            let mut graph = AudioGraph::new(slots, edges, 0, 6).unwrap();
+            graph.prepare(48000, 256);
+            graph
+        };
+        let mut serial = build();
+        let mut parallel = build();
+        let workers = WorkerPool::new(2);
+        assert_eq!(serial.levels.iter().map(|level| level.len()).collect::<Vec<_>>(), [1, 4, 1, 1]);
+
+        let input: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.03).sin() * 0.5).collect();
+        let mut a = AudioBuffer::new(input.clone(), 48000);
+        let mut b = AudioBuffer::new(input, 48000);
+        a.process_blocks(256, |block| serial.process(block));
+        b.process_blocks(256, |block| parallel.process_parallel(block, &workers));
+        assert_eq!(a.samples, b.samples);
+    }
+
+    #[test]
+    fn test_one_frame_feedback_is_a_recursive_filter() {
+        // mix(1) = input + 0.5 × mix one frame ago: y[n] = x[n] + 0.5 y[n - 1].
+        let slots: Vec<(u32, Box<dyn AudioNode>)> = vec![
+            (0, Box::new(PassThrough)),
+            (1, Box::new(MixNode)),
+            (2, Box::new(PassThrough)),
+        ];
+        let edges = vec![Edge::new(0, 1, 1.0), Edge::new(1, 1, 0.5).delayed(1), Edge::new(1, 2, 1.0)];
+        let mut graph = AudioGraph::new(slots, edges, 0, 2).unwrap();
+        graph.prepare(48000, 64);
+        assert_eq!(graph.latency_samples(), 0);
+
+        let mut buffer = AudioBuffer::new(vec![1.0; 64], 48000);
+        graph.process_parallel(&mut buffer, &WorkerPool::new(1));
+        let mut y = 0.0f32;
+        for (n, &out) in buffer.samples.iter().enumerate() {
+            y = 1.0 + 0.5 * y;
+            assert!((out - y).abs() < 1e-6, "frame {n}: {out} vs {y}");
+        }
+
+        graph.reset();
+        let mut buffer = AudioBuffer::new(vec![1.0], 48000);
+        graph.process(&mut buffer);
+        assert_eq!(buffer.samples, vec![1.0]);
     }
 }
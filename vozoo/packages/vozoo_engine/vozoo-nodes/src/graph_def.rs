@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
+use vozoo_core::VozooError;
 
-use crate::chain_def::build_node_public;
-use crate::graph::{AudioGraph, MixNode, PassThrough};
+use crate::chain_def::{build_node_public, json_error};
+use crate::graph::{AudioGraph, Edge, MixNode, PassThrough};
+use crate::subgraph::{build_subgraph, MacroDef, Resolver, SubgraphDef};
+use crate::validate::Diagnostic;
 
 /// JSON-serializable graph definition.
 ///
@@ -22,11 +25,42 @@ use crate::graph::{AudioGraph, MixNode, PassThrough};
 ///   ]
 /// }
 /// ```
+///
+/// Edges connect main ports unless they name others with `from_port` or
+/// `to_port`, e.g. to key a compressor from another node:
+///
+/// ```json
+/// { "from": 1, "to": 2, "to_port": "sidechain" }
+/// ```
+///
+/// An edge with a `delay` (in frames at the processing rate) feeds back what
+/// its source produced that long ago, so it may close a cycle, e.g. an echo
+/// whose repeats run through a filter:
+///
+/// ```json
+/// { "from": 3, "to": 1, "gain": 0.6, "delay": 12000 }
+/// ```
+///
+/// A `subgraph` node runs another graph or chain, named or inline. Names
+/// are looked up in `definitions`, then among the built-in graphs, chain
+/// building blocks and presets. Its other params set the macros the
+/// definition exposes, which stay live parameters of the node:
+///
+/// ```json
+/// { "id": 1, "type": "subgraph", "params": { "chain": "Voice Cleanup", "target_rms": 0.3 } }
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GraphDef {
     pub name: String,
     pub nodes: Vec<GraphNodeDef>,
     pub edges: Vec<GraphEdgeDef>,
+    /// Parameters a `subgraph` node running this graph exposes; targets
+    /// name nodes by ID.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub macros: Vec<MacroDef>,
+    /// Graphs and chains this graph's `subgraph` nodes can name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub definitions: Vec<SubgraphDef>,
 }
 
 /// A node in the graph definition.
@@ -48,9 +82,25 @@ pub struct GraphNodeDef {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GraphEdgeDef {
     pub from: u32,
+    /// Output port of `from`; the main output if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_port: Option<String>,
     pub to: u32,
+    /// Input port of `to`; the main input if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_port: Option<String>,
     #[serde(default = "default_gain")]
     pub gain: f32,
+    /// Feedback delay in frames; an edge with one may close a cycle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay: Option<usize>,
+}
+
+impl GraphEdgeDef {
+    /// Edge between the main ports of two nodes.
+    pub fn new(from: u32, to: u32, gain: f32) -> Self {
+        Self { from, from_port: None, to, to_port: None, gain, delay: None }
+    }
 }
 
 fn default_gain() -> f32 {
@@ -58,14 +108,33 @@ fn default_gain() -> f32 {
 }
 
 impl GraphDef {
-    /// Build an executable AudioGraph from this definition.
-    pub fn build(&self) -> Result<AudioGraph, String> {
+    /// Build an executable AudioGraph from this definition, with its
+    /// `subgraph` nodes nested inside.
+    pub fn build(&self) -> Result<AudioGraph, VozooError> {
+        self.build_with(&mut Resolver::default())
+    }
+
+    pub(crate) fn build_with(&self, resolver: &mut Resolver) -> Result<AudioGraph, VozooError> {
+        resolver.enter(&self.definitions);
+        let graph = self.build_nodes(resolver);
+        resolver.leave();
+        graph
+    }
+
+    fn build_nodes(&self, resolver: &mut Resolver) -> Result<AudioGraph, VozooError> {
         let mut slots: Vec<(u32, Box<dyn vozoo_core::AudioNode>)> = Vec::new();
 
-        for node_def in &self.nodes {
+        for (index, node_def) in self.nodes.iter().enumerate() {
             let node: Box<dyn vozoo_core::AudioNode> = match node_def.node_type.as_str() {
                 "input" | "output" | "passthrough" => Box::new(PassThrough),
                 "mix" => Box::new(MixNode),
+                "subgraph" => Box::new(build_subgraph(&node_def.params, resolver).map_err(|err| {
+                    let reason = match err {
+                        VozooError::InvalidGraph(reason) => reason,
+                        other => other.to_string(),
+                    };
+                    VozooError::InvalidGraph(format!("subgraph node {}: {reason}", node_def.id))
+                })?),
                 _ => {
                     // Delegate to existing node factory.
                     let chain_node_def = crate::chain_def::NodeDef {
@@ -73,16 +142,26 @@ impl GraphDef {
                         params: node_def.params.clone(),
                     };
                     build_node_public(&chain_node_def)
-                        .ok_or_else(|| format!("Unknown node type: '{}'", node_def.node_type))?
+                        .ok_or_else(|| VozooError::UnknownNode {
+                            index,
+                            node_type: node_def.node_type.clone(),
+                        })?
                 }
             };
             slots.push((node_def.id, node));
         }
 
-        let edges: Vec<(u32, u32, f32)> = self
+        let edges: Vec<Edge> = self
             .edges
             .iter()
-            .map(|e| (e.from, e.to, e.gain))
+            .map(|e| Edge {
+                from: e.from,
+                from_port: e.from_port.clone(),
+                to: e.to,
+                to_port: e.to_port.clone(),
+                gain: e.gain,
+                delay: e.delay,
+            })
             .collect();
 
         // Find input and output nodes.
@@ -91,24 +170,30 @@ impl GraphDef {
             .iter()
             .find(|n| n.node_type == "input")
             .map(|n| n.id)
-            .ok_or("Graph must have an 'input' node")?;
+            .ok_or_else(|| VozooError::InvalidGraph("it has no 'input' node".into()))?;
 
         let output_id = self
             .nodes
             .iter()
             .find(|n| n.node_type == "output")
             .map(|n| n.id)
-            .ok_or("Graph must have an 'output' node")?;
+            .ok_or_else(|| VozooError::InvalidGraph("it has no 'output' node".into()))?;
 
         AudioGraph::new(slots, edges, input_id, output_id)
     }
 
+    /// Check the definition for mistakes, from ones that stop it building
+    /// to nodes that will never be heard. Empty if it's clean.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        crate::validate::validate_graph(self)
+    }
+
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).unwrap_or_default()
     }
 
-    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(json)
+    pub fn from_json(json: &str) -> Result<Self, VozooError> {
+        serde_json::from_str(json).map_err(json_error)
     }
 }
 
@@ -128,14 +213,16 @@ pub fn preset_graph_defs() -> Vec<GraphDef> {
                 GraphNodeDef { id: 6, node_type: "output".into(), params: serde_json::json!({}), x: 1000.0, y: 150.0 },
             ],
             edges: vec![
-                GraphEdgeDef { from: 0, to: 1, gain: 1.0 },
-                GraphEdgeDef { from: 1, to: 2, gain: 1.0 },    // dry → compressor
-                GraphEdgeDef { from: 2, to: 3, gain: 0.6 },    // compressed (wet)
-                GraphEdgeDef { from: 1, to: 3, gain: 0.4 },    // dry bypass
-                GraphEdgeDef { from: 3, to: 4, gain: 1.0 },
-                GraphEdgeDef { from: 4, to: 5, gain: 1.0 },
-                GraphEdgeDef { from: 5, to: 6, gain: 1.0 },
+                GraphEdgeDef::new(0, 1, 1.0),
+                GraphEdgeDef::new(1, 2, 1.0),    // dry → compressor
+                GraphEdgeDef::new(2, 3, 0.6),    // compressed (wet)
+                GraphEdgeDef::new(1, 3, 0.4),    // dry bypass
+                GraphEdgeDef::new(3, 4, 1.0),
+                GraphEdgeDef::new(4, 5, 1.0),
+                GraphEdgeDef::new(5, 6, 1.0),
             ],
+            macros: Vec::new(),
+            definitions: Vec::new(),
         },
         // Dual Character: pitch shift + chorus blended
         GraphDef {
@@ -152,16 +239,43 @@ pub fn preset_graph_defs() -> Vec<GraphDef> {
                 GraphNodeDef { id: 8, node_type: "output".into(), params: serde_json::json!({}), x: 1150.0, y: 150.0 },
             ],
             edges: vec![
-                GraphEdgeDef { from: 0, to: 1, gain: 1.0 },
-                GraphEdgeDef { from: 1, to: 2, gain: 1.0 },
-                GraphEdgeDef { from: 2, to: 3, gain: 1.0 },    // → pitch shift
-                GraphEdgeDef { from: 2, to: 4, gain: 1.0 },    // → chorus
-                GraphEdgeDef { from: 3, to: 5, gain: 0.6 },    // pitch shift → mix
-                GraphEdgeDef { from: 4, to: 5, gain: 0.4 },    // chorus → mix
-                GraphEdgeDef { from: 5, to: 6, gain: 1.0 },
-                GraphEdgeDef { from: 6, to: 7, gain: 1.0 },
-                GraphEdgeDef { from: 7, to: 8, gain: 1.0 },
+                GraphEdgeDef::new(0, 1, 1.0),
+                GraphEdgeDef::new(1, 2, 1.0),
+                GraphEdgeDef::new(2, 3, 1.0),    // → pitch shift
+                GraphEdgeDef::new(2, 4, 1.0),    // → chorus
+                GraphEdgeDef::new(3, 5, 0.6),    // pitch shift → mix
+                GraphEdgeDef::new(4, 5, 0.4),    // chorus → mix
+                GraphEdgeDef::new(5, 6, 1.0),
+                GraphEdgeDef::new(6, 7, 1.0),
+                GraphEdgeDef::new(7, 8, 1.0),
             ],
+            macros: Vec::new(),
+            definitions: Vec::new(),
+        },
+        // Canyon Echo: repeats fed back through a low-pass, each darker
+        // and quieter than the last
+        GraphDef {
+            name: "Canyon Echo".into(),
+            nodes: vec![
+                GraphNodeDef { id: 0, node_type: "input".into(), params: serde_json::json!({}), x: 0.0, y: 150.0 },
+                GraphNodeDef { id: 1, node_type: "dc_blocker".into(), params: serde_json::json!({}), x: 150.0, y: 150.0 },
+                GraphNodeDef { id: 2, node_type: "mix".into(), params: serde_json::json!({}), x: 350.0, y: 150.0 },
+                GraphNodeDef { id: 3, node_type: "lowpass".into(), params: serde_json::json!({"freq": 2500.0}), x: 350.0, y: 300.0 },
+                GraphNodeDef { id: 4, node_type: "gain".into(), params: serde_json::json!({"factor": 0.45}), x: 200.0, y: 300.0 },
+                GraphNodeDef { id: 5, node_type: "lookahead_limiter".into(), params: serde_json::json!({"ceiling_db": -1.0}), x: 550.0, y: 150.0 },
+                GraphNodeDef { id: 6, node_type: "output".into(), params: serde_json::json!({}), x: 700.0, y: 150.0 },
+            ],
+            edges: vec![
+                GraphEdgeDef::new(0, 1, 1.0),
+                GraphEdgeDef::new(1, 2, 1.0),
+                GraphEdgeDef::new(2, 3, 1.0),
+                GraphEdgeDef::new(3, 4, 1.0),
+                GraphEdgeDef { delay: Some(12000), ..GraphEdgeDef::new(4, 2, 1.0) },    // 250 ms feedback
+                GraphEdgeDef::new(2, 5, 1.0),
+                GraphEdgeDef::new(5, 6, 1.0),
+            ],
+            macros: Vec::new(),
+            definitions: Vec::new(),
         },
     ]
 }
@@ -237,4 +351,134 @@ mod tests {
         graph.process(&mut buffer);
         assert!((buffer.samples[0] - 1.5).abs() < 1e-6);
     }
+
+    #[test]
+    fn test_dry_path_lines_up_with_limited_path() {
+        use vozoo_core::AudioBuffer;
+
+        // A quiet signal passes the limiter unchanged; without compensation
+        // the two paths would comb filter.
+        let json = r#"{
+            "name": "Test Alignment",
+            "nodes": [
+                { "id": 0, "type": "input" },
+                { "id": 1, "type": "lookahead_limiter", "params": { "ceiling_db": 0.0 } },
+                { "id": 2, "type": "mix" },
+                { "id": 3, "type": "output" }
+            ],
+            "edges": [
+                { "from": 0, "to": 1, "gain": 0.5 },
+                { "from": 1, "to": 2, "gain": 1.0 },
+                { "from": 0, "to": 2, "gain": 0.5 },
+                { "from": 2, "to": 3, "gain": 1.0 }
+            ]
+        }"#;
+        let mut graph = GraphDef::from_json(json).unwrap().build().unwrap();
+        graph.prepare(48000, 512);
+        let latency = graph.latency_samples();
+        assert_eq!(latency, 240 + 7);
+
+        let input: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.05).sin() * 0.3).collect();
+        let mut buffer = AudioBuffer::new(input.clone(), 48000);
+        buffer.process_blocks(512, |block| graph.process(block));
+        for (i, (&out, &x)) in buffer.samples[latency..].iter().zip(&input).enumerate() {
+            assert!((out - x).abs() < 1e-6, "sample {i}: {out} vs {x}");
+        }
+    }
+
+    #[test]
+    fn test_sidechain_edge_keys_compressor() {
+        use vozoo_core::AudioBuffer;
+
+        // The input sits below the threshold, but a boosted copy on the
+        // sidechain pushes the compressor into gain reduction.
+        let json = r#"{
+            "name": "Test Sidechain",
+            "nodes": [
+                { "id": 0, "type": "input" },
+                { "id": 1, "type": "gain", "params": { "factor": 8.0 } },
+                { "id": 2, "type": "compressor", "params": { "threshold_db": -20, "ratio": 10, "knee_db": 0 } },
+                { "id": 3, "type": "output" }
+            ],
+            "edges": [
+                { "from": 0, "to": 1 },
+                { "from": 0, "to": 2 },
+                { "from": 1, "to": 2, "to_port": "sidechain" },
+                { "from": 2, "to": 3 }
+            ]
+        }"#;
+        let def = GraphDef::from_json(json).unwrap();
+        assert_eq!(def.edges[2].to_port.as_deref(), Some("sidechain"));
+        assert!(def.to_json().contains(r#""to_port":"sidechain""#));
+        assert!(!def.to_json().contains("from_port"));
+
+        let mut graph = def.build().unwrap();
+        let input = vec![0.05f32; 4800];
+        let mut buffer = AudioBuffer::new(input, 48000);
+        buffer.process_blocks(512, |block| graph.process(block));
+        let last = *buffer.samples.last().unwrap();
+        assert!(last < 0.03, "sidechain should duck the input, got {last}");
+    }
+
+    #[test]
+    fn test_unknown_port_is_rejected() {
+        let json = r#"{
+            "name": "Bad Port",
+            "nodes": [
+                { "id": 0, "type": "input" },
+                { "id": 1, "type": "gain" },
+                { "id": 2, "type": "output" }
+            ],
+            "edges": [
+                { "from": 0, "to": 1, "to_port": "sidechain" },
+                { "from": 1, "to": 2 }
+            ]
+        }"#;
+        let err = GraphDef::from_json(json).unwrap().build().err().expect("gain has no sidechain");
+        assert!(err.to_string().contains("no input port 'sidechain'"), "{err}");
+    }
+
+    #[test]
+    fn test_feedback_echo_graph() {
+        use vozoo_core::AudioBuffer;
+
+        // input → mix → output, with the mix fed back through a gain 100
+        // frames later: an impulse repeats every 100 frames at half level.
+        let json = r#"{
+            "name": "Test Echo",
+            "nodes": [
+                { "id": 0, "type": "input" },
+                { "id": 1, "type": "mix" },
+                { "id": 2, "type": "gain", "params": { "factor": 0.5 } },
+                { "id": 3, "type": "output" }
+            ],
+            "edges": [
+                { "from": 0, "to": 1 },
+                { "from": 1, "to": 2 },
+                { "from": 2, "to": 1, "delay": 100 },
+                { "from": 1, "to": 3 }
+            ]
+        }"#;
+        let def = GraphDef::from_json(json).unwrap();
+        assert!(def.to_json().contains(r#""delay":100"#));
+
+        let mut impulse = vec![0.0; 400];
+        impulse[0] = 1.0;
+        let mut expected = vec![0.0; 400];
+        for (k, level) in [1.0, 0.5, 0.25, 0.125].into_iter().enumerate() {
+            expected[k * 100] = level;
+        }
+        for block in [37, 256, 1000] {
+            let mut graph = def.build().unwrap();
+            graph.prepare(48000, block);
+            let mut buffer = AudioBuffer::new(impulse.clone(), 48000);
+            buffer.process_blocks(block, |b| graph.process(b));
+            assert_eq!(buffer.samples, expected, "block size {block}");
+        }
+
+        // Without the delay the loop can't be scheduled.
+        let cyclic = json.replace(r#", "delay": 100"#, "");
+        let err = GraphDef::from_json(&cyclic).unwrap().build().err().expect("cycle");
+        assert!(matches!(err, VozooError::GraphCycle));
+    }
 }
@@ -3,86 +3,146 @@ pub mod chain_def;
 pub mod effects;
 pub mod graph;
 pub mod graph_def;
+pub mod subgraph;
+pub mod validate;
+pub mod workers;
 mod presets;
 
 #[cfg(test)]
 mod tests;
 
+use std::io::ErrorKind;
 use std::os::raw::c_int;
-use vozoo_core::{read_wav, write_wav};
+use vozoo_codec::{AudioFile, AudioFormat};
+use vozoo_core::{resample, AudioBuffer, VozooError, PROCESSING_SAMPLE_RATE};
 
-pub use chain_def::{available_nodes, preset_chain_defs, ChainDef, NodeDef};
-pub use graph::AudioGraph;
+pub use chain_def::{available_nodes, building_block_defs, preset_chain_defs, ChainDef, NodeDef};
+pub use graph::{AudioGraph, Edge};
 pub use graph_def::{preset_graph_defs, GraphDef, GraphEdgeDef, GraphNodeDef};
+pub use subgraph::{MacroDef, MacroTarget, SubgraphDef};
+pub use validate::{validate_json, Diagnostic, Severity};
+pub use workers::WorkerPool;
 pub use presets::build_preset_chain;
 
-/// Process a WAV file with the given preset ID.
-/// Returns 0 on success, -1 on read error, -2 on write error.
-pub fn process_file(input_path: &str, output_path: &str, preset_id: c_int) -> c_int {
-    let mut buffer = match read_wav(input_path) {
-        Ok(b) => b,
-        Err(_) => return -1,
-    };
-
-    let mut chain = build_preset_chain(preset_id);
-    chain.process(&mut buffer);
+/// Block size used when streaming a whole file through a chain or graph.
+pub const FILE_BLOCK_SIZE: usize = 1024;
 
-    match write_wav(output_path, &buffer) {
-        Ok(()) => 0,
-        Err(_) => -2,
-    }
+/// What to run a file through.
+#[derive(Debug, Clone, Copy)]
+pub enum FileEffect<'a> {
+    Preset(c_int),
+    /// Chain definition JSON.
+    Chain(&'a str),
+    /// Graph definition JSON.
+    Graph(&'a str),
 }
 
-/// Process a WAV file with a JSON graph definition (DAG routing).
-/// Returns 0 on success, -1 on read error, -2 on write error, -3 on invalid JSON.
-pub fn process_file_with_graph(input_path: &str, output_path: &str, graph_json: &str) -> c_int {
-    let graph_def = match GraphDef::from_json(graph_json) {
-        Ok(d) => d,
-        Err(_) => return -3,
-    };
+/// Read an audio file converted to the processing rate, along with the rate
+/// the file was stored at.
+fn read_for_processing(path: &str) -> Result<(AudioBuffer, u32), VozooError> {
+    let buffer = AudioFile::open(path)
+        .map_err(|source| VozooError::Read { path: path.to_string(), source })?
+        .buffer;
+    let file_rate = buffer.sample_rate();
+    Ok((resample(&buffer, PROCESSING_SAMPLE_RATE), file_rate))
+}
 
-    let mut buffer = match read_wav(input_path) {
-        Ok(b) => b,
-        Err(_) => return -1,
+/// Write processed audio back at the input file's rate, unless a `resample`
+/// node chose another rate.
+fn write_processed(path: &str, format: AudioFormat, buffer: AudioBuffer, file_rate: u32) -> Result<(), VozooError> {
+    let buffer = if buffer.sample_rate() == PROCESSING_SAMPLE_RATE && file_rate != PROCESSING_SAMPLE_RATE {
+        resample(&buffer, file_rate)
+    } else {
+        buffer
     };
+    AudioFile { buffer, format }.save_as(path, format).map_err(|source| match source.kind() {
+        ErrorKind::Unsupported => VozooError::UnsupportedFormat(source.to_string()),
+        _ => VozooError::Write { path: path.to_string(), source },
+    })
+}
 
-    let mut graph = match graph_def.build() {
-        Ok(g) => g,
-        Err(_) => return -3,
-    };
-    graph.process(&mut buffer);
+/// Streams one block of a file through an effect.
+type BlockFn = Box<dyn FnMut(&mut AudioBuffer)>;
 
-    match write_wav(output_path, &buffer) {
-        Ok(()) => 0,
-        Err(_) => -2,
-    }
+/// A prepared chain's output latency, and its output frames per input
+/// frame at the processing rate.
+fn chain_timing(chain: &chain::LinearChain) -> (usize, f64) {
+    let output_rate = chain.output_sample_rate(PROCESSING_SAMPLE_RATE);
+    let ratio = chain.time_scale() * output_rate as f64 / PROCESSING_SAMPLE_RATE as f64;
+    (chain.latency_samples(PROCESSING_SAMPLE_RATE), ratio)
 }
 
-/// Process a WAV file with a JSON chain definition.
-/// Returns 0 on success, -1 on read error, -2 on write error, -3 on invalid JSON.
-pub fn process_file_with_chain(input_path: &str, output_path: &str, chain_json: &str) -> c_int {
-    let mut chain_def = match ChainDef::from_json(chain_json) {
-        Ok(d) => d,
-        Err(_) => return -3,
+/// Process an audio file in any readable format and write the result as
+/// `format`, or in the format the output path's extension names.
+///
+/// The effect is built before the input is read, so a bad definition fails
+/// without touching either file.
+pub fn process_file_as(
+    input_path: &str,
+    output_path: &str,
+    effect: FileEffect<'_>,
+    format: Option<AudioFormat>,
+) -> Result<(), VozooError> {
+    let format = format.or_else(|| AudioFormat::from_path(output_path)).ok_or_else(|| {
+        VozooError::UnsupportedFormat(format!("can't tell the format of '{output_path}' from its extension"))
+    })?;
+
+    // Along with the effect: the frames its output lags by, and the output
+    // frames it makes per input frame.
+    let (mut process, latency, output_ratio): (BlockFn, usize, f64) = match effect {
+        FileEffect::Preset(id) => {
+            let mut chain = build_preset_chain(id);
+            chain.prepare(PROCESSING_SAMPLE_RATE, FILE_BLOCK_SIZE);
+            let (latency, output_ratio) = chain_timing(&chain);
+            (Box::new(move |block| chain.process(block)), latency, output_ratio)
+        }
+        FileEffect::Chain(json) => {
+            let mut chain_def = ChainDef::from_json(json)?;
+            // Kid-safety: always finish with a limiter so no chain can clip or blast
+            // (docs/SIMPLE_VOICE_SPEC.md §6). No-op if one is already present.
+            chain_def.ensure_trailing_limiter();
+            let mut chain = chain_def.build()?;
+            chain.prepare(PROCESSING_SAMPLE_RATE, FILE_BLOCK_SIZE);
+            let (latency, output_ratio) = chain_timing(&chain);
+            (Box::new(move |block| chain.process(block)), latency, output_ratio)
+        }
+        FileEffect::Graph(json) => {
+            let mut graph = GraphDef::from_json(json)?.build()?;
+            graph.prepare(PROCESSING_SAMPLE_RATE, FILE_BLOCK_SIZE);
+            // Offline, parallel branches can run on every core. The pool lives
+            // as long as the file does, so its threads start once.
+            let workers = WorkerPool::for_available_cores();
+            let latency = graph.latency_samples();
+            (Box::new(move |block| graph.process_parallel(block, &workers)), latency, 1.0)
+        }
     };
 
-    // Kid-safety: always finish with a limiter so no chain can clip or blast
-    // (docs/SIMPLE_VOICE_SPEC.md §6). No-op if one is already present.
-    chain_def.ensure_trailing_limiter();
+    let (mut buffer, file_rate) = read_for_processing(input_path)?;
+    // Lookahead (every chain ends in a limiter) delays the output. Push
+    // enough silence through to flush the tail out, then drop the delay.
+    let frames = buffer.frames();
+    let flush = (latency as f64 / output_ratio).ceil() as usize;
+    buffer.samples.resize((frames + flush) * buffer.channels() as usize, 0.0);
+    buffer.process_blocks(FILE_BLOCK_SIZE, |block| process(block));
+    let channels = buffer.channels() as usize;
+    let end = (latency + (frames as f64 * output_ratio).round() as usize) * channels;
+    buffer.samples.truncate(end);
+    buffer.samples.drain(..(latency * channels).min(buffer.samples.len()));
+    write_processed(output_path, format, buffer, file_rate)
+}
 
-    let mut buffer = match read_wav(input_path) {
-        Ok(b) => b,
-        Err(_) => return -1,
-    };
+/// Process an audio file with the given preset ID; the output format
+/// follows the output path's extension.
+pub fn process_file(input_path: &str, output_path: &str, preset_id: c_int) -> Result<(), VozooError> {
+    process_file_as(input_path, output_path, FileEffect::Preset(preset_id), None)
+}
 
-    let mut chain = match chain_def.build() {
-        Ok(c) => c,
-        Err(_) => return -3,
-    };
-    chain.process(&mut buffer);
+/// Process an audio file with a JSON graph definition (DAG routing).
+pub fn process_file_with_graph(input_path: &str, output_path: &str, graph_json: &str) -> Result<(), VozooError> {
+    process_file_as(input_path, output_path, FileEffect::Graph(graph_json), None)
+}
 
-    match write_wav(output_path, &buffer) {
-        Ok(()) => 0,
-        Err(_) => -2,
-    }
+/// Process an audio file with a JSON chain definition.
+pub fn process_file_with_chain(input_path: &str, output_path: &str, chain_json: &str) -> Result<(), VozooError> {
+    process_file_as(input_path, output_path, FileEffect::Chain(chain_json), None)
 }
@@ -0,0 +1,485 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use vozoo_core::{
+    AudioBuffer, AudioNode, ChannelLayout, MeterTap, ParamDescriptor, ParamHandle, SmoothedParam, VozooError,
+    PROCESSING_SAMPLE_RATE,
+};
+
+use crate::chain::LinearChain;
+use crate::chain_def::{available_nodes, building_block_defs, preset_chain_defs, ChainDef};
+use crate::graph::AudioGraph;
+use crate::graph_def::{preset_graph_defs, GraphDef};
+
+/// A parameter a reusable definition exposes to the `subgraph` nodes that
+/// run it, fanned out to one or more inner parameters.
+///
+/// ```json
+/// { "key": "drive", "name": "Drive", "targets": [{ "node": 1, "param": "factor" }] }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacroDef {
+    pub key: String,
+    #[serde(default)]
+    pub name: String,
+    pub targets: Vec<MacroTarget>,
+}
+
+/// An inner parameter driven by a macro: a node ID in a graph, a node
+/// index in a chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacroTarget {
+    pub node: u32,
+    pub param: String,
+}
+
+/// A graph or chain definition a graph declares for its `subgraph` nodes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SubgraphDef {
+    Graph(GraphDef),
+    Chain(ChainDef),
+}
+
+impl SubgraphDef {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Graph(def) => &def.name,
+            Self::Chain(def) => &def.name,
+        }
+    }
+
+    pub fn macros(&self) -> &[MacroDef] {
+        match self {
+            Self::Graph(def) => &def.macros,
+            Self::Chain(def) => &def.macros,
+        }
+    }
+
+    /// Type of the inner node a macro target points at, if it exists.
+    fn target_type(&self, node: u32) -> Option<&str> {
+        match self {
+            Self::Graph(def) => def.nodes.iter().find(|n| n.id == node).map(|n| n.node_type.as_str()),
+            Self::Chain(def) => def.nodes.get(node as usize).map(|n| n.node_type.as_str()),
+        }
+    }
+
+    fn target_params(&mut self, node: u32) -> Option<&mut serde_json::Value> {
+        match self {
+            Self::Graph(def) => def.nodes.iter_mut().find(|n| n.id == node).map(|n| &mut n.params),
+            Self::Chain(def) => def.nodes.get_mut(node as usize).map(|n| &mut n.params),
+        }
+    }
+}
+
+/// Finds the definitions `subgraph` nodes refer to by name while a graph
+/// builds, and catches definitions that end up containing themselves.
+///
+/// Names are looked up in the `definitions` of the enclosing graphs,
+/// innermost first, then among the built-in graphs, or for chains the
+/// built-in building blocks and presets.
+#[derive(Default)]
+pub(crate) struct Resolver {
+    scopes: Vec<Vec<SubgraphDef>>,
+    /// Named definitions being built, outermost first.
+    building: Vec<String>,
+}
+
+impl Resolver {
+    pub(crate) fn enter(&mut self, definitions: &[SubgraphDef]) {
+        self.scopes.push(definitions.to_vec());
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn lookup(&self, kind: &str, name: &str) -> Option<SubgraphDef> {
+        let declared = self
+            .scopes
+            .iter()
+            .rev()
+            .flatten()
+            .find(|def| def.name() == name && matches!((kind, def), ("graph", SubgraphDef::Graph(_)) | ("chain", SubgraphDef::Chain(_))))
+            .cloned();
+        declared.or_else(|| match kind {
+            "graph" => preset_graph_defs().into_iter().find(|def| def.name == name).map(SubgraphDef::Graph),
+            _ => building_block_defs()
+                .into_iter()
+                .chain(preset_chain_defs())
+                .find(|def| def.name == name)
+                .map(SubgraphDef::Chain),
+        })
+    }
+}
+
+/// Build the node for a `subgraph` node definition with `params` such as
+/// `{"graph": "Parallel Compression"}` or `{"chain": {...}, "target_rms": 0.3}`.
+pub(crate) fn build_subgraph(params: &serde_json::Value, resolver: &mut Resolver) -> Result<Subgraph, VozooError> {
+    let (kind, reference) = ["graph", "chain"]
+        .into_iter()
+        .find_map(|kind| params.get(kind).map(|r| (kind, r)))
+        .ok_or_else(|| VozooError::InvalidGraph("a subgraph needs a 'graph' or 'chain' to run".into()))?;
+
+    let (mut def, name) = match reference {
+        serde_json::Value::String(name) => {
+            let def = resolver
+                .lookup(kind, name)
+                .ok_or_else(|| VozooError::InvalidGraph(format!("no {kind} named '{name}'")))?;
+            if resolver.building.contains(name) {
+                let path: Vec<&str> = resolver.building.iter().map(String::as_str).chain([name.as_str()]).collect();
+                return Err(VozooError::InvalidGraph(format!("{kind} '{name}' contains itself ({})", path.join(" → "))));
+            }
+            (def, Some(name.clone()))
+        }
+        inline => {
+            let def = match kind {
+                "graph" => serde_json::from_value(inline.clone()).map(SubgraphDef::Graph),
+                _ => serde_json::from_value(inline.clone()).map(SubgraphDef::Chain),
+            };
+            (def.map_err(|e| VozooError::InvalidGraph(format!("inline {kind}: {e}")))?, None)
+        }
+    };
+
+    // Macro values set on the node replace the inner parameters they drive.
+    let macros = def.macros().to_vec();
+    for mac in &macros {
+        for target in &mac.targets {
+            let known = match def.target_type(target.node) {
+                Some("subgraph") => true,
+                Some(node_type) => available_nodes()
+                    .iter()
+                    .any(|n| n.node_type == node_type && n.params.iter().any(|p| p.key == target.param)),
+                None => false,
+            };
+            if !known {
+                return Err(VozooError::InvalidGraph(format!(
+                    "macro '{}' of '{}' targets node {} parameter '{}', which doesn't exist",
+                    mac.key,
+                    def.name(),
+                    target.node,
+                    target.param
+                )));
+            }
+        }
+    }
+    let values = params.as_object().into_iter().flatten().filter(|(key, _)| *key != kind);
+    for (key, value) in values {
+        let mac = macros
+            .iter()
+            .find(|m| m.key == *key)
+            .ok_or_else(|| VozooError::InvalidGraph(format!("'{}' has no macro '{key}'", def.name())))?;
+        for target in &mac.targets {
+            if let Some(params) = def.target_params(target.node) {
+                if !params.is_object() {
+                    *params = serde_json::json!({});
+                }
+                params[target.param.as_str()] = value.clone();
+            }
+        }
+    }
+
+    if let Some(name) = &name {
+        resolver.building.push(name.clone());
+    }
+    let inner = match &def {
+        SubgraphDef::Graph(graph) => graph.build_with(resolver).map(Inner::Graph),
+        SubgraphDef::Chain(chain) => chain.build().map(Inner::Chain),
+    };
+    if name.is_some() {
+        resolver.building.pop();
+    }
+    let inner = inner?;
+
+    let live = match &inner {
+        Inner::Graph(graph) => graph.params(),
+        Inner::Chain(chain) => chain.params(),
+    };
+    let macros = macros
+        .iter()
+        .enumerate()
+        .filter_map(|(id, mac)| {
+            let targets: Vec<ParamHandle> = mac
+                .targets
+                .iter()
+                .filter_map(|t| live.iter().find(|(node, p)| *node == t.node && p.descriptor.key == t.param))
+                .map(|(_, p)| p.clone())
+                .collect();
+            // Macros over parameters that can't change live only apply at build time.
+            let first = targets.first()?.descriptor;
+            let value = targets[0].get();
+            let name = if mac.name.is_empty() { &mac.key } else { &mac.name };
+            let descriptor = ParamDescriptor { id: id as u32, key: intern(&mac.key), name: intern(name), default: value, ..first };
+            Some(Macro { param: SmoothedParam::new(descriptor, value), applied: value, targets })
+        })
+        .collect();
+
+    Ok(Subgraph { name: def.name().to_string(), inner, macros, sample_rate: PROCESSING_SAMPLE_RATE })
+}
+
+/// Leak each distinct macro key or name once, for the `&'static str`s of
+/// a `ParamDescriptor`. Graphs are rebuilt with the same few names, so this
+/// stays small.
+fn intern(text: &str) -> &'static str {
+    static INTERNED: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+    let mut interned = INTERNED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(&existing) = interned.iter().find(|&&s| s == text) {
+        return existing;
+    }
+    let leaked: &'static str = Box::leak(text.to_string().into_boxed_str());
+    interned.push(leaked);
+    leaked
+}
+
+enum Inner {
+    Graph(AudioGraph),
+    Chain(LinearChain),
+}
+
+struct Macro {
+    param: SmoothedParam,
+    /// Value last passed on to the targets.
+    applied: f32,
+    targets: Vec<ParamHandle>,
+}
+
+/// A graph or chain running as one node of a graph, with its macros as the
+/// node's parameters. Built by `GraphDef::build` for `subgraph` nodes.
+pub struct Subgraph {
+    name: String,
+    inner: Inner,
+    macros: Vec<Macro>,
+    sample_rate: u32,
+}
+
+impl AudioNode for Subgraph {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        self.sample_rate = sample_rate;
+        for mac in &mut self.macros {
+            mac.param.prepare(sample_rate);
+        }
+        match &mut self.inner {
+            Inner::Graph(graph) => graph.prepare(sample_rate, max_block),
+            Inner::Chain(chain) => chain.prepare(sample_rate, max_block),
+        }
+    }
+
+    fn output_sample_rate(&self, input_rate: u32) -> u32 {
+        match &self.inner {
+            Inner::Graph(_) => input_rate,
+            Inner::Chain(chain) => chain.output_sample_rate(input_rate),
+        }
+    }
+
+    fn latency_samples(&self) -> usize {
+        match &self.inner {
+            Inner::Graph(graph) => graph.latency_samples(),
+            Inner::Chain(chain) => chain.latency_samples(self.sample_rate),
+        }
+    }
+
+    fn max_latency_samples(&self) -> usize {
+        match &self.inner {
+            Inner::Graph(graph) => graph.max_latency_samples(),
+            Inner::Chain(chain) => chain.max_latency_samples(self.sample_rate),
+        }
+    }
+
+    fn time_scale(&self) -> f64 {
+        match &self.inner {
+            Inner::Graph(_) => 1.0,
+            Inner::Chain(chain) => chain.time_scale(),
+        }
+    }
+
+    fn changes_length(&self) -> bool {
+        match &self.inner {
+            Inner::Graph(_) => false,
+            Inner::Chain(chain) => chain.changes_length(),
+        }
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer) {
+        // The targets glide on their own, so macros pass on targets only.
+        for mac in &mut self.macros {
+            let value = mac.param.target();
+            if value != mac.applied {
+                mac.applied = value;
+                mac.targets.iter().for_each(|t| t.set(value));
+            }
+        }
+        match &mut self.inner {
+            Inner::Graph(graph) => graph.process(buffer),
+            Inner::Chain(chain) => chain.process(buffer),
+        }
+    }
+
+    fn reset(&mut self) {
+        match &mut self.inner {
+            Inner::Graph(graph) => graph.reset(),
+            Inner::Chain(chain) => chain.reset(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn params(&self) -> Vec<ParamHandle> {
+        self.macros.iter().map(|m| m.param.handle()).collect()
+    }
+
+    fn meters(&self) -> Vec<Arc<MeterTap>> {
+        let meters = match &self.inner {
+            Inner::Graph(graph) => graph.meters(),
+            Inner::Chain(chain) => chain.meters(),
+        };
+        meters.into_iter().map(|(_, m)| m).collect()
+    }
+
+    fn input_layout(&self) -> Option<ChannelLayout> {
+        None
+    }
+
+    fn output_layout(&self, input: ChannelLayout) -> ChannelLayout {
+        match &self.inner {
+            Inner::Graph(graph) => graph.output_layout(input),
+            Inner::Chain(chain) => chain.output_layout(input),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(json: &str) -> Result<AudioGraph, VozooError> {
+        GraphDef::from_json(json).unwrap().build()
+    }
+
+    #[test]
+    fn test_named_building_block_exposes_macro() {
+        let json = r#"{
+            "name": "Cleaned",
+            "nodes": [
+                { "id": 0, "type": "input" },
+                { "id": 1, "type": "subgraph", "params": { "chain": "Voice Cleanup", "target_rms": 0.3 } },
+                { "id": 2, "type": "output" }
+            ],
+            "edges": [{ "from": 0, "to": 1 }, { "from": 1, "to": 2 }]
+        }"#;
+        let mut graph = build(json).unwrap();
+        let params = graph.params();
+        assert_eq!(params.len(), 1);
+        let (node, handle) = &params[0];
+        assert_eq!((*node, handle.descriptor.key, handle.descriptor.name), (1, "target_rms", "Target RMS"));
+        assert!((handle.get() - 0.3).abs() < 1e-6);
+
+        graph.prepare(48000, 512);
+        let mut buffer = AudioBuffer::new((0..4800).map(|i| (i as f32 * 0.05).sin() * 0.1).collect(), 48000);
+        buffer.process_blocks(512, |block| graph.process(block));
+        assert!(buffer.samples.iter().all(|s| s.is_finite()));
+    }
+
+    #[test]
+    fn test_inline_macro_fans_out() {
+        // One macro drives both gains: 1.0 → 2.0 * 2.0 = 4.0.
+        let json = r#"{
+            "name": "Outer",
+            "definitions": [{
+                "name": "Twice",
+                "nodes": [
+                    { "id": 0, "type": "input" },
+                    { "id": 1, "type": "gain" },
+                    { "id": 2, "type": "gain" },
+                    { "id": 3, "type": "output" }
+                ],
+                "edges": [{ "from": 0, "to": 1 }, { "from": 1, "to": 2 }, { "from": 2, "to": 3 }],
+                "macros": [{ "key": "drive", "targets": [{ "node": 1, "param": "factor" }, { "node": 2, "param": "factor" }] }]
+            }],
+            "nodes": [
+                { "id": 0, "type": "input" },
+                { "id": 5, "type": "subgraph", "params": { "graph": "Twice", "drive": 2.0 } },
+                { "id": 9, "type": "output" }
+            ],
+            "edges": [{ "from": 0, "to": 5 }, { "from": 5, "to": 9 }]
+        }"#;
+        let def = GraphDef::from_json(json).unwrap();
+        assert!(matches!(def.definitions[0], SubgraphDef::Graph(_)));
+        let mut graph = def.build().unwrap();
+        graph.prepare(48000, 64);
+        let mut buffer = AudioBuffer::new(vec![1.0; 64], 48000);
+        graph.process(&mut buffer);
+        assert!((buffer.samples[63] - 4.0).abs() < 1e-5, "{}", buffer.samples[63]);
+
+        // Moving the macro moves both gains: 0.5 * 0.5.
+        let (node, handle) = graph.params().pop().unwrap();
+        assert_eq!((node, handle.descriptor.key), (5, "drive"));
+        handle.set(0.5);
+        let mut last = 0.0;
+        for _ in 0..200 {
+            let mut buffer = AudioBuffer::new(vec![1.0; 64], 48000);
+            graph.process(&mut buffer);
+            last = buffer.samples[63];
+        }
+        assert!((last - 0.25).abs() < 1e-4, "{last}");
+    }
+
+    #[test]
+    fn test_reference_cycle_is_rejected() {
+        let json = r#"{
+            "name": "Outer",
+            "definitions": [
+                {
+                    "name": "A",
+                    "nodes": [
+                        { "id": 0, "type": "input" },
+                        { "id": 1, "type": "subgraph", "params": { "graph": "B" } },
+                        { "id": 2, "type": "output" }
+                    ],
+                    "edges": [{ "from": 0, "to": 1 }, { "from": 1, "to": 2 }]
+                },
+                {
+                    "name": "B",
+                    "nodes": [
+                        { "id": 0, "type": "input" },
+                        { "id": 1, "type": "subgraph", "params": { "graph": "A" } },
+                        { "id": 2, "type": "output" }
+                    ],
+                    "edges": [{ "from": 0, "to": 1 }, { "from": 1, "to": 2 }]
+                }
+            ],
+            "nodes": [
+                { "id": 0, "type": "input" },
+                { "id": 1, "type": "subgraph", "params": { "graph": "A" } },
+                { "id": 2, "type": "output" }
+            ],
+            "edges": [{ "from": 0, "to": 1 }, { "from": 1, "to": 2 }]
+        }"#;
+        let err = build(json).err().expect("A and B include each other");
+        assert!(err.to_string().contains("A → B → A"), "{err}");
+    }
+
+    #[test]
+    fn test_bad_references_are_rejected() {
+        let graph = |params: &str| {
+            format!(
+                r#"{{
+                    "name": "Outer",
+                    "nodes": [
+                        {{ "id": 0, "type": "input" }},
+                        {{ "id": 1, "type": "subgraph", "params": {params} }},
+                        {{ "id": 2, "type": "output" }}
+                    ],
+                    "edges": [{{ "from": 0, "to": 1 }}, {{ "from": 1, "to": 2 }}]
+                }}"#
+            )
+        };
+        let err = build(&graph(r#"{ "graph": "Nowhere" }"#)).err().unwrap();
+        assert!(err.to_string().contains("no graph named 'Nowhere'"), "{err}");
+        let err = build(&graph(r#"{ "chain": "Voice Cleanup", "volume": 1 }"#)).err().unwrap();
+        assert!(err.to_string().contains("no macro 'volume'"), "{err}");
+        let err = build(&graph("{}")).err().unwrap();
+        assert!(err.to_string().contains("'graph' or 'chain'"), "{err}");
+    }
+}
@@ -1,5 +1,5 @@
 use crate::presets::build_preset_chain;
-use vozoo_core::{read_wav, write_wav, AudioBuffer};
+use vozoo_core::{read_wav, write_wav, AudioBuffer, VozooError};
 use std::f32::consts::TAU;
 
 fn generate_test_wav(path: &str) -> AudioBuffer {
@@ -19,8 +19,7 @@ fn test_gorilla_preset() {
     let output = "/tmp/vozoo_test_gorilla_out.wav";
     let original = generate_test_wav(input);
 
-    let result = crate::process_file(input, output, 0);
-    assert_eq!(result, 0);
+    crate::process_file(input, output, 0).unwrap();
 
     let processed = read_wav(output).unwrap();
     // Phase vocoder preserves duration
@@ -37,8 +36,7 @@ fn test_cat_preset() {
     let output = "/tmp/vozoo_test_cat_out.wav";
     let original = generate_test_wav(input);
 
-    let result = crate::process_file(input, output, 1);
-    assert_eq!(result, 0);
+    crate::process_file(input, output, 1).unwrap();
 
     let processed = read_wav(output).unwrap();
     // Phase vocoder preserves duration
@@ -54,8 +52,7 @@ fn test_robot_preset() {
     let output = "/tmp/vozoo_test_robot_out.wav";
     generate_test_wav(input);
 
-    let result = crate::process_file(input, output, 2);
-    assert_eq!(result, 0);
+    crate::process_file(input, output, 2).unwrap();
 
     let processed = read_wav(output).unwrap();
     // Robot doesn't change length
@@ -73,8 +70,7 @@ fn test_chorus_preset() {
     let output = "/tmp/vozoo_test_chorus_out.wav";
     let original = generate_test_wav(input);
 
-    let result = crate::process_file(input, output, 3);
-    assert_eq!(result, 0);
+    crate::process_file(input, output, 3).unwrap();
 
     let processed = read_wav(output).unwrap();
     // Chorus doesn't change length
@@ -90,8 +86,7 @@ fn test_reverb_preset() {
     let output = "/tmp/vozoo_test_reverb_out.wav";
     let original = generate_test_wav(input);
 
-    let result = crate::process_file(input, output, 4);
-    assert_eq!(result, 0);
+    crate::process_file(input, output, 4).unwrap();
 
     let processed = read_wav(output).unwrap();
     // Reverb doesn't change length
@@ -104,7 +99,27 @@ fn test_reverb_preset() {
 #[test]
 fn test_invalid_input_returns_error() {
     let result = crate::process_file("/tmp/nonexistent.wav", "/tmp/out.wav", 0);
-    assert_eq!(result, -1);
+    match result {
+        Err(VozooError::Read { path, source }) => {
+            assert_eq!(path, "/tmp/nonexistent.wav");
+            assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+        }
+        other => panic!("expected a read error, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_invalid_definition_fails_before_reading() {
+    let chain = r#"{"name":"x","nodes":[{"type":"wobble"}]}"#;
+    let result = crate::process_file_with_chain("/tmp/nonexistent.wav", "/tmp/out.wav", chain);
+    assert!(matches!(result, Err(VozooError::UnknownNode { index: 0, .. })), "{result:?}");
+
+    let graph = r#"{"name":"x","nodes":[{"id":0,"type":"input"}],"edges":[]}"#;
+    let result = crate::process_file_with_graph("/tmp/nonexistent.wav", "/tmp/out.wav", graph);
+    assert!(matches!(result, Err(VozooError::InvalidGraph(_))), "{result:?}");
+
+    let result = crate::process_file_with_chain("/tmp/nonexistent.wav", "/tmp/out.wav", "{\"name\":");
+    assert!(matches!(result, Err(VozooError::InvalidJson { line: 1, .. })), "{result:?}");
 }
 
 #[test]
@@ -127,3 +142,127 @@ fn test_chain_produces_valid_audio() {
         }
     }
 }
+
+#[test]
+fn test_file_output_is_aligned_with_its_input() {
+    let input = "/tmp/vozoo_test_latency_in.wav";
+    let output = "/tmp/vozoo_test_latency_out.wav";
+    let mut samples = vec![0.0f32; 4800];
+    samples[1000] = 0.5;
+    write_wav(input, &AudioBuffer::new(samples, 48000)).unwrap();
+
+    // The trailing lookahead limiter delays the output; the file must not.
+    let chain = r#"{"name":"unity","nodes":[{"type":"gain","params":{"factor":1.0}}]}"#;
+    let mut def = crate::ChainDef::from_json(chain).unwrap();
+    def.ensure_trailing_limiter();
+    assert!(def.build().unwrap().latency_samples(48000) > 0);
+    crate::process_file_with_chain(input, output, chain).unwrap();
+
+    let processed = read_wav(output).unwrap();
+    assert_eq!(processed.frames(), 4800);
+    let peak = (0..processed.samples.len())
+        .max_by(|&a, &b| processed.samples[a].abs().total_cmp(&processed.samples[b].abs()))
+        .unwrap();
+    assert_eq!(peak, 1000);
+    assert!((processed.samples[peak] - 0.5).abs() < 0.01);
+
+    std::fs::remove_file(input).ok();
+    std::fs::remove_file(output).ok();
+}
+
+#[test]
+fn test_hrtf_chain_writes_stereo_file() {
+    let input = "/tmp/vozoo_test_hrtf_in.wav";
+    let output = "/tmp/vozoo_test_hrtf_out.wav";
+    let original = generate_test_wav(input);
+
+    let chain = r#"{"name":"3d","nodes":[{"type":"hrtf","params":{"azimuth":-90}}]}"#;
+    crate::process_file_with_chain(input, output, chain).unwrap();
+
+    // The trailing limiter must keep the spatial output stereo.
+    let processed = read_wav(output).unwrap();
+    assert_eq!(processed.channels(), 2);
+    assert_eq!(processed.frames(), original.frames());
+    let left: f32 = processed.channel(0).map(|s| s * s).sum();
+    let right: f32 = processed.channel(1).map(|s| s * s).sum();
+    assert!(left > right, "azimuth=-90 should be louder on the left");
+
+    std::fs::remove_file(input).ok();
+    std::fs::remove_file(output).ok();
+}
+
+#[test]
+fn test_stereo_input_is_downmixed_for_mono_nodes() {
+    let planes = vec![vec![0.5f32; 4800], vec![-0.5f32; 4800]];
+    let mut buffer = AudioBuffer::from_planar(&planes, 48000);
+
+    // Gain is channel-agnostic, the lowpass is mono-only.
+    let mut chain = crate::chain::LinearChain::new();
+    chain.add(Box::new(crate::effects::gain::Gain::new(0.5)));
+    chain.add(Box::new(crate::effects::biquad::BiquadFilter::new(
+        crate::effects::biquad::FilterType::LowPass,
+        1000.0,
+        0.707,
+    )));
+    chain.process(&mut buffer);
+
+    assert_eq!(buffer.channels(), 1);
+    assert_eq!(buffer.frames(), 4800);
+    // L and R cancel out in the downmix.
+    assert!(buffer.samples.iter().all(|s| s.abs() < 1e-6));
+}
+
+#[test]
+fn test_file_is_processed_at_48k_and_written_at_its_own_rate() {
+    let input = "/tmp/vozoo_test_rate_in.wav";
+    let output = "/tmp/vozoo_test_rate_out.wav";
+    let samples: Vec<f32> = (0..44100)
+        .map(|i| (i as f32 / 44100.0 * 440.0 * TAU).sin() * 0.5)
+        .collect();
+    write_wav(input, &AudioBuffer::new(samples, 44100)).unwrap();
+
+    let chain = r#"{"name":"nr","nodes":[{"type":"noise_reduction"}]}"#;
+    crate::process_file_with_chain(input, output, chain).unwrap();
+    let processed = read_wav(output).unwrap();
+    assert_eq!(processed.sample_rate(), 44100);
+    assert_eq!(processed.frames(), 44100);
+
+    // A resample node picks the output rate instead.
+    let chain = r#"{"name":"phone","nodes":[{"type":"resample","params":{"sample_rate":16000}}]}"#;
+    crate::process_file_with_chain(input, output, chain).unwrap();
+    let processed = read_wav(output).unwrap();
+    assert_eq!(processed.sample_rate(), 16000);
+    assert!((15900..=16000).contains(&processed.frames()), "{} frames", processed.frames());
+
+    std::fs::remove_file(input).ok();
+    std::fs::remove_file(output).ok();
+}
+
+#[test]
+fn test_process_reads_and_writes_flac() {
+    let input = "/tmp/vozoo_test_codec_in.wav";
+    let flac = "/tmp/vozoo_test_codec_out.flac";
+    let wav = "/tmp/vozoo_test_codec_out.wav";
+    let original = generate_test_wav(input);
+
+    let unity = r#"{"name":"unity","nodes":[{"type":"gain","params":{"factor":1.0}}]}"#;
+    crate::process_file_with_chain(input, flac, unity).unwrap();
+    let encoded = vozoo_codec::AudioFile::open(flac).unwrap();
+    assert_eq!(encoded.format, vozoo_codec::AudioFormat::Flac);
+    assert_eq!(encoded.buffer.frames(), original.frames());
+
+    // FLAC in, WAV out.
+    crate::process_file_with_chain(flac, wav, unity).unwrap();
+    assert_eq!(read_wav(wav).unwrap().frames(), original.frames());
+
+    // An explicit format wins over the extension; unknown extensions fail.
+    let effect = crate::FileEffect::Chain(unity);
+    crate::process_file_as(input, wav, effect, Some(vozoo_codec::AudioFormat::Flac)).unwrap();
+    assert_eq!(vozoo_codec::AudioFormat::detect(wav).unwrap(), vozoo_codec::AudioFormat::Flac);
+    let result = crate::process_file_with_chain(input, "/tmp/vozoo_test_codec_out.xyz", unity);
+    assert!(matches!(result, Err(VozooError::UnsupportedFormat(_))), "{result:?}");
+
+    for path in [input, flac, wav] {
+        std::fs::remove_file(path).ok();
+    }
+}
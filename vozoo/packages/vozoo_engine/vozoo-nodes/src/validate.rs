@@ -0,0 +1,620 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use serde::Serialize;
+use vozoo_core::{AudioNode, PROCESSING_SAMPLE_RATE};
+
+use crate::chain_def::{available_nodes, build_node_public, json_error, ChainDef, NodeDef, NodeInfo};
+use crate::graph_def::GraphDef;
+use crate::subgraph::{build_subgraph, Resolver};
+
+/// Node types only graphs know, with no parameters of their own.
+const GRAPH_ANCHORS: &[&str] = &["input", "output", "mix", "passthrough"];
+
+/// Parameters factories still read that `available_nodes()` no longer lists.
+const LEGACY_PARAMS: &[(&str, &str)] = &[("pitch_shift", "factor")];
+
+/// How bad a finding is: errors stop the definition from building or
+/// make it silent, warnings build but probably don't do what was meant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One finding of `GraphDef::validate` or `ChainDef::validate`.
+///
+/// `node` is a node ID in graphs and a node index in chains, the same
+/// numbering `set_param` uses; `edge` is an index into a graph's edges.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable name of the check, e.g. "dangling_edge", for UIs to key on.
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edge: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub param: Option<String>,
+    /// Suggested fix.
+    pub fix: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, code: &'static str, message: String, fix: String) -> Self {
+        Self { severity, code, message, node: None, edge: None, param: None, fix }
+    }
+
+    fn error(code: &'static str, message: String, fix: String) -> Self {
+        Self::new(Severity::Error, code, message, fix)
+    }
+
+    fn warning(code: &'static str, message: String, fix: String) -> Self {
+        Self::new(Severity::Warning, code, message, fix)
+    }
+
+    fn at_node(mut self, node: u32) -> Self {
+        self.node = Some(node);
+        self
+    }
+
+    fn at_edge(mut self, edge: usize) -> Self {
+        self.edge = Some(edge);
+        self
+    }
+
+    fn at_param(mut self, param: &str) -> Self {
+        self.param = Some(param.to_string());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{severity}[{}]", self.code)?;
+        if let Some(node) = self.node {
+            write!(f, " node {node}")?;
+        }
+        if let Some(edge) = self.edge {
+            write!(f, " edge {edge}")?;
+        }
+        if let Some(param) = &self.param {
+            write!(f, " '{param}'")?;
+        }
+        write!(f, ": {} (fix: {})", self.message, self.fix)
+    }
+}
+
+/// Validate a chain or graph definition given as JSON; a definition with
+/// `edges` is a graph. Malformed JSON is reported as a single error.
+pub fn validate_json(json: &str) -> Vec<Diagnostic> {
+    let value: serde_json::Value = match serde_json::from_str(json) {
+        Ok(value) => value,
+        Err(err) => return vec![invalid_json(err)],
+    };
+    let parsed = if value.get("edges").is_some() {
+        serde_json::from_value::<GraphDef>(value).map(|def| def.validate())
+    } else {
+        serde_json::from_value::<ChainDef>(value).map(|def| def.validate())
+    };
+    parsed.unwrap_or_else(|err| vec![invalid_json(err)])
+}
+
+fn invalid_json(err: serde_json::Error) -> Diagnostic {
+    Diagnostic::error(
+        "invalid_json",
+        json_error(err).to_string(),
+        "fix the JSON so it matches the chain or graph schema".into(),
+    )
+}
+
+pub(crate) fn validate_chain(def: &ChainDef) -> Vec<Diagnostic> {
+    let catalog = available_nodes();
+    let mut found = Vec::new();
+    if def.nodes.is_empty() {
+        found.push(Diagnostic::warning(
+            "empty",
+            "the chain has no nodes and passes audio through unchanged".into(),
+            "add an effect node".into(),
+        ));
+    }
+    for (index, node) in def.nodes.iter().enumerate() {
+        let location = index as u32;
+        match catalog.iter().find(|info| info.node_type == node.node_type) {
+            Some(info) => check_params(info, &node.params, location, &mut found),
+            None => found.push(unknown_type(&node.node_type, location, catalog.iter().map(|n| n.node_type.as_str()))),
+        }
+    }
+    found
+}
+
+pub(crate) fn validate_graph(def: &GraphDef) -> Vec<Diagnostic> {
+    let catalog = available_nodes();
+    let mut found = Vec::new();
+
+    // Nodes: unique IDs, known types, sensible params.
+    let mut seen = HashSet::new();
+    let mut resolver = Resolver::default();
+    resolver.enter(&def.definitions);
+    let mut ports: HashMap<u32, (Vec<&'static str>, Vec<&'static str>)> = HashMap::new();
+    for node in &def.nodes {
+        if !seen.insert(node.id) {
+            found.push(
+                Diagnostic::error(
+                    "duplicate_id",
+                    format!("more than one node has ID {}; edges to it reach only the first", node.id),
+                    "give every node its own ID".into(),
+                )
+                .at_node(node.id),
+            );
+            continue;
+        }
+        let built: Option<Box<dyn AudioNode>> = match node.node_type.as_str() {
+            anchor if GRAPH_ANCHORS.contains(&anchor) => {
+                check_no_params(&node.params, node.id, &mut found);
+                None
+            }
+            "subgraph" => match build_subgraph(&node.params, &mut resolver) {
+                Ok(subgraph) => Some(Box::new(subgraph)),
+                Err(err) => {
+                    found.push(
+                        Diagnostic::error(
+                            "invalid_subgraph",
+                            err.to_string(),
+                            "point the subgraph at an existing graph or chain and set only its macros".into(),
+                        )
+                        .at_node(node.id),
+                    );
+                    None
+                }
+            },
+            node_type => match catalog.iter().find(|info| info.node_type == node_type) {
+                Some(info) => {
+                    check_params(info, &node.params, node.id, &mut found);
+                    build_node_public(&NodeDef { node_type: node.node_type.clone(), params: node.params.clone() })
+                }
+                None => {
+                    let types = catalog.iter().map(|n| n.node_type.as_str()).chain(GRAPH_ANCHORS.iter().copied());
+                    found.push(unknown_type(node_type, node.id, types.chain(["subgraph"])));
+                    None
+                }
+            },
+        };
+        if let Some(built) = built {
+            if built.output_sample_rate(PROCESSING_SAMPLE_RATE) != PROCESSING_SAMPLE_RATE {
+                found.push(
+                    Diagnostic::error(
+                        "rate_change",
+                        format!("'{}' changes the sample rate, which only chains support", node.node_type),
+                        "remove it, or run this section as a chain".into(),
+                    )
+                    .at_node(node.id),
+                );
+            } else if built.changes_length() {
+                found.push(
+                    Diagnostic::error(
+                        "length_change",
+                        format!("'{}' changes the length of its audio, which only chains support", node.node_type),
+                        "run it in a chain, where the engine paces its input".into(),
+                    )
+                    .at_node(node.id),
+                );
+            }
+            ports.insert(node.id, (built.input_ports().to_vec(), built.output_ports().to_vec()));
+        }
+    }
+
+    // Anchors.
+    let anchor = |kind: &str| def.nodes.iter().filter(|n| n.node_type == kind).map(|n| n.id).collect::<Vec<_>>();
+    let (inputs, outputs) = (anchor("input"), anchor("output"));
+    for (kind, ids) in [("input", &inputs), ("output", &outputs)] {
+        match ids.as_slice() {
+            [] => found.push(Diagnostic::error(
+                "missing_anchor",
+                format!("the graph has no '{kind}' node"),
+                format!("add a node of type '{kind}' and connect it"),
+            )),
+            [_] => {}
+            [first, rest @ ..] => found.extend(rest.iter().map(|&id| {
+                Diagnostic::warning(
+                    "extra_anchor",
+                    format!("only the first '{kind}' node (ID {first}) is used as the graph's {kind}"),
+                    format!("remove this '{kind}' node or make it a 'passthrough'"),
+                )
+                .at_node(id)
+            })),
+        }
+    }
+
+    // Edges: both ends exist and name ports they have.
+    let mut valid = Vec::new();
+    for (index, edge) in def.edges.iter().enumerate() {
+        let mut ok = true;
+        for (end, id) in [("source", edge.from), ("target", edge.to)] {
+            if !seen.contains(&id) {
+                ok = false;
+                found.push(
+                    Diagnostic::error(
+                        "dangling_edge",
+                        format!("its {end} node {id} doesn't exist, so the edge is ignored"),
+                        "connect it to an existing node or remove it".into(),
+                    )
+                    .at_edge(index),
+                );
+            }
+        }
+        let checks = [(edge.from, &edge.from_port, "output"), (edge.to, &edge.to_port, "input")];
+        for (id, port, direction) in checks {
+            let (Some(port), Some((ins, outs))) = (port, ports.get(&id)) else {
+                continue;
+            };
+            let names = if direction == "input" { ins } else { outs };
+            if !names.contains(&port.as_str()) {
+                ok = false;
+                found.push(
+                    Diagnostic::error(
+                        "unknown_port",
+                        format!("node {id} has no {direction} port '{port}'"),
+                        format!("use one of: {}", names.join(", ")),
+                    )
+                    .at_edge(index)
+                    .at_node(id),
+                );
+            }
+        }
+        if !edge.gain.is_finite() {
+            found.push(
+                Diagnostic::error("invalid_gain", format!("gain {} isn't a number", edge.gain), "set a finite gain".into())
+                    .at_edge(index),
+            );
+        } else if edge.gain == 0.0 {
+            found.push(
+                Diagnostic::warning("silent_edge", "gain 0 carries no signal".into(), "raise the gain or remove the edge".into())
+                    .at_edge(index),
+            );
+        }
+        if edge.delay == Some(0) {
+            found.push(
+                Diagnostic::warning(
+                    "zero_delay",
+                    "a delay of 0 frames can't break a cycle".into(),
+                    "give the feedback edge a delay of at least 1 frame".into(),
+                )
+                .at_edge(index),
+            );
+        }
+        if ok {
+            valid.push(index);
+        }
+    }
+
+    found.extend(check_cycles(def, &valid));
+    found.extend(check_reach(def, &valid, inputs.first().copied(), outputs.first().copied()));
+    found
+}
+
+/// Report nodes on cycles no delayed edge breaks, once per node.
+fn check_cycles(def: &GraphDef, valid: &[usize]) -> Vec<Diagnostic> {
+    let mut ids: Vec<u32> = def.nodes.iter().map(|n| n.id).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let forward: Vec<_> = valid.iter().map(|&i| &def.edges[i]).filter(|e| e.delay.unwrap_or(0) == 0).collect();
+    let mut indegree: HashMap<u32, usize> = ids.iter().map(|&id| (id, 0)).collect();
+    for edge in &forward {
+        *indegree.entry(edge.to).or_default() += 1;
+    }
+    let mut ready: VecDeque<u32> = ids.iter().copied().filter(|id| indegree[id] == 0).collect();
+    while let Some(id) = ready.pop_front() {
+        for edge in forward.iter().filter(|e| e.from == id) {
+            let degree = indegree.get_mut(&edge.to).expect("edge ends exist");
+            *degree -= 1;
+            if *degree == 0 {
+                ready.push_back(edge.to);
+            }
+        }
+    }
+    let stuck: Vec<u32> = ids.iter().copied().filter(|id| indegree[id] > 0).collect();
+    if stuck.is_empty() {
+        return Vec::new();
+    }
+    let list: Vec<String> = stuck.iter().map(u32::to_string).collect();
+    let edge = valid
+        .iter()
+        .copied()
+        .find(|&i| def.edges[i].delay.unwrap_or(0) == 0 && stuck.contains(&def.edges[i].from) && stuck.contains(&def.edges[i].to));
+    let diagnostic = Diagnostic::error(
+        "cycle",
+        format!("nodes {} form a cycle without a delayed edge", list.join(", ")),
+        "give one edge of the loop a \"delay\" to make it a feedback loop, or remove it".into(),
+    );
+    vec![match edge {
+        Some(edge) => diagnostic.at_edge(edge),
+        None => diagnostic,
+    }]
+}
+
+/// Report nodes the input never reaches and nodes that never reach the
+/// output.
+fn check_reach(def: &GraphDef, valid: &[usize], input: Option<u32>, output: Option<u32>) -> Vec<Diagnostic> {
+    let edges: Vec<(u32, u32)> = valid.iter().map(|&i| (def.edges[i].from, def.edges[i].to)).collect();
+    let reach = |start: u32, forward: bool| {
+        let mut seen = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(id) = queue.pop_front() {
+            for &(from, to) in &edges {
+                let (here, next) = if forward { (from, to) } else { (to, from) };
+                if here == id && seen.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        seen
+    };
+
+    let mut found = Vec::new();
+    let (Some(input), Some(output)) = (input, output) else {
+        return found;
+    };
+    let fed = reach(input, true);
+    let heard = reach(output, false);
+    if !fed.contains(&output) {
+        found.push(
+            Diagnostic::error(
+                "disconnected",
+                format!("no path leads from the input (node {input}) to the output (node {output}), so the graph is silent"),
+                "connect the input to the output through the effect nodes".into(),
+            )
+            .at_node(output),
+        );
+    }
+    let mut reported = HashSet::new();
+    for node in &def.nodes {
+        if node.id == input || node.id == output || !reported.insert(node.id) {
+            continue;
+        }
+        if !fed.contains(&node.id) {
+            found.push(
+                Diagnostic::warning(
+                    "unreachable",
+                    format!("'{}' gets no signal from the input", node.node_type),
+                    format!("add an edge into node {} from the input side, or remove it", node.id),
+                )
+                .at_node(node.id),
+            );
+        } else if !heard.contains(&node.id) {
+            found.push(
+                Diagnostic::warning(
+                    "dead_end",
+                    format!("'{}' doesn't lead to the output, so it isn't heard", node.node_type),
+                    format!("add an edge from node {} towards the output, or remove it", node.id),
+                )
+                .at_node(node.id),
+            );
+        }
+    }
+    found
+}
+
+fn check_no_params(params: &serde_json::Value, node: u32, found: &mut Vec<Diagnostic>) {
+    for key in params.as_object().into_iter().flat_map(|p| p.keys()) {
+        found.push(
+            Diagnostic::warning("unknown_param", "this node takes no parameters".into(), "remove it".into())
+                .at_node(node)
+                .at_param(key),
+        );
+    }
+}
+
+fn check_params(info: &NodeInfo, params: &serde_json::Value, node: u32, found: &mut Vec<Diagnostic>) {
+    let entries = match params {
+        serde_json::Value::Null => return,
+        serde_json::Value::Object(entries) => entries,
+        _ => {
+            found.push(
+                Diagnostic::warning(
+                    "invalid_params",
+                    "params should be an object of named values; they are ignored".into(),
+                    "write them as { \"key\": value }".into(),
+                )
+                .at_node(node),
+            );
+            return;
+        }
+    };
+    for (key, value) in entries {
+        let legacy = LEGACY_PARAMS.contains(&(info.node_type.as_str(), key.as_str()));
+        let Some(param) = info.params.iter().find(|p| p.key == *key) else {
+            if !legacy {
+                let fix = match closest(key, info.params.iter().map(|p| p.key.as_str())) {
+                    Some(near) => format!("did you mean '{near}'?"),
+                    None if info.params.is_empty() => "remove it; this node takes no parameters".into(),
+                    None => {
+                        let keys: Vec<&str> = info.params.iter().map(|p| p.key.as_str()).collect();
+                        format!("use one of: {}", keys.join(", "))
+                    }
+                };
+                found.push(
+                    Diagnostic::warning("unknown_param", format!("'{}' has no parameter '{key}'; it's ignored", info.node_type), fix)
+                        .at_node(node)
+                        .at_param(key),
+                );
+            }
+            continue;
+        };
+        match value.as_f64() {
+            None => found.push(
+                Diagnostic::warning(
+                    "invalid_param",
+                    format!("{value} isn't a number, so the default {} is used", param.default),
+                    format!("set a number between {} and {}", param.min, param.max),
+                )
+                .at_node(node)
+                .at_param(key),
+            ),
+            Some(v) if v < param.min || v > param.max => {
+                let clamped = v.clamp(param.min, param.max);
+                found.push(
+                    Diagnostic::warning(
+                        "param_out_of_range",
+                        format!("{v} is outside {} .. {} and will be clamped to {clamped}", param.min, param.max),
+                        format!("set {key} to {clamped}"),
+                    )
+                    .at_node(node)
+                    .at_param(key),
+                );
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+fn unknown_type<'a>(node_type: &str, node: u32, known: impl Iterator<Item = &'a str>) -> Diagnostic {
+    let fix = match closest(node_type, known) {
+        Some(near) => format!("did you mean '{near}'?"),
+        None => "use a type listed by available_nodes()".into(),
+    };
+    Diagnostic::error("unknown_node", format!("unknown node type '{node_type}'"), fix).at_node(node)
+}
+
+/// The candidate nearest to `name` by edit distance, if it's close enough
+/// to be a typo.
+fn closest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(2);
+    candidates
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|&(d, _)| d <= limit)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, c)| c)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1).min(row[j] + 1).min(diagonal + usize::from(ca != cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_def::preset_chain_defs;
+    use crate::graph_def::preset_graph_defs;
+
+    fn codes(found: &[Diagnostic]) -> Vec<&'static str> {
+        found.iter().map(|d| d.code).collect()
+    }
+
+    #[test]
+    fn test_presets_are_clean() {
+        for def in preset_chain_defs() {
+            assert_eq!(def.validate(), Vec::new(), "chain '{}'", def.name);
+        }
+        for def in preset_graph_defs() {
+            assert_eq!(def.validate(), Vec::new(), "graph '{}'", def.name);
+        }
+    }
+
+    #[test]
+    fn test_graph_mistakes_are_located() {
+        let json = r#"{
+            "name": "Messy",
+            "nodes": [
+                { "id": 0, "type": "input" },
+                { "id": 1, "type": "lowpas", "params": { "freq": 800 } },
+                { "id": 2, "type": "gain", "params": { "factor": 9.0, "volume": 1 } },
+                { "id": 2, "type": "gain" },
+                { "id": 3, "type": "chorus" },
+                { "id": 4, "type": "reverb" },
+                { "id": 5, "type": "output" }
+            ],
+            "edges": [
+                { "from": 0, "to": 2 },
+                { "from": 2, "to": 5 },
+                { "from": 2, "to": 7 },
+                { "from": 0, "to": 3, "to_port": "sidechain" },
+                { "from": 0, "to": 4 }
+            ]
+        }"#;
+        let found = validate_json(json);
+        let at = |code: &str| found.iter().find(|d| d.code == code).unwrap_or_else(|| panic!("no {code} in {found:#?}"));
+
+        let unknown = at("unknown_node");
+        assert_eq!((unknown.node, unknown.severity), (Some(1), Severity::Error));
+        assert!(unknown.fix.contains("'lowpass'"), "{}", unknown.fix);
+        assert_eq!(at("duplicate_id").node, Some(2));
+        let range = at("param_out_of_range");
+        assert_eq!((range.node, range.param.as_deref()), (Some(2), Some("factor")));
+        assert!(range.message.contains("clamped to 4"), "{}", range.message);
+        assert_eq!(at("unknown_param").param.as_deref(), Some("volume"));
+        assert_eq!(at("dangling_edge").edge, Some(2));
+        assert_eq!((at("unknown_port").edge, at("unknown_port").node), (Some(3), Some(3)));
+        let unreachable: Vec<_> = found.iter().filter(|d| d.code == "unreachable").map(|d| d.node).collect();
+        assert_eq!(unreachable, [Some(1), Some(3)]);
+        assert_eq!(at("dead_end").node, Some(4));
+        assert!(!found.iter().any(|d| d.code == "disconnected"));
+    }
+
+    #[test]
+    fn test_graph_structure_errors() {
+        let json = r#"{
+            "name": "Loop",
+            "nodes": [
+                { "id": 0, "type": "input" },
+                { "id": 1, "type": "mix" },
+                { "id": 2, "type": "gain" }
+            ],
+            "edges": [
+                { "from": 0, "to": 1 },
+                { "from": 1, "to": 2 },
+                { "from": 2, "to": 1 }
+            ]
+        }"#;
+        let found = validate_json(json);
+        assert_eq!(codes(&found), ["missing_anchor", "cycle"]);
+        assert!(found[0].message.contains("'output'"));
+        assert_eq!(found[1].edge, Some(1));
+
+        // A delay turns the loop into feedback.
+        let fixed = json.replace(r#"{ "from": 2, "to": 1 }"#, r#"{ "from": 2, "to": 1, "delay": 100 }"#);
+        assert_eq!(codes(&validate_json(&fixed)), ["missing_anchor"]);
+
+        let stretched = fixed.replace(r#""type": "gain""#, r#""type": "time_stretch", "params": { "rate": 1 }"#);
+        let found = validate_json(&stretched);
+        assert_eq!(codes(&found), ["length_change", "missing_anchor"]);
+        assert_eq!(found[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_chain_and_json_diagnostics() {
+        let found = validate_json(r#"{ "name": "c", "nodes": [{ "type": "gain", "params": { "factor": "loud" } }, { "type": "ringmod" }] }"#);
+        assert_eq!(codes(&found), ["invalid_param", "unknown_node"]);
+        assert_eq!(found[1].node, Some(1));
+        assert!(found[1].fix.contains("'ring_mod'"));
+
+        let found = validate_json(r#"{ "name": "c", "nodes": [ }"#);
+        assert_eq!(codes(&found), ["invalid_json"]);
+        assert!(found[0].message.contains("line 1"), "{}", found[0].message);
+
+        let json = serde_json::to_string(&found).unwrap();
+        assert!(json.contains(r#""severity":"error""#) && !json.contains("\"node\""), "{json}");
+    }
+}
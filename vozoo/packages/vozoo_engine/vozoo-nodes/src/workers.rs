@@ -0,0 +1,224 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+/// A fixed set of threads that stay parked between jobs, so running a
+/// graph level on them costs a wake-up rather than a thread spawn.
+///
+/// The calling thread takes part in every job, so a pool of `n` threads
+/// runs up to `n + 1` items at once. Dropping the pool stops its threads.
+pub struct WorkerPool {
+    shared: Arc<Shared>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled when a job is published or the pool shuts down.
+    work: Condvar,
+    /// Signalled when the last item of a job finishes.
+    done: Condvar,
+}
+
+/// Runs item `i` of the current job.
+type Task = &'static (dyn Fn(usize) + Sync);
+
+#[derive(Default)]
+struct State {
+    task: Option<Task>,
+    /// Items handed out and items finished for the current job.
+    claimed: usize,
+    finished: usize,
+    items: usize,
+    panicked: bool,
+    shutdown: bool,
+}
+
+impl WorkerPool {
+    /// Start a pool with `threads` worker threads besides the caller's.
+    pub fn new(threads: usize) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            work: Condvar::new(),
+            done: Condvar::new(),
+        });
+        let threads = (0..threads)
+            .map(|i| {
+                let shared = Arc::clone(&shared);
+                thread::Builder::new()
+                    .name(format!("vozoo-worker-{i}"))
+                    .spawn(move || shared.work_loop())
+                    .expect("failed to start worker thread")
+            })
+            .collect();
+        Self { shared, threads }
+    }
+
+    /// A pool sized to use every core, counting the caller's.
+    pub fn for_available_cores() -> Self {
+        let cores = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new(cores - 1)
+    }
+
+    /// Number of worker threads, not counting the caller.
+    pub fn threads(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Call `f` once for every item, spread over the pool and the calling
+    /// thread. Returns when all items are done; a panic in `f` is raised
+    /// again here once the others have finished.
+    pub fn for_each<T: Send>(&self, items: &mut [T], f: impl Fn(&mut T) + Sync) {
+        if self.threads.is_empty() || items.len() < 2 {
+            items.iter_mut().for_each(f);
+            return;
+        }
+
+        let base = ItemsPtr(items.as_mut_ptr());
+        let task = move |i: usize| {
+            // SAFETY: every index below `items.len()` is claimed exactly
+            // once, so each item is borrowed by one thread at a time.
+            f(unsafe { &mut *base.get().add(i) })
+        };
+        self.run(items.len(), &task);
+    }
+
+    fn run(&self, items: usize, task: &(dyn Fn(usize) + Sync)) {
+        // SAFETY: the task is only reached through `State::task` while an
+        // item is claimed, and this call doesn't return (or unwind) before
+        // every claimed item has finished and the task has been taken back.
+        let task: Task = unsafe { std::mem::transmute::<&(dyn Fn(usize) + Sync), Task>(task) };
+
+        let mut state = self.shared.lock();
+        *state = State { task: Some(task), items, ..State::default() };
+        drop(state);
+        self.shared.work.notify_all();
+
+        let mut state = self.shared.work_on(self.shared.lock());
+        while state.finished < state.items {
+            state = self.shared.done.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        state.task = None;
+        let panicked = state.panicked;
+        drop(state);
+        if panicked {
+            panic!("a worker pool task panicked");
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.work.notify_all();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn work_loop(&self) {
+        let mut state = self.lock();
+        loop {
+            if state.shutdown {
+                return;
+            }
+            if state.task.is_some() && state.claimed < state.items {
+                state = self.work_on(state);
+            } else {
+                state = self.work.wait(state).unwrap_or_else(|e| e.into_inner());
+            }
+        }
+    }
+
+    /// Claim and run items of the current job until none are left.
+    fn work_on<'a>(&'a self, mut state: MutexGuard<'a, State>) -> MutexGuard<'a, State> {
+        while let Some(task) = state.task.filter(|_| state.claimed < state.items) {
+            let i = state.claimed;
+            state.claimed += 1;
+            drop(state);
+            let ok = panic::catch_unwind(AssertUnwindSafe(|| task(i))).is_ok();
+            state = self.lock();
+            state.panicked |= !ok;
+            state.finished += 1;
+            if state.finished == state.items {
+                self.done.notify_all();
+            }
+        }
+        state
+    }
+}
+
+/// Base of the slice a `for_each` job works through.
+struct ItemsPtr<T>(*mut T);
+
+impl<T> ItemsPtr<T> {
+    fn get(&self) -> *mut T {
+        self.0
+    }
+}
+
+impl<T> Clone for ItemsPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ItemsPtr<T> {}
+
+// SAFETY: items are `Send` and each is handed to a single thread.
+unsafe impl<T: Send> Send for ItemsPtr<T> {}
+unsafe impl<T: Send> Sync for ItemsPtr<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_every_item_runs_once() {
+        let pool = WorkerPool::new(3);
+        let mut items = vec![0u32; 37];
+        for round in 1..=50 {
+            pool.for_each(&mut items, |item| *item += 1);
+            assert!(items.iter().all(|&item| item == round));
+        }
+    }
+
+    #[test]
+    fn test_threads_are_reused() {
+        let pool = WorkerPool::new(2);
+        let names = Mutex::new(std::collections::HashSet::new());
+        let mut items = vec![(); 64];
+        for _ in 0..20 {
+            pool.for_each(&mut items, |_| {
+                names.lock().unwrap().insert(thread::current().id());
+            });
+        }
+        assert!(names.into_inner().unwrap().len() <= pool.threads() + 1);
+    }
+
+    #[test]
+    fn test_panic_reaches_the_caller() {
+        let pool = WorkerPool::new(2);
+        let ran = AtomicUsize::new(0);
+        let mut items: Vec<usize> = (0..8).collect();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.for_each(&mut items, |&mut i| {
+                ran.fetch_add(1, Ordering::Relaxed);
+                assert_ne!(i, 5);
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(ran.load(Ordering::Relaxed), 8);
+
+        // The pool still works afterwards.
+        pool.for_each(&mut items, |i| *i += 1);
+        assert_eq!(items, (1..9).collect::<Vec<_>>());
+    }
+}